@@ -0,0 +1,168 @@
+use std::{
+    path::Path,
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+use anyhow::{Context, Result};
+use futures::AsyncWriteExt;
+
+use crate::file::{io_error, set_file_mode, Sink};
+
+// Size of the blocks that the bloom file is written in.
+// Bloom filter size is rounded up to a multiple of this so we can write any block with direct io.
+pub(crate) const BLOCK_SIZE: usize = 4096;
+const WORDS_PER_BLOCK: usize = BLOCK_SIZE / 8;
+const NUM_HASHES: u64 = 4;
+
+// A writer that is made while the filter is disabled doesn't add its keys to the bloom file, so it marks the file stale
+// with this file instead of removing it. Open rebuilds a stale filter from the keys, the marker is removed when the
+// rebuilt filter is written.
+const STALE_MARKER: &str = "bloom_stale";
+
+/// Advisory filter over the keys of a store.
+/// `may_contain` never returns false for a key that is in the store.
+pub struct BloomFilter {
+    words: Box<[AtomicU64]>,
+}
+
+impl BloomFilter {
+    pub(crate) fn new(num_bits: u64) -> Self {
+        let num_blocks = std::cmp::max(
+            1,
+            usize::try_from(num_bits.div_ceil(u64::try_from(BLOCK_SIZE * 8).unwrap())).unwrap(),
+        );
+
+        Self {
+            words: (0..num_blocks * WORDS_PER_BLOCK)
+                .map(|_| AtomicU64::new(0))
+                .collect(),
+        }
+    }
+
+    pub(crate) fn from_bytes(buf: &[u8]) -> Self {
+        Self {
+            words: buf
+                .chunks_exact(8)
+                .map(|chunk| AtomicU64::new(u64::from_be_bytes(chunk.try_into().unwrap())))
+                .collect(),
+        }
+    }
+
+    pub(crate) fn size_in_bytes(num_bits: u64) -> usize {
+        Self::new(num_bits).words.len() * 8
+    }
+
+    pub fn num_bits(&self) -> u64 {
+        u64::try_from(self.words.len()).unwrap() * 64
+    }
+
+    pub fn may_contain(&self, key: u64) -> bool {
+        self.bit_positions(key).all(|bit| {
+            let word = self.words[usize::try_from(bit / 64).unwrap()].load(Ordering::SeqCst);
+            word & (1 << (bit % 64)) != 0
+        })
+    }
+
+    // Sets the bits for the key and returns the indices of the blocks that were touched.
+    pub(crate) fn insert(&self, key: u64) -> Vec<usize> {
+        let mut blocks = Vec::with_capacity(usize::try_from(NUM_HASHES).unwrap());
+
+        for bit in self.bit_positions(key) {
+            let word_idx = usize::try_from(bit / 64).unwrap();
+            self.words[word_idx].fetch_or(1 << (bit % 64), Ordering::SeqCst);
+
+            let block = word_idx / WORDS_PER_BLOCK;
+            if !blocks.contains(&block) {
+                blocks.push(block);
+            }
+        }
+
+        blocks
+    }
+
+    pub(crate) fn write_block(&self, block: usize, buf: &mut [u8]) {
+        let words = &self.words[block * WORDS_PER_BLOCK..(block + 1) * WORDS_PER_BLOCK];
+        for (word, chunk) in words.iter().zip(buf.chunks_exact_mut(8)) {
+            chunk.copy_from_slice(&word.load(Ordering::SeqCst).to_be_bytes());
+        }
+    }
+
+    // Double hashing over a stable mixing function.
+    // We can't use std hashers here since their output isn't guaranteed to be stable and the bits are persisted.
+    fn bit_positions(&self, key: u64) -> impl Iterator<Item = u64> {
+        let num_bits = self.num_bits();
+        let h1 = mix(key);
+        let h2 = mix(h1) | 1;
+        (0..NUM_HASHES).map(move |i| h1.wrapping_add(i.wrapping_mul(h2)) % num_bits)
+    }
+}
+
+// splitmix64 finalizer
+fn mix(mut x: u64) -> u64 {
+    x = x.wrapping_add(0x9e3779b97f4a7c15);
+    x = (x ^ (x >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94d049bb133111eb);
+    x ^ (x >> 31)
+}
+
+// Writes the whole filter to a new file and renames it onto the bloom file.
+pub(crate) async fn write_bloom_file(
+    dir: &Path,
+    filter: &BloomFilter,
+    file_mode: Option<u32>,
+    direct_io: bool,
+) -> Result<()> {
+    let mut path = dir.to_owned();
+    path.push("new_bloom");
+    glommio::io::remove(&path).await.ok();
+    let mut sink = Sink::create(&path, file_mode, direct_io)
+        .await
+        .context("build new bloom file")?;
+    let mut buf = vec![0; BLOCK_SIZE];
+    for block in 0..filter.words.len() / WORDS_PER_BLOCK {
+        filter.write_block(block, &mut buf);
+        sink.write_all(&buf)
+            .await
+            .context("write to new bloom file")?;
+    }
+    sink.sync().await.context("sync new bloom file to disk")?;
+    sink.close()
+        .await
+        .map_err(io_error)
+        .context("close new bloom file")?;
+    let mut final_path = dir.to_owned();
+    final_path.push("bloom");
+    glommio::io::rename(&path, &final_path)
+        .await
+        .map_err(io_error)
+        .context("rename bloom file")?;
+    clear_stale(dir).context("remove bloom_stale file")?;
+
+    Ok(())
+}
+
+pub(crate) fn is_stale(dir: &Path) -> bool {
+    dir.join(STALE_MARKER).is_file()
+}
+
+pub(crate) fn mark_stale(dir: &Path, file_mode: Option<u32>) -> Result<()> {
+    let path = dir.join(STALE_MARKER);
+    if path.is_file() {
+        return Ok(());
+    }
+
+    std::fs::File::create(&path).context("create bloom_stale file")?;
+    set_file_mode(&path, file_mode)?;
+    // The marker has to be durable before the writer appends keys that aren't in the filter.
+    std::fs::File::open(dir)
+        .and_then(|dir| dir.sync_all())
+        .context("sync store dir")
+}
+
+fn clear_stale(dir: &Path) -> Result<()> {
+    match std::fs::remove_file(dir.join(STALE_MARKER)) {
+        Ok(()) => Ok(()),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+        Err(e) => Err(e.into()),
+    }
+}
@@ -0,0 +1,112 @@
+use anyhow::{anyhow, Context, Result};
+use glommio::Task;
+
+use crate::{writer::check_table_count, TimestoreError, Writer};
+
+type Record = (u64, Vec<Vec<u8>>);
+
+/// Wraps a Writer and buffers appended records in memory, writing them to disk in the background.
+///
+/// `push` returns without doing any io until the buffer reaches `max_records` records or `max_bytes` bytes.
+/// At that point the buffer is handed to a background task that appends it to the store.
+/// If the previous background flush is still running `push` waits for it, so at most two buffers are held in memory.
+///
+/// Records are only durable after they are flushed. Buffered records that weren't flushed yet are lost
+/// if the process crashes or if the BufferedWriter is dropped without calling `flush` or `close`.
+pub struct BufferedWriter {
+    writer: Option<Writer>,
+    flush_task: Option<Task<(Writer, Result<()>)>>,
+    num_tables: usize,
+    buffer: Vec<Record>,
+    buffered_bytes: usize,
+    max_records: usize,
+    max_bytes: usize,
+}
+
+impl BufferedWriter {
+    pub fn new(
+        writer: Writer,
+        max_records: usize,
+        max_bytes: usize,
+    ) -> Result<Self, TimestoreError> {
+        if max_records == 0 || max_bytes == 0 {
+            return Err(anyhow!("max_records and max_bytes have to be at least 1").into());
+        }
+
+        Ok(Self {
+            num_tables: writer.table_names().len(),
+            writer: Some(writer),
+            flush_task: None,
+            buffer: Vec::new(),
+            buffered_bytes: 0,
+            max_records,
+            max_bytes,
+        })
+    }
+
+    /// Adds a record to the buffer. Starts a background flush if the buffer is full.
+    pub async fn push(&mut self, key: u64, values: Vec<Vec<u8>>) -> Result<(), TimestoreError> {
+        check_table_count(values.len(), self.num_tables)?;
+
+        self.buffered_bytes += values.iter().map(|v| v.len()).sum::<usize>();
+        self.buffer.push((key, values));
+
+        if self.buffer.len() >= self.max_records || self.buffered_bytes >= self.max_bytes {
+            self.wait_flush().await?;
+            self.start_flush();
+        }
+
+        Ok(())
+    }
+
+    /// Writes all buffered records to the store and waits until they are durable.
+    pub async fn flush(&mut self) -> Result<(), TimestoreError> {
+        self.wait_flush().await?;
+        self.start_flush();
+        Ok(self.wait_flush().await?)
+    }
+
+    /// Flushes all buffered records and returns the inner Writer.
+    pub async fn close(mut self) -> Result<Writer, TimestoreError> {
+        self.flush().await?;
+        Ok(self.writer.take().unwrap())
+    }
+
+    fn start_flush(&mut self) {
+        if self.buffer.is_empty() {
+            return;
+        }
+
+        let records = std::mem::take(&mut self.buffer);
+        self.buffered_bytes = 0;
+        let mut writer = self.writer.take().unwrap();
+
+        self.flush_task = Some(glommio::spawn_local(async move {
+            let res = append_all(&mut writer, records).await;
+            (writer, res)
+        }));
+    }
+
+    async fn wait_flush(&mut self) -> Result<()> {
+        if let Some(task) = self.flush_task.take() {
+            let (writer, res) = task.await;
+            self.writer = Some(writer);
+            res.context("background flush")?;
+        }
+
+        Ok(())
+    }
+}
+
+async fn append_all(writer: &mut Writer, records: Vec<Record>) -> Result<()> {
+    for (key, values) in records {
+        writer
+            .append(key, values)
+            .await
+            .with_context(|| format!("append key {}", key))?;
+    }
+    // The writer can hold the records in memory for write combining.
+    writer.flush().await.context("flush writer")?;
+
+    Ok(())
+}
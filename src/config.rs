@@ -1,13 +1,214 @@
-use std::path::{Path, PathBuf};
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+
+use crate::Observer;
 
 #[derive(Debug, Default, Clone, PartialEq, derive_builder::Builder)]
 pub struct Config {
     path: PathBuf,
     create_if_not_exists: bool,
+    /// Names of the tables in the order the store was created with. Open errors if they don't match the store.
+    /// If this is empty, the tables of an existing store are used.
+    /// This can also be a subset of the tables of the store in any order, then only those tables are loaded
+    /// and the store can only be read.
+    /// A store can be created without any tables, then it only holds keys.
     tables: Vec<String>,
+    /// Number of entries per segment of the in memory caos structures that hold keys and offsets.
+    /// Memory is allocated one segment at a time so this has to be at least 1.
+    #[builder(default = "1024")]
     segment_length: u32,
+    /// Overrides segment_length for the offsets of the listed tables.
+    #[builder(default)]
+    table_segment_lengths: HashMap<String, u32>,
+    #[builder(default, setter(into))]
+    observer: Observer,
+    /// Size in bits of the bloom filter that is kept over the keys. Rounded up to a multiple of 4096 * 8.
+    /// The filter is disabled if this is None. The bloom file of a store is kept while the filter is disabled,
+    /// a writer that is made without the filter marks it stale so it is rebuilt from the keys when the filter is enabled again.
+    #[builder(default)]
+    bloom_filter_bits: Option<u64>,
+    /// Buffer size of the readers that load the files of the store on open.
+    #[builder(default = "DEFAULT_OPEN_BUFFER_SIZE")]
+    open_buffer_size: usize,
+    /// Number of reads in flight while loading the files of the store on open.
+    #[builder(default = "DEFAULT_OPEN_CONCURRENCY")]
+    open_concurrency: usize,
+    /// Maximum size of a data file. If this is set, data of each table is split into files named `data.<start offset>`
+    /// and a new file is started when a record doesn't fit into the current one.
+    /// Records are never split so a file can be larger than this if it holds a single large record.
+    /// Data is kept in a single `data` file if this is None. This can't be changed after the store is created.
+    #[builder(default)]
+    data_segment_size: Option<u64>,
+    /// Space that is preallocated for the data files at a time, so they grow in large chunks instead of a few bytes per append.
+    /// The reported size of the files doesn't change, only the space on disk is reserved. Disabled if this is 0.
+    #[builder(default)]
+    preallocate_bytes: u64,
+    /// Appends error if a value is larger than this, before anything is allocated or written. Disabled if this is None.
+    #[builder(default)]
+    max_value_bytes: Option<usize>,
+    /// Appends of the writer are held in memory until their values, offsets and keys add up to this many bytes,
+    /// then they are written with a single write per file and committed together, see `Writer::flush`.
+    /// This cuts the write amplification of tiny records. Records that are held in memory aren't visible to readers
+    /// and are lost on a crash or if the writer is dropped without flushing. Disabled if this is 0.
+    #[builder(default)]
+    write_combine_bytes: usize,
+    /// What `Writer::append` and `Writer::append_many` do with a record that has the same key as the record before it.
+    #[builder(default)]
+    on_duplicate_key: DuplicateKeyPolicy,
+    /// Makes the key based and positional reads of readers check that the in memory keys and offsets agree
+    /// at the position of the record before reading it, and error instead of returning the wrong bytes.
+    /// Meant for development, it adds a few lookups to every read.
+    #[builder(default)]
+    validate_reads: bool,
+    /// Makes open read back the value of every committed record and check that the read returns as many bytes
+    /// as the offsets say, so a corrupt store fails at open instead of at the first read of the broken record.
+    /// This reads all of the data so it is slow on large stores. Progress is reported with `StoreObserver::on_verify_progress`.
+    #[builder(default)]
+    verify_on_open: bool,
+    /// Size in bytes of the cache of values that `Reader::read` keeps in memory, so reading a key again doesn't do any io.
+    /// Each reader made by `ReaderFactory::make` has its own cache that is shared by its clones.
+    /// The least recently used values are evicted first, values larger than the cache aren't cached. Disabled if this is 0.
+    #[builder(default)]
+    read_cache_bytes: usize,
+    /// Largest read that is issued for a value, larger values are read in chunks of this size that are copied
+    /// into one buffer, see `ReadBuf`. The ranges given to `read_many` are read as they are.
+    /// Defaults to the most bytes Linux reads in a single call, larger reads come back short.
+    #[builder(default = "DEFAULT_MAX_READ_BYTES")]
+    max_read_bytes: usize,
+    /// Keeps a tag for each record so records can be found by tag with `Reader::keys_for_tag`.
+    /// Tags are given with `Writer::append_with_tag`, records appended in any other way get the tag 0.
+    /// Enabling this on an existing store gives all of its records the tag 0. It can't be disabled again,
+    /// open errors for a store that has tags if this is false, since records appended without it wouldn't get a tag.
+    #[builder(default)]
+    tag_index: bool,
+    /// Opens the files of the store with O_DIRECT. If this is false the files are opened as buffered files instead,
+    /// which is slower but works on filesystems that don't support O_DIRECT like NFS or overlayfs.
+    /// Alignment of buffered files is 1 so writes aren't padded.
+    #[builder(default = "true")]
+    direct_io: bool,
+    /// Syncs the store directory after the length file is renamed on each commit, so the rename survives a crash.
+    /// Disabling this makes appends faster but a crash can lose the last commits even though their data was synced.
+    #[builder(default = "true")]
+    sync_dir: bool,
+    /// Permissions of the files the store creates, e.g. 0o640. The process umask doesn't apply to them.
+    /// Directories get the same permissions with the execute bit added wherever the read bit is set.
+    /// Files and directories that already exist are left as they are.
+    #[builder(default)]
+    file_mode: Option<u32>,
+    /// Where the files of the tables are placed in the store directory. This can't be changed after the store is created.
+    #[builder(default)]
+    table_layout: TableLayout,
+    /// Byte order of the values in the length, keys, offsets and tags files.
+    /// This can't be changed after the store is created.
+    #[builder(default)]
+    endianness: Endianness,
+    /// Where the committed number of records of the store is kept. This can't be changed after the store is created.
+    #[builder(default)]
+    length_source: LengthSource,
+    /// Splits the store into shards by key, see `open_sharded`. Shard i holds the keys in [boundaries[i - 1], boundaries[i]),
+    /// the first shard starts at 0 and the last one has no upper bound. Each shard is a separate store in the
+    /// `shard.<i>` directory of the path, see `shard_config`. The boundaries have to be increasing.
+    /// The store isn't sharded if this is empty.
+    #[builder(default)]
+    shard_boundaries: Vec<u64>,
+}
+
+/// Handling of a record that is appended with the same key as the last record.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum DuplicateKeyPolicy {
+    /// The record is appended like any other, so the store has the key twice.
+    #[default]
+    Allow,
+    /// The append returns an error.
+    Error,
+    /// The record is ignored and the append returns the position of the existing record.
+    Skip,
+    /// The values of the last record are replaced with the values of the new one, see `Writer::overwrite_last`.
+    Overwrite,
+}
+
+/// Naming scheme of the files of the tables.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum TableLayout {
+    /// Each table has its own directory, `<path>/<table>/data` and `<path>/<table>/offsets`.
+    #[default]
+    Nested,
+    /// Files of all tables are in the store directory, `<path>/<table>.data` and `<path>/<table>.offsets`.
+    Flat,
+}
+
+impl TableLayout {
+    /// Returns the directory that holds the files of the given table.
+    pub(crate) fn table_dir(&self, path: &Path, table: &str) -> PathBuf {
+        match self {
+            TableLayout::Nested => path.join(table),
+            TableLayout::Flat => path.to_owned(),
+        }
+    }
+
+    /// Returns the name of a file of the given table inside its directory, e.g. "data" or "offsets".
+    pub(crate) fn file_name(&self, table: &str, name: &str) -> String {
+        match self {
+            TableLayout::Nested => name.to_owned(),
+            TableLayout::Flat => format!("{}.{}", table, name),
+        }
+    }
+
+    pub(crate) fn table_file(&self, path: &Path, table: &str, name: &str) -> PathBuf {
+        self.table_dir(path, table)
+            .join(self.file_name(table, name))
+    }
+}
+
+/// Byte order of the u64 values in the files of the store.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Endianness {
+    /// Big endian values sort the same way as their bytes.
+    #[default]
+    Big,
+    /// Little endian, e.g. for reading files that were written by other tools.
+    Little,
+}
+
+/// Where the committed length of a store comes from, see `Config::length_source`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum LengthSource {
+    /// The length is kept in the length file, which is replaced by writing a new file and renaming it on each commit.
+    /// Records that are appended together are committed together.
+    #[default]
+    LengthFile,
+    /// The length is the size of the keys file divided by 8, so writing the key of a record commits it
+    /// and appends don't write and rename the length file. Only for a single writer appending to the store.
+    /// Records that are appended together are committed one by one as their keys reach the disk
+    /// and a torn write of the keys file leaves a partial key at its end, which open removes.
+    /// This needs `Config::direct_io` to be disabled since direct io pads the keys file to the alignment.
+    KeysFileSize,
+}
+
+impl Endianness {
+    pub(crate) fn encode(&self, val: u64) -> [u8; 8] {
+        match self {
+            Endianness::Big => val.to_be_bytes(),
+            Endianness::Little => val.to_le_bytes(),
+        }
+    }
+
+    /// Decodes the first 8 bytes of the buffer.
+    pub(crate) fn decode(&self, buf: &[u8]) -> u64 {
+        let buf = buf[..8].try_into().unwrap();
+        match self {
+            Endianness::Big => u64::from_be_bytes(buf),
+            Endianness::Little => u64::from_le_bytes(buf),
+        }
+    }
 }
 
+pub(crate) const DEFAULT_OPEN_BUFFER_SIZE: usize = 512 * 1024;
+pub(crate) const DEFAULT_OPEN_CONCURRENCY: usize = 8;
+pub(crate) const DEFAULT_MAX_READ_BYTES: usize = 0x7fff_f000;
+
 impl Config {
     pub fn path(&self) -> &Path {
         &self.path
@@ -21,7 +222,117 @@ impl Config {
         &self.tables
     }
 
+    pub(crate) fn set_tables(&mut self, tables: Vec<String>) {
+        self.tables = tables;
+    }
+
     pub fn segment_length(&self) -> u32 {
         self.segment_length
     }
+
+    pub fn table_segment_lengths(&self) -> &HashMap<String, u32> {
+        &self.table_segment_lengths
+    }
+
+    pub fn observer(&self) -> &Observer {
+        &self.observer
+    }
+
+    pub fn bloom_filter_bits(&self) -> Option<u64> {
+        self.bloom_filter_bits
+    }
+
+    pub fn open_buffer_size(&self) -> usize {
+        self.open_buffer_size
+    }
+
+    pub fn open_concurrency(&self) -> usize {
+        self.open_concurrency
+    }
+
+    pub fn data_segment_size(&self) -> Option<u64> {
+        self.data_segment_size
+    }
+
+    pub fn preallocate_bytes(&self) -> u64 {
+        self.preallocate_bytes
+    }
+
+    pub fn max_value_bytes(&self) -> Option<usize> {
+        self.max_value_bytes
+    }
+
+    pub fn write_combine_bytes(&self) -> usize {
+        self.write_combine_bytes
+    }
+
+    pub fn on_duplicate_key(&self) -> DuplicateKeyPolicy {
+        self.on_duplicate_key
+    }
+
+    pub fn validate_reads(&self) -> bool {
+        self.validate_reads
+    }
+
+    pub fn verify_on_open(&self) -> bool {
+        self.verify_on_open
+    }
+
+    pub fn read_cache_bytes(&self) -> usize {
+        self.read_cache_bytes
+    }
+
+    pub fn max_read_bytes(&self) -> usize {
+        self.max_read_bytes
+    }
+
+    pub fn tag_index(&self) -> bool {
+        self.tag_index
+    }
+
+    pub fn direct_io(&self) -> bool {
+        self.direct_io
+    }
+
+    pub fn sync_dir(&self) -> bool {
+        self.sync_dir
+    }
+
+    pub fn file_mode(&self) -> Option<u32> {
+        self.file_mode
+    }
+
+    pub fn table_layout(&self) -> TableLayout {
+        self.table_layout
+    }
+
+    pub fn endianness(&self) -> Endianness {
+        self.endianness
+    }
+
+    pub fn length_source(&self) -> LengthSource {
+        self.length_source
+    }
+
+    pub fn shard_boundaries(&self) -> &[u64] {
+        &self.shard_boundaries
+    }
+
+    /// Returns the config of the given shard of a sharded store, it has the same settings in the directory of the shard.
+    /// Shards can be opened on their own with `open`, e.g. to write to each shard from its own executor.
+    pub fn shard_config(&self, shard: usize) -> Config {
+        Config {
+            path: self.path.join(format!("shard.{}", shard)),
+            shard_boundaries: Vec::new(),
+            ..self.clone()
+        }
+    }
+
+    /// Returns the segment length that should be used for the given table.
+    pub fn table_segment_length(&self, table: &str) -> u32 {
+        self.table_segment_lengths
+            .get(table)
+            .copied()
+            .unwrap_or(self.segment_length)
+    }
 }
@@ -0,0 +1,223 @@
+use std::{
+    cell::RefCell,
+    path::{Path, PathBuf},
+    sync::{Arc, RwLock},
+};
+
+use anyhow::{anyhow, Context, Result};
+use glommio::io::Directory;
+
+use crate::{
+    file::{io_error, File},
+    TableLayout,
+};
+
+// Data of a table is either kept in a single "data" file or split into segment files if data_segment_size is set.
+// Segment files are named "data.<start>" where start is the offset of the first byte of the segment.
+// With the flat table layout the names are prefixed with the name of the table, "<table>.data" and "<table>.data.<start>".
+// Offsets in the offsets file are global so they don't change when a new segment is started.
+// A record is never split between segments, a new segment is started at the offset of the first record that doesn't fit.
+
+/// Start offsets of the data files of a table. Shared between the writer and the readers of a store.
+#[derive(Clone)]
+pub(crate) struct DataSegments {
+    dir: PathBuf,
+    // Name of the single data file, segment files are named "<data_name>.<start>".
+    data_name: String,
+    segment_size: Option<u64>,
+    starts: Arc<RwLock<Vec<u64>>>,
+}
+
+impl DataSegments {
+    // Lists the data files in the table directory.
+    pub(crate) async fn load(
+        layout: TableLayout,
+        path: &Path,
+        table: &str,
+        segment_size: Option<u64>,
+    ) -> Result<Self> {
+        let dir = &layout.table_dir(path, table);
+        let data_name = layout.file_name(table, "data");
+        let segment_prefix = format!("{}.", data_name);
+        let has_single_file = dir.join(&data_name).is_file();
+
+        let directory = Directory::open(dir)
+            .await
+            .map_err(io_error)
+            .context("open table dir")?;
+        let mut starts = Vec::new();
+        for entry in directory
+            .sync_read_dir()
+            .map_err(io_error)
+            .context("read table dir")?
+        {
+            let entry = entry.context("read table dir entry")?;
+            let name = entry.file_name();
+            let start = name
+                .to_str()
+                .and_then(|name| name.strip_prefix(segment_prefix.as_str()))
+                .and_then(|start| start.parse::<u64>().ok());
+            if let Some(start) = start {
+                starts.push(start);
+            }
+        }
+        directory
+            .close()
+            .await
+            .map_err(io_error)
+            .context("close table dir")?;
+        starts.sort_unstable();
+
+        match segment_size {
+            None => {
+                if !starts.is_empty() {
+                    return Err(anyhow!(
+                        "table at {} has data segments but data_segment_size is not set",
+                        dir.display()
+                    ));
+                }
+                if !has_single_file {
+                    return Err(anyhow!("table at {} has no data file", dir.display()));
+                }
+                starts.push(0);
+            }
+            Some(_) => {
+                if has_single_file {
+                    return Err(anyhow!(
+                        "table at {} was created without data segments but data_segment_size is set",
+                        dir.display()
+                    ));
+                }
+                if starts.first() != Some(&0) {
+                    return Err(anyhow!(
+                        "table at {} is missing the first data segment",
+                        dir.display()
+                    ));
+                }
+            }
+        }
+
+        Ok(Self {
+            dir: dir.to_owned(),
+            data_name,
+            segment_size,
+            starts: Arc::new(RwLock::new(starts)),
+        })
+    }
+
+    pub(crate) fn segment_size(&self) -> Option<u64> {
+        self.segment_size
+    }
+
+    pub(crate) fn starts(&self) -> Vec<u64> {
+        self.starts.read().unwrap().clone()
+    }
+
+    pub(crate) fn file_name(&self, start: u64) -> String {
+        match self.segment_size {
+            Some(_) => format!("{}.{}", self.data_name, start),
+            None => self.data_name.clone(),
+        }
+    }
+
+    pub(crate) fn path(&self, start: u64) -> PathBuf {
+        self.dir.join(self.file_name(start))
+    }
+
+    // Returns the index and the start offset of the segment that contains the given offset.
+    pub(crate) fn locate(&self, offset: u64) -> (usize, u64) {
+        let starts = self.starts.read().unwrap();
+        let idx = starts.partition_point(|&start| start <= offset) - 1;
+        (idx, starts[idx])
+    }
+
+    // Returns true if a value of the given size that would be written at the given offset should go into a new segment.
+    // Empty values never start a new segment since they don't take any space.
+    pub(crate) fn should_roll(&self, offset: u64, size: u64) -> bool {
+        let segment_size = match self.segment_size {
+            Some(segment_size) => segment_size,
+            None => return false,
+        };
+        let (_, start) = self.locate(offset);
+        size > 0 && offset > start && offset - start + size > segment_size
+    }
+
+    pub(crate) fn push(&self, start: u64) {
+        self.starts.write().unwrap().push(start);
+    }
+
+    // Removes the segments that start after the given offset, the first segment is always kept.
+    // Returns the start offsets of the removed segments.
+    pub(crate) fn truncate(&self, offset: u64) -> Vec<u64> {
+        let mut starts = self.starts.write().unwrap();
+        let len = std::cmp::max(1, starts.partition_point(|&start| start <= offset));
+        starts.split_off(len)
+    }
+}
+
+// Data files of a table that are opened for reading on demand.
+pub(crate) struct TableFiles {
+    segments: DataSegments,
+    files: RefCell<Vec<Option<File>>>,
+    direct_io: bool,
+}
+
+impl TableFiles {
+    pub(crate) fn new(segments: DataSegments, direct_io: bool) -> Self {
+        Self {
+            segments,
+            files: RefCell::new(Vec::new()),
+            direct_io,
+        }
+    }
+
+    pub(crate) fn segments(&self) -> &DataSegments {
+        &self.segments
+    }
+
+    pub(crate) fn direct_io(&self) -> bool {
+        self.direct_io
+    }
+
+    // Returns the alignment of one of the files that are already open, they are all on the same device.
+    pub(crate) fn alignment(&self) -> Option<u64> {
+        self.files
+            .borrow()
+            .iter()
+            .flatten()
+            .next()
+            .map(|file| file.alignment())
+    }
+
+    // Closes the files that were opened.
+    pub(crate) async fn close(self) -> Result<()> {
+        for file in self.files.into_inner().into_iter().flatten() {
+            file.close().await.context("close data file")?;
+        }
+        Ok(())
+    }
+
+    // Returns the file that contains the given offset and the position of the offset inside that file.
+    pub(crate) async fn file_at(&self, offset: u64) -> Result<(File, u64)> {
+        let (idx, start) = self.segments.locate(offset);
+        Ok((self.file(idx).await?, offset - start))
+    }
+
+    pub(crate) async fn file(&self, idx: usize) -> Result<File> {
+        if let Some(Some(file)) = self.files.borrow().get(idx) {
+            return Ok(file.clone());
+        }
+
+        let start = self.segments.starts()[idx];
+        let file = File::open_read(&self.segments.path(start), self.direct_io)
+            .await
+            .context("open data file")?;
+
+        let mut files = self.files.borrow_mut();
+        if files.len() <= idx {
+            files.resize(idx + 1, None);
+        }
+        // Another task might have opened the same file while this one was waiting.
+        Ok(files[idx].get_or_insert(file).clone())
+    }
+}
@@ -0,0 +1,39 @@
+/// Error returned by the public api.
+///
+/// Errors that have a dedicated variant are returned as that variant and don't carry the context messages
+/// that were added on the way up. All other errors are returned as `Other` with their full context.
+#[derive(Debug, thiserror::Error)]
+pub enum TimestoreError {
+    #[error("table '{0}' not found")]
+    TableNotFound(String),
+    /// Returned by operations that need the record of the key to exist, e.g. `Writer::fill`.
+    /// Reads return None for keys that aren't in the store instead.
+    #[error("key {0} is not in the store")]
+    KeyNotFound(u64),
+    #[error("ordering error found at index {index}. {prev} > {val}")]
+    OrderingViolation { index: usize, prev: u64, val: u64 },
+    #[error(transparent)]
+    Io(std::io::Error),
+    #[error(transparent)]
+    Other(anyhow::Error),
+}
+
+impl From<anyhow::Error> for TimestoreError {
+    fn from(err: anyhow::Error) -> Self {
+        let err = match err.downcast::<TimestoreError>() {
+            Ok(err) => return err,
+            Err(err) => err,
+        };
+
+        match err.downcast::<std::io::Error>() {
+            Ok(err) => Self::Io(err),
+            Err(err) => Self::Other(err),
+        }
+    }
+}
+
+impl From<std::io::Error> for TimestoreError {
+    fn from(err: std::io::Error) -> Self {
+        Self::Io(err)
+    }
+}
@@ -0,0 +1,86 @@
+use std::{fs::File, path::Path, sync::Arc};
+
+use anyhow::{Context, Result};
+use arrow::{
+    array::{BinaryBuilder, UInt64Builder},
+    datatypes::{DataType, Field, Schema},
+    record_batch::RecordBatch,
+};
+use parquet::{arrow::ArrowWriter, file::properties::WriterProperties};
+
+use crate::{IterParamsBuilder, Reader, TimestoreError};
+
+// Number of rows that are written to the parquet file as one record batch and one row group.
+const BATCH_ROWS: usize = 64 * 1024;
+
+/// Writes the records of `table` in the given key range into a parquet file.
+/// The file has a `key: UInt64` column and a `value: Binary` column.
+///
+/// The records are written in batches while iterating, so the memory used doesn't depend on the size of the range.
+pub async fn export_to_parquet(
+    reader: &Reader,
+    table: &str,
+    from: u64,
+    to: u64,
+    out: &Path,
+) -> Result<(), TimestoreError> {
+    let schema = Arc::new(Schema::new(vec![
+        Field::new("key", DataType::UInt64, false),
+        Field::new("value", DataType::Binary, false),
+    ]));
+
+    let params = IterParamsBuilder::default()
+        .from(from)
+        .to(to)
+        .table(Some(table))
+        .build()
+        .context("build iter params")?;
+
+    let file = File::create(out).context("create parquet file")?;
+    // The writer buffers a row group in memory until it is full, so row groups are limited to one batch.
+    let props = WriterProperties::builder()
+        .set_max_row_group_size(BATCH_ROWS)
+        .build();
+    let mut writer =
+        ArrowWriter::try_new(file, schema.clone(), Some(props)).context("create parquet writer")?;
+
+    let mut keys = UInt64Builder::new();
+    let mut values = BinaryBuilder::new();
+    let mut rows = 0;
+
+    if let Some(mut iter) = reader.iter(params).await.context("create iter")? {
+        while let Some(((_, key), value)) = iter.next().await.context("iterate table")? {
+            keys.append_value(key);
+            values.append_value(&value);
+            rows += 1;
+
+            if rows == BATCH_ROWS {
+                write_batch(&mut writer, &schema, &mut keys, &mut values)?;
+                rows = 0;
+            }
+        }
+    }
+
+    if rows > 0 {
+        write_batch(&mut writer, &schema, &mut keys, &mut values)?;
+    }
+    writer.close().context("close parquet writer")?;
+
+    Ok(())
+}
+
+fn write_batch(
+    writer: &mut ArrowWriter<File>,
+    schema: &Arc<Schema>,
+    keys: &mut UInt64Builder,
+    values: &mut BinaryBuilder,
+) -> Result<()> {
+    let batch = RecordBatch::try_new(
+        schema.clone(),
+        vec![Arc::new(keys.finish()), Arc::new(values.finish())],
+    )
+    .context("build record batch")?;
+    writer.write(&batch).context("write record batch")?;
+
+    Ok(())
+}
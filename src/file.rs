@@ -0,0 +1,395 @@
+use std::{
+    fs::Permissions,
+    os::unix::fs::PermissionsExt,
+    path::Path,
+    pin::Pin,
+    rc::Rc,
+    task::{Context as TaskContext, Poll},
+};
+
+use anyhow::{anyhow, Context, Result};
+use futures::{
+    future::Either, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, Stream, StreamExt,
+};
+use glommio::{
+    io::{
+        BufferedFile, DmaFile, DmaStreamReader, ImmutableFileBuilder, ImmutableFilePreSealSink,
+        IoVec, MergedBufferLimit, OpenOptions, ReadAmplificationLimit, ReadResult,
+        StreamReaderBuilder, StreamWriter, StreamWriterBuilder,
+    },
+    ByteSliceMutExt,
+};
+
+// Files are opened with O_DIRECT through glommio's dma files unless direct io is disabled in the config.
+// Then they are opened as buffered files, which also work on filesystems that don't support O_DIRECT like NFS or overlayfs.
+// Buffered files have no alignment requirements so they report an alignment of 1.
+
+// Converts an error of glommio or std into an anyhow error that keeps the io::Error,
+// so it is returned as TimestoreError::Io instead of being flattened into a message.
+pub(crate) fn io_error(err: impl Into<std::io::Error>) -> anyhow::Error {
+    anyhow::Error::new(err.into())
+}
+
+// Number of reads in flight in File::read_many for buffered files if no concurrency is given.
+const BUFFERED_READ_CONCURRENCY: usize = 32;
+
+// Size of the buffers that direct io sinks write, a sync writes the whole buffer even if it is only partially filled.
+const SINK_BUFFER_SIZE: u64 = 128 << 10;
+
+#[derive(Clone)]
+pub(crate) enum File {
+    Direct(Rc<DmaFile>),
+    Buffered(Rc<BufferedFile>),
+}
+
+impl File {
+    pub(crate) async fn open(path: &Path, opts: &OpenOptions, direct_io: bool) -> Result<Self> {
+        let file = if direct_io {
+            File::Direct(Rc::new(opts.dma_open(path).await.map_err(io_error)?))
+        } else {
+            File::Buffered(Rc::new(opts.buffered_open(path).await.map_err(io_error)?))
+        };
+
+        Ok(file)
+    }
+
+    /// Opens an existing file for reading.
+    pub(crate) async fn open_read(path: &Path, direct_io: bool) -> Result<Self> {
+        Self::open(path, OpenOptions::new().read(true), direct_io).await
+    }
+
+    pub(crate) fn alignment(&self) -> u64 {
+        match self {
+            File::Direct(file) => file.alignment(),
+            File::Buffered(_) => 1,
+        }
+    }
+
+    pub(crate) fn align_up(&self, v: u64) -> u64 {
+        match self {
+            File::Direct(file) => file.align_up(v),
+            File::Buffered(_) => v,
+        }
+    }
+
+    pub(crate) async fn file_size(&self) -> Result<u64> {
+        match self {
+            File::Direct(file) => file.file_size().await,
+            File::Buffered(file) => file.file_size().await,
+        }
+        .map_err(io_error)
+    }
+
+    pub(crate) async fn read_at(&self, pos: u64, len: usize) -> Result<ReadResult> {
+        match self {
+            File::Direct(file) => file.read_at(pos, len).await,
+            File::Buffered(file) => file.read_at(pos, len).await,
+        }
+        .map_err(io_error)
+    }
+
+    /// Writes the data at the given position without syncing it.
+    ///
+    /// Direct io writes have to be a multiple of the block size so the existing data in the first and the last block
+    /// is read and combined with the write so it isn't overwritten. This pads the file to the alignment.
+    /// Returns the number of bytes that are written to the file including the padding.
+    pub(crate) async fn write_at(&self, data: &[u8], pos: u64) -> Result<u64> {
+        if data.is_empty() {
+            return Ok(0);
+        }
+
+        let file = match self {
+            File::Direct(file) => file,
+            File::Buffered(file) => {
+                file.write_at(data.to_vec(), pos)
+                    .await
+                    .map_err(io_error)
+                    .context("failed to write data")?;
+                return Ok(u64::try_from(data.len()).unwrap());
+            }
+        };
+
+        let write_pos = file.align_down(pos);
+        assert!(write_pos <= pos);
+        let end = pos + u64::try_from(data.len()).unwrap();
+        let write_end = file.align_up(end);
+
+        let extra_read_size = usize::try_from(pos - write_pos).unwrap();
+        let bufsize = usize::try_from(write_end - write_pos).unwrap();
+
+        let mut buf = file.alloc_dma_buffer(bufsize);
+        buf.as_bytes_mut().fill(0);
+
+        if extra_read_size > 0 || end < write_end {
+            // Bytes after the end of the file are not returned so this can be shorter than bufsize.
+            let read_buf = file
+                .read_at_aligned(write_pos, bufsize)
+                .await
+                .map_err(io_error)
+                .context("read extra data for alignment")?;
+
+            if read_buf.len() < extra_read_size {
+                return Err(anyhow!("failed to read extra data, size mismatch"));
+            }
+
+            buf.write_at(0, &*read_buf);
+        }
+
+        buf.write_at(extra_read_size, data);
+        file.write_at(buf, write_pos)
+            .await
+            .map_err(io_error)
+            .context("failed to write data")?;
+
+        Ok(write_end - write_pos)
+    }
+
+    /// Reads the given iovecs, results can come back in a different order than the iovecs.
+    /// Reads of direct io files are merged according to the given limits, buffered files are read one iovec at a time.
+    pub(crate) fn read_many<V, S>(
+        &self,
+        iovs: S,
+        concurrency: Option<usize>,
+        buffer_limit: MergedBufferLimit,
+        read_amp_limit: ReadAmplificationLimit,
+    ) -> impl Stream<Item = Result<(V, ReadResult)>>
+    where
+        V: IoVec + Unpin,
+        S: Stream<Item = V> + Unpin,
+    {
+        match self {
+            File::Direct(file) => {
+                let reads = file.read_many(iovs, buffer_limit, read_amp_limit);
+                let reads = match concurrency {
+                    Some(concurrency) => reads.with_concurrency(concurrency),
+                    None => reads,
+                };
+                Either::Left(reads.map(|res| res.map_err(io_error)))
+            }
+            File::Buffered(file) => {
+                let file = file.clone();
+                let reads = iovs
+                    .map(move |iov| {
+                        let file = file.clone();
+                        async move {
+                            let buf = file
+                                .read_at(iov.pos(), iov.size())
+                                .await
+                                .map_err(io_error)?;
+                            Ok((iov, buf))
+                        }
+                    })
+                    .buffer_unordered(concurrency.unwrap_or(BUFFERED_READ_CONCURRENCY));
+                Either::Right(reads)
+            }
+        }
+    }
+
+    pub(crate) async fn fdatasync(&self) -> Result<()> {
+        match self {
+            File::Direct(file) => file.fdatasync().await,
+            File::Buffered(file) => file.fdatasync().await,
+        }
+        .map_err(io_error)
+    }
+
+    pub(crate) async fn truncate(&self, size: u64) -> Result<()> {
+        match self {
+            File::Direct(file) => file.truncate(size).await,
+            File::Buffered(file) => file.truncate(size).await,
+        }
+        .map_err(io_error)
+    }
+
+    pub(crate) async fn pre_allocate(&self, size: u64, keep_size: bool) -> Result<()> {
+        match self {
+            File::Direct(file) => file.pre_allocate(size, keep_size).await,
+            File::Buffered(file) => file.pre_allocate(size, keep_size).await,
+        }
+        .map_err(io_error)
+    }
+
+    /// Closes the file if this is the last reference to it.
+    pub(crate) async fn close(self) -> Result<()> {
+        match self {
+            File::Direct(file) => file.close_rc().await.map(|_| ()),
+            File::Buffered(file) => match Rc::try_unwrap(file) {
+                Ok(file) => file.close().await,
+                Err(_) => Ok(()),
+            },
+        }
+        .map_err(io_error)
+    }
+}
+
+/// Sets the permissions of a file the store created, see `Config::file_mode`.
+pub(crate) fn set_file_mode(path: &Path, mode: Option<u32>) -> Result<()> {
+    if let Some(mode) = mode {
+        std::fs::set_permissions(path, Permissions::from_mode(mode)).context("set file mode")?;
+    }
+
+    Ok(())
+}
+
+/// Sets the permissions of a directory the store created, adding the execute bit wherever the read bit is set
+/// so the files in it can be reached.
+pub(crate) fn set_dir_mode(path: &Path, mode: Option<u32>) -> Result<()> {
+    set_file_mode(path, mode.map(|mode| mode | ((mode & 0o444) >> 2)))
+}
+
+/// Writer that creates a new file and writes it sequentially.
+pub(crate) enum Sink {
+    Direct(ImmutableFilePreSealSink),
+    Buffered(StreamWriter),
+}
+
+impl Sink {
+    pub(crate) async fn create(
+        path: &Path,
+        file_mode: Option<u32>,
+        direct_io: bool,
+    ) -> Result<Self> {
+        let sink = if direct_io {
+            Sink::Direct(
+                ImmutableFileBuilder::new(path)
+                    .with_buffer_size(usize::try_from(SINK_BUFFER_SIZE).unwrap())
+                    .build_sink()
+                    .await
+                    .map_err(io_error)?,
+            )
+        } else {
+            let file = BufferedFile::create(path).await.map_err(io_error)?;
+            Sink::Buffered(StreamWriterBuilder::new(file).build())
+        };
+        set_file_mode(path, file_mode)?;
+
+        Ok(sink)
+    }
+
+    /// Returns the number of bytes that reach the file when the given number of bytes is written and synced.
+    pub(crate) fn padded_size(&self, bytes: u64) -> u64 {
+        match self {
+            Sink::Direct(_) => bytes.div_ceil(SINK_BUFFER_SIZE) * SINK_BUFFER_SIZE,
+            Sink::Buffered(_) => bytes,
+        }
+    }
+
+    /// Writes everything that was written so far to disk.
+    pub(crate) async fn sync(&mut self) -> Result<()> {
+        match self {
+            Sink::Direct(sink) => sink.sync().await.map(|_| ()).map_err(io_error),
+            // The buffered writer only syncs on close, flushing here hands the data to the kernel and close syncs it.
+            Sink::Buffered(sink) => sink.flush().await.map_err(io_error),
+        }
+    }
+}
+
+impl AsyncWrite for Sink {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut TaskContext<'_>,
+        buf: &[u8],
+    ) -> Poll<std::io::Result<usize>> {
+        match self.get_mut() {
+            Sink::Direct(sink) => Pin::new(sink).poll_write(cx, buf),
+            Sink::Buffered(sink) => Pin::new(sink).poll_write(cx, buf),
+        }
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut TaskContext<'_>) -> Poll<std::io::Result<()>> {
+        match self.get_mut() {
+            Sink::Direct(sink) => Pin::new(sink).poll_flush(cx),
+            Sink::Buffered(sink) => Pin::new(sink).poll_flush(cx),
+        }
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut TaskContext<'_>) -> Poll<std::io::Result<()>> {
+        match self.get_mut() {
+            Sink::Direct(sink) => Pin::new(sink).poll_close(cx),
+            Sink::Buffered(sink) => Pin::new(sink).poll_close(cx),
+        }
+    }
+}
+
+/// Sequential reader over an existing file.
+pub(crate) enum StreamReader {
+    Direct(DmaStreamReader),
+    Buffered(glommio::io::StreamReader),
+}
+
+impl StreamReader {
+    /// Read ahead and concurrency only apply to direct io, buffered files are read one buffer at a time.
+    pub(crate) async fn open(
+        path: &Path,
+        buffer_size: usize,
+        read_ahead: usize,
+        concurrency: Option<usize>,
+        direct_io: bool,
+    ) -> Result<Self> {
+        let reader = if direct_io {
+            let mut builder = ImmutableFileBuilder::new(path);
+            if let Some(concurrency) = concurrency {
+                builder = builder
+                    .with_buffer_size(buffer_size)
+                    .with_sequential_concurrency(concurrency);
+            }
+            StreamReader::Direct(
+                builder
+                    .build_existing()
+                    .await
+                    .map_err(io_error)?
+                    .stream_reader()
+                    .with_buffer_size(buffer_size)
+                    .with_read_ahead(read_ahead)
+                    .build(),
+            )
+        } else {
+            let file = BufferedFile::open(path).await.map_err(io_error)?;
+            StreamReader::Buffered(
+                StreamReaderBuilder::new(file)
+                    .with_buffer_size(buffer_size)
+                    .build(),
+            )
+        };
+
+        Ok(reader)
+    }
+
+    /// Skips the given number of bytes.
+    pub(crate) async fn skip(&mut self, bytes: u64) -> Result<()> {
+        match self {
+            StreamReader::Direct(reader) => reader.skip(bytes),
+            // Seeking the buffered reader doesn't drop the data it already buffered so the bytes are read and discarded.
+            StreamReader::Buffered(reader) => {
+                let skipped =
+                    futures::io::copy((&mut *reader).take(bytes), &mut futures::io::sink()).await?;
+                if skipped < bytes {
+                    return Err(anyhow!("unexpected end of file while skipping"));
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    pub(crate) async fn close(self) -> Result<()> {
+        match self {
+            StreamReader::Direct(reader) => reader.close().await,
+            StreamReader::Buffered(reader) => reader.close().await,
+        }
+        .map_err(io_error)
+    }
+}
+
+impl AsyncRead for StreamReader {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut TaskContext<'_>,
+        buf: &mut [u8],
+    ) -> Poll<std::io::Result<usize>> {
+        match self.get_mut() {
+            StreamReader::Direct(reader) => Pin::new(reader).poll_read(cx, buf),
+            StreamReader::Buffered(reader) => Pin::new(reader).poll_read(cx, buf),
+        }
+    }
+}
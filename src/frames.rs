@@ -0,0 +1,117 @@
+use anyhow::{anyhow, Context};
+use futures::{Stream, StreamExt};
+
+use crate::{IterParamsBuilder, Reader, RowIter, TimestoreError, Writer};
+
+// Each record is exported as one frame:
+// key: u64, number of tables: u32, then for each table: length of the value: u64, value bytes.
+// All integers are big endian. Frames don't depend on the layout of the store so they can be imported into
+// any store that has the same number of tables.
+
+impl Reader {
+    /// Returns a stream of frames that encode the records that `iter` would return for the same from and to,
+    /// with the values of all tables. The frames can be appended to another store with `Writer::import_frames`.
+    pub fn export_frames(
+        &self,
+        from: u64,
+        to: u64,
+    ) -> impl Stream<Item = Result<Vec<u8>, TimestoreError>> + '_ {
+        futures::stream::try_unfold(None, move |iter: Option<RowIter>| async move {
+            let mut iter = match iter {
+                Some(iter) => iter,
+                None => {
+                    let params = IterParamsBuilder::default()
+                        .from(from)
+                        .to(to)
+                        .build()
+                        .context("build iter params")?;
+                    let tables = self
+                        .table_names()
+                        .iter()
+                        .map(String::as_str)
+                        .collect::<Vec<_>>();
+                    match self.iter_rows(params, &tables).await? {
+                        Some(iter) => iter,
+                        None => return Ok(None),
+                    }
+                }
+            };
+
+            let ((_, key), values) = match iter.next().await? {
+                Some(row) => row,
+                None => return Ok(None),
+            };
+
+            Ok(Some((encode_frame(key, &values), Some(iter))))
+        })
+    }
+}
+
+impl Writer {
+    /// Appends the records in the frames made by `Reader::export_frames` and returns the number of appended records.
+    /// Each item of the stream has to be a single frame.
+    pub async fn import_frames<S>(&mut self, frames: S) -> Result<u64, TimestoreError>
+    where
+        S: Stream<Item = Result<Vec<u8>, TimestoreError>>,
+    {
+        futures::pin_mut!(frames);
+        let mut count = 0;
+
+        while let Some(frame) = frames.next().await {
+            let frame = frame.with_context(|| format!("read frame {}", count))?;
+            let (key, values) =
+                decode_frame(&frame).with_context(|| format!("decode frame {}", count))?;
+            self.append(key, values)
+                .await
+                .with_context(|| format!("append frame {}", count))?;
+
+            count += 1;
+        }
+
+        Ok(count)
+    }
+}
+
+fn encode_frame(key: u64, values: &[Vec<u8>]) -> Vec<u8> {
+    let size = 12 + values.iter().map(|v| 8 + v.len()).sum::<usize>();
+    let mut frame = Vec::with_capacity(size);
+    frame.extend_from_slice(&key.to_be_bytes());
+    frame.extend_from_slice(&u32::try_from(values.len()).unwrap().to_be_bytes());
+    for value in values.iter() {
+        frame.extend_from_slice(&u64::try_from(value.len()).unwrap().to_be_bytes());
+        frame.extend_from_slice(value);
+    }
+    frame
+}
+
+fn decode_frame(mut frame: &[u8]) -> anyhow::Result<(u64, Vec<Vec<u8>>)> {
+    let key = u64::from_be_bytes(take(&mut frame, 8)?.try_into().unwrap());
+    let num_values = u32::from_be_bytes(take(&mut frame, 4)?.try_into().unwrap());
+
+    // Each value takes at least 8 bytes, so a corrupt count can't make this allocate more than the frame size.
+    let mut values = Vec::with_capacity(std::cmp::min(
+        usize::try_from(num_values).unwrap(),
+        frame.len() / 8,
+    ));
+    for _ in 0..num_values {
+        let len = u64::from_be_bytes(take(&mut frame, 8)?.try_into().unwrap());
+        let len = usize::try_from(len).context("value length overflow")?;
+        values.push(take(&mut frame, len)?.to_vec());
+    }
+
+    if !frame.is_empty() {
+        return Err(anyhow!("frame has {} trailing bytes", frame.len()));
+    }
+
+    Ok((key, values))
+}
+
+// Splits off the first len bytes of the buffer.
+fn take<'a>(buf: &mut &'a [u8], len: usize) -> anyhow::Result<&'a [u8]> {
+    if buf.len() < len {
+        return Err(anyhow!("frame is truncated"));
+    }
+    let (head, tail) = buf.split_at(len);
+    *buf = tail;
+    Ok(head)
+}
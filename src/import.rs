@@ -0,0 +1,107 @@
+use anyhow::{anyhow, Context, Result};
+use futures::{AsyncBufRead, AsyncBufReadExt, StreamExt};
+
+use crate::{TimestoreError, Writer};
+
+// Number of rows that are appended with a single Writer::append_many call.
+const BATCH_ROWS: usize = 1024;
+
+/// Appends one record per line of comma separated input.
+/// `value_cols` has to list one column per table, in the same order as the table names.
+/// Returns the number of imported rows.
+///
+/// Keys have to be increasing and greater than the last key of the store. The rows are appended in batches
+/// that are committed together, if a line can't be imported the rows of the batches before it stay in the store.
+pub async fn import_csv(
+    writer: &mut Writer,
+    reader: impl AsyncBufRead + Unpin,
+    key_col: usize,
+    value_cols: &[usize],
+) -> Result<u64, TimestoreError> {
+    if value_cols.len() != writer.table_names().len() {
+        return Err(anyhow!(
+            "number of value columns ({}) does not equal the number of tables ({})",
+            value_cols.len(),
+            writer.table_names().len()
+        )
+        .into());
+    }
+
+    let mut lines = reader.lines();
+    let mut prev_key = writer.last_key();
+    let mut count = 0;
+    let mut line_num = 0;
+    let mut batch = Vec::with_capacity(BATCH_ROWS);
+    let mut batch_first_line = 0;
+
+    while let Some(line) = lines.next().await {
+        line_num += 1;
+        let line = line.with_context(|| format!("read line {}", line_num))?;
+
+        if line.is_empty() {
+            continue;
+        }
+
+        let cols = line.split(',').collect::<Vec<&str>>();
+
+        let key = cols
+            .get(key_col)
+            .ok_or_else(|| anyhow!("key column {} not found at line {}", key_col, line_num))?;
+        let key = key
+            .trim()
+            .parse::<u64>()
+            .with_context(|| format!("parse key at line {}", line_num))?;
+
+        if let Some(prev_key) = prev_key {
+            if prev_key >= key {
+                return Err(anyhow!(
+                    "key at line {} is not increasing. {} >= {}",
+                    line_num,
+                    prev_key,
+                    key
+                )
+                .into());
+            }
+        }
+        prev_key = Some(key);
+
+        let mut values = Vec::with_capacity(value_cols.len());
+        for &col in value_cols.iter() {
+            let val = cols
+                .get(col)
+                .ok_or_else(|| anyhow!("value column {} not found at line {}", col, line_num))?;
+            values.push(val.as_bytes().to_vec());
+        }
+
+        if batch.is_empty() {
+            batch_first_line = line_num;
+        }
+        batch.push((key, values));
+        if batch.len() == BATCH_ROWS {
+            count += append_batch(writer, &mut batch, batch_first_line, line_num).await?;
+        }
+    }
+    count += append_batch(writer, &mut batch, batch_first_line, line_num).await?;
+
+    Ok(count)
+}
+
+async fn append_batch(
+    writer: &mut Writer,
+    batch: &mut Vec<(u64, Vec<Vec<u8>>)>,
+    first_line: usize,
+    last_line: usize,
+) -> Result<u64> {
+    if batch.is_empty() {
+        return Ok(0);
+    }
+
+    let rows = std::mem::take(batch);
+    let count = u64::try_from(rows.len()).unwrap();
+    writer
+        .append_many(rows)
+        .await
+        .with_context(|| format!("append lines {} to {}", first_line, last_line))?;
+
+    Ok(count)
+}
@@ -0,0 +1,250 @@
+use anyhow::{anyhow, Context, Result};
+use glommio::io::OpenOptions;
+
+use crate::{
+    data_segments::{DataSegments, TableFiles},
+    file::File,
+    open::{check_store_files, load_tables, read_length},
+    reader::{read_at, ReadBuf},
+    Config, Endianness, TimestoreError,
+};
+
+// The lazy reader keeps the keys and offsets on disk instead of loading them into memory like `open` does,
+// so opening a store takes the same amount of memory regardless of its size.
+// Keys are found with a binary search that reads one key from the keys file per step and
+// the offsets of a record are read from the offsets file when it is read.
+
+/// Opens a store for reading without loading its keys and offsets into memory.
+///
+/// Lookups read the keys file so they are slower than the lookups of a `Reader`.
+/// The reader sees the records that were committed when it was opened, `LazyReader::refresh` picks up new ones.
+pub async fn open_lazy(mut cfg: Config) -> Result<LazyReader, TimestoreError> {
+    load_tables(&mut cfg).await?;
+    check_store_files(&cfg)?;
+
+    let length = read_length(
+        cfg.path(),
+        cfg.open_buffer_size(),
+        cfg.open_concurrency(),
+        cfg.direct_io(),
+        cfg.endianness(),
+        cfg.length_source(),
+    )
+    .await?;
+
+    let keys_file = File::open(
+        &cfg.path().join("keys"),
+        OpenOptions::new().read(true),
+        cfg.direct_io(),
+    )
+    .await
+    .context("open keys file")?;
+
+    let mut tables = Vec::with_capacity(cfg.tables().len());
+    for name in cfg.tables().iter() {
+        let path = cfg.table_layout().table_file(cfg.path(), name, "offsets");
+        let offsets_file = File::open(&path, OpenOptions::new().read(true), cfg.direct_io())
+            .await
+            .with_context(|| format!("open offsets file of table '{}'", name))?;
+
+        let segments = DataSegments::load(
+            cfg.table_layout(),
+            cfg.path(),
+            name,
+            cfg.data_segment_size(),
+        )
+        .await
+        .with_context(|| format!("load data files of table '{}'", name))?;
+
+        tables.push(LazyTable {
+            offsets_file,
+            files: TableFiles::new(segments, cfg.direct_io()),
+        });
+    }
+
+    Ok(LazyReader {
+        cfg,
+        length,
+        keys_file,
+        tables,
+    })
+}
+
+struct LazyTable {
+    offsets_file: File,
+    files: TableFiles,
+}
+
+/// Reader that keeps the keys and offsets of the store on disk, see `open_lazy`.
+pub struct LazyReader {
+    cfg: Config,
+    length: u64,
+    keys_file: File,
+    tables: Vec<LazyTable>,
+}
+
+impl LazyReader {
+    pub fn table_names(&self) -> &[String] {
+        self.cfg.tables()
+    }
+
+    /// Returns the number of records the reader sees.
+    pub fn len(&self) -> u64 {
+        self.length
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.length == 0
+    }
+
+    /// Reads the length of the store again so the records that were committed since the last call are visible.
+    pub async fn refresh(&mut self) -> Result<(), TimestoreError> {
+        self.length = read_length(
+            self.cfg.path(),
+            self.cfg.open_buffer_size(),
+            self.cfg.open_concurrency(),
+            self.cfg.direct_io(),
+            self.cfg.endianness(),
+            self.cfg.length_source(),
+        )
+        .await?;
+
+        Ok(())
+    }
+
+    /// Returns the key of the record at the given position, None if the position is out of range.
+    pub async fn key_at_position(&self, position: u64) -> Result<Option<u64>, TimestoreError> {
+        if position >= self.length {
+            return Ok(None);
+        }
+
+        Ok(Some(
+            read_u64s::<1>(&self.keys_file, position, self.cfg.endianness())
+                .await
+                .context("read keys file")?[0],
+        ))
+    }
+
+    /// Returns the position of the record with the given key, None if it is not in the store.
+    pub async fn position(&self, key: u64) -> Result<Option<u64>, TimestoreError> {
+        let (mut lo, mut hi) = (0, self.length);
+        while lo < hi {
+            let mid = lo + (hi - lo) / 2;
+            let mid_key = read_u64s::<1>(&self.keys_file, mid, self.cfg.endianness())
+                .await
+                .context("read keys file")?[0];
+            match mid_key.cmp(&key) {
+                std::cmp::Ordering::Less => lo = mid + 1,
+                std::cmp::Ordering::Greater => hi = mid,
+                std::cmp::Ordering::Equal => return Ok(Some(mid)),
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// Reads the value of the record with the given key from the given table, None if the key is not in the store.
+    pub async fn read(&self, table: &str, key: u64) -> Result<Option<ReadBuf>, TimestoreError> {
+        let table_idx = self.table_index(table)?;
+
+        match self.position(key).await? {
+            Some(position) => Ok(Some(self.read_at(table, table_idx, position).await?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Reads the value of the record at the given position, None if the position is out of range.
+    pub async fn read_at_position(
+        &self,
+        table: &str,
+        position: u64,
+    ) -> Result<Option<ReadBuf>, TimestoreError> {
+        let table_idx = self.table_index(table)?;
+
+        if position >= self.length {
+            return Ok(None);
+        }
+
+        Ok(Some(self.read_at(table, table_idx, position).await?))
+    }
+
+    /// Closes the files of the reader.
+    pub async fn close(self) -> Result<(), TimestoreError> {
+        self.keys_file.close().await.context("close keys file")?;
+        for table in self.tables {
+            table
+                .offsets_file
+                .close()
+                .await
+                .context("close offsets file")?;
+            table.files.close().await?;
+        }
+
+        Ok(())
+    }
+
+    fn table_index(&self, table: &str) -> Result<usize> {
+        self.cfg
+            .tables()
+            .iter()
+            .position(|name| name == table)
+            .ok_or_else(|| anyhow!("table '{}' not found", table))
+    }
+
+    async fn read_at(&self, table: &str, table_idx: usize, position: u64) -> Result<ReadBuf> {
+        let LazyTable {
+            offsets_file,
+            files,
+        } = &self.tables[table_idx];
+
+        let (start, end) = if position == 0 {
+            let [end] = read_u64s::<1>(offsets_file, 0, self.cfg.endianness()).await?;
+            (0, end)
+        } else {
+            let [start, end] =
+                read_u64s::<2>(offsets_file, position - 1, self.cfg.endianness()).await?;
+            (start, end)
+        };
+        if end < start {
+            return Err(anyhow!(
+                "offsets of table '{}' are decreasing at position {}",
+                table,
+                position
+            ));
+        }
+        let len = usize::try_from(end - start).unwrap();
+
+        let buf = if len == 0 {
+            ReadBuf::default()
+        } else {
+            let (file, pos) = files.file_at(start).await?;
+            read_at(&file, pos, len, self.cfg.max_read_bytes()).await?
+        };
+
+        self.cfg.observer().on_read(table, buf.len());
+
+        Ok(buf)
+    }
+}
+
+// Reads N u64 values starting at the given index of the file.
+async fn read_u64s<const N: usize>(
+    file: &File,
+    index: u64,
+    endianness: Endianness,
+) -> Result<[u64; N]> {
+    let buf = file
+        .read_at(index * 8, N * 8)
+        .await
+        .context("read from file")?;
+    if buf.len() < N * 8 {
+        return Err(anyhow!("unexpected end of file at index {}", index));
+    }
+
+    let mut vals = [0; N];
+    for (val, chunk) in vals.iter_mut().zip(buf.chunks_exact(8)) {
+        *val = endianness.decode(chunk);
+    }
+
+    Ok(vals)
+}
@@ -1,9 +1,53 @@
+mod bloom;
+mod buffered_writer;
 mod config;
+mod data_segments;
+mod error;
+#[cfg(feature = "arrow")]
+mod export;
+mod file;
+mod frames;
+mod import;
+mod lazy;
+mod lock;
+mod maintenance;
+mod metadata;
+mod multi_writer;
+mod observer;
 mod open;
+mod read_cache;
 mod reader;
+mod sharded;
+mod tags;
+mod verify;
 mod writer;
+mod writer_guard;
+mod writer_handle;
 
-pub use config::{Config, ConfigBuilder};
-pub use open::{open, ReaderFactory, WriterFactory};
-pub use reader::{Iter, IterParams, IterParamsBuilder, Reader};
-pub use writer::Writer;
+pub use bloom::BloomFilter;
+pub use buffered_writer::BufferedWriter;
+pub use config::{
+    Config, ConfigBuilder, DuplicateKeyPolicy, Endianness, LengthSource, TableLayout,
+};
+pub use error::TimestoreError;
+#[cfg(feature = "arrow")]
+pub use export::export_to_parquet;
+pub use import::import_csv;
+pub use lazy::{open_lazy, LazyReader};
+pub use maintenance::{compact, delete_range, rebuild_offsets, CompactReport};
+pub use metadata::{read_store_info, read_table_names, StoreInfo};
+pub use multi_writer::MultiWriter;
+pub use observer::{Observer, StoreObserver};
+pub use open::{open, reopen_writer, ReaderFactory, WriterFactory};
+pub use reader::{
+    Iter, IterParams, IterParamsBuilder, JoinIter, LengthIter, ReadBuf, ReadConcurrency,
+    ReadOptions, ReadOptionsBuilder, Reader, RowIter,
+};
+pub use sharded::{
+    open_sharded, ShardedIter, ShardedReader, ShardedReaderFactory, ShardedWriter,
+    ShardedWriterFactory,
+};
+pub use verify::{verify, VerifyReport};
+pub use writer::{AppendEstimate, AppendStats, Writer};
+pub use writer_guard::WriterGuard;
+pub use writer_handle::WriterHandle;
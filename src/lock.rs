@@ -0,0 +1,77 @@
+use std::{fs::OpenOptions, os::unix::io::AsRawFd, path::Path};
+
+use anyhow::{anyhow, Context, Result};
+
+use crate::file::set_file_mode;
+
+// Exclusive advisory lock on the `lock` file of a store, taken with flock.
+// Open holds it while it initializes the store and the writer holds it for its lifetime.
+// flock locks belong to the open file so two opens in the same process exclude each other too.
+// The lock is released when this is dropped.
+pub(crate) struct StoreLock {
+    _file: std::fs::File,
+}
+
+impl StoreLock {
+    // Takes the lock without waiting, returns None if it is held by someone else.
+    pub(crate) fn try_lock(dir: &Path, file_mode: Option<u32>) -> Result<Option<Self>> {
+        let path = dir.join("lock");
+        let exists = path.exists();
+        let file = OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .read(true)
+            .write(true)
+            .open(&path)
+            .context("open lock file")?;
+        if !exists {
+            set_file_mode(&path, file_mode)?;
+        }
+
+        Self::flock(file)
+    }
+
+    // Creates the lock file and takes the lock, returns None if the file exists already.
+    pub(crate) fn try_lock_new(dir: &Path, file_mode: Option<u32>) -> Result<Option<Self>> {
+        let path = dir.join("lock");
+        let file = match OpenOptions::new()
+            .create_new(true)
+            .read(true)
+            .write(true)
+            .open(&path)
+        {
+            Ok(file) => file,
+            Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => return Ok(None),
+            Err(e) => return Err(anyhow!(e).context("create lock file")),
+        };
+        set_file_mode(&path, file_mode)?;
+
+        Self::flock(file)
+    }
+
+    // Takes the lock without creating the lock file, returns None if the file doesn't exist or the lock is held.
+    // The file is opened read only so stores on read only filesystems can be opened.
+    pub(crate) fn try_lock_existing(dir: &Path) -> Result<Option<Self>> {
+        let file = match OpenOptions::new().read(true).open(dir.join("lock")) {
+            Ok(file) => file,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(anyhow!(e).context("open lock file")),
+        };
+
+        Self::flock(file)
+    }
+
+    fn flock(file: std::fs::File) -> Result<Option<Self>> {
+        // SAFETY: the file descriptor is valid while file is alive.
+        let res = unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX | libc::LOCK_NB) };
+        if res == 0 {
+            return Ok(Some(Self { _file: file }));
+        }
+
+        let err = std::io::Error::last_os_error();
+        if err.raw_os_error() == Some(libc::EWOULDBLOCK) {
+            return Ok(None);
+        }
+        Err(anyhow!(err).context("lock store"))
+    }
+}
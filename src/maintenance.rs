@@ -0,0 +1,547 @@
+use std::{ops::Range, path::Path};
+
+use anyhow::{anyhow, Context, Result};
+use futures::{AsyncReadExt, AsyncWriteExt};
+
+use crate::{
+    config::{DEFAULT_OPEN_BUFFER_SIZE, DEFAULT_OPEN_CONCURRENCY},
+    data_segments::DataSegments,
+    file::{io_error, Sink, StreamReader},
+    lock::StoreLock,
+    open::{file_size, load_ordered_u64_file, load_u64_file, read_length},
+    writer::commit_length,
+    Config, Endianness, LengthSource, Observer, TableLayout, TimestoreError,
+};
+
+// Maintenance operations that rewrite the files of a store.
+// These have to be run on a closed store, they take the lock of the store and error if a writer holds it.
+// New versions of the files are written next to the old ones and renamed onto them after they are all synced.
+// Each rename is atomic but the renames as a whole are not, so an interruption can leave the store inconsistent.
+// The length file is committed last.
+
+/// Physically deletes the records with keys in [from, to) and compacts the data files.
+/// Returns the number of deleted records.
+/// This is not supported for stores with data segments.
+pub async fn delete_range(cfg: &Config, from: u64, to: u64) -> Result<u64, TimestoreError> {
+    if cfg.data_segment_size().is_some() {
+        return Err(anyhow!("delete_range is not supported for stores with data segments").into());
+    }
+    let _lock = lock_closed_store(cfg)?;
+    Ok(delete_keys(
+        cfg.path(),
+        cfg.tables(),
+        from..to,
+        cfg.table_layout(),
+        cfg.file_mode(),
+        cfg.direct_io(),
+        cfg.endianness(),
+        cfg.length_source(),
+    )
+    .await?)
+}
+
+/// Bytes removed from the data files of each table by `compact`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CompactReport {
+    /// Table names and the number of bytes removed from their data files, in the order of the tables in the config.
+    pub reclaimed: Vec<(String, u64)>,
+}
+
+impl CompactReport {
+    /// Total number of bytes removed from all tables.
+    pub fn total(&self) -> u64 {
+        self.reclaimed.iter().map(|(_, bytes)| bytes).sum()
+    }
+}
+
+/// Removes the bytes after the end of the committed data from the data files.
+/// This doesn't remove any records, it only drops padding and uncommitted data that was left at the end of the files.
+/// Data segments that start after the end of the committed data are removed.
+pub async fn compact(cfg: &Config) -> Result<CompactReport, TimestoreError> {
+    let _lock = lock_closed_store(cfg)?;
+    let dir = cfg.path();
+    let direct_io = cfg.direct_io();
+    let file_mode = cfg.file_mode();
+    let length = read_length(
+        dir,
+        DEFAULT_OPEN_BUFFER_SIZE,
+        DEFAULT_OPEN_CONCURRENCY,
+        direct_io,
+        cfg.endianness(),
+        cfg.length_source(),
+    )
+    .await?;
+    let len = usize::try_from(length).unwrap();
+
+    let layout = cfg.table_layout();
+    let mut report = CompactReport::default();
+    for name in cfg.tables().iter() {
+        let path = layout.table_file(dir, name, "offsets");
+
+        let offsets = load_ordered_u64_file(
+            &path,
+            len,
+            DEFAULT_OPEN_BUFFER_SIZE,
+            DEFAULT_OPEN_CONCURRENCY,
+            direct_io,
+            cfg.endianness(),
+        )
+        .await
+        .with_context(|| format!("failed to load offsets of table '{}'", name))?;
+        let max_offset = offsets.last().copied().unwrap_or(0);
+
+        let path = layout.table_dir(dir, name);
+        let segments = DataSegments::load(layout, dir, name, cfg.data_segment_size())
+            .await
+            .with_context(|| format!("list data files of table '{}'", name))?;
+        let (_, last_start) = segments.locate(max_offset);
+
+        let mut reclaimed = 0;
+        for start in segments.truncate(max_offset) {
+            reclaimed += file_size(&segments.path(start), direct_io).await?;
+            glommio::io::remove(segments.path(start))
+                .await
+                .map_err(io_error)
+                .with_context(|| format!("remove data segment of table '{}'", name))?;
+        }
+
+        let size = file_size(&segments.path(last_start), direct_io).await?;
+        let end = max_offset - last_start;
+        if size < end {
+            return Err(anyhow!(
+                "data file for table '{}' is {} bytes but offsets require at least {}",
+                name,
+                size,
+                end
+            )
+            .into());
+        }
+        if size > end {
+            let file_name = segments.file_name(last_start);
+            copy_ranges(&path, &file_name, &[(0, end)], file_mode, direct_io)
+                .await
+                .with_context(|| format!("write compacted data file of table '{}'", name))?;
+            replace_file(&path, &file_name)
+                .await
+                .with_context(|| format!("replace data file of table '{}'", name))?;
+            reclaimed += size - end;
+        }
+
+        report.reclaimed.push((name.clone(), reclaimed));
+    }
+
+    Ok(report)
+}
+
+/// Replaces the offsets of the table with the ones of records with the given value lengths, keeping the keys.
+/// This is for data files that were rewritten outside of the store, e.g. with every value recompressed.
+/// `new_lengths` has a length for each committed record and the data file has to hold at least the sum of them.
+/// This is not supported for stores with data segments.
+pub async fn rebuild_offsets(
+    cfg: &Config,
+    table: &str,
+    new_lengths: &[u64],
+) -> Result<(), TimestoreError> {
+    if cfg.data_segment_size().is_some() {
+        return Err(
+            anyhow!("rebuild_offsets is not supported for stores with data segments").into(),
+        );
+    }
+    if !cfg.tables().iter().any(|name| name == table) {
+        return Err(TimestoreError::TableNotFound(table.to_owned()));
+    }
+
+    let _lock = lock_closed_store(cfg)?;
+    let dir = cfg.path();
+    let direct_io = cfg.direct_io();
+    let length = read_length(
+        dir,
+        DEFAULT_OPEN_BUFFER_SIZE,
+        DEFAULT_OPEN_CONCURRENCY,
+        direct_io,
+        cfg.endianness(),
+        cfg.length_source(),
+    )
+    .await?;
+    if u64::try_from(new_lengths.len()).unwrap() != length {
+        return Err(anyhow!(
+            "got {} lengths but the store has {} records",
+            new_lengths.len(),
+            length
+        )
+        .into());
+    }
+
+    let mut offsets = Vec::with_capacity(new_lengths.len());
+    let mut offset = 0u64;
+    for &len in new_lengths.iter() {
+        offset = offset
+            .checked_add(len)
+            .ok_or_else(|| anyhow!("offset overflow"))?;
+        offsets.push(offset);
+    }
+
+    let layout = cfg.table_layout();
+    let size = file_size(&layout.table_file(dir, table, "data"), direct_io)
+        .await
+        .with_context(|| format!("read data file size of table '{}'", table))?;
+    if size < offset {
+        return Err(anyhow!(
+            "data file for table '{}' is {} bytes but the lengths require at least {}",
+            table,
+            size,
+            offset
+        )
+        .into());
+    }
+
+    let path = layout.table_dir(dir, table);
+    write_u64_file(
+        &path,
+        &layout.file_name(table, "offsets"),
+        &offsets,
+        cfg.file_mode(),
+        direct_io,
+        cfg.endianness(),
+    )
+    .await
+    .with_context(|| format!("write offsets file of table '{}'", table))?;
+    replace_file(&path, &layout.file_name(table, "offsets"))
+        .await
+        .with_context(|| format!("replace offsets file of table '{}'", table))?;
+
+    Ok(())
+}
+
+// Takes the lock of the store so a maintenance operation can't rewrite the files under a writer.
+fn lock_closed_store(cfg: &Config) -> Result<StoreLock> {
+    StoreLock::try_lock(cfg.path(), cfg.file_mode())?.ok_or_else(|| {
+        anyhow!(
+            "store at {} is locked by a writer, maintenance has to run on a closed store",
+            cfg.path().display()
+        )
+    })
+}
+
+// The caller has to hold the lock of the store, Writer::expire_before holds it through the writer.
+#[allow(clippy::too_many_arguments)]
+pub(crate) async fn delete_keys(
+    dir: &Path,
+    tables: &[String],
+    keys_to_delete: Range<u64>,
+    layout: TableLayout,
+    file_mode: Option<u32>,
+    direct_io: bool,
+    endianness: Endianness,
+    length_source: LengthSource,
+) -> Result<u64> {
+    let length = read_length(
+        dir,
+        DEFAULT_OPEN_BUFFER_SIZE,
+        DEFAULT_OPEN_CONCURRENCY,
+        direct_io,
+        endianness,
+        length_source,
+    )
+    .await?;
+    let len = usize::try_from(length).unwrap();
+
+    let keys = {
+        let mut path = dir.to_owned();
+        path.push("keys");
+        load_ordered_u64_file(
+            &path,
+            len,
+            DEFAULT_OPEN_BUFFER_SIZE,
+            DEFAULT_OPEN_CONCURRENCY,
+            direct_io,
+            endianness,
+        )
+        .await
+        .context("read keys file")?
+    };
+
+    let start = keys.partition_point(|&k| k < keys_to_delete.start);
+    let end = keys.partition_point(|&k| k < keys_to_delete.end);
+    if start >= end {
+        return Ok(0);
+    }
+
+    for name in tables.iter() {
+        let path = layout.table_file(dir, name, "offsets");
+
+        let offsets = load_ordered_u64_file(
+            &path,
+            len,
+            DEFAULT_OPEN_BUFFER_SIZE,
+            DEFAULT_OPEN_CONCURRENCY,
+            direct_io,
+            endianness,
+        )
+        .await
+        .with_context(|| format!("failed to load offsets of table '{}'", name))?;
+
+        let start_offset = if start == 0 { 0 } else { offsets[start - 1] };
+        let end_offset = offsets[end - 1];
+        let max_offset = *offsets.last().unwrap();
+        let removed = end_offset - start_offset;
+
+        let new_offsets = offsets[..start]
+            .iter()
+            .copied()
+            .chain(offsets[end..].iter().map(|&offset| offset - removed))
+            .collect::<Vec<u64>>();
+
+        let path = layout.table_dir(dir, name);
+        copy_ranges(
+            &path,
+            &layout.file_name(name, "data"),
+            &[(0, start_offset), (end_offset, max_offset)],
+            file_mode,
+            direct_io,
+        )
+        .await
+        .with_context(|| format!("write compacted data file of table '{}'", name))?;
+        write_u64_file(
+            &path,
+            &layout.file_name(name, "offsets"),
+            &new_offsets,
+            file_mode,
+            direct_io,
+            endianness,
+        )
+        .await
+        .with_context(|| format!("write offsets file of table '{}'", name))?;
+    }
+
+    let new_keys = keys[..start]
+        .iter()
+        .chain(keys[end..].iter())
+        .copied()
+        .collect::<Vec<u64>>();
+    write_u64_file(dir, "keys", &new_keys, file_mode, direct_io, endianness)
+        .await
+        .context("write keys file")?;
+
+    let tags_path = dir.join("tags");
+    let has_tags = tags_path.is_file();
+    if has_tags {
+        let tags = load_u64_file(
+            &tags_path,
+            len,
+            DEFAULT_OPEN_BUFFER_SIZE,
+            DEFAULT_OPEN_CONCURRENCY,
+            direct_io,
+            endianness,
+        )
+        .await
+        .context("read tags file")?;
+        let new_tags = tags[..start]
+            .iter()
+            .chain(tags[end..].iter())
+            .copied()
+            .collect::<Vec<u64>>();
+        write_u64_file(dir, "tags", &new_tags, file_mode, direct_io, endianness)
+            .await
+            .context("write tags file")?;
+    }
+
+    for name in tables.iter() {
+        let path = layout.table_dir(dir, name);
+        replace_file(&path, &layout.file_name(name, "data"))
+            .await
+            .with_context(|| format!("replace data file of table '{}'", name))?;
+        replace_file(&path, &layout.file_name(name, "offsets"))
+            .await
+            .with_context(|| format!("replace offsets file of table '{}'", name))?;
+    }
+    replace_file(dir, "keys")
+        .await
+        .context("replace keys file")?;
+    if has_tags {
+        replace_file(dir, "tags")
+            .await
+            .context("replace tags file")?;
+    }
+
+    // The replaced keys file already has the new length if the length comes from its size.
+    let new_length = u64::try_from(new_keys.len()).unwrap();
+    if length_source == LengthSource::LengthFile {
+        commit_length(
+            dir,
+            new_length,
+            &Observer::default(),
+            file_mode,
+            direct_io,
+            endianness,
+            true,
+        )
+        .await
+        .context("commit new length")?;
+    }
+
+    Ok(length - new_length)
+}
+
+// Inserts the value into the data file of the table at the start of the record at pos, which has an empty value,
+// and moves the offsets of that record and all records after it by the size of the value.
+#[allow(clippy::too_many_arguments)]
+pub(crate) async fn insert_value(
+    dir: &Path,
+    table: &str,
+    length: u64,
+    pos: usize,
+    value: &[u8],
+    layout: TableLayout,
+    file_mode: Option<u32>,
+    direct_io: bool,
+    endianness: Endianness,
+) -> Result<()> {
+    let offsets = load_ordered_u64_file(
+        &layout.table_file(dir, table, "offsets"),
+        usize::try_from(length).unwrap(),
+        DEFAULT_OPEN_BUFFER_SIZE,
+        DEFAULT_OPEN_CONCURRENCY,
+        direct_io,
+        endianness,
+    )
+    .await
+    .with_context(|| format!("failed to load offsets of table '{}'", table))?;
+
+    let start_offset = if pos == 0 { 0 } else { offsets[pos - 1] };
+    let max_offset = offsets.last().copied().unwrap_or(0);
+    let added = u64::try_from(value.len()).unwrap();
+
+    let mut new_offsets = offsets;
+    for offset in new_offsets[pos..].iter_mut() {
+        *offset = offset
+            .checked_add(added)
+            .ok_or_else(|| anyhow!("table '{}' data offset overflow", table))?;
+    }
+
+    let path = layout.table_dir(dir, table);
+    let name = layout.file_name(table, "data");
+    let mut reader = StreamReader::open(&path.join(&name), 512 * 1024, 8, None, direct_io)
+        .await
+        .context("open data file")?;
+    let mut sink = new_file_sink(&path, &name, file_mode, direct_io).await?;
+    futures::io::copy((&mut reader).take(start_offset), &mut sink)
+        .await
+        .context("copy data")?;
+    sink.write_all(value).await.context("write value")?;
+    futures::io::copy((&mut reader).take(max_offset - start_offset), &mut sink)
+        .await
+        .context("copy data")?;
+    reader.close().await.context("close data file")?;
+    sink.sync().await.context("sync new file to disk")?;
+    sink.close()
+        .await
+        .map_err(io_error)
+        .context("close new file")?;
+
+    write_u64_file(
+        &path,
+        &layout.file_name(table, "offsets"),
+        &new_offsets,
+        file_mode,
+        direct_io,
+        endianness,
+    )
+    .await
+    .with_context(|| format!("write offsets file of table '{}'", table))?;
+
+    // The offsets are replaced first so an interruption before the data file is replaced leaves offsets
+    // that point past the end of the data file, which is detected when the store is opened.
+    replace_file(&path, &layout.file_name(table, "offsets"))
+        .await
+        .with_context(|| format!("replace offsets file of table '{}'", table))?;
+    replace_file(&path, &name)
+        .await
+        .with_context(|| format!("replace data file of table '{}'", table))?;
+
+    Ok(())
+}
+
+// Writes the given values to "new_{name}" in dir.
+async fn write_u64_file(
+    dir: &Path,
+    name: &str,
+    vals: &[u64],
+    file_mode: Option<u32>,
+    direct_io: bool,
+    endianness: Endianness,
+) -> Result<()> {
+    let buf = vals
+        .iter()
+        .flat_map(|&val| endianness.encode(val))
+        .collect::<Vec<u8>>();
+
+    let mut sink = new_file_sink(dir, name, file_mode, direct_io).await?;
+    sink.write_all(&buf).await.context("write to new file")?;
+    sink.sync().await.context("sync new file to disk")?;
+    sink.close()
+        .await
+        .map_err(io_error)
+        .context("close new file")
+}
+
+// Copies the given [start, end) byte ranges of "{name}" into "new_{name}" in dir.
+async fn copy_ranges(
+    dir: &Path,
+    name: &str,
+    ranges: &[(u64, u64)],
+    file_mode: Option<u32>,
+    direct_io: bool,
+) -> Result<()> {
+    let mut path = dir.to_owned();
+    path.push(name);
+
+    let mut reader = StreamReader::open(&path, 512 * 1024, 8, None, direct_io)
+        .await
+        .context("open file")?;
+
+    let mut sink = new_file_sink(dir, name, file_mode, direct_io).await?;
+
+    let mut pos = 0;
+    for &(start, end) in ranges.iter() {
+        reader.skip(start - pos).await.context("skip data")?;
+        futures::io::copy((&mut reader).take(end - start), &mut sink)
+            .await
+            .context("copy data")?;
+        pos = end;
+    }
+
+    reader.close().await.context("close file")?;
+
+    sink.sync().await.context("sync new file to disk")?;
+    sink.close()
+        .await
+        .map_err(io_error)
+        .context("close new file")
+}
+
+async fn new_file_sink(
+    dir: &Path,
+    name: &str,
+    file_mode: Option<u32>,
+    direct_io: bool,
+) -> Result<Sink> {
+    let mut path = dir.to_owned();
+    path.push(format!("new_{}", name));
+    glommio::io::remove(&path).await.ok();
+    Sink::create(&path, file_mode, direct_io)
+        .await
+        .context("build new file")
+}
+
+// Renames "new_{name}" onto "{name}" in dir.
+async fn replace_file(dir: &Path, name: &str) -> Result<()> {
+    let mut path = dir.to_owned();
+    path.push(format!("new_{}", name));
+    let mut final_path = dir.to_owned();
+    final_path.push(name);
+    glommio::io::rename(&path, &final_path)
+        .await
+        .map_err(io_error)
+        .context("rename file")
+}
@@ -0,0 +1,266 @@
+use std::{
+    path::Path,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{anyhow, Context, Result};
+use futures::AsyncWriteExt;
+
+use crate::{
+    config::{DEFAULT_OPEN_BUFFER_SIZE, DEFAULT_OPEN_CONCURRENCY},
+    file::{io_error, Sink},
+    open::{file_size, read_file},
+    Config, Endianness, LengthSource, TableLayout, TimestoreError,
+};
+
+// The names of the tables are kept in the "tables" file in the order they were given when the store was created,
+// one name per line. Open checks the configured tables against it since the order of the tables matters.
+
+// The settings the store was created with are kept in the "store_info" file, one "name=value" per line,
+// so they can be read back for diagnostics or to reopen with a matching config.
+// It is only written when the store is initialized, open checks the settings that can't change against it.
+
+/// Version of the file format written by this crate.
+pub(crate) const FORMAT_VERSION: u32 = 1;
+
+/// Settings of a store, see `Reader::store_config` and `read_store_info`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StoreInfo {
+    /// Version of the file format of the store.
+    pub format_version: u32,
+    /// See `Config::segment_length`.
+    pub segment_length: u32,
+    /// See `Config::data_segment_size`.
+    pub data_segment_size: Option<u64>,
+    /// See `Config::table_layout`.
+    pub table_layout: TableLayout,
+    /// See `Config::endianness`.
+    pub endianness: Endianness,
+    /// See `Config::length_source`.
+    pub length_source: LengthSource,
+    /// Wall clock time the store was created at in nanoseconds since the unix epoch, see `Writer::append_now`.
+    /// Stores that were created before this was kept get the time they were first opened at by a writer.
+    pub created_at: u64,
+}
+
+impl StoreInfo {
+    // Settings of a new store that is created with the config.
+    pub(crate) fn from_config(cfg: &Config) -> Self {
+        Self {
+            format_version: FORMAT_VERSION,
+            segment_length: cfg.segment_length(),
+            data_segment_size: cfg.data_segment_size(),
+            table_layout: cfg.table_layout(),
+            endianness: cfg.endianness(),
+            length_source: cfg.length_source(),
+            created_at: unix_time_nanos(),
+        }
+    }
+
+    fn to_file_contents(self) -> String {
+        let layout = match self.table_layout {
+            TableLayout::Nested => "nested",
+            TableLayout::Flat => "flat",
+        };
+        let data_segment_size = match self.data_segment_size {
+            Some(size) => size.to_string(),
+            None => "none".to_owned(),
+        };
+        let endianness = match self.endianness {
+            Endianness::Big => "big",
+            Endianness::Little => "little",
+        };
+        let length_source = match self.length_source {
+            LengthSource::LengthFile => "length_file",
+            LengthSource::KeysFileSize => "keys_file_size",
+        };
+
+        format!(
+            "format_version={}\nsegment_length={}\ndata_segment_size={}\ntable_layout={}\nendianness={}\nlength_source={}\ncreated_at={}\n",
+            self.format_version, self.segment_length, data_segment_size, layout, endianness, length_source, self.created_at
+        )
+    }
+
+    fn from_file_contents(contents: &str) -> Result<Self> {
+        let mut values = std::collections::HashMap::new();
+        for line in contents.lines() {
+            let (name, value) = line
+                .split_once('=')
+                .ok_or_else(|| anyhow!("invalid line {:?}", line))?;
+            values.insert(name, value);
+        }
+        let get = |name: &str| {
+            values
+                .get(name)
+                .copied()
+                .ok_or_else(|| anyhow!("missing {}", name))
+        };
+
+        let format_version = get("format_version")?
+            .parse()
+            .context("parse format_version")?;
+        if format_version > FORMAT_VERSION {
+            return Err(anyhow!(
+                "format version {} is newer than the supported version {}",
+                format_version,
+                FORMAT_VERSION
+            ));
+        }
+
+        Ok(Self {
+            format_version,
+            segment_length: get("segment_length")?
+                .parse()
+                .context("parse segment_length")?,
+            data_segment_size: match get("data_segment_size")? {
+                "none" => None,
+                size => Some(size.parse().context("parse data_segment_size")?),
+            },
+            table_layout: match get("table_layout")? {
+                "nested" => TableLayout::Nested,
+                "flat" => TableLayout::Flat,
+                layout => return Err(anyhow!("unknown table layout {:?}", layout)),
+            },
+            // Files that were written before the setting existed are big endian.
+            endianness: match values.get("endianness").copied() {
+                None | Some("big") => Endianness::Big,
+                Some("little") => Endianness::Little,
+                Some(endianness) => return Err(anyhow!("unknown endianness {:?}", endianness)),
+            },
+            // Stores that were written before the setting existed have a length file.
+            length_source: match values.get("length_source").copied() {
+                None | Some("length_file") => LengthSource::LengthFile,
+                Some("keys_file_size") => LengthSource::KeysFileSize,
+                Some(source) => return Err(anyhow!("unknown length source {:?}", source)),
+            },
+            // 0 if the file was written before the creation time was kept, it is replaced when the store is written to.
+            created_at: match values.get("created_at") {
+                Some(created_at) => created_at.parse().context("parse created_at")?,
+                None => 0,
+            },
+        })
+    }
+}
+
+pub(crate) fn unix_time_nanos() -> u64 {
+    let since_epoch = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    u64::try_from(since_epoch.as_nanos()).unwrap_or(u64::MAX)
+}
+
+/// Reads the names of the tables of a store in the order they were created in.
+/// `direct_io` has to be disabled on filesystems that don't support it, see `Config::direct_io`.
+pub async fn read_table_names(path: &Path, direct_io: bool) -> Result<Vec<String>, TimestoreError> {
+    Ok(load_table_names(path, direct_io).await?)
+}
+
+pub(crate) async fn load_table_names(path: &Path, direct_io: bool) -> Result<Vec<String>> {
+    let contents = read_text_file(path, "tables", direct_io).await?;
+
+    Ok(contents.lines().map(str::to_owned).collect())
+}
+
+/// Reads the settings of a store without opening it.
+/// `direct_io` has to be disabled on filesystems that don't support it, see `Config::direct_io`.
+pub async fn read_store_info(path: &Path, direct_io: bool) -> Result<StoreInfo, TimestoreError> {
+    Ok(load_store_info(path, direct_io).await?)
+}
+
+pub(crate) async fn load_store_info(path: &Path, direct_io: bool) -> Result<StoreInfo> {
+    let contents = read_text_file(path, "store_info", direct_io).await?;
+
+    StoreInfo::from_file_contents(&contents).context("parse store_info file")
+}
+
+async fn read_text_file(dir: &Path, name: &str, direct_io: bool) -> Result<String> {
+    let mut path = dir.to_owned();
+    path.push(name);
+
+    let size = file_size(&path, direct_io)
+        .await
+        .with_context(|| format!("read {} file size", name))?;
+    let buf = read_file(
+        &path,
+        usize::try_from(size).unwrap(),
+        DEFAULT_OPEN_BUFFER_SIZE,
+        DEFAULT_OPEN_CONCURRENCY,
+        direct_io,
+    )
+    .await
+    .with_context(|| format!("read {} file", name))?;
+
+    String::from_utf8(buf).with_context(|| format!("{} file is not valid utf8", name))
+}
+
+// Writes the names to a new file and renames it onto the tables file.
+pub(crate) async fn write_table_names(
+    dir: &Path,
+    tables: &[String],
+    file_mode: Option<u32>,
+    direct_io: bool,
+) -> Result<()> {
+    if let Some(name) = tables
+        .iter()
+        .find(|name| name.is_empty() || name.contains('\n'))
+    {
+        return Err(anyhow!("invalid table name {:?}", name));
+    }
+
+    let contents = tables
+        .iter()
+        .map(|name| format!("{}\n", name))
+        .collect::<String>();
+
+    write_text_file(dir, "tables", &contents, file_mode, direct_io).await
+}
+
+pub(crate) async fn write_store_info(
+    dir: &Path,
+    info: StoreInfo,
+    file_mode: Option<u32>,
+    direct_io: bool,
+) -> Result<()> {
+    write_text_file(
+        dir,
+        "store_info",
+        &info.to_file_contents(),
+        file_mode,
+        direct_io,
+    )
+    .await
+}
+
+// Writes the contents to a new file and renames it onto the file with the given name.
+async fn write_text_file(
+    dir: &Path,
+    name: &str,
+    contents: &str,
+    file_mode: Option<u32>,
+    direct_io: bool,
+) -> Result<()> {
+    let mut path = dir.to_owned();
+    path.push(format!("new_{}", name));
+    glommio::io::remove(&path).await.ok();
+    let mut sink = Sink::create(&path, file_mode, direct_io)
+        .await
+        .with_context(|| format!("build new {} file", name))?;
+    sink.write_all(contents.as_bytes())
+        .await
+        .with_context(|| format!("write to new {} file", name))?;
+    sink.sync()
+        .await
+        .with_context(|| format!("sync new {} file to disk", name))?;
+    sink.close()
+        .await
+        .map_err(io_error)
+        .with_context(|| format!("close new {} file", name))?;
+    let mut final_path = dir.to_owned();
+    final_path.push(name);
+    glommio::io::rename(&path, &final_path)
+        .await
+        .map_err(io_error)
+        .with_context(|| format!("rename {} file", name))?;
+
+    Ok(())
+}
@@ -0,0 +1,142 @@
+use anyhow::{anyhow, Context};
+
+use crate::{writer::duplicate_key, DuplicateKeyPolicy, TimestoreError, Writer};
+
+/// Appends records with the same key to several stores so that either all of the stores get the record or none of them do.
+///
+/// An append is done in two phases. First the data, offsets and keys are written to every store without committing them.
+/// Then the length of every store is committed one after the other. The stores have to be written only through
+/// the MultiWriter so their lengths are always equal after an append returns.
+///
+/// If the process crashes during the second phase some of the stores have one more record than the others.
+/// `new` detects this and rolls the record back from the stores that have it, since that append never returned.
+/// A crash during the first phase needs no recovery since uncommitted data is ignored on open.
+pub struct MultiWriter {
+    writers: Vec<Writer>,
+}
+
+impl MultiWriter {
+    /// Creates a MultiWriter over writers of the given stores, rolling back a half committed append if there is one.
+    pub async fn new(mut writers: Vec<Writer>) -> Result<Self, TimestoreError> {
+        // Records that are held for write combining would make the lengths differ.
+        for (i, writer) in writers.iter_mut().enumerate() {
+            writer
+                .flush()
+                .await
+                .with_context(|| format!("flush store {}", i))?;
+        }
+
+        let min_length = writers.iter().map(|w| w.length).min().unwrap_or(0);
+        let max_length = writers.iter().map(|w| w.length).max().unwrap_or(0);
+
+        if max_length - min_length > 1 {
+            return Err(anyhow!(
+                "lengths of the stores differ by more than one record ({} and {}), they were not written by a MultiWriter",
+                min_length,
+                max_length
+            )
+            .into());
+        }
+
+        if max_length > min_length {
+            for (i, writer) in writers.iter_mut().enumerate() {
+                if writer.length > min_length {
+                    writer
+                        .truncate_to(usize::try_from(min_length).unwrap())
+                        .await
+                        .with_context(|| format!("roll back the last record of store {}", i))?;
+                }
+            }
+        }
+
+        let mut last_keys = writers.iter().map(|w| w.keys_reader.last());
+        if let Some(first) = last_keys.next() {
+            if last_keys.any(|key| key != first) {
+                return Err(anyhow!("last keys of the stores are not the same").into());
+            }
+        }
+
+        Ok(Self { writers })
+    }
+
+    pub fn writers(&self) -> &[Writer] {
+        &self.writers
+    }
+
+    pub fn into_writers(self) -> Vec<Writer> {
+        self.writers
+    }
+
+    /// Appends a record to all stores and returns the position it was written at, which is the same in every store.
+    /// `per_store_values` holds the values of the tables of each store in the order the writers were given.
+    ///
+    /// A key that equals the last key is handled by the `DuplicateKeyPolicy` of the stores, which has to be the same for all of them.
+    /// `Skip` returns the position of the last record without writing anything. `Overwrite` is rejected
+    /// since overwriting the last record can't be done atomically across the stores.
+    ///
+    /// If this returns an error, the MultiWriter has to be recreated from reopened stores before writing again.
+    pub async fn append_all(
+        &mut self,
+        key: u64,
+        per_store_values: Vec<Vec<Vec<u8>>>,
+    ) -> Result<u64, TimestoreError> {
+        if per_store_values.len() != self.writers.len() {
+            return Err(anyhow!(
+                "number of value lists ({}) does not equal the number of stores ({})",
+                per_store_values.len(),
+                self.writers.len()
+            )
+            .into());
+        }
+        for (writer, values) in self.writers.iter().zip(per_store_values.iter()) {
+            writer.check_values(values)?;
+        }
+
+        // The last keys of the stores are the same, see new.
+        if let Some(first) = self.writers.first() {
+            if first.keys_reader.last() == Some(key) {
+                let policy = first.on_duplicate_key;
+                if self.writers.iter().any(|w| w.on_duplicate_key != policy) {
+                    return Err(anyhow!(
+                        "key {} is equal to the last key and the stores have different duplicate key policies",
+                        key
+                    )
+                    .into());
+                }
+                match policy {
+                    DuplicateKeyPolicy::Allow => (),
+                    DuplicateKeyPolicy::Error => return Err(duplicate_key(key).into()),
+                    DuplicateKeyPolicy::Skip => return Ok(first.length - 1),
+                    DuplicateKeyPolicy::Overwrite => {
+                        return Err(anyhow!(
+                            "key {} is equal to the last key, overwriting is not supported by MultiWriter",
+                            key
+                        )
+                        .into())
+                    }
+                }
+            }
+        }
+
+        // 1) write the record to every store without committing it
+        let prepared = futures::future::try_join_all(
+            self.writers
+                .iter_mut()
+                .zip(per_store_values)
+                .map(|(writer, values)| writer.prepare_append(vec![(key, values)])),
+        )
+        .await
+        .context("write record to the stores")?;
+
+        // 2) commit the lengths
+        let mut pos = 0;
+        for (i, (writer, prepared)) in self.writers.iter_mut().zip(prepared).enumerate() {
+            pos = writer
+                .commit_prepared(prepared)
+                .await
+                .with_context(|| format!("commit store {}", i))?;
+        }
+
+        Ok(pos)
+    }
+}
@@ -0,0 +1,103 @@
+use std::{cell::Cell, fmt, rc::Rc, sync::Arc};
+
+use crate::AppendStats;
+
+/// Hooks that are called by the store on appends, reads and syncs.
+/// All methods default to doing nothing so implementors only need to override the ones they care about.
+pub trait StoreObserver {
+    fn on_append(&self, _key: u64, _bytes: usize) {}
+    fn on_read(&self, _table: &str, _bytes: usize) {}
+    /// Called instead of `on_read` when `Reader::read` returns a value from the read cache.
+    fn on_cache_hit(&self, _table: &str, _bytes: usize) {}
+    fn on_sync(&self) {}
+    /// Called after the store directory is synced when a commit renames the length file, see `Config::sync_dir`.
+    fn on_dir_sync(&self) {}
+    /// Called for each write to the data, offsets, keys, tags and bloom files with the number of bytes written.
+    fn on_write(&self, _bytes: usize) {}
+    /// Called on open when a data file extends past the last committed offset,
+    /// e.g. because of a crash in the middle of an append.
+    fn on_uncommitted_data(&self, _table: &str, _bytes: u64) {}
+    /// Called on open with `Config::verify_on_open` after each record of a table is verified,
+    /// with the number of records of the table that are verified so far and the total.
+    fn on_verify_progress(&self, _table: &str, _verified: u64, _total: u64) {}
+    /// Called when a WriterGuard is dropped without calling `finish`.
+    fn on_unfinished_writer(&self) {}
+}
+
+struct NoopObserver;
+
+impl StoreObserver for NoopObserver {}
+
+/// Shared handle to a StoreObserver. Defaults to an observer that does nothing.
+#[derive(Clone)]
+pub struct Observer {
+    observer: Arc<dyn StoreObserver>,
+    // Set by Writer::append_with_stats to count the bytes written and the syncs of an append.
+    stats: Option<Rc<Cell<AppendStats>>>,
+}
+
+impl Observer {
+    pub fn new(observer: Arc<dyn StoreObserver>) -> Self {
+        Self {
+            observer,
+            stats: None,
+        }
+    }
+
+    /// Returns an observer that calls the same hooks and also adds the io it sees to the given stats.
+    pub(crate) fn with_stats(&self, stats: Rc<Cell<AppendStats>>) -> Self {
+        Self {
+            observer: self.observer.clone(),
+            stats: Some(stats),
+        }
+    }
+
+    /// Counts the number of bytes that reached a file, including the padding of direct io writes.
+    pub(crate) fn count_write(&self, bytes: u64) {
+        if let Some(stats) = &self.stats {
+            let mut s = stats.get();
+            s.physical_bytes += bytes;
+            stats.set(s);
+        }
+    }
+
+    pub(crate) fn count_sync(&self) {
+        if let Some(stats) = &self.stats {
+            let mut s = stats.get();
+            s.fsyncs += 1;
+            stats.set(s);
+        }
+    }
+}
+
+impl<T: StoreObserver + 'static> From<Arc<T>> for Observer {
+    fn from(observer: Arc<T>) -> Self {
+        Self::new(observer)
+    }
+}
+
+impl Default for Observer {
+    fn default() -> Self {
+        Self::new(Arc::new(NoopObserver))
+    }
+}
+
+impl fmt::Debug for Observer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Observer")
+    }
+}
+
+impl PartialEq for Observer {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.observer, &other.observer)
+    }
+}
+
+impl std::ops::Deref for Observer {
+    type Target = dyn StoreObserver;
+
+    fn deref(&self) -> &Self::Target {
+        &*self.observer
+    }
+}
@@ -2,91 +2,209 @@ use std::{
     fs::create_dir_all,
     path::{Path, PathBuf},
     rc::Rc,
+    sync::Arc,
+    time::{Duration, Instant},
 };
 
 use anyhow::{anyhow, Context, Result};
 use futures::AsyncReadExt;
-use glommio::io::{DmaFile, ImmutableFileBuilder, OpenOptions};
+use glommio::io::OpenOptions;
 
-use crate::{Config, Reader, Writer};
+use crate::{
+    bloom::{self, write_bloom_file, BloomFilter},
+    data_segments::{DataSegments, TableFiles},
+    file::{io_error, set_dir_mode, set_file_mode, File, StreamReader},
+    lock::StoreLock,
+    metadata::{
+        load_store_info, load_table_names, unix_time_nanos, write_store_info, write_table_names,
+        StoreInfo,
+    },
+    read_cache::{CacheGeneration, ReadCache},
+    reader::SharedLength,
+    tags::TagIndex,
+    verify::verify_records,
+    Config, DuplicateKeyPolicy, Endianness, LengthSource, Observer, Reader, TableLayout,
+    TimestoreError, Writer,
+};
 
-// 1) read length file
+// 1) read length file, or the size of the keys file with LengthSource::KeysFileSize
 // 2) open and validate keys file
 // 3) open and validate offset files
 // 4) open and validate data files
 // 5) create writer and reader
+// 6) read back every record if verify_on_open is set
+
+/// Opens the store, creating it first if `Config::create_if_not_exists` is set.
+///
+/// Stores are locked with an advisory lock on their `lock` file so only one open initializes a store at a time,
+/// concurrent opens of a store that isn't initialized yet wait for it. The returned WriterFactory keeps the lock
+/// and passes it to its writer, which holds it for its lifetime. Opening a store that is locked by a writer
+/// works but making a writer from it errors, the store has to be opened again after the other writer is dropped
+/// since the factory doesn't have the records that the other writer appended.
+///
+/// Opening a store that is already initialized doesn't create or write any files, so stores on read only
+/// filesystems can be opened. Missing metadata files of older stores are written by the first writer.
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(level = "debug", skip_all, fields(path = ?cfg.path()), err)
+)]
+pub async fn open(mut cfg: Config) -> Result<(WriterFactory, ReaderFactory), TimestoreError> {
+    if cfg.open_buffer_size() == 0 || cfg.open_concurrency() == 0 {
+        return Err(anyhow!("open_buffer_size and open_concurrency have to be at least 1").into());
+    }
+    if cfg.max_read_bytes() == 0 {
+        return Err(anyhow!("max_read_bytes has to be at least 1").into());
+    }
+    if cfg.segment_length() == 0 {
+        return Err(anyhow!("segment_length has to be at least 1").into());
+    }
+    for (name, &segment_length) in cfg.table_segment_lengths().iter() {
+        if segment_length == 0 {
+            return Err(anyhow!("segment_length of table '{}' has to be at least 1", name).into());
+        }
+    }
+    if !cfg.shard_boundaries().is_empty() {
+        return Err(anyhow!(
+            "config has shard boundaries, sharded stores are opened with open_sharded"
+        )
+        .into());
+    }
+    if cfg.length_source() == LengthSource::KeysFileSize && cfg.direct_io() {
+        return Err(anyhow!(
+            "length_source KeysFileSize needs direct_io to be disabled, direct io pads the keys file"
+        )
+        .into());
+    }
+    if cfg.data_segment_size() == Some(0) {
+        return Err(anyhow!("data_segment_size has to be at least 1").into());
+    }
 
-pub async fn open(cfg: Config) -> Result<(WriterFactory, ReaderFactory)> {
     if cfg.create_if_not_exists() {
-        create_dir_all(cfg.path()).context("create dir if not exists")?;
+        create_dir_if_not_exists(cfg.path(), cfg.file_mode())
+            .context("create dir if not exists")?;
+    }
+    let lock = if cfg.path().is_dir() {
+        lock_store(&cfg).await.context("lock store")?
+    } else {
+        None
+    };
+    // Without a lock file no writer had the store open, see WriterFactory::make.
+    let lock_file_missing = lock.is_none() && !cfg.path().join("lock").exists();
+
+    // Checked before creating anything so a wrong table list doesn't leave new table files behind.
+    let is_subset = load_tables(&mut cfg).await?;
 
+    // Set if the length file is written by this open, see ReaderFactory::created.
+    let mut created = false;
+    // Stores are only initialized while holding the lock, an open without it found the store initialized already.
+    let initialize = cfg.create_if_not_exists() && lock.is_some();
+    if initialize {
         let mut path = cfg.path().to_owned();
         path.push("length");
-        let file = create_if_not_exists(&path)
+        let file = create_if_not_exists(&path, cfg.file_mode(), cfg.direct_io())
             .await
             .context("create length file if not exists")?;
-        let size = file
-            .file_size()
-            .await
-            .map_err(|e| anyhow!("{}", e))
-            .context("read length file size")?;
+        let size = file.file_size().await.context("read length file size")?;
         if size == 0 {
-            let mut buf = file.alloc_dma_buffer(usize::try_from(file.align_up(8)).unwrap());
-            buf.as_bytes_mut().fill(0);
-            file.write_at(buf, 0)
+            file.write_at(&cfg.endianness().encode(0), 0)
                 .await
-                .map_err(|e| anyhow!("{}", e))
                 .context("write zero to length file")?;
+            created = true;
         }
-        file.close()
-            .await
-            .map_err(|e| anyhow!("{}", e))
-            .context("close length file")?;
+        file.close().await.context("close length file")?;
 
         let mut path = cfg.path().to_owned();
         path.push("keys");
-        let file = create_if_not_exists(&path)
+        let file = create_if_not_exists(&path, cfg.file_mode(), cfg.direct_io())
             .await
             .context("create length file if not exists")?;
-        file.close()
-            .await
-            .map_err(|e| anyhow!("{}", e))
-            .context("close keys file")?;
+        file.close().await.context("close keys file")?;
 
+        let layout = cfg.table_layout();
         for name in cfg.tables().iter() {
-            let mut path = cfg.path().to_owned();
-            path.push(name.as_str());
-
-            create_dir_all(&path).context("create table dir if not exists")?;
+            create_dir_if_not_exists(&layout.table_dir(cfg.path(), name), cfg.file_mode())
+                .context("create table dir if not exists")?;
 
-            path.push("offsets");
-            let file = create_if_not_exists(&path)
+            let path = layout.table_file(cfg.path(), name, "offsets");
+            let file = create_if_not_exists(&path, cfg.file_mode(), cfg.direct_io())
                 .await
                 .context("create offsets file if not exists")?;
-            file.close()
-                .await
-                .map_err(|e| anyhow!("{}", e))
-                .context("close offsets file")?;
+            file.close().await.context("close offsets file")?;
 
-            path.pop();
-            path.push("data");
+            // Only create the data file of the configured layout if the table doesn't have one yet,
+            // so opening with the wrong data_segment_size gives an error instead of mixing the layouts.
+            if !layout.table_file(cfg.path(), name, "data").exists()
+                && !layout.table_file(cfg.path(), name, "data.0").exists()
+            {
+                let path = layout.table_file(cfg.path(), name, data_file_name(&cfg));
 
-            let file = create_if_not_exists(&path)
-                .await
-                .context("create data file if not exists")?;
-            file.close()
-                .await
-                .map_err(|e| anyhow!("{}", e))
-                .context("close data file")?;
+                let file = create_if_not_exists(&path, cfg.file_mode(), cfg.direct_io())
+                    .await
+                    .context("create data file if not exists")?;
+                file.close().await.context("close data file")?;
+            }
         }
     }
 
-    let length = {
-        let mut path = cfg.path().to_owned();
-        path.push("length");
-        let buf = read_file(&path, 8).await.context("read length file")?;
-        u64::from_be_bytes(buf.try_into().unwrap())
+    check_store_files(&cfg)?;
+
+    // Records that are appended without the index wouldn't get a tag, so the tags would go stale.
+    if !cfg.tag_index() && cfg.path().join("tags").is_file() {
+        return Err(anyhow!(
+            "store at {} has a tag index, it has to be opened with tag_index enabled",
+            cfg.path().display()
+        )
+        .into());
+    }
+
+    // Stores that were created before the tables file existed get one that is written from the config
+    // by the next open that initializes the store or by the next writer.
+    let write_tables = !cfg.path().join("tables").is_file();
+    if write_tables && initialize {
+        write_table_names(cfg.path(), cfg.tables(), cfg.file_mode(), cfg.direct_io())
+            .await
+            .context("write tables file")?;
+    }
+
+    let stored_info = if cfg.path().join("store_info").is_file() {
+        Some(
+            load_store_info(cfg.path(), cfg.direct_io())
+                .await
+                .context("read store_info file")?,
+        )
+    } else {
+        None
     };
+    if let Some(stored_info) = stored_info {
+        check_store_info(&stored_info, &StoreInfo::from_config(&cfg))?;
+    }
+    // Stores that were created before the file existed or before it had the creation time get it written
+    // by the next open that initializes the store or by the next writer, with the current time as the creation time.
+    let mut store_info = stored_info.unwrap_or_else(|| StoreInfo::from_config(&cfg));
+    let write_info = stored_info.is_none_or(|info| info.created_at == 0);
+    if write_info {
+        store_info.created_at = unix_time_nanos();
+    }
+
+    // A writer that holds the lock can be in the middle of committing through the new_length file.
+    if lock.is_some() {
+        recover_length(&cfg)
+            .await
+            .context("recover new_length file")?;
+    }
+    if cfg.length_source() == LengthSource::KeysFileSize && lock.is_some() {
+        remove_torn_key(&cfg).await.context("remove torn key")?;
+    }
+
+    let length = read_length(
+        cfg.path(),
+        cfg.open_buffer_size(),
+        cfg.open_concurrency(),
+        cfg.direct_io(),
+        cfg.endianness(),
+        cfg.length_source(),
+    )
+    .await?;
     let len = usize::try_from(length).unwrap();
 
     let segment_len = usize::try_from(cfg.segment_length()).unwrap();
@@ -97,27 +215,99 @@ pub async fn open(cfg: Config) -> Result<(WriterFactory, ReaderFactory)> {
         let mut path = cfg.path().to_owned();
         path.push("keys");
 
-        let vals = load_ordered_u64_file(&path, len)
-            .await
-            .context("read keys file")?;
+        let vals = load_ordered_u64_file(
+            &path,
+            len,
+            cfg.open_buffer_size(),
+            cfg.open_concurrency(),
+            cfg.direct_io(),
+            cfg.endianness(),
+        )
+        .await
+        .context("read keys file")?;
 
         keys.0.append(&vals);
 
         keys
     };
 
-    let mut table_offset_writers = Vec::with_capacity(cfg.tables().len());
-    let mut table_offset_readers = Vec::with_capacity(cfg.tables().len());
-    let mut max_offsets = Vec::with_capacity(cfg.tables().len());
-    for name in cfg.tables().iter() {
-        let mut path = cfg.path().to_owned();
-        path.push(name.as_str());
-        path.push("offsets");
+    // The filter is only written by an open that initializes the store, otherwise the writer writes it when it is made.
+    let mut write_bloom = false;
+    let bloom = match cfg.bloom_filter_bits() {
+        Some(num_bits) => {
+            let (filter, rebuilt) = load_bloom_filter(
+                cfg.path(),
+                num_bits,
+                &keys_reader,
+                cfg.open_buffer_size(),
+                cfg.open_concurrency(),
+                cfg.direct_io(),
+            )
+            .await
+            .context("load bloom filter")?;
+            if rebuilt && initialize {
+                write_bloom_file(cfg.path(), &filter, cfg.file_mode(), cfg.direct_io())
+                    .await
+                    .context("write bloom file")?;
+            } else {
+                write_bloom = rebuilt;
+            }
+            Some(Arc::new(filter))
+        }
+        // The bloom file is left as it is, the writer marks it stale if it is made without the filter.
+        None => None,
+    };
+
+    // The tags file is only created by an open that initializes the store, otherwise the writer creates it when it is made.
+    let tags = if cfg.tag_index() {
+        let path = cfg.path().join("tags");
+        if !path.is_file() && initialize {
+            create_tags_file(&path, len, cfg.file_mode(), cfg.direct_io())
+                .await
+                .context("create tags file")?;
+        }
+        Some(
+            load_tag_index(
+                &path,
+                len,
+                cfg.open_buffer_size(),
+                cfg.open_concurrency(),
+                cfg.direct_io(),
+                cfg.endianness(),
+            )
+            .await
+            .context("load tag index")?,
+        )
+    } else {
+        None
+    };
 
-        let vals = load_ordered_u64_file(&path, len)
+    let table_offset_vals = futures::future::try_join_all(cfg.tables().iter().map(|name| {
+        let path = cfg.table_layout().table_file(cfg.path(), name, "offsets");
+
+        let cfg = &cfg;
+        async move {
+            load_ordered_u64_file(
+                &path,
+                len,
+                cfg.open_buffer_size(),
+                cfg.open_concurrency(),
+                cfg.direct_io(),
+                cfg.endianness(),
+            )
             .await
-            .with_context(|| format!("failed to load offstes of table '{}'", name.as_str()))?;
+            .with_context(|| format!("failed to load offstes of table '{}'", name.as_str()))
+        }
+    }))
+    .await?;
 
+    let mut table_segment_lengths = Vec::with_capacity(cfg.tables().len());
+    let mut table_offset_writers = Vec::with_capacity(cfg.tables().len());
+    let mut table_offset_readers = Vec::with_capacity(cfg.tables().len());
+    let mut max_offsets = Vec::with_capacity(cfg.tables().len());
+    for (name, vals) in cfg.tables().iter().zip(table_offset_vals) {
+        let segment_len = usize::try_from(cfg.table_segment_length(name)).unwrap();
+        table_segment_lengths.push(segment_len);
         let mut offsets = caos::new::<u64>(segment_len);
 
         max_offsets.push(vals.last().copied().unwrap_or(0));
@@ -128,174 +318,772 @@ pub async fn open(cfg: Config) -> Result<(WriterFactory, ReaderFactory)> {
         table_offset_readers.push(offsets.1);
     }
 
+    let mut table_segments = Vec::with_capacity(cfg.tables().len());
     for (name, &max_offset) in cfg.tables().iter().zip(max_offsets.iter()) {
-        let mut path = cfg.path().to_owned();
-        path.push(name.as_str());
-        path.push("data");
+        let segments = DataSegments::load(
+            cfg.table_layout(),
+            cfg.path(),
+            name,
+            cfg.data_segment_size(),
+        )
+        .await
+        .with_context(|| format!("load data files of table '{}'", name))?;
 
-        let file = ImmutableFileBuilder::new(&path)
-            .build_existing()
-            .await
-            .map_err(|e| anyhow!("{}", e))
-            .context("open data file")?;
-        if file.file_size() < max_offset {
-            return Err(anyhow!(
-                "data file size is smaller than maximum offset found in offsets for table '{}'",
-                name
-            ));
+        // Segments that start after the end of the data are left over from an interrupted truncation.
+        for start in segments.truncate(max_offset) {
+            glommio::io::remove(segments.path(start))
+                .await
+                .map_err(io_error)
+                .context("remove leftover data segment")?;
         }
+
+        let starts = segments.starts();
+        for (i, &start) in starts.iter().enumerate() {
+            let end = starts.get(i + 1).copied().unwrap_or(max_offset);
+            let is_last = i + 1 == starts.len();
+
+            let file = File::open_read(&segments.path(start), cfg.direct_io())
+                .await
+                .context("open data file")?;
+            let size = file.file_size().await.context("get data file size")?;
+            // Writes are padded to the alignment so only bytes past the aligned end are uncommitted.
+            let committed_size = file.align_up(end - start);
+            file.close().await.context("close data file")?;
+
+            if size < end - start {
+                return Err(anyhow!(
+                    "data file for table '{}' is {} bytes but offsets require at least {}",
+                    name,
+                    size,
+                    end - start
+                )
+                .into());
+            }
+
+            if is_last && size > committed_size {
+                #[cfg(feature = "tracing")]
+                tracing::warn!(
+                    table = name.as_str(),
+                    size,
+                    max_offset,
+                    "data file has uncommitted bytes after the last offset"
+                );
+                cfg.observer()
+                    .on_uncommitted_data(name, size - committed_size);
+            }
+        }
+
+        table_segments.push(segments);
     }
 
+    // Written after the config is checked against the files so a rejected config isn't persisted.
+    let write_info = if write_info && initialize {
+        write_store_info(cfg.path(), store_info, cfg.file_mode(), cfg.direct_io())
+            .await
+            .context("write store_info file")?;
+        false
+    } else {
+        write_info
+    };
+
+    let cache_generation = CacheGeneration::default();
+    let shared_length = SharedLength::new(length);
+
     let writer_factory = WriterFactory {
         path: cfg.path().to_owned(),
         keys: keys_writer,
+        keys_reader: keys_reader.clone(),
+        shared_length: shared_length.clone(),
         table_offsets: table_offset_writers,
+        table_offsets_readers: table_offset_readers.clone(),
+        segment_length: segment_len,
+        table_segment_lengths: table_segment_lengths.clone(),
         table_names: cfg.tables().to_vec(),
         write_offsets: max_offsets,
         length,
+        observer: cfg.observer().clone(),
+        bloom: bloom.clone(),
+        write_bloom,
+        tags: tags.clone(),
+        table_segments: table_segments.clone(),
+        is_subset,
+        preallocate_bytes: cfg.preallocate_bytes(),
+        max_value_bytes: cfg.max_value_bytes(),
+        max_read_bytes: cfg.max_read_bytes(),
+        open_buffer_size: cfg.open_buffer_size(),
+        open_concurrency: cfg.open_concurrency(),
+        write_combine_bytes: cfg.write_combine_bytes(),
+        on_duplicate_key: cfg.on_duplicate_key(),
+        direct_io: cfg.direct_io(),
+        file_mode: cfg.file_mode(),
+        table_layout: cfg.table_layout(),
+        endianness: cfg.endianness(),
+        sync_dir: cfg.sync_dir(),
+        length_source: cfg.length_source(),
+        cache_generation: cache_generation.clone(),
+        // A factory for a subset of the tables can't make a writer so it doesn't need the lock.
+        lock: lock.filter(|_| !is_subset),
+        lock_file_missing,
+        created,
+        store_info,
+        write_store_info: write_info,
+        write_tables: write_tables && !initialize,
     };
 
     let reader_factory = ReaderFactory {
-        path: cfg.path().to_owned(),
+        path: cfg.path().into(),
         keys: keys_reader,
+        length: shared_length,
         table_offsets: table_offset_readers,
+        segment_length: segment_len,
+        table_segment_lengths,
         table_names: cfg.tables().to_vec(),
+        observer: cfg.observer().clone(),
+        bloom,
+        tags,
+        table_segments,
+        direct_io: cfg.direct_io(),
+        validate_reads: cfg.validate_reads(),
+        read_cache_bytes: cfg.read_cache_bytes(),
+        max_read_bytes: cfg.max_read_bytes(),
+        cache_generation,
+        store_info,
+        created,
     };
 
+    if cfg.verify_on_open() {
+        let reader = reader_factory.make().await?;
+        verify_records(&reader, length, cfg.open_concurrency())
+            .await
+            .context("verify records")?;
+        reader.close().await?;
+    }
+
     Ok((writer_factory, reader_factory))
 }
 
-async fn create_if_not_exists(path: &Path) -> Result<DmaFile> {
+// How long open waits for another open to initialize the store.
+const INIT_WAIT_TIMEOUT: Duration = Duration::from_secs(30);
+// Longest sleep between the attempts to take the lock while waiting.
+const MAX_INIT_WAIT_INTERVAL: Duration = Duration::from_millis(100);
+
+// Takes the lock of the store, waiting while another open holds it and the store isn't initialized.
+// Returns None without the lock if the store is initialized, the holder can be a writer that won't release it.
+// Errors if the store isn't initialized within INIT_WAIT_TIMEOUT, e.g. if the holder is stuck.
+async fn lock_store(cfg: &Config) -> Result<Option<StoreLock>> {
+    // The lock file is only created when the store is initialized so stores on read only filesystems can be opened.
+    if !cfg.create_if_not_exists() || cfg.path().join("store_info").is_file() {
+        return StoreLock::try_lock_existing(cfg.path());
+    }
+    let start = Instant::now();
+    let mut interval = Duration::from_millis(1);
+    loop {
+        if let Some(lock) = StoreLock::try_lock(cfg.path(), cfg.file_mode())? {
+            return Ok(Some(lock));
+        }
+        // The store_info file is written last when a store is initialized.
+        if cfg.path().join("store_info").is_file() {
+            return Ok(None);
+        }
+        if start.elapsed() >= INIT_WAIT_TIMEOUT {
+            return Err(anyhow!(
+                "store at {} is being initialized by another open that didn't finish in {:?}",
+                cfg.path().display(),
+                INIT_WAIT_TIMEOUT
+            ));
+        }
+        glommio::timer::sleep(interval).await;
+        interval = (interval * 2).min(MAX_INIT_WAIT_INTERVAL);
+    }
+}
+
+// Checks the configured tables against the tables file of the store, or takes the tables from it if none are configured.
+// The configured tables can be a subset of the tables of the store in any order, returns true if they are.
+pub(crate) async fn load_tables(cfg: &mut Config) -> Result<bool> {
+    if !cfg.path().join("tables").is_file() {
+        return Ok(false);
+    }
+
+    let tables = load_table_names(cfg.path(), cfg.direct_io()).await?;
+    if cfg.tables().is_empty() {
+        cfg.set_tables(tables);
+        return Ok(false);
+    }
+
+    let is_subset = cfg.tables().len() < tables.len()
+        && cfg
+            .tables()
+            .iter()
+            .enumerate()
+            .all(|(i, name)| tables.contains(name) && !cfg.tables()[..i].contains(name));
+    if cfg.tables() != tables.as_slice() && !is_subset {
+        return Err(anyhow!(
+            "configured tables {:?} don't match the tables of the store {:?}",
+            cfg.tables(),
+            tables
+        ));
+    }
+
+    Ok(is_subset)
+}
+
+// Checks that the store exists and has all the files it needs so we can give a clear error instead of failing on open.
+pub(crate) fn check_store_files(cfg: &Config) -> Result<()> {
+    if !cfg.path().is_dir() {
+        return Err(anyhow!("store at {} does not exist", cfg.path().display()));
+    }
+
+    let layout = cfg.table_layout();
+    let mut paths = vec![cfg.path().join("length"), cfg.path().join("keys")];
+    for name in cfg.tables().iter() {
+        paths.push(layout.table_file(cfg.path(), name, "offsets"));
+        // The layout of the data files is checked when they are loaded, either one of them is enough here.
+        if !layout.table_file(cfg.path(), name, "data").is_file() {
+            paths.push(layout.table_file(cfg.path(), name, data_file_name(cfg)));
+        }
+    }
+
+    for path in paths.iter() {
+        if !path.is_file() {
+            return Err(anyhow!(
+                "store at {} is missing file {}",
+                cfg.path().display(),
+                path.display()
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+// Checks the settings that can't be changed after the store is created against the ones of the config.
+fn check_store_info(stored: &StoreInfo, config: &StoreInfo) -> Result<()> {
+    // Values that are decoded with the wrong byte order would look like a corrupt store.
+    if stored.endianness != config.endianness {
+        return Err(anyhow!(
+            "store was written with {:?} endianness but the config has {:?}",
+            stored.endianness,
+            config.endianness
+        ));
+    }
+    // The length of the store would be read from a file that isn't kept up to date.
+    if stored.length_source != config.length_source {
+        return Err(anyhow!(
+            "store was written with length source {:?} but the config has {:?}",
+            stored.length_source,
+            config.length_source
+        ));
+    }
+    if stored.data_segment_size != config.data_segment_size {
+        return Err(anyhow!(
+            "store was created with data_segment_size {:?} but the config has {:?}",
+            stored.data_segment_size,
+            config.data_segment_size
+        ));
+    }
+    if stored.table_layout != config.table_layout {
+        return Err(anyhow!(
+            "store was created with the {:?} table layout but the config has {:?}",
+            stored.table_layout,
+            config.table_layout
+        ));
+    }
+
+    Ok(())
+}
+
+// Name of the first data file of a table.
+fn data_file_name(cfg: &Config) -> &'static str {
+    match cfg.data_segment_size() {
+        Some(_) => "data.0",
+        None => "data",
+    }
+}
+
+async fn create_if_not_exists(
+    path: &Path,
+    file_mode: Option<u32>,
+    direct_io: bool,
+) -> Result<File> {
+    let exists = path.exists();
     let mut opts = OpenOptions::new();
     opts.create(true).read(true).write(true);
-    opts.dma_open(path)
+    let file = File::open(path, &opts, direct_io)
         .await
-        .map_err(|e| anyhow!("{}", e))
-        .context("open file")
+        .context("open file")?;
+    if !exists {
+        set_file_mode(path, file_mode)?;
+    }
+
+    Ok(file)
+}
+
+pub(crate) fn create_dir_if_not_exists(path: &Path, file_mode: Option<u32>) -> Result<()> {
+    if path.is_dir() {
+        return Ok(());
+    }
+
+    create_dir_all(path).context("create dir")?;
+    set_dir_mode(path, file_mode)
 }
 
 #[derive(Clone)]
 pub struct ReaderFactory {
-    path: PathBuf,
+    path: Rc<Path>,
     keys: caos::Reader<u64>,
+    // Number of records in keys, it grows while the writer of the store appends.
+    length: SharedLength,
     table_offsets: Vec<caos::Reader<u64>>,
+    segment_length: usize,
+    table_segment_lengths: Vec<usize>,
     table_names: Vec<String>,
+    observer: Observer,
+    bloom: Option<Arc<BloomFilter>>,
+    tags: Option<TagIndex>,
+    table_segments: Vec<DataSegments>,
+    direct_io: bool,
+    validate_reads: bool,
+    read_cache_bytes: usize,
+    max_read_bytes: usize,
+    cache_generation: CacheGeneration,
+    store_info: StoreInfo,
+    created: bool,
 }
 
 impl ReaderFactory {
-    pub async fn make(&self) -> Result<Reader> {
-        let mut table_files = Vec::with_capacity(self.table_names.len());
+    /// Returns true if the store was created by the `open` call that made this factory,
+    /// false if it opened a store that already existed.
+    pub fn created(&self) -> bool {
+        self.created
+    }
 
-        for name in self.table_names.iter() {
-            let mut path = self.path.clone();
-            path.push(name.as_str());
-            path.push("data");
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "debug", skip_all, err)
+    )]
+    pub async fn make(&self) -> Result<Reader, TimestoreError> {
+        Ok(self
+            .make_with(
+                self.keys.clone(),
+                self.length.clone(),
+                self.table_offsets.clone(),
+                self.tags.clone(),
+            )
+            .await?)
+    }
+
+    /// Same as `make_as_of`, kept for the callers that use this name.
+    pub async fn make_at(&self, length: u64) -> Result<Reader, TimestoreError> {
+        self.make_as_of(length).await
+    }
+
+    /// Makes a reader that sees exactly the first `length` records, e.g. the length returned by `Writer::sync_point`.
+    /// Records that are appended later aren't visible to it, its iterators end at its last record.
+    ///
+    /// The in memory keys and offsets of the records are copied so this takes time and memory proportional to `length`.
+    pub async fn make_as_of(&self, length: u64) -> Result<Reader, TimestoreError> {
+        let len = usize::try_from(length).unwrap();
+        let current_len = self.length.get();
+        if len > current_len {
+            return Err(anyhow!(
+                "length {} is beyond the {} records of the store",
+                length,
+                current_len
+            )
+            .into());
+        }
+
+        let keys = copy_prefix(&self.keys, len, self.segment_length);
+        let table_offsets = self
+            .table_offsets
+            .iter()
+            .zip(self.table_segment_lengths.iter())
+            .map(|(offsets, &segment_length)| copy_prefix(offsets, len, segment_length))
+            .collect();
+        let tags = self.tags.as_ref().map(|tags| tags.truncated(len));
+
+        Ok(self
+            .make_with(keys, SharedLength::new(length), table_offsets, tags)
+            .await?)
+    }
 
-            let file = DmaFile::open(&path)
+    async fn make_with(
+        &self,
+        keys: caos::Reader<u64>,
+        length: SharedLength,
+        table_offsets: Vec<caos::Reader<u64>>,
+        tags: Option<TagIndex>,
+    ) -> Result<Reader> {
+        let mut table_files = Vec::with_capacity(self.table_names.len());
+
+        for (name, segments) in self.table_names.iter().zip(self.table_segments.iter()) {
+            let files = TableFiles::new(segments.clone(), self.direct_io);
+            // Open the last file up front so a missing file is reported here instead of on the first read.
+            files
+                .file(segments.starts().len() - 1)
                 .await
-                .map_err(|e| anyhow!("{}", e))
-                .context("open data file")?;
+                .with_context(|| format!("open data file of table '{}'", name))?;
 
-            table_files.push(Rc::new(file));
+            table_files.push(Rc::new(files));
         }
 
         Ok(Reader {
-            keys: self.keys.clone(),
-            table_offsets: self.table_offsets.clone(),
+            path: self.path.clone(),
+            keys,
+            length,
+            table_offsets,
             table_names: self.table_names.clone(),
             table_files,
+            observer: self.observer.clone(),
+            bloom: self.bloom.clone(),
+            tags,
+            validate_reads: self.validate_reads,
+            max_read_bytes: self.max_read_bytes,
+            cache: (self.read_cache_bytes > 0)
+                .then(|| ReadCache::new(self.read_cache_bytes, self.cache_generation.clone())),
+            store_info: self.store_info,
         })
     }
 }
 
+// Returns a new caos reader with the first len values of the given one.
+fn copy_prefix(reader: &caos::Reader<u64>, len: usize, segment_length: usize) -> caos::Reader<u64> {
+    let (mut writer, copy) = caos::new::<u64>(segment_length);
+    writer.append(&reader.iter_from(0).take(len).collect::<Vec<u64>>());
+    copy
+}
+
 pub struct WriterFactory {
     path: PathBuf,
     keys: caos::Writer<u64>,
+    keys_reader: caos::Reader<u64>,
+    shared_length: SharedLength,
     table_offsets: Vec<caos::Writer<u64>>,
+    table_offsets_readers: Vec<caos::Reader<u64>>,
+    segment_length: usize,
+    table_segment_lengths: Vec<usize>,
     table_names: Vec<String>,
     write_offsets: Vec<u64>,
     length: u64,
+    observer: Observer,
+    bloom: Option<Arc<BloomFilter>>,
+    // Set if the filter was built by open but not written to the bloom file because open didn't hold the lock.
+    write_bloom: bool,
+    tags: Option<TagIndex>,
+    table_segments: Vec<DataSegments>,
+    // Set if the store was opened with a subset of its tables, writing isn't possible without all of them.
+    is_subset: bool,
+    preallocate_bytes: u64,
+    max_value_bytes: Option<usize>,
+    max_read_bytes: usize,
+    // Used by the writer to reload the store, see Writer::reload.
+    open_buffer_size: usize,
+    open_concurrency: usize,
+    write_combine_bytes: usize,
+    on_duplicate_key: DuplicateKeyPolicy,
+    direct_io: bool,
+    file_mode: Option<u32>,
+    table_layout: TableLayout,
+    endianness: Endianness,
+    sync_dir: bool,
+    length_source: LengthSource,
+    cache_generation: CacheGeneration,
+    lock: Option<StoreLock>,
+    // Set if open didn't get the lock because the store has no lock file.
+    lock_file_missing: bool,
+    created: bool,
+    store_info: StoreInfo,
+    // Set if the store_info file is missing or has no creation time and open didn't write it.
+    write_store_info: bool,
+    // Set if the tables file is missing and open didn't write it.
+    write_tables: bool,
 }
 
 impl WriterFactory {
-    pub async fn make(self) -> Result<Writer> {
-        let mut opts = OpenOptions::new();
-        opts.write(true);
-        opts.read(true);
+    /// Returns true if the store was created by the `open` call that made this factory, see `ReaderFactory::created`.
+    pub fn created(&self) -> bool {
+        self.created
+    }
 
-        let keys_file = {
-            let mut path = self.path.clone();
-            path.push("keys");
+    /// Returns the offset in the data of the given table where the value of the first record appended by the writer will be written.
+    pub fn write_offset(&self, table: &str) -> Result<u64, TimestoreError> {
+        let pos = self
+            .table_names
+            .iter()
+            .position(|n| n == table)
+            .ok_or_else(|| TimestoreError::TableNotFound(table.to_owned()))?;
+        Ok(self.write_offsets[pos])
+    }
 
-            let file = opts
-                .dma_open(&path)
+    /// Makes the writer of the store.
+    /// This consumes the factory since the in memory keys and offsets that the readers follow can only have one writer,
+    /// see `reopen_writer` for making another writer after this one is dropped.
+    ///
+    /// Errors if another writer held the lock of the store when it was opened, see `open`.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "debug", skip_all, err)
+    )]
+    pub async fn make(self) -> Result<Writer, TimestoreError> {
+        if self.is_subset {
+            return Err(anyhow!(
+                "can't make a writer for a store that was opened with a subset of its tables"
+            )
+            .into());
+        }
+
+        // The factory has the state of the store from the time it was opened, so the lock can only be taken here
+        // if no writer could have appended since then. A writer that would have creates the lock file.
+        let lock = match self.lock {
+            Some(lock) => Some(lock),
+            None if self.lock_file_missing => StoreLock::try_lock_new(&self.path, self.file_mode)?,
+            None => None,
+        }
+        .ok_or_else(|| {
+            anyhow!(
+                "store at {} was locked by another writer since it was opened, it has to be opened again to make a writer",
+                self.path.display()
+            )
+        })?;
+
+        if self.write_tables {
+            write_table_names(
+                &self.path,
+                &self.table_names,
+                self.file_mode,
+                self.direct_io,
+            )
+            .await
+            .context("write tables file")?;
+        }
+        if self.write_store_info {
+            write_store_info(&self.path, self.store_info, self.file_mode, self.direct_io)
                 .await
-                .map_err(|e| anyhow!("{}", e))
-                .context("open keys file")?;
+                .context("write store_info file")?;
+        }
 
-            Rc::new(file)
-        };
+        let (keys_file, table_files, table_offsets_files) = open_writer_files(
+            &self.path,
+            &self.table_names,
+            &self.table_segments,
+            self.table_layout,
+            self.direct_io,
+        )
+        .await?;
 
-        let mut table_files = Vec::with_capacity(self.table_names.len());
-        let mut table_offsets_files = Vec::with_capacity(self.table_names.len());
+        let bloom_file = match &self.bloom {
+            Some(bloom) => {
+                if self.write_bloom {
+                    write_bloom_file(&self.path, bloom, self.file_mode, self.direct_io)
+                        .await
+                        .context("write bloom file")?;
+                }
 
-        for name in self.table_names.iter() {
-            let mut path = self.path.clone();
-            path.push(name.as_str());
-            path.push("data");
+                let mut path = self.path.clone();
+                path.push("bloom");
 
-            let file = opts
-                .dma_open(&path)
+                let file = File::open(
+                    &path,
+                    OpenOptions::new().write(true).read(true),
+                    self.direct_io,
+                )
                 .await
-                .map_err(|e| anyhow!("{}", e))
-                .context("open data file")?;
+                .context("open bloom file")?;
 
-            table_files.push(Rc::new(file));
+                Some(file)
+            }
+            None => {
+                // Keys that are appended by this writer aren't added to the filter.
+                if self.path.join("bloom").is_file() {
+                    bloom::mark_stale(&self.path, self.file_mode)
+                        .context("mark bloom file stale")?;
+                }
+                None
+            }
+        };
 
-            let mut path = self.path.clone();
-            path.push(name.as_str());
-            path.push("offsets");
+        let tags_file = match self.tags {
+            Some(_) => {
+                let mut path = self.path.clone();
+                path.push("tags");
+                if !path.is_file() {
+                    create_tags_file(
+                        &path,
+                        usize::try_from(self.length).unwrap(),
+                        self.file_mode,
+                        self.direct_io,
+                    )
+                    .await
+                    .context("create tags file")?;
+                }
 
-            let file = opts
-                .dma_open(&path)
+                let file = File::open(
+                    &path,
+                    OpenOptions::new().write(true).read(true),
+                    self.direct_io,
+                )
                 .await
-                .map_err(|e| anyhow!("{}", e))
-                .context("open data file")?;
+                .context("open tags file")?;
 
-            table_offsets_files.push(Rc::new(file));
-        }
+                Some(file)
+            }
+            None => None,
+        };
 
-        Ok(Writer {
+        let mut writer = Writer {
             path: self.path,
             keys: self.keys,
+            keys_reader: self.keys_reader,
+            shared_length: self.shared_length,
             keys_file,
             table_offsets: self.table_offsets,
+            table_offsets_readers: self.table_offsets_readers,
             table_offsets_files,
+            segment_length: self.segment_length,
+            table_segment_lengths: self.table_segment_lengths,
             table_names: self.table_names,
             table_files,
             write_offsets: self.write_offsets,
             length: self.length,
-        })
+            observer: self.observer,
+            bloom: self.bloom.zip(bloom_file),
+            tags: self.tags.zip(tags_file),
+            reserved: vec![0; self.table_segments.len()],
+            table_segments: self.table_segments,
+            preallocate_bytes: self.preallocate_bytes,
+            max_value_bytes: self.max_value_bytes,
+            max_read_bytes: self.max_read_bytes,
+            open_buffer_size: self.open_buffer_size,
+            open_concurrency: self.open_concurrency,
+            write_combine_bytes: self.write_combine_bytes,
+            combined: Vec::new(),
+            combined_bytes: 0,
+            on_duplicate_key: self.on_duplicate_key,
+            direct_io: self.direct_io,
+            file_mode: self.file_mode,
+            table_layout: self.table_layout,
+            endianness: self.endianness,
+            sync_dir: self.sync_dir,
+            length_source: self.length_source,
+            cache_generation: self.cache_generation,
+            clock: (
+                Instant::now(),
+                unix_time_nanos().saturating_sub(self.store_info.created_at),
+            ),
+            _lock: lock,
+        };
+
+        for table in 0..writer.table_names.len() {
+            let offset = writer.write_offsets[table];
+            let (_, start) = writer.table_segments[table].locate(offset);
+            writer
+                .reserve(table, offset - start)
+                .await
+                .context("preallocate data file")?;
+        }
+
+        Ok(writer)
     }
 }
 
-async fn load_ordered_u64_file(path: &Path, len: usize) -> Result<Vec<u64>> {
-    let buf = read_file(path, len * 8).await.context("read file")?;
+/// Opens the store again and makes a writer for it, so a writer can be closed and reopened without calling `open` and `make` separately.
+///
+/// Readers made from the returned ReaderFactory follow the new writer.
+/// Readers from earlier calls to `open` don't see the records that it appends, they have to be made again.
+/// The previous writer of the store has to be dropped before calling this.
+pub async fn reopen_writer(cfg: Config) -> Result<(Writer, ReaderFactory), TimestoreError> {
+    let (writer_factory, reader_factory) = open(cfg).await?;
+    let writer = writer_factory.make().await?;
 
-    let mut prev = 0;
-    let mut vals = Vec::with_capacity(len);
+    Ok((writer, reader_factory))
+}
 
-    for chunk in buf.chunks_exact(8).take(len) {
-        let val = u64::from_be_bytes(chunk.try_into().unwrap());
+// Opens the keys file and the last data file and the offsets file of each table for writing.
+pub(crate) async fn open_writer_files(
+    dir: &Path,
+    table_names: &[String],
+    table_segments: &[DataSegments],
+    table_layout: TableLayout,
+    direct_io: bool,
+) -> Result<(File, Vec<File>, Vec<File>)> {
+    let mut opts = OpenOptions::new();
+    opts.write(true);
+    opts.read(true);
 
-        if prev > val {
-            return Err(anyhow!("ordering error found. {} > {}", prev, val));
-        }
+    let keys_file = {
+        let mut path = dir.to_owned();
+        path.push("keys");
+
+        File::open(&path, &opts, direct_io)
+            .await
+            .context("open keys file")?
+    };
+
+    let mut table_files = Vec::with_capacity(table_names.len());
+    let mut table_offsets_files = Vec::with_capacity(table_names.len());
+
+    for (name, segments) in table_names.iter().zip(table_segments.iter()) {
+        let path = segments.path(*segments.starts().last().unwrap());
+
+        let file = File::open(&path, &opts, direct_io)
+            .await
+            .context("open data file")?;
 
-        prev = val;
-        vals.push(val);
+        table_files.push(file);
+
+        let path = table_layout.table_file(dir, name, "offsets");
+
+        let file = File::open(&path, &opts, direct_io)
+            .await
+            .context("open offsets file")?;
+
+        table_offsets_files.push(file);
+    }
+
+    Ok((keys_file, table_files, table_offsets_files))
+}
+
+pub(crate) async fn load_ordered_u64_file(
+    path: &Path,
+    len: usize,
+    buffer_size: usize,
+    read_ahead: usize,
+    direct_io: bool,
+    endianness: Endianness,
+) -> Result<Vec<u64>> {
+    let vals = load_u64_file(path, len, buffer_size, read_ahead, direct_io, endianness).await?;
+
+    if let Some(index) = vals.windows(2).position(|w| w[0] > w[1]) {
+        return Err(TimestoreError::OrderingViolation {
+            index: index + 1,
+            prev: vals[index],
+            val: vals[index + 1],
+        }
+        .into());
     }
 
+    Ok(vals)
+}
+
+// Reads the first len u64 values of the file.
+pub(crate) async fn load_u64_file(
+    path: &Path,
+    len: usize,
+    buffer_size: usize,
+    read_ahead: usize,
+    direct_io: bool,
+    endianness: Endianness,
+) -> Result<Vec<u64>> {
+    let buf = read_file(path, len * 8, buffer_size, read_ahead, direct_io)
+        .await
+        .context("read file")?;
+
+    let vals = buf
+        .chunks_exact(8)
+        .take(len)
+        .map(|chunk| endianness.decode(chunk))
+        .collect::<Vec<u64>>();
+
     if vals.len() != len {
         return Err(anyhow!("length is invalid"));
     }
@@ -303,25 +1091,280 @@ async fn load_ordered_u64_file(path: &Path, len: usize) -> Result<Vec<u64>> {
     Ok(vals)
 }
 
-async fn read_file(path: &Path, len: usize) -> Result<Vec<u8>> {
+// Loads the tags of the first len records, all records have the tag 0 if the tags file doesn't exist yet.
+async fn load_tag_index(
+    path: &Path,
+    len: usize,
+    buffer_size: usize,
+    read_ahead: usize,
+    direct_io: bool,
+    endianness: Endianness,
+) -> Result<TagIndex> {
+    if !path.is_file() {
+        return Ok(TagIndex::from_tags(&vec![0; len]));
+    }
+
+    let tags = load_u64_file(path, len, buffer_size, read_ahead, direct_io, endianness)
+        .await
+        .context("read tags file")?;
+
+    Ok(TagIndex::from_tags(&tags))
+}
+
+// Creates the tags file with the tag 0 for the first len records, for enabling the tag index on an existing store.
+async fn create_tags_file(
+    path: &Path,
+    len: usize,
+    file_mode: Option<u32>,
+    direct_io: bool,
+) -> Result<()> {
+    let file = create_if_not_exists(path, file_mode, direct_io)
+        .await
+        .context("create tags file")?;
+    file.write_at(&vec![0; len * 8], 0)
+        .await
+        .context("write tags of existing records")?;
+    file.fdatasync().await.context("sync tags file")?;
+    file.close().await.context("close tags file")
+}
+
+// Loads the bloom filter from disk or builds it from the keys if the file is missing, stale or has a different size.
+// Returns true if the filter was built, it has to be written to the bloom file then.
+async fn load_bloom_filter(
+    dir: &Path,
+    num_bits: u64,
+    keys: &caos::Reader<u64>,
+    buffer_size: usize,
+    read_ahead: usize,
+    direct_io: bool,
+) -> Result<(BloomFilter, bool)> {
+    let mut path = dir.to_owned();
+    path.push("bloom");
+
+    let size = BloomFilter::size_in_bytes(num_bits);
+
+    let existing_size = match std::fs::metadata(&path) {
+        Ok(meta) => Some(meta.len()),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+        Err(e) => return Err(io_error(e).context("read bloom file metadata")),
+    };
+
+    if existing_size == Some(u64::try_from(size).unwrap()) && !bloom::is_stale(dir) {
+        let buf = read_file(&path, size, buffer_size, read_ahead, direct_io)
+            .await
+            .context("read bloom file")?;
+        return Ok((BloomFilter::from_bytes(&buf), false));
+    }
+
+    let filter = BloomFilter::new(num_bits);
+    for key in keys.iter_from(0) {
+        filter.insert(key);
+    }
+
+    Ok((filter, true))
+}
+
+pub(crate) async fn read_length(
+    dir: &Path,
+    buffer_size: usize,
+    read_ahead: usize,
+    direct_io: bool,
+    endianness: Endianness,
+    length_source: LengthSource,
+) -> Result<u64> {
+    if length_source == LengthSource::KeysFileSize {
+        // A partial key at the end of the file is a torn write, it isn't counted.
+        let size = file_size(&dir.join("keys"), direct_io)
+            .await
+            .context("read keys file size")?;
+        return Ok(size / 8);
+    }
+
+    let mut path = dir.to_owned();
+    path.push("length");
+
+    // The file is empty if creating the store was interrupted before the initial zero was written.
+    // It can be longer than 8 bytes since the initial zero is padded to the alignment.
+    let size = file_size(&path, direct_io)
+        .await
+        .context("read length file size")?;
+    if size == 0 {
+        return Ok(0);
+    }
+    if size < 8 {
+        return Err(anyhow!(
+            "length file is {} bytes, it has to be empty or at least 8 bytes",
+            size
+        ));
+    }
+
+    let buf = read_file(&path, 8, buffer_size, read_ahead, direct_io)
+        .await
+        .context("read length file")?;
+    Ok(endianness.decode(&buf))
+}
+
+pub(crate) async fn read_file(
+    path: &Path,
+    len: usize,
+    buffer_size: usize,
+    read_ahead: usize,
+    direct_io: bool,
+) -> Result<Vec<u8>> {
     let mut buf = vec![0; len];
 
-    let mut file = ImmutableFileBuilder::new(&path)
-        .build_existing()
+    let mut file = StreamReader::open(path, buffer_size, read_ahead, None, direct_io)
         .await
-        .map_err(|e| anyhow!("{}", e))
-        .context("open file")?
-        .stream_reader()
-        .with_buffer_size(512 * 1024)
-        .with_read_ahead(8)
-        .build();
+        .context("open file")?;
 
     file.read_exact(&mut buf).await.context("read contents")?;
 
-    file.close()
-        .await
-        .map_err(|e| anyhow!("{}", e))
-        .context("close file")?;
+    file.close().await.context("close file")?;
 
     Ok(buf)
 }
+
+// The length is committed by writing a "new_length" file and renaming it onto the length file.
+// A "new_length" file that is left over means the process stopped before the rename, after the files were written.
+// Recovery rule: if the new length is exactly the committed one + 1 and the keys, offsets and data files hold
+// that many records, the rename is completed (roll forward). Otherwise the file is removed (roll back),
+// which leaves the store at the committed length.
+// An interrupted batched append or truncation is rolled back, the store stays as it was before it.
+async fn recover_length(cfg: &Config) -> Result<()> {
+    let new_path = cfg.path().join("new_length");
+    if !new_path.is_file() {
+        return Ok(());
+    }
+
+    let length = read_length(
+        cfg.path(),
+        cfg.open_buffer_size(),
+        cfg.open_concurrency(),
+        cfg.direct_io(),
+        cfg.endianness(),
+        cfg.length_source(),
+    )
+    .await?;
+    // A short file wasn't synced so it can't have been committed.
+    let new_length = load_u64_file(
+        &new_path,
+        1,
+        cfg.open_buffer_size(),
+        cfg.open_concurrency(),
+        cfg.direct_io(),
+        cfg.endianness(),
+    )
+    .await
+    .ok()
+    .map(|vals| vals[0]);
+
+    let roll_forward = match new_length {
+        Some(new_length) if new_length == length + 1 => {
+            supports_length(cfg, usize::try_from(new_length).unwrap())
+                .await
+                .is_ok()
+        }
+        _ => false,
+    };
+
+    if roll_forward {
+        #[cfg(feature = "tracing")]
+        tracing::warn!(
+            length,
+            new_length,
+            "rolling forward interrupted length commit"
+        );
+        glommio::io::rename(&new_path, cfg.path().join("length"))
+            .await
+            .map_err(io_error)
+            .context("rename new_length file")?;
+    } else {
+        #[cfg(feature = "tracing")]
+        tracing::warn!(length, new_length, "removing leftover new_length file");
+        glommio::io::remove(&new_path)
+            .await
+            .map_err(io_error)
+            .context("remove new_length file")?;
+    }
+
+    Ok(())
+}
+
+// With LengthSource::KeysFileSize the keys file is the commit, so a write that was torn by a crash
+// can leave a partial key at its end. The record of the partial key was never committed so the key is removed,
+// the next append writes its key at the same position.
+async fn remove_torn_key(cfg: &Config) -> Result<()> {
+    let path = cfg.path().join("keys");
+    let file = File::open(
+        &path,
+        OpenOptions::new().read(true).write(true),
+        cfg.direct_io(),
+    )
+    .await
+    .context("open keys file")?;
+    let size = file.file_size().await.context("read keys file size")?;
+    if size % 8 != 0 {
+        #[cfg(feature = "tracing")]
+        tracing::warn!(size, "removing torn key from the end of the keys file");
+        file.truncate(size - size % 8)
+            .await
+            .context("truncate keys file")?;
+        file.fdatasync().await.context("sync keys file")?;
+    }
+    file.close().await.context("close keys file")?;
+
+    Ok(())
+}
+
+// Checks that the files of the store hold at least len records with ordered keys and offsets.
+async fn supports_length(cfg: &Config, len: usize) -> Result<()> {
+    load_ordered_u64_file(
+        &cfg.path().join("keys"),
+        len,
+        cfg.open_buffer_size(),
+        cfg.open_concurrency(),
+        cfg.direct_io(),
+        cfg.endianness(),
+    )
+    .await
+    .context("read keys file")?;
+
+    for name in cfg.tables().iter() {
+        let offsets = load_ordered_u64_file(
+            &cfg.table_layout().table_file(cfg.path(), name, "offsets"),
+            len,
+            cfg.open_buffer_size(),
+            cfg.open_concurrency(),
+            cfg.direct_io(),
+            cfg.endianness(),
+        )
+        .await
+        .with_context(|| format!("read offsets of table '{}'", name))?;
+        let max_offset = offsets.last().copied().unwrap_or(0);
+
+        let segments = DataSegments::load(
+            cfg.table_layout(),
+            cfg.path(),
+            name,
+            cfg.data_segment_size(),
+        )
+        .await
+        .with_context(|| format!("load data files of table '{}'", name))?;
+        let (_, start) = segments.locate(max_offset);
+        let size = file_size(&segments.path(start), cfg.direct_io()).await?;
+        if size < max_offset - start {
+            return Err(anyhow!("data file of table '{}' is too short", name));
+        }
+    }
+
+    Ok(())
+}
+
+pub(crate) async fn file_size(path: &Path, direct_io: bool) -> Result<u64> {
+    let file = File::open_read(path, direct_io)
+        .await
+        .context("open file")?;
+    let size = file.file_size().await.context("read file size")?;
+    file.close().await.context("close file")?;
+    Ok(size)
+}
@@ -0,0 +1,154 @@
+use std::{
+    cell::RefCell,
+    collections::{BTreeMap, HashMap},
+    rc::Rc,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+};
+
+use crate::ReadBuf;
+
+// Readers that are made with Config::read_cache_bytes set keep the values returned by Reader::read in memory,
+// keyed by the index of the table and the key. Clones of a reader share the cache.
+// Each entry also keeps the location of the value it was read from and is only used if the key still has that location,
+// so records that are rewritten with a different size or duplicate keys don't return stale values.
+// Writes that replace values in place bump a generation that is shared with the writer, which clears the caches.
+
+/// Counter that the writer bumps whenever it replaces or removes records, so readers drop their cached values.
+#[derive(Clone, Default)]
+pub(crate) struct CacheGeneration(Arc<AtomicU64>);
+
+impl CacheGeneration {
+    pub(crate) fn bump(&self) {
+        self.0.fetch_add(1, Ordering::SeqCst);
+    }
+
+    fn get(&self) -> u64 {
+        self.0.load(Ordering::SeqCst)
+    }
+}
+
+/// Least recently used cache of read values.
+#[derive(Clone)]
+pub(crate) struct ReadCache {
+    inner: Rc<RefCell<Inner>>,
+    generation: CacheGeneration,
+}
+
+struct Inner {
+    capacity: usize,
+    size: usize,
+    // Generation the entries were read at.
+    generation: u64,
+    // Incremented on each access, the entry with the smallest tick is evicted first.
+    tick: u64,
+    entries: HashMap<(usize, u64), Entry>,
+    lru: BTreeMap<u64, (usize, u64)>,
+}
+
+struct Entry {
+    location: (u64, usize),
+    buf: ReadBuf,
+    tick: u64,
+}
+
+impl ReadCache {
+    pub(crate) fn new(capacity: usize, generation: CacheGeneration) -> Self {
+        Self {
+            inner: Rc::new(RefCell::new(Inner {
+                capacity,
+                size: 0,
+                generation: generation.get(),
+                tick: 0,
+                entries: HashMap::new(),
+                lru: BTreeMap::new(),
+            })),
+            generation,
+        }
+    }
+
+    /// Returns the current generation, it has to be taken before reading a value that is passed to insert.
+    pub(crate) fn generation(&self) -> u64 {
+        self.generation.get()
+    }
+
+    /// Returns the cached value if it was read from the given location.
+    pub(crate) fn get(&self, table: usize, key: u64, location: (u64, usize)) -> Option<ReadBuf> {
+        let mut inner = self.inner.borrow_mut();
+        inner.check_generation(self.generation.get());
+
+        let tick = inner.next_tick();
+        let entry = inner.entries.get_mut(&(table, key))?;
+        if entry.location != location {
+            return None;
+        }
+        let old_tick = std::mem::replace(&mut entry.tick, tick);
+        let buf = entry.buf.clone();
+
+        inner.lru.remove(&old_tick);
+        inner.lru.insert(tick, (table, key));
+
+        Some(buf)
+    }
+
+    /// Adds the value, evicting the least recently used ones until it fits. Values larger than the cache aren't added.
+    /// The value isn't added if the generation changed since it was read, it could have been read while it was replaced.
+    pub(crate) fn insert(
+        &self,
+        generation: u64,
+        table: usize,
+        key: u64,
+        location: (u64, usize),
+        buf: ReadBuf,
+    ) {
+        let mut inner = self.inner.borrow_mut();
+        inner.check_generation(self.generation.get());
+
+        if generation != inner.generation || buf.len() > inner.capacity {
+            return;
+        }
+
+        inner.remove(table, key);
+        while inner.size + buf.len() > inner.capacity {
+            let (_, oldest) = inner.lru.pop_first().unwrap();
+            inner.remove(oldest.0, oldest.1);
+        }
+
+        let tick = inner.next_tick();
+        inner.size += buf.len();
+        inner.lru.insert(tick, (table, key));
+        inner.entries.insert(
+            (table, key),
+            Entry {
+                location,
+                buf,
+                tick,
+            },
+        );
+    }
+}
+
+impl Inner {
+    fn check_generation(&mut self, generation: u64) {
+        if generation != self.generation {
+            self.entries.clear();
+            self.lru.clear();
+            self.size = 0;
+            self.generation = generation;
+        }
+    }
+
+    fn next_tick(&mut self) -> u64 {
+        self.tick += 1;
+        self.tick
+    }
+
+    fn remove(&mut self, table: usize, key: u64) {
+        if let Some(entry) = self.entries.remove(&(table, key)) {
+            self.lru.remove(&entry.tick);
+            self.size -= entry.buf.len();
+        }
+    }
+}
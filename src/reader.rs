@@ -1,18 +1,67 @@
-use std::rc::Rc;
+use std::{
+    cell::RefCell,
+    collections::VecDeque,
+    path::Path,
+    rc::Rc,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+};
 
 use anyhow::{anyhow, Context, Result};
-use futures::{AsyncReadExt, Stream, StreamExt};
-use glommio::io::{
-    DmaFile, DmaStreamReader, ImmutableFileBuilder, IoVec, MergedBufferLimit,
-    ReadAmplificationLimit, ReadResult,
+use futures::{future::Either, AsyncReadExt, Stream, StreamExt};
+use glommio::io::{IoVec, MergedBufferLimit, ReadAmplificationLimit, ReadResult};
+
+use crate::{
+    data_segments::TableFiles,
+    file::{File, StreamReader},
+    metadata::StoreInfo,
+    read_cache::ReadCache,
+    tags::TagIndex,
+    BloomFilter, Observer, TimestoreError,
 };
 
+/// Number of records in the in memory keys of a reader, shared with the writer that appends to them.
+/// Keys can repeat so this can't be derived from the position of the last key.
+/// The writer makes a new one when it replaces its in memory keys, readers that keep the old keys keep the old length.
+#[derive(Clone, Default)]
+pub(crate) struct SharedLength(Arc<AtomicU64>);
+
+impl SharedLength {
+    pub(crate) fn new(length: u64) -> Self {
+        Self(Arc::new(AtomicU64::new(length)))
+    }
+
+    /// Has to be called after the keys and offsets of the new records are in memory.
+    pub(crate) fn set(&self, length: u64) {
+        self.0.store(length, Ordering::SeqCst);
+    }
+
+    pub(crate) fn get(&self) -> usize {
+        usize::try_from(self.0.load(Ordering::SeqCst)).unwrap()
+    }
+}
+
+/// Reader of a store.
+///
+/// Cloning a reader is cheap, the clones share the open data files and the in memory keys and offsets.
+/// Readers made by separate `ReaderFactory::make` calls open their own files.
 #[derive(Clone)]
 pub struct Reader {
+    pub(crate) path: Rc<Path>,
     pub(crate) keys: caos::Reader<u64>,
+    pub(crate) length: SharedLength,
     pub(crate) table_offsets: Vec<caos::Reader<u64>>,
     pub(crate) table_names: Vec<String>,
-    pub(crate) table_files: Vec<Rc<DmaFile>>,
+    pub(crate) table_files: Vec<Rc<TableFiles>>,
+    pub(crate) observer: Observer,
+    pub(crate) bloom: Option<Arc<BloomFilter>>,
+    pub(crate) validate_reads: bool,
+    pub(crate) max_read_bytes: usize,
+    pub(crate) cache: Option<ReadCache>,
+    pub(crate) tags: Option<TagIndex>,
+    pub(crate) store_info: StoreInfo,
 }
 
 impl Reader {
@@ -24,79 +73,292 @@ impl Reader {
         &self.table_names
     }
 
-    pub async fn iter(&self, params: IterParams<'_>) -> Result<Option<Iter>> {
-        let pos = match self.keys.next_position(params.from) {
-            Some(pos) => pos,
-            None => return Ok(None),
-        };
+    /// Returns the directory of the store.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
 
-        let stream_reader = if let Some(table) = params.table {
-            let (file, offsets) = self.get_file_and_offsets(table)?;
+    /// Returns the settings that are kept in the store, they can also be read without opening it with `read_store_info`.
+    /// Settings that can change between opens like `segment_length` are the ones the store was created with.
+    pub fn store_config(&self) -> StoreInfo {
+        self.store_info
+    }
 
-            let path = file.path().context("get path of table file")?.to_owned();
-            let stream_reader = ImmutableFileBuilder::new(&path)
-                .with_buffer_size(params.buffer_size)
-                .with_sequential_concurrency(params.concurrency)
-                .build_existing()
-                .await
-                .map_err(|e| anyhow!("{}", e))
-                .context("open table file")?
-                .stream_reader()
-                .with_buffer_size(params.buffer_size)
-                .with_read_ahead(params.concurrency)
-                .build();
+    /// Returns the end offsets of the records in the data of the given table.
+    /// The record at position i is stored at [offsets[i - 1], offsets[i]) with the first record starting at 0.
+    /// Offsets are global across the data files of the table if it is split into segments.
+    pub fn table_offsets(&self, table: &str) -> Result<caos::Reader<u64>, TimestoreError> {
+        let (_, offsets) = self.get_file_and_offsets(table)?;
+        Ok(offsets)
+    }
+
+    /// Returns the total size of the values of all tables, using the end offset of the last record of each table.
+    /// Padding and preallocated space at the end of the data files isn't counted.
+    pub fn total_data_size(&self) -> Result<u64, TimestoreError> {
+        let len = self.length.get();
+        if len == 0 {
+            return Ok(0);
+        }
+        let pos = len - 1;
+        let (last, _) = self.keys_from(len)?;
 
-            let io_vecs = IoVecIter::from_caos_and_position(offsets, pos);
+        let mut total = 0u64;
+        for offsets in self.table_offsets.iter() {
+            total = total
+                .checked_add(offset_at(offsets, pos, last)?)
+                .ok_or_else(|| anyhow!("total data size overflows u64"))?;
+        }
 
-            Some((stream_reader, io_vecs))
-        } else {
-            None
+        Ok(total)
+    }
+
+    /// Returns the alignment that direct io requires for reads from the data files of the given table.
+    pub fn alignment(&self, table: &str) -> Result<u64, TimestoreError> {
+        let (files, _) = self.get_file_and_offsets(table)?;
+        Ok(files
+            .alignment()
+            .ok_or_else(|| anyhow!("no data file of table '{}' is open", table))?)
+    }
+
+    pub fn bloom_filter(&self) -> Option<&BloomFilter> {
+        self.bloom.as_deref()
+    }
+
+    /// Closes the data files if this is the last clone of the reader.
+    /// Returns false without closing anything if other clones or iterators made from this reader still use the files,
+    /// they are closed when the last one of them is closed or dropped.
+    pub async fn close(self) -> Result<bool, TimestoreError> {
+        let mut closed = true;
+        for (name, files) in self.table_names.iter().zip(self.table_files) {
+            match Rc::try_unwrap(files) {
+                Ok(files) => files
+                    .close()
+                    .await
+                    .with_context(|| format!("close data files of table '{}'", name))?,
+                Err(_) => closed = false,
+            }
+        }
+
+        Ok(closed)
+    }
+
+    pub fn contains_key(&self, key: u64) -> bool {
+        self.may_contain(key) && self.keys.position(key).is_some()
+    }
+
+    /// Returns the number of keys in [from, to) using only the in memory keys.
+    pub fn count_range(&self, from: u64, to: u64) -> usize {
+        if from >= to {
+            return 0;
+        }
+
+        let start = match self.first_position_at_or_after(from) {
+            Some(start) => start,
+            None => return 0,
+        };
+        let end = match self.first_position_at_or_after(to) {
+            Some(end) => end,
+            None => self.length.get(),
         };
 
-        let (current_key, keys) = if pos == 0 {
-            (0, self.keys.iter_from(0))
-        } else {
-            let mut iter = self.keys.iter_from(pos - 1);
-            let current_key = iter.next().unwrap();
+        end - start
+    }
+
+    /// Returns the keys that `iter` would return for the same from and to, using only the in memory keys.
+    /// Like `iter`, this starts with the first key that is greater than `from`
+    /// and stops after the first key that is greater than or equal to `to`.
+    pub fn keys_in_range(&self, from: u64, to: u64) -> Vec<u64> {
+        self.raw_key_iter(from, to).collect()
+    }
+
+    /// Returns a synchronous iterator over the keys that `iter` would return for the same from and to.
+    /// This only walks the in memory keys and bypasses data access entirely, so it is the fastest way to scan keys.
+    pub fn raw_key_iter(&self, from: u64, to: u64) -> std::iter::Take<caos::Iter<u64>> {
+        let start = match self.keys.next_position(from) {
+            Some(start) if to > 0 => start,
+            _ => return self.keys.iter_from(0).take(0),
+        };
+
+        let keys = self.keys.iter_from(start);
+        match self.first_position_at_or_after(to) {
+            Some(end) => keys.take((end + 1).saturating_sub(start)),
+            None => keys.take(usize::MAX),
+        }
+    }
+
+    /// Returns the keys that `iter` would return for the same from and to as a stream, see `raw_key_iter`.
+    /// No data files are touched so this is meant for feeding keys into other async code.
+    pub fn key_stream(&self, from: u64, to: u64) -> impl Stream<Item = u64> {
+        futures::stream::iter(self.raw_key_iter(from, to))
+    }
+
+    // Returns the position of the first key that is greater than or equal to the given key.
+    fn first_position_at_or_after(&self, key: u64) -> Option<usize> {
+        match key.checked_sub(1) {
+            Some(key) => self.keys.next_position(key),
+            None => self.keys.last().map(|_| 0),
+        }
+    }
+
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            level = "debug",
+            skip_all,
+            fields(from = params.from, to = params.to, table = params.table),
+            err,
+        )
+    )]
+    pub async fn iter(&self, params: IterParams<'_>) -> Result<Option<Iter>, TimestoreError> {
+        if let Some(table) = params.table {
+            self.table_index(table)?;
+        }
+        if params.step == 0 {
+            return Err(anyhow!("step has to be at least 1").into());
+        }
+
+        let pos = match self.keys.next_position(params.from) {
+            Some(pos) => pos,
+            None if params.empty_as_iter && self.keys.last().is_none() => 0,
+            None => return Ok(None),
+        };
 
-            (current_key, iter)
+        let stream_reader = match params.table {
+            Some(table) => Some(self.table_stream(table, pos, &params)?),
+            None => None,
         };
 
+        let (current_key, mut keys) = self.keys_from(pos)?;
+
         let table_io_vecs = self
             .table_offsets
             .iter()
             .map(|offsets| IoVecIter::from_caos_and_position(offsets.clone(), pos))
-            .collect();
+            .collect::<Result<Vec<_>>>()?;
 
         let to = std::cmp::min(params.to, self.keys.last().unwrap_or(0));
 
         Ok(Some(Iter {
             started: false,
             current_key,
+            next_key: keys.next(),
+            in_flight: None,
             keys,
             stream_reader,
             table_io_vecs,
             current_table_io_vecs: self.table_names.iter().map(|_| (0, 0)).collect(),
             to,
+            step: params.step,
             table_names: self.table_names.clone(),
             table_files: self.table_files.clone(),
+            table: params.table.map(str::to_owned),
+            observer: self.observer.clone(),
+            max_read_bytes: self.max_read_bytes,
+        }))
+    }
+
+    /// Returns an iterator that reads the values of all the given tables for each key.
+    /// Each table is read with its own stream reader using the buffer settings in params, `params.table` is ignored.
+    pub async fn iter_rows(
+        &self,
+        params: IterParams<'_>,
+        tables: &[&str],
+    ) -> Result<Option<RowIter>, TimestoreError> {
+        for table in tables.iter() {
+            self.table_index(table)?;
+        }
+        if params.step == 0 {
+            return Err(anyhow!("step has to be at least 1").into());
+        }
+
+        let pos = match self.keys.next_position(params.from) {
+            Some(pos) => pos,
+            None if params.empty_as_iter && self.keys.last().is_none() => 0,
+            None => return Ok(None),
+        };
+
+        let streams = tables
+            .iter()
+            .map(|table| self.table_stream(table, pos, &params))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let (current_key, keys) = self.keys_from(pos)?;
+
+        let to = std::cmp::min(params.to, self.keys.last().unwrap_or(0));
+
+        Ok(Some(RowIter {
+            started: false,
+            current_key,
+            in_flight: None,
+            keys,
+            streams,
+            tables: tables.iter().map(|&table| table.to_owned()).collect(),
+            to,
+            step: params.step,
+            observer: self.observer.clone(),
+        }))
+    }
+
+    /// Returns an iterator over the records in the range of `params` that have a non-empty value in both tables,
+    /// with the values of both tables. `params.table` is ignored.
+    pub async fn iter_join(
+        &self,
+        params: IterParams<'_>,
+        left: &str,
+        right: &str,
+    ) -> Result<Option<JoinIter>, TimestoreError> {
+        Ok(self
+            .iter_rows(params, &[left, right])
+            .await?
+            .map(|rows| JoinIter { rows }))
+    }
+
+    /// Returns an iterator over the keys in [from, to) and the sizes of their values in each table.
+    /// The sizes are computed from the in memory offsets so no data is read.
+    pub fn iter_lengths(&self, from: u64, to: u64) -> Result<Option<LengthIter>, TimestoreError> {
+        let pos = match self.first_position_at_or_after(from) {
+            Some(pos) if from < to => pos,
+            _ => return Ok(None),
+        };
+
+        let table_io_vecs = self
+            .table_offsets
+            .iter()
+            .map(|offsets| IoVecIter::from_caos_and_position(offsets.clone(), pos))
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(Some(LengthIter {
+            keys: self.keys.iter_from(pos),
+            table_io_vecs,
+            to,
         }))
     }
 
+    /// Reads the given ranges of the value of the key. Positions of the iovecs are relative to the start of the value.
+    /// Results can come back in a different order than the iovecs, each one is returned with the index of its iovec.
+    /// `concurrency` is either a number of reads in flight or `ReadConcurrency::Auto`,
+    /// which collects the iovecs first and picks the concurrency and the buffer limit from their sizes.
     pub async fn read_many<V, S>(
         &self,
         table: &str,
         key: u64,
         iovs: S,
-        concurrency: usize,
+        concurrency: impl Into<ReadConcurrency>,
         buffer_limit: MergedBufferLimit,
         read_amp_limit: ReadAmplificationLimit,
-    ) -> Result<Option<impl Stream<Item = Result<ReadResult>>>>
+    ) -> Result<
+        Option<impl Stream<Item = Result<(usize, ReadResult), TimestoreError>>>,
+        TimestoreError,
+    >
     where
         V: IoVec + Unpin,
         S: Stream<Item = V> + Unpin,
     {
-        let (file, offsets) = self.get_file_and_offsets(table)?;
+        let (files, offsets) = self.get_file_and_offsets(table)?;
+
+        if !self.may_contain(key) {
+            return Ok(None);
+        }
 
         let pos = match self.keys.position(key) {
             Some(pos) => pos,
@@ -106,69 +368,538 @@ impl Reader {
         let base_offset = if pos == 0 {
             0
         } else {
-            offsets.iter_from(pos - 1).next().unwrap()
+            offset_at(&offsets, pos - 1, key)?
+        };
+        let (file, base_offset) = files.file_at(base_offset).await?;
+
+        let (iovs, empty) = skip_empty_iovs(index_iovs(iovs, base_offset));
+
+        let (concurrency, buffer_limit, iovs) = match concurrency.into() {
+            ReadConcurrency::Fixed(concurrency) => (concurrency, buffer_limit, Either::Left(iovs)),
+            ReadConcurrency::Auto => {
+                let iovs = iovs.collect::<Vec<_>>().await;
+                let total_bytes = iovs.iter().map(|iov| iov.size as u64).sum();
+                let (concurrency, buffer_limit) =
+                    ReadConcurrency::Auto.resolve(iovs.len(), total_bytes, buffer_limit);
+                (
+                    concurrency,
+                    buffer_limit,
+                    Either::Right(futures::stream::iter(iovs)),
+                )
+            }
         };
 
-        let iovs = iovs.map(move |iov| (iov.pos() + base_offset, iov.size()));
+        let observer = self.observer.clone();
+        let table = table.to_owned();
 
         Ok(Some(
-            file.read_many(iovs, buffer_limit, read_amp_limit)
-                .with_concurrency(concurrency)
-                .map(|res| match res {
-                    Ok((_, buf)) => Ok(buf),
-                    Err(e) => Err(anyhow!("{}", e).context("read from file")),
-                }),
+            file.read_many(iovs, Some(concurrency), buffer_limit, read_amp_limit)
+                .map(move |res| match res {
+                    Ok((iov, buf)) => {
+                        observer.on_read(&table, buf.len());
+                        Ok((iov.index, buf))
+                    }
+                    Err(e) => Err(e.context("read from file").into()),
+                })
+                .chain(empty),
         ))
     }
 
-    pub async fn read(&self, table: &str, key: u64) -> Result<Option<ReadResult>> {
-        let (table_file, table_offsets) = self.get_file_and_offsets(table)?;
+    /// Same as `read_many` with the settings given by `opts`.
+    pub async fn read_many_with<V, S>(
+        &self,
+        table: &str,
+        key: u64,
+        iovs: S,
+        opts: ReadOptions,
+    ) -> Result<
+        Option<impl Stream<Item = Result<(usize, ReadResult), TimestoreError>>>,
+        TimestoreError,
+    >
+    where
+        V: IoVec + Unpin,
+        S: Stream<Item = V> + Unpin,
+    {
+        self.read_many(
+            table,
+            key,
+            iovs,
+            opts.concurrency,
+            opts.buffer_limit(),
+            opts.read_amp_limit(),
+        )
+        .await
+    }
+
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "debug", skip(self), err)
+    )]
+    /// Reads the value of the key in the given table, returns None if the store doesn't have the key.
+    /// If `Config::read_cache_bytes` is set the value is returned from the cache if it was read before.
+    pub async fn read(&self, table: &str, key: u64) -> Result<Option<ReadBuf>, TimestoreError> {
+        let (files, _) = self.get_file_and_offsets(table)?;
+
+        let location = match self.value_location(table, key)? {
+            Some(location) => location,
+            None => return Ok(None),
+        };
+
+        let table_index = self.table_index(table)?;
+        let cache_generation = self.cache.as_ref().map(|cache| cache.generation());
+        if let Some(cache) = &self.cache {
+            if let Some(buf) = cache.get(table_index, key, location) {
+                self.observer.on_cache_hit(table, buf.len());
+                return Ok(Some(buf));
+            }
+        }
+
+        let (pos, len) = location;
+        let (file, pos) = files.file_at(pos).await?;
+        let buf = read_at(&file, pos, len, self.max_read_bytes).await?;
+
+        self.observer.on_read(table, buf.len());
+
+        if let (Some(cache), Some(generation)) = (&self.cache, cache_generation) {
+            cache.insert(generation, table_index, key, location, buf.clone());
+        }
+
+        Ok(Some(buf))
+    }
+
+    /// Reads `len` bytes of the value of the key starting at `offset` bytes into the value,
+    /// e.g. a single field of a value that is made of fixed size fields.
+    /// Errors if the range goes past the end of the value.
+    pub async fn read_sub(
+        &self,
+        table: &str,
+        key: u64,
+        offset: usize,
+        len: usize,
+    ) -> Result<Option<ReadBuf>, TimestoreError> {
+        let (files, _) = self.get_file_and_offsets(table)?;
+
+        let (pos, value_len) = match self.value_location(table, key)? {
+            Some(location) => location,
+            None => return Ok(None),
+        };
+        if offset.checked_add(len).is_none_or(|end| end > value_len) {
+            return Err(anyhow!(
+                "range {}..{} is out of the value of key {} which has {} bytes",
+                offset,
+                offset.saturating_add(len),
+                key,
+                value_len
+            )
+            .into());
+        }
+
+        // Values never span data files so the range is in the file of the start of the value.
+        let (file, pos) = files.file_at(pos).await?;
+        let buf = read_at(
+            &file,
+            pos + u64::try_from(offset).unwrap(),
+            len,
+            self.max_read_bytes,
+        )
+        .await?;
+
+        self.observer.on_read(table, buf.len());
+
+        Ok(Some(buf))
+    }
+
+    /// Reads the values of the last `n` records of the table, returning them in key order.
+    /// Returns fewer than `n` records if the store doesn't have that many.
+    pub async fn tail(&self, table: &str, n: usize) -> Result<Vec<(u64, ReadBuf)>, TimestoreError> {
+        let (files, offsets) = self.get_file_and_offsets(table)?;
+
+        let len = self.length.get();
+        if len == 0 {
+            return Ok(Vec::new());
+        }
+        let start = len.saturating_sub(n);
+
+        let (prev_key, keys) = self.keys_from(start)?;
+        let mut start_offset = if start == 0 {
+            0
+        } else {
+            offset_at(&offsets, start - 1, prev_key)?
+        };
+        let mut end_offsets = offsets.iter_from(start);
+        let mut locations = Vec::with_capacity(len - start);
+        for key in keys.take(len - start) {
+            let end_offset = end_offsets.next().ok_or_else(|| length_mismatch(key))?;
+            locations.push((key, start_offset, end_offset - start_offset));
+            start_offset = end_offset;
+        }
+
+        let max_read_bytes = self.max_read_bytes;
+        let values = futures::future::try_join_all(locations.into_iter().map(|(key, pos, len)| {
+            let files = files.clone();
+            async move {
+                let (file, pos) = files.file_at(pos).await?;
+                let buf =
+                    read_at(&file, pos, usize::try_from(len).unwrap(), max_read_bytes).await?;
+                Ok::<_, anyhow::Error>((key, buf))
+            }
+        }))
+        .await?;
+
+        for (_, buf) in values.iter() {
+            self.observer.on_read(table, buf.len());
+        }
+
+        Ok(values)
+    }
+
+    /// Returns the (offset, length) of the value of the given key inside the data of the table.
+    /// The offset is global across the data files of the table if it is split into segments.
+    pub fn value_location(
+        &self,
+        table: &str,
+        key: u64,
+    ) -> Result<Option<(u64, usize)>, TimestoreError> {
+        let (_, table_offsets) = self.get_file_and_offsets(table)?;
+
+        if !self.may_contain(key) {
+            return Ok(None);
+        }
 
         let pos = match self.keys.position(key) {
             Some(pos) => pos,
             None => return Ok(None),
         };
 
-        let (pos, len) = if pos == 0 {
-            let len = table_offsets.iter_from(0).next().unwrap();
-            (0, len)
-        } else {
-            let mut iter = table_offsets.iter_from(pos - 1);
-            let start = iter.next().unwrap();
-            let end = iter.next().unwrap();
+        Ok(Some(self.location_at(&table_offsets, pos, key)?))
+    }
+
+    /// Returns the keys of the records that were appended with the given tag in increasing order.
+    /// Records that were appended without a tag have the tag 0. Errors if the store wasn't opened with `Config::tag_index`.
+    pub fn keys_for_tag(&self, tag: u64) -> Result<Vec<u64>, TimestoreError> {
+        let tags = self
+            .tags
+            .as_ref()
+            .ok_or_else(|| anyhow!("store has no tag index"))?;
 
-            (start, end - start)
+        Ok(tags
+            .positions(tag)
+            .into_iter()
+            .map_while(|pos| self.key_at_position(pos))
+            .collect())
+    }
+
+    /// Returns the key of the record at the given position, None if the position is out of range.
+    pub fn key_at_position(&self, position: usize) -> Option<u64> {
+        self.keys.iter_from(position).next()
+    }
+
+    /// Reads the value of the record at the given position, None if the position is out of range.
+    pub async fn read_at_position(
+        &self,
+        table: &str,
+        position: usize,
+    ) -> Result<Option<ReadBuf>, TimestoreError> {
+        let (files, offsets) = self.get_file_and_offsets(table)?;
+
+        let key = match self.key_at_position(position) {
+            Some(key) => key,
+            None => return Ok(None),
         };
+        let (pos, len) = self.location_at(&offsets, position, key)?;
 
-        table_file
-            .read_at(pos, usize::try_from(len).unwrap())
-            .await
-            .map_err(|e| anyhow!("{}", e))
-            .context("read from file")
-            .map(Some)
+        let (file, pos) = files.file_at(pos).await?;
+        let buf = read_at(&file, pos, len, self.max_read_bytes).await?;
+
+        self.observer.on_read(table, buf.len());
+
+        Ok(Some(buf))
     }
 
-    fn get_file_and_offsets(&self, table: &str) -> Result<(Rc<DmaFile>, caos::Reader<u64>)> {
-        match self.table_names.iter().position(|n| table == n) {
-            Some(pos) => Ok((
-                self.table_files.get(pos).unwrap().clone(),
-                self.table_offsets.get(pos).unwrap().clone(),
-            )),
-            None => Err(anyhow!("table '{}' not found", table)),
+    /// Reads the data of the given table that an iter with the same from and to would read and discards it.
+    /// This is only a hint to warm up the device before a scan.
+    pub async fn prefetch(&self, table: &str, from: u64, to: u64) -> Result<(), TimestoreError> {
+        let (files, offsets) = self.get_file_and_offsets(table)?;
+
+        let pos = match self.keys.next_position(from) {
+            Some(pos) => pos,
+            None => return Ok(()),
+        };
+
+        // iter stops after the first key that is greater than or equal to `to`
+        let end_pos = match to.checked_sub(1).and_then(|to| self.keys.next_position(to)) {
+            Some(end_pos) => std::cmp::max(end_pos + 1, pos + 1),
+            None => usize::MAX,
+        };
+
+        let start = if pos == 0 {
+            0
+        } else {
+            offsets
+                .iter_from(pos - 1)
+                .next()
+                .ok_or_else(|| anyhow!("internal offset/key length mismatch at position {}", pos))?
+        };
+        let end = match offsets.iter_from(pos).take(end_pos - pos).last() {
+            Some(end) => end,
+            None => return Ok(()),
+        };
+
+        if start >= end {
+            return Ok(());
+        }
+
+        let (first_segment, _) = files.segments().locate(start);
+        let (last_segment, _) = files.segments().locate(end - 1);
+        let starts = files.segments().starts();
+        for segment in first_segment..=last_segment {
+            let segment_start = starts[segment];
+            let segment_end = starts.get(segment + 1).copied().unwrap_or(u64::MAX);
+            let range_start = std::cmp::max(start, segment_start) - segment_start;
+            let range_end = std::cmp::min(end, segment_end) - segment_start;
+
+            let iovs = (range_start..range_end)
+                .step_by(PREFETCH_CHUNK_SIZE)
+                .map(|pos| {
+                    let len =
+                        std::cmp::min(u64::try_from(PREFETCH_CHUNK_SIZE).unwrap(), range_end - pos);
+                    (pos, usize::try_from(len).unwrap())
+                });
+
+            let file = files.file(segment).await?;
+            let mut reads = file.read_many(
+                futures::stream::iter(iovs),
+                Some(8),
+                MergedBufferLimit::NoMerging,
+                ReadAmplificationLimit::NoAmplification,
+            );
+            while let Some(res) = reads.next().await {
+                res.context("read from file")?;
+            }
+        }
+
+        Ok(())
+    }
+
+    // Returns the (offset, length) of the value of the record at the given position, validating it if validate_reads is set.
+    fn location_at(
+        &self,
+        offsets: &caos::Reader<u64>,
+        pos: usize,
+        key: u64,
+    ) -> Result<(u64, usize)> {
+        if self.validate_reads {
+            validate_location(&self.keys, offsets, pos, key)?;
+        }
+        location_at(offsets, pos, key)
+    }
+
+    // Checks the bloom filter if there is one, a false return means the key is definitely not in the store.
+    fn may_contain(&self, key: u64) -> bool {
+        match &self.bloom {
+            Some(bloom) => bloom.may_contain(key),
+            None => true,
+        }
+    }
+
+    // Returns the key before the given position and an iterator over the keys starting at the position.
+    fn keys_from(&self, pos: usize) -> Result<(u64, caos::Iter<u64>)> {
+        if pos == 0 {
+            return Ok((0, self.keys.iter_from(0)));
+        }
+
+        let mut iter = self.keys.iter_from(pos - 1);
+        let current_key = iter
+            .next()
+            .ok_or_else(|| anyhow!("internal key length mismatch at position {}", pos - 1))?;
+
+        Ok((current_key, iter))
+    }
+
+    fn table_stream(
+        &self,
+        table: &str,
+        pos: usize,
+        params: &IterParams<'_>,
+    ) -> Result<TableStream, TimestoreError> {
+        let (files, offsets) = self.get_file_and_offsets(table)?;
+
+        Ok(TableStream {
+            files,
+            io_vecs: IoVecIter::from_caos_and_position(offsets, pos)?,
+            current: (0, 0),
+            reader: None,
+            buffer_size: params.buffer_size,
+            concurrency: params.concurrency,
+            read_ahead: params.read_ahead.unwrap_or(params.concurrency),
+            step: params.step,
+            prefetch_window: params.prefetch_window,
+            pending: VecDeque::new(),
+            prefetched: VecDeque::new(),
+        })
+    }
+
+    fn table_index(&self, table: &str) -> Result<usize, TimestoreError> {
+        self.table_names
+            .iter()
+            .position(|n| table == n)
+            .ok_or_else(|| TimestoreError::TableNotFound(table.to_owned()))
+    }
+
+    fn get_file_and_offsets(
+        &self,
+        table: &str,
+    ) -> Result<(Rc<TableFiles>, caos::Reader<u64>), TimestoreError> {
+        let pos = self.table_index(table)?;
+        Ok((
+            self.table_files.get(pos).unwrap().clone(),
+            self.table_offsets.get(pos).unwrap().clone(),
+        ))
+    }
+}
+
+const PREFETCH_CHUNK_SIZE: usize = 1024 * 1024;
+
+// Bytes that ReadConcurrency::Auto aims to keep in flight.
+const AUTO_BYTES_IN_FLIGHT: u64 = 16 * 1024 * 1024;
+// Reads smaller than this on average are merged by ReadConcurrency::Auto.
+const AUTO_SMALL_READ_SIZE: u64 = 64 * 1024;
+
+/// Number of reads `Reader::read_many` keeps in flight.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReadConcurrency {
+    Fixed(usize),
+    /// Picks the concurrency from the number and the total size of the reads.
+    /// Many small reads get a high concurrency and are merged up to the size the device allows in a single request,
+    /// few large reads get a low concurrency so the bytes in flight stay bounded. Never more than `MAX_AUTO`.
+    Auto,
+}
+
+impl ReadConcurrency {
+    /// Upper limit of the concurrency that `Auto` picks.
+    pub const MAX_AUTO: usize = 64;
+
+    /// Returns the concurrency and buffer limit to use for `count` reads of `total_bytes` in total.
+    /// `Fixed` returns its concurrency and the given buffer limit.
+    pub fn resolve(
+        &self,
+        count: usize,
+        total_bytes: u64,
+        buffer_limit: MergedBufferLimit,
+    ) -> (usize, MergedBufferLimit) {
+        match *self {
+            ReadConcurrency::Fixed(concurrency) => (concurrency, buffer_limit),
+            ReadConcurrency::Auto => {
+                let count = std::cmp::max(count, 1);
+                let avg_size = std::cmp::max(total_bytes / u64::try_from(count).unwrap(), 1);
+                let concurrency = usize::try_from(AUTO_BYTES_IN_FLIGHT / avg_size)
+                    .unwrap_or(usize::MAX)
+                    .clamp(1, Self::MAX_AUTO)
+                    .min(count);
+                let buffer_limit = if avg_size < AUTO_SMALL_READ_SIZE {
+                    MergedBufferLimit::DeviceMaxSingleRequest
+                } else {
+                    MergedBufferLimit::NoMerging
+                };
+
+                (concurrency, buffer_limit)
+            }
+        }
+    }
+}
+
+impl From<usize> for ReadConcurrency {
+    fn from(concurrency: usize) -> Self {
+        ReadConcurrency::Fixed(concurrency)
+    }
+}
+
+/// Settings of `Reader::read_many_with`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, derive_builder::Builder)]
+pub struct ReadOptions {
+    #[builder(setter(into), default = "ReadConcurrency::Auto")]
+    concurrency: ReadConcurrency,
+    /// Largest read that adjacent reads are merged into. None merges up to the largest request the device allows
+    /// and 0 disables merging. `ReadConcurrency::Auto` picks this itself so it only applies to a fixed concurrency.
+    #[builder(default)]
+    max_merged_bytes: Option<usize>,
+    /// Number of bytes between two reads that can be read to merge them. 0 only merges reads that are adjacent.
+    #[builder(default)]
+    max_read_amplification: usize,
+}
+
+impl Default for ReadOptions {
+    fn default() -> Self {
+        ReadOptionsBuilder::default().build().unwrap()
+    }
+}
+
+impl ReadOptions {
+    fn buffer_limit(&self) -> MergedBufferLimit {
+        match self.max_merged_bytes {
+            None => MergedBufferLimit::DeviceMaxSingleRequest,
+            Some(0) => MergedBufferLimit::NoMerging,
+            Some(bytes) => MergedBufferLimit::Custom(bytes),
+        }
+    }
+
+    fn read_amp_limit(&self) -> ReadAmplificationLimit {
+        match self.max_read_amplification {
+            0 => ReadAmplificationLimit::NoAmplification,
+            bytes => ReadAmplificationLimit::Custom(bytes),
         }
     }
 }
 
 #[derive(Debug, Default, Clone, Copy, PartialEq, derive_builder::Builder)]
+#[builder(build_fn(validate = "Self::validate"))]
 pub struct IterParams<'input> {
+    /// Start of the key range. This and `to` have no defaults so `build` fails if they are not set.
     from: u64,
     to: u64,
+    /// Table that is read by the stream reader of the iterator.
+    /// The buffer settings below only apply to this table, other tables are read with random reads by `Iter::read`.
     #[builder(default)]
     table: Option<&'input str>,
     #[builder(default = "512 * 1024")]
     buffer_size: usize,
     #[builder(default = "8")]
     concurrency: usize,
+    /// Number of buffers the stream reader reads ahead. Defaults to `concurrency`.
+    #[builder(default)]
+    read_ahead: Option<usize>,
+    /// Number of records to advance on each call to next after the first one.
+    /// Records in between are skipped without reading their data.
+    #[builder(default = "1")]
+    step: usize,
+    /// Number of upcoming records whose values are read together with `read_many` when the stream needs more data,
+    /// so adjacent values are fetched in merged reads and `next` is served from the buffered values.
+    /// 0 reads the values with the stream reader instead.
+    #[builder(default)]
+    prefetch_window: usize,
+    /// Makes `Reader::iter` and `Reader::iter_rows` return an iterator without any records for an empty store
+    /// instead of None, so None only means that `from` is past the last key of the store.
+    #[builder(default)]
+    empty_as_iter: bool,
+}
+
+impl IterParams<'_> {
+    pub(crate) fn to(&self) -> u64 {
+        self.to
+    }
+}
+
+impl IterParamsBuilder<'_> {
+    fn validate(&self) -> Result<(), String> {
+        if self.step == Some(0) {
+            return Err("step has to be at least 1".to_owned());
+        }
+        if self.read_ahead == Some(Some(0)) {
+            return Err("read_ahead has to be at least 1".to_owned());
+        }
+
+        Ok(())
+    }
 }
 
 struct IoVecIter {
@@ -177,16 +908,18 @@ struct IoVecIter {
 }
 
 impl IoVecIter {
-    fn from_caos_and_position(caos: caos::Reader<u64>, pos: usize) -> Self {
+    fn from_caos_and_position(caos: caos::Reader<u64>, pos: usize) -> Result<Self> {
         if pos == 0 {
-            Self {
+            Ok(Self {
                 start: 0,
                 inner: caos.iter_from(0),
-            }
+            })
         } else {
             let mut inner = caos.iter_from(pos - 1);
-            let start = inner.next().unwrap();
-            Self { start, inner }
+            let start = inner.next().ok_or_else(|| {
+                anyhow!("internal offset/key length mismatch at position {}", pos)
+            })?;
+            Ok(Self { start, inner })
         }
     }
 }
@@ -205,109 +938,804 @@ impl Iterator for IoVecIter {
     }
 }
 
+// Sequential reader over the data files of the table that is selected in IterParams.
+struct TableStream {
+    files: Rc<TableFiles>,
+    io_vecs: IoVecIter,
+    // Position and length of the record that advance moved to, read is called until it finishes.
+    current: (u64, usize),
+    // Reader of the data file that is currently being read, with the index of the file and the position of the reader.
+    // It is taken out while reading so a read that is dropped midway can't leave it at an unknown position,
+    // the file is opened again by the next read instead.
+    reader: Option<(StreamReader, usize, u64)>,
+    buffer_size: usize,
+    concurrency: usize,
+    read_ahead: usize,
+    step: usize,
+    prefetch_window: usize,
+    // Records that were taken from io_vecs to find the records of the prefetch window but weren't advanced to yet.
+    pending: VecDeque<(u64, usize)>,
+    // Values read by the last prefetch with the records they belong to, in the order of the records.
+    prefetched: VecDeque<((u64, usize), ReadResult)>,
+}
+
+impl TableStream {
+    // Skips the given number of records and moves to the next one without reading it.
+    fn advance(&mut self, skip: usize, key: u64) -> Result<()> {
+        for _ in 0..skip {
+            self.next_io_vec().ok_or_else(|| length_mismatch(key))?;
+        }
+        self.current = self.next_io_vec().ok_or_else(|| length_mismatch(key))?;
+        Ok(())
+    }
+
+    fn next_io_vec(&mut self) -> Option<(u64, usize)> {
+        self.pending.pop_front().or_else(|| self.io_vecs.next())
+    }
+
+    // Reads the record that advance moved to. Calling this again reads the same record.
+    async fn read(&mut self) -> Result<Vec<u8>> {
+        let (pos, len) = self.current;
+        if len == 0 {
+            return Ok(Vec::new());
+        }
+        if self.prefetch_window > 0 {
+            return self.read_prefetched().await;
+        }
+
+        let (segment, start) = self.files.segments().locate(pos);
+        let pos = pos - start;
+
+        let (mut reader, reader_pos) = match self.reader.take() {
+            Some((reader, reader_segment, reader_pos))
+                if reader_segment == segment && reader_pos <= pos =>
+            {
+                (reader, reader_pos)
+            }
+            _ => {
+                let path = self.files.segments().path(start);
+                let reader = StreamReader::open(
+                    &path,
+                    self.buffer_size,
+                    self.read_ahead,
+                    Some(self.concurrency),
+                    self.files.direct_io(),
+                )
+                .await
+                .context("open table file")?;
+                (reader, 0)
+            }
+        };
+
+        reader
+            .skip(pos - reader_pos)
+            .await
+            .context("skip to value")?;
+        let mut buf = vec![0; len];
+        reader
+            .read_exact(&mut buf)
+            .await
+            .context("read from file")?;
+        self.reader = Some((reader, segment, pos + u64::try_from(len).unwrap()));
+
+        Ok(buf)
+    }
+
+    // Returns the value of the current record from the prefetched values,
+    // reading it with the values of the following records in the window if it wasn't prefetched.
+    async fn read_prefetched(&mut self) -> Result<Vec<u8>> {
+        // Non-empty values of a table start at increasing positions so the ones before the current record
+        // belong to records that were skipped.
+        while let Some(((pos, _), _)) = self.prefetched.front() {
+            if *pos >= self.current.0 {
+                break;
+            }
+            self.prefetched.pop_front();
+        }
+        if self
+            .prefetched
+            .front()
+            .is_none_or(|(io_vec, _)| *io_vec != self.current)
+        {
+            self.prefetch().await?;
+        }
+
+        let (_, buf) = self.prefetched.pop_front().unwrap();
+        Ok(buf.to_vec())
+    }
+
+    // Reads the values of the current record and the records that the next calls to advance will move to,
+    // up to prefetch_window records. Stops at the end of the data file of the current record since
+    // a single read_many call reads from one file.
+    async fn prefetch(&mut self) -> Result<()> {
+        let (segment, start) = self.files.segments().locate(self.current.0);
+
+        let mut records = vec![self.current];
+        for window_pos in 1..self.prefetch_window {
+            let idx = window_pos * self.step;
+            while self.pending.len() < idx {
+                match self.io_vecs.next() {
+                    Some(io_vec) => self.pending.push_back(io_vec),
+                    None => break,
+                }
+            }
+            let io_vec = match self.pending.get(idx - 1) {
+                Some(&io_vec) => io_vec,
+                None => break,
+            };
+            if io_vec.1 == 0 {
+                continue;
+            }
+            if self.files.segments().locate(io_vec.0).0 != segment {
+                break;
+            }
+            records.push(io_vec);
+        }
+
+        let file = self.files.file(segment).await?;
+        let iovs = records
+            .iter()
+            .enumerate()
+            .map(|(index, &(pos, size))| IndexedIoVec {
+                index,
+                pos: pos - start,
+                size,
+            })
+            .collect::<Vec<_>>();
+        let mut bufs = file
+            .read_many(
+                futures::stream::iter(iovs),
+                Some(self.concurrency),
+                MergedBufferLimit::DeviceMaxSingleRequest,
+                ReadAmplificationLimit::NoAmplification,
+            )
+            .map(|res| res.map(|(iov, buf)| (iov.index, buf)))
+            .collect::<Vec<_>>()
+            .await
+            .into_iter()
+            .collect::<Result<Vec<_>>>()
+            .context("read from file")?;
+        bufs.sort_unstable_by_key(|(index, _)| *index);
+
+        self.prefetched = records
+            .into_iter()
+            .zip(bufs)
+            .map(|(io_vec, (_, buf))| (io_vec, buf))
+            .collect();
+
+        Ok(())
+    }
+}
+
 pub struct Iter {
     started: bool,
     current_key: u64,
+    // Key that will be returned by the next call to next, read ahead so it can be peeked.
+    next_key: Option<u64>,
+    // Key range of a call to next that didn't finish, the next call reads this record again.
+    in_flight: Option<(u64, u64)>,
     keys: caos::Iter<u64>,
-    stream_reader: Option<(DmaStreamReader, IoVecIter)>,
+    stream_reader: Option<TableStream>,
     table_io_vecs: Vec<IoVecIter>,
     current_table_io_vecs: Vec<(u64, usize)>,
     to: u64,
+    step: usize,
     table_names: Vec<String>,
-    table_files: Vec<Rc<DmaFile>>,
+    table_files: Vec<Rc<TableFiles>>,
+    table: Option<String>,
+    observer: Observer,
+    max_read_bytes: usize,
 }
 
 impl Iter {
-    pub async fn next(&mut self) -> Result<Option<((u64, u64), Vec<u8>)>> {
+    /// Returns the key that the next call to `next` would return without advancing the iterator or reading any data.
+    pub fn peek_key(&self) -> Option<u64> {
+        if let Some((_, key)) = self.in_flight {
+            return Some(key);
+        }
+        if self.current_key >= self.to {
+            return None;
+        }
+
+        self.next_key
+    }
+
+    /// Moves to the next record and returns its key range and the value of the table that is selected in IterParams.
+    ///
+    /// This is cancellation safe. If the returned future is dropped before it finishes, e.g. because of a timeout,
+    /// or if it returns an error, the next call reads the same record again. Call `cancel` to skip the record instead.
+    pub async fn next(&mut self) -> Result<Option<((u64, u64), Vec<u8>)>, TimestoreError> {
+        let (prev_key, key) = match self.in_flight {
+            Some(range) => range,
+            None => match self.advance()? {
+                Some(range) => range,
+                None => return Ok(None),
+            },
+        };
+
+        let buf = match &mut self.stream_reader {
+            Some(stream) => stream.read().await?,
+            None => Vec::new(),
+        };
+        self.in_flight = None;
+
+        if let Some(table) = &self.table {
+            self.observer.on_read(table, buf.len());
+        }
+
+        Ok(Some(((prev_key, key), buf)))
+    }
+
+    /// Same as `next` but returns the value of the selected table as the buffer of a `read_at`, so the bytes stay
+    /// in the buffer they were read into instead of being copied into a Vec. The value is None if no table is selected.
+    ///
+    /// Each value is read with its own read at its position while `next` reads the table sequentially
+    /// through the buffers of the stream reader, so this saves the copy but is slower for scans over many small values.
+    /// This is cancellation safe in the same way as `next`.
+    pub async fn next_result(
+        &mut self,
+    ) -> Result<Option<((u64, u64), Option<ReadBuf>)>, TimestoreError> {
+        let range = match self.in_flight {
+            Some(range) => range,
+            None => match self.advance()? {
+                Some(range) => range,
+                None => return Ok(None),
+            },
+        };
+
+        let buf = match &self.table {
+            Some(table) => Some(self.read(table).await?),
+            None => None,
+        };
+        self.in_flight = None;
+
+        Ok(Some((range, buf)))
+    }
+
+    /// Abandons the record of a call to `next` that was dropped or errored,
+    /// so the following call moves on to the record after it instead of reading it again.
+    /// Does nothing if the last call to `next` finished.
+    pub fn cancel(&mut self) {
+        self.in_flight = None;
+    }
+
+    // Moves to the next record without reading its data and returns its key range.
+    fn advance(&mut self) -> Result<Option<(u64, u64)>> {
+        let skip = if self.started { self.step - 1 } else { 0 };
         self.started = true;
 
         if self.current_key >= self.to {
             return Ok(None);
         }
 
-        let next_key = match self.keys.next() {
+        let next_key = match self.next_key {
             Some(next_key) => next_key,
             None => return Ok(None),
         };
+        self.next_key = self.keys.nth(self.step - 1);
 
         for (current_io_vec, io_vecs) in self
             .current_table_io_vecs
             .iter_mut()
             .zip(self.table_io_vecs.iter_mut())
         {
-            *current_io_vec = io_vecs.next().unwrap();
+            *current_io_vec = io_vecs.nth(skip).ok_or_else(|| length_mismatch(next_key))?;
+        }
+        if let Some(stream) = &mut self.stream_reader {
+            stream.advance(skip, next_key)?;
         }
 
         let prev_key = self.current_key;
         self.current_key = next_key;
+        self.in_flight = Some((prev_key, next_key));
 
-        let buf = if let Some((reader, io_vecs)) = &mut self.stream_reader {
-            let (_, len) = io_vecs.next().unwrap();
-            let mut buf = vec![0; len];
-            reader
-                .read_exact(&mut buf)
-                .await
-                .context("read from file")?;
+        Ok(Some((prev_key, next_key)))
+    }
 
-            buf
-        } else {
-            Vec::new()
-        };
+    pub async fn read(&self, table: &str) -> Result<ReadBuf, TimestoreError> {
+        if !self.started {
+            return Err(
+                anyhow!("iter.next has to be called before calling read or read_many").into(),
+            );
+        }
+
+        let (files, io_vec) = self.get_file_and_io_vec(table)?;
+
+        let (file, pos) = files.file_at(io_vec.0).await?;
+        let buf = read_at(&file, pos, io_vec.1, self.max_read_bytes).await?;
 
-        Ok(Some(((prev_key, self.current_key), buf)))
+        self.observer.on_read(table, buf.len());
+
+        Ok(buf)
     }
 
-    pub async fn read(&self, table: &str) -> Result<ReadResult> {
+    /// Returns the length of the value of the current key in the given table without reading it.
+    pub fn current_value_len(&self, table: &str) -> Result<usize, TimestoreError> {
         if !self.started {
-            return Err(anyhow!(
-                "iter.next has to be called before calling read or read_many"
-            ));
+            return Err(
+                anyhow!("iter.next has to be called before calling current_value_len").into(),
+            );
         }
 
-        let (file, io_vec) = self.get_file_and_io_vec(table)?;
+        let (_, io_vec) = self.get_file_and_io_vec(table)?;
 
-        file.read_at(io_vec.0, io_vec.1)
-            .await
-            .map_err(|e| anyhow!("{}", e))
-            .context("read from file")
+        Ok(io_vec.1)
     }
 
+    /// Reads the values of all tables for the current key concurrently.
+    pub async fn read_all(&self) -> Result<Vec<(String, ReadBuf)>, TimestoreError> {
+        if !self.started {
+            return Err(
+                anyhow!("iter.next has to be called before calling read or read_many").into(),
+            );
+        }
+
+        let futs = self
+            .table_names
+            .iter()
+            .zip(self.table_files.iter())
+            .zip(self.current_table_io_vecs.iter())
+            .map(|((name, files), &(pos, len))| async move {
+                let (file, pos) = files.file_at(pos).await?;
+                let buf = read_at(&file, pos, len, self.max_read_bytes)
+                    .await
+                    .with_context(|| format!("read table '{}'", name))?;
+
+                self.observer.on_read(name, buf.len());
+
+                Ok::<_, anyhow::Error>((name.clone(), buf))
+            });
+
+        Ok(futures::future::try_join_all(futs).await?)
+    }
+
+    /// Reads the given ranges of the value of the current key, see `Reader::read_many`.
     pub async fn read_many<V, S>(
         &self,
         table: &str,
         iovs: S,
         buffer_limit: MergedBufferLimit,
         read_amp_limit: ReadAmplificationLimit,
-    ) -> Result<impl Stream<Item = Result<ReadResult>>>
+    ) -> Result<impl Stream<Item = Result<(usize, ReadResult), TimestoreError>>, TimestoreError>
     where
         V: IoVec + Unpin,
         S: Stream<Item = V> + Unpin,
     {
         if !self.started {
-            return Err(anyhow!(
-                "iter.next has to be called before calling read or read_many"
-            ));
+            return Err(
+                anyhow!("iter.next has to be called before calling read or read_many").into(),
+            );
         }
 
-        let (file, base_io_vec) = self.get_file_and_io_vec(table)?;
+        let (files, base_io_vec) = self.get_file_and_io_vec(table)?;
+        let (file, base_pos) = files.file_at(base_io_vec.0).await?;
+
+        let (iovs, empty) = skip_empty_iovs(index_iovs(iovs, base_pos));
 
-        let iovs = iovs.map(move |iov| (iov.pos() + base_io_vec.0, iov.size()));
+        let observer = self.observer.clone();
+        let table = table.to_owned();
 
         Ok(file
-            .read_many(iovs, buffer_limit, read_amp_limit)
-            .map(|res| match res {
-                Ok((_, buf)) => Ok(buf),
-                Err(e) => Err(anyhow!("{}", e).context("read from file")),
-            }))
+            .read_many(iovs, None, buffer_limit, read_amp_limit)
+            .map(move |res| match res {
+                Ok((iov, buf)) => {
+                    observer.on_read(&table, buf.len());
+                    Ok((iov.index, buf))
+                }
+                Err(e) => Err(e.context("read from file").into()),
+            })
+            .chain(empty))
     }
 
-    fn get_file_and_io_vec(&self, table: &str) -> Result<(Rc<DmaFile>, (u64, usize))> {
+    fn get_file_and_io_vec(
+        &self,
+        table: &str,
+    ) -> Result<(Rc<TableFiles>, (u64, usize)), TimestoreError> {
         match self.table_names.iter().position(|n| n == table) {
             Some(pos) => Ok((
                 self.table_files.get(pos).unwrap().clone(),
                 *self.current_table_io_vecs.get(pos).unwrap(),
             )),
-            None => Err(anyhow!("table '{}' not found", table)),
+            None => Err(TimestoreError::TableNotFound(table.to_owned())),
+        }
+    }
+}
+
+/// Iterator returned by `Reader::iter_lengths`.
+pub struct LengthIter {
+    keys: caos::Iter<u64>,
+    table_io_vecs: Vec<IoVecIter>,
+    to: u64,
+}
+
+impl Iterator for LengthIter {
+    type Item = (u64, Vec<usize>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let key = self.keys.next()?;
+        if key >= self.to {
+            return None;
+        }
+
+        let lengths = self
+            .table_io_vecs
+            .iter_mut()
+            .map(|io_vecs| io_vecs.next().map(|(_, len)| len))
+            .collect::<Option<Vec<usize>>>()?;
+
+        Some((key, lengths))
+    }
+}
+
+/// Iterator returned by `Reader::iter_rows`.
+pub struct RowIter {
+    started: bool,
+    current_key: u64,
+    // Key range of a call to next that didn't finish, see Iter::next.
+    in_flight: Option<(u64, u64)>,
+    keys: caos::Iter<u64>,
+    streams: Vec<TableStream>,
+    tables: Vec<String>,
+    to: u64,
+    step: usize,
+    observer: Observer,
+}
+
+impl RowIter {
+    /// Returns the key range of the next record and the values of the tables in the order they were given to `iter_rows`.
+    /// This is cancellation safe in the same way as `Iter::next`.
+    pub async fn next(&mut self) -> Result<Option<((u64, u64), Vec<Vec<u8>>)>, TimestoreError> {
+        let (prev_key, key) = match self.in_flight {
+            Some(range) => range,
+            None => match self.advance()? {
+                Some(range) => range,
+                None => return Ok(None),
+            },
+        };
+
+        let row =
+            futures::future::try_join_all(self.streams.iter_mut().map(|stream| stream.read()))
+                .await?;
+        self.in_flight = None;
+
+        for (table, buf) in self.tables.iter().zip(row.iter()) {
+            self.observer.on_read(table, buf.len());
+        }
+
+        Ok(Some(((prev_key, key), row)))
+    }
+
+    /// Abandons the record of a call to `next` that was dropped or errored, see `Iter::cancel`.
+    pub fn cancel(&mut self) {
+        self.in_flight = None;
+    }
+
+    fn advance(&mut self) -> Result<Option<(u64, u64)>> {
+        let skip = if self.started { self.step - 1 } else { 0 };
+        self.started = true;
+
+        if self.current_key >= self.to {
+            return Ok(None);
+        }
+
+        let next_key = match self.keys.nth(skip) {
+            Some(next_key) => next_key,
+            None => return Ok(None),
+        };
+        for stream in self.streams.iter_mut() {
+            stream.advance(skip, next_key)?;
+        }
+
+        let prev_key = self.current_key;
+        self.current_key = next_key;
+        self.in_flight = Some((prev_key, next_key));
+
+        Ok(Some((prev_key, next_key)))
+    }
+}
+
+/// Iterator returned by `Reader::iter_join`.
+pub struct JoinIter {
+    rows: RowIter,
+}
+
+impl JoinIter {
+    /// Returns the key of the next record where both values are non-empty, with the left and right values.
+    /// Records with an empty value are skipped without reading anything, they still count for the step of the iterator.
+    /// This is cancellation safe in the same way as `Iter::next`.
+    pub async fn next(&mut self) -> Result<Option<(u64, Vec<u8>, Vec<u8>)>, TimestoreError> {
+        if self.rows.in_flight.is_none() {
+            loop {
+                if self.rows.advance()?.is_none() {
+                    return Ok(None);
+                }
+                if self.rows.streams.iter().all(|stream| stream.current.1 > 0) {
+                    break;
+                }
+            }
         }
+
+        let ((_, key), mut row) = match self.rows.next().await? {
+            Some(row) => row,
+            None => return Ok(None),
+        };
+        let right = row.pop().unwrap();
+        let left = row.pop().unwrap();
+
+        Ok(Some((key, left, right)))
+    }
+
+    /// Abandons the record of a call to `next` that was dropped or errored, see `Iter::cancel`.
+    pub fn cancel(&mut self) {
+        self.rows.cancel();
+    }
+}
+
+/// Buffer of a value that was read from the store, it derefs to the bytes of the value.
+/// Values of up to `Config::max_read_bytes` are the buffer of a single read, larger ones are read in chunks
+/// that are copied into one buffer.
+#[derive(Clone, Debug, Default)]
+pub struct ReadBuf(ReadBufInner);
+
+#[derive(Clone, Debug)]
+enum ReadBufInner {
+    Read(ReadResult),
+    Chunks(Rc<[u8]>),
+}
+
+impl Default for ReadBufInner {
+    fn default() -> Self {
+        Self::Read(ReadResult::default())
+    }
+}
+
+impl std::ops::Deref for ReadBuf {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match &self.0 {
+            ReadBufInner::Read(buf) => buf,
+            ReadBufInner::Chunks(buf) => buf,
+        }
+    }
+}
+
+// Reads from the file without issuing a read for empty ranges.
+// Ranges larger than max_read_bytes are read in chunks of at most max_read_bytes.
+pub(crate) async fn read_at(
+    file: &File,
+    pos: u64,
+    len: usize,
+    max_read_bytes: usize,
+) -> Result<ReadBuf> {
+    if len <= max_read_bytes {
+        return Ok(ReadBuf(ReadBufInner::Read(
+            read_exact(file, pos, len).await?,
+        )));
+    }
+
+    let mut buf = Vec::with_capacity(len);
+    while buf.len() < len {
+        let chunk = std::cmp::min(len - buf.len(), max_read_bytes);
+        let chunk_pos = pos + u64::try_from(buf.len()).unwrap();
+        buf.extend_from_slice(&read_exact(file, chunk_pos, chunk).await?);
+    }
+
+    Ok(ReadBuf(ReadBufInner::Chunks(buf.into())))
+}
+
+// Reads exactly len bytes with a single read.
+async fn read_exact(file: &File, pos: u64, len: usize) -> Result<ReadResult> {
+    if len == 0 {
+        return Ok(ReadResult::default());
+    }
+
+    let buf = file.read_at(pos, len).await.context("read from file")?;
+    // Reads come back short if the file ends early or if they are larger than the kernel reads at once,
+    // returning the partial value would look like a valid read.
+    if buf.len() != len {
+        return Err(anyhow!(
+            "read of {} bytes at position {} returned {} bytes",
+            len,
+            pos,
+            buf.len()
+        ));
+    }
+
+    Ok(buf)
+}
+
+// Returns the offset at the given position, erroring if the offsets are shorter than the keys.
+fn offset_at(offsets: &caos::Reader<u64>, pos: usize, key: u64) -> Result<u64> {
+    offsets
+        .iter_from(pos)
+        .next()
+        .ok_or_else(|| length_mismatch(key))
+}
+
+// Returns the (offset, length) of the value of the record at the given position.
+pub(crate) fn location_at(
+    offsets: &caos::Reader<u64>,
+    pos: usize,
+    key: u64,
+) -> Result<(u64, usize)> {
+    let (start, end) = if pos == 0 {
+        (0, offset_at(offsets, 0, key)?)
+    } else {
+        (
+            offset_at(offsets, pos - 1, key)?,
+            offset_at(offsets, pos, key)?,
+        )
+    };
+
+    Ok((start, usize::try_from(end - start).unwrap()))
+}
+
+// Checks that the key at the position is the given key and that the offsets of the record don't decrease.
+// These only fail if the in memory keys and offsets got out of sync.
+fn validate_location(
+    keys: &caos::Reader<u64>,
+    offsets: &caos::Reader<u64>,
+    pos: usize,
+    key: u64,
+) -> Result<()> {
+    let found = keys.iter_from(pos).next();
+    if found != Some(key) {
+        return Err(anyhow!(
+            "read validation failed, key at position {} is {:?} instead of {}",
+            pos,
+            found,
+            key
+        ));
+    }
+
+    let start = match pos {
+        0 => 0,
+        _ => offset_at(offsets, pos - 1, key)?,
+    };
+    let end = offset_at(offsets, pos, key)?;
+    if end < start {
+        return Err(anyhow!(
+            "read validation failed, offsets of key {} at position {} decrease from {} to {}",
+            key,
+            pos,
+            start,
+            end
+        ));
+    }
+
+    Ok(())
+}
+
+fn length_mismatch(key: u64) -> anyhow::Error {
+    anyhow!("internal offset/key length mismatch at key {}", key)
+}
+
+// Iovec that keeps the index it had in the stream given by the user, so results that come back out of order can be matched to it.
+struct IndexedIoVec {
+    index: usize,
+    pos: u64,
+    size: usize,
+}
+
+impl IoVec for IndexedIoVec {
+    fn pos(&self) -> u64 {
+        self.pos
+    }
+
+    fn size(&self) -> usize {
+        self.size
+    }
+}
+
+// Numbers the iovecs and moves them to the given position in the file.
+fn index_iovs<V, S>(iovs: S, base_pos: u64) -> impl Stream<Item = IndexedIoVec> + Unpin
+where
+    V: IoVec,
+    S: Stream<Item = V> + Unpin,
+{
+    iovs.enumerate().map(move |(index, iov)| IndexedIoVec {
+        index,
+        pos: iov.pos() + base_pos,
+        size: iov.size(),
+    })
+}
+
+// Filters out the empty iovecs so no read is issued for them.
+// Returns the filtered iovecs and a stream that yields an empty buffer for each empty iovec,
+// the second stream should be polled after the first one is exhausted.
+fn skip_empty_iovs<S>(
+    iovs: S,
+) -> (
+    impl Stream<Item = IndexedIoVec> + Unpin,
+    impl Stream<Item = Result<(usize, ReadResult), TimestoreError>>,
+)
+where
+    S: Stream<Item = IndexedIoVec> + Unpin,
+{
+    let empty_indices = Rc::new(RefCell::new(VecDeque::new()));
+
+    let indices = empty_indices.clone();
+    let iovs = iovs.filter(move |iov| {
+        if iov.size == 0 {
+            indices.borrow_mut().push_back(iov.index);
+        }
+        futures::future::ready(iov.size > 0)
+    });
+
+    let empty = futures::stream::iter(std::iter::from_fn(move || {
+        let index = empty_indices.borrow_mut().pop_front()?;
+        Some(Ok((index, ReadResult::default())))
+    }));
+
+    (iovs, empty)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_iter_offset_key_length_mismatch() {
+        glommio::LocalExecutor::default().run(async {
+            let (mut keys, keys_reader) = caos::new::<u64>(4);
+            keys.append(&[1, 2, 3]);
+            let (mut offsets, offsets_reader) = caos::new::<u64>(4);
+            offsets.append(&[10]);
+
+            let mut key_iter = keys_reader.iter_from(0);
+            let mut iter = Iter {
+                started: false,
+                current_key: 0,
+                next_key: key_iter.next(),
+                in_flight: None,
+                keys: key_iter,
+                stream_reader: None,
+                table_io_vecs: vec![IoVecIter::from_caos_and_position(offsets_reader, 0).unwrap()],
+                current_table_io_vecs: vec![(0, 0)],
+                to: 3,
+                step: 1,
+                table_names: vec!["table0".to_owned()],
+                table_files: Vec::new(),
+                table: None,
+                observer: Observer::default(),
+                max_read_bytes: usize::MAX,
+            };
+
+            assert_eq!(iter.next().await.unwrap().unwrap().0, (0, 1));
+            let err = iter.next().await.unwrap_err();
+            assert_eq!(
+                err.to_string(),
+                "internal offset/key length mismatch at key 2"
+            );
+        });
+    }
+
+    #[test]
+    fn test_validate_location() {
+        let (mut keys, keys_reader) = caos::new::<u64>(4);
+        keys.append(&[1, 2, 3, 4, 5]);
+        let (mut offsets, offsets_reader) = caos::new::<u64>(4);
+        offsets.append(&[10, 20, 15, 30]);
+
+        validate_location(&keys_reader, &offsets_reader, 0, 1).unwrap();
+        validate_location(&keys_reader, &offsets_reader, 1, 2).unwrap();
+
+        let err = validate_location(&keys_reader, &offsets_reader, 1, 3).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "read validation failed, key at position 1 is Some(2) instead of 3"
+        );
+        let err = validate_location(&keys_reader, &offsets_reader, 2, 3).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "read validation failed, offsets of key 3 at position 2 decrease from 20 to 15"
+        );
+        let err = validate_location(&keys_reader, &offsets_reader, 4, 5).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "internal offset/key length mismatch at key 5"
+        );
+        assert!(validate_location(&keys_reader, &offsets_reader, 5, 6).is_err());
     }
 }
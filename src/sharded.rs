@@ -0,0 +1,235 @@
+use std::collections::VecDeque;
+
+use anyhow::{anyhow, Context};
+
+use crate::{
+    open::create_dir_if_not_exists, Config, Iter, IterParams, ReadBuf, Reader, ReaderFactory,
+    TimestoreError, Writer, WriterFactory,
+};
+
+// A sharded store is a set of independent stores that each hold a range of the keys, see Config::shard_boundaries.
+// Nothing is kept about the shards apart from their directories, the boundaries come from the config.
+// The key ranges of the shards are disjoint and ordered, so merged reads go through the shards one after the other.
+
+/// Opens all shards of a sharded store, creating them if `Config::create_if_not_exists` is set.
+///
+/// The returned factories hold a factory of each shard and route reads and writes to the shard of the key.
+/// Writing to all shards from a single ShardedWriter runs the appends of the shards on one executor,
+/// to spread them over executors open each shard with `open` and `Config::shard_config` on its own executor instead.
+pub async fn open_sharded(
+    cfg: Config,
+) -> Result<(ShardedWriterFactory, ShardedReaderFactory), TimestoreError> {
+    let boundaries = cfg.shard_boundaries().to_vec();
+    if boundaries.is_empty() {
+        return Err(anyhow!("config has no shard boundaries").into());
+    }
+    if boundaries.windows(2).any(|pair| pair[0] >= pair[1]) {
+        return Err(anyhow!("shard boundaries {:?} are not increasing", boundaries).into());
+    }
+
+    if cfg.create_if_not_exists() {
+        create_dir_if_not_exists(cfg.path(), cfg.file_mode())
+            .context("create dir if not exists")?;
+    }
+
+    let mut writer_factories = Vec::with_capacity(boundaries.len() + 1);
+    let mut reader_factories = Vec::with_capacity(boundaries.len() + 1);
+    for shard in 0..=boundaries.len() {
+        let (writer_factory, reader_factory) = crate::open(cfg.shard_config(shard))
+            .await
+            .with_context(|| format!("open shard {}", shard))?;
+        writer_factories.push(writer_factory);
+        reader_factories.push(reader_factory);
+    }
+
+    Ok((
+        ShardedWriterFactory {
+            boundaries: boundaries.clone(),
+            factories: writer_factories,
+        },
+        ShardedReaderFactory {
+            boundaries,
+            factories: reader_factories,
+        },
+    ))
+}
+
+// Returns the index of the shard that holds the key.
+fn shard_of(boundaries: &[u64], key: u64) -> usize {
+    boundaries.partition_point(|&boundary| boundary <= key)
+}
+
+pub struct ShardedWriterFactory {
+    boundaries: Vec<u64>,
+    factories: Vec<WriterFactory>,
+}
+
+impl ShardedWriterFactory {
+    pub async fn make(self) -> Result<ShardedWriter, TimestoreError> {
+        let mut writers = Vec::with_capacity(self.factories.len());
+        for (shard, factory) in self.factories.into_iter().enumerate() {
+            writers.push(
+                factory
+                    .make()
+                    .await
+                    .with_context(|| format!("make writer of shard {}", shard))?,
+            );
+        }
+
+        Ok(ShardedWriter {
+            boundaries: self.boundaries,
+            writers,
+        })
+    }
+}
+
+/// Writer of a sharded store that appends each record to the shard of its key.
+/// Keys only have to be ordered within each shard, appends to different shards can be interleaved.
+pub struct ShardedWriter {
+    boundaries: Vec<u64>,
+    writers: Vec<Writer>,
+}
+
+impl ShardedWriter {
+    /// Returns the index of the shard the key is written to.
+    pub fn shard_of(&self, key: u64) -> usize {
+        shard_of(&self.boundaries, key)
+    }
+
+    pub fn writers(&self) -> &[Writer] {
+        &self.writers
+    }
+
+    pub fn into_writers(self) -> Vec<Writer> {
+        self.writers
+    }
+
+    /// Appends the record to the shard of its key and returns the position it was written at in that shard.
+    pub async fn append(&mut self, key: u64, values: Vec<Vec<u8>>) -> Result<u64, TimestoreError> {
+        let shard = self.shard_of(key);
+        self.writers[shard].append(key, values).await
+    }
+
+    /// Flushes the records that the writers of the shards hold for write combining, see `Writer::flush`.
+    pub async fn flush(&mut self) -> Result<(), TimestoreError> {
+        for (shard, writer) in self.writers.iter_mut().enumerate() {
+            writer
+                .flush()
+                .await
+                .with_context(|| format!("flush shard {}", shard))?;
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Clone)]
+pub struct ShardedReaderFactory {
+    boundaries: Vec<u64>,
+    factories: Vec<ReaderFactory>,
+}
+
+impl ShardedReaderFactory {
+    pub async fn make(&self) -> Result<ShardedReader, TimestoreError> {
+        let mut readers = Vec::with_capacity(self.factories.len());
+        for (shard, factory) in self.factories.iter().enumerate() {
+            readers.push(
+                factory
+                    .make()
+                    .await
+                    .with_context(|| format!("make reader of shard {}", shard))?,
+            );
+        }
+
+        Ok(ShardedReader {
+            boundaries: self.boundaries.clone(),
+            readers,
+        })
+    }
+}
+
+/// Reader of a sharded store that reads from the shard of each key and merges the shards in key order.
+#[derive(Clone)]
+pub struct ShardedReader {
+    boundaries: Vec<u64>,
+    readers: Vec<Reader>,
+}
+
+impl ShardedReader {
+    /// Returns the index of the shard that holds the key.
+    pub fn shard_of(&self, key: u64) -> usize {
+        shard_of(&self.boundaries, key)
+    }
+
+    pub fn readers(&self) -> &[Reader] {
+        &self.readers
+    }
+
+    /// Returns the keys of all shards in order.
+    pub fn keys(&self) -> impl Iterator<Item = u64> + '_ {
+        self.readers
+            .iter()
+            .flat_map(|reader| reader.keys().iter_from(0))
+    }
+
+    /// Reads the value of the key in the given table from the shard of the key, see `Reader::read`.
+    pub async fn read(&self, table: &str, key: u64) -> Result<Option<ReadBuf>, TimestoreError> {
+        self.readers[self.shard_of(key)].read(table, key).await
+    }
+
+    /// Returns an iterator over the records of all shards in the range of `params`, see `Reader::iter`.
+    /// The records are the ones a single store with the keys of all shards would return.
+    pub async fn iter(&self, params: IterParams<'_>) -> Result<ShardedIter, TimestoreError> {
+        let mut iters = VecDeque::with_capacity(self.readers.len());
+        for (shard, reader) in self.readers.iter().enumerate() {
+            if let Some(iter) = reader
+                .iter(params)
+                .await
+                .with_context(|| format!("iterate shard {}", shard))?
+            {
+                iters.push_back(iter);
+            }
+        }
+
+        Ok(ShardedIter {
+            iters,
+            last_key: None,
+            to: params.to(),
+        })
+    }
+}
+
+/// Iterator returned by `ShardedReader::iter`.
+pub struct ShardedIter {
+    iters: VecDeque<Iter>,
+    // Key of the last record that was returned, it starts the key range of the next record.
+    last_key: Option<u64>,
+    to: u64,
+}
+
+impl ShardedIter {
+    /// Moves to the next record and returns its key range and the value of the table that is selected in IterParams,
+    /// see `Iter::next`. The key range of the first record of a shard starts at the last key of the shard before it.
+    pub async fn next(&mut self) -> Result<Option<((u64, u64), Vec<u8>)>, TimestoreError> {
+        loop {
+            // The iterators of the shards stop at the end of their own keys, the range ends here.
+            if self.last_key.is_some_and(|last_key| last_key >= self.to) {
+                return Ok(None);
+            }
+            let iter = match self.iters.front_mut() {
+                Some(iter) => iter,
+                None => return Ok(None),
+            };
+            match iter.next().await? {
+                Some(((prev_key, key), buf)) => {
+                    let prev_key = self.last_key.unwrap_or(prev_key);
+                    self.last_key = Some(key);
+                    return Ok(Some(((prev_key, key), buf)));
+                }
+                None => {
+                    self.iters.pop_front();
+                }
+            }
+        }
+    }
+}
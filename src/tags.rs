@@ -0,0 +1,63 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, RwLock},
+};
+
+// Stores that are opened with tag_index enabled keep a "tags" file next to the keys file with the tag of each record,
+// at the same position as the key of the record. It is written and committed together with the keys so only
+// the first `length` tags are valid. Records that are appended without a tag have the tag 0.
+// The positions of the records of each tag are kept in memory so records can be found by tag without reading the file.
+
+/// Positions of the records of each tag. Shared between the writer and the readers of a store.
+#[derive(Clone, Default)]
+pub(crate) struct TagIndex {
+    positions: Arc<RwLock<HashMap<u64, Vec<usize>>>>,
+}
+
+impl TagIndex {
+    pub(crate) fn from_tags(tags: &[u64]) -> Self {
+        let index = Self::default();
+        for (pos, &tag) in tags.iter().enumerate() {
+            index.insert(tag, pos);
+        }
+        index
+    }
+
+    // Positions have to be inserted in increasing order.
+    pub(crate) fn insert(&self, tag: u64, pos: usize) {
+        self.positions
+            .write()
+            .unwrap()
+            .entry(tag)
+            .or_default()
+            .push(pos);
+    }
+
+    pub(crate) fn positions(&self, tag: u64) -> Vec<usize> {
+        self.positions
+            .read()
+            .unwrap()
+            .get(&tag)
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    // Returns a new index without the positions at and after len.
+    // The index is not modified so readers that use it keep seeing the old state like they do with the keys.
+    pub(crate) fn truncated(&self, len: usize) -> Self {
+        let positions = self
+            .positions
+            .read()
+            .unwrap()
+            .iter()
+            .filter_map(|(&tag, positions)| {
+                let end = positions.partition_point(|&pos| pos < len);
+                (end > 0).then(|| (tag, positions[..end].to_vec()))
+            })
+            .collect();
+
+        Self {
+            positions: Arc::new(RwLock::new(positions)),
+        }
+    }
+}
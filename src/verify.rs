@@ -0,0 +1,259 @@
+use std::path::Path;
+
+use anyhow::{anyhow, Context, Result};
+use futures::StreamExt;
+
+use crate::{
+    config::{DEFAULT_OPEN_BUFFER_SIZE, DEFAULT_OPEN_CONCURRENCY},
+    data_segments::DataSegments,
+    open::{file_size, read_file, read_length},
+    Config, Endianness, Reader, TimestoreError,
+};
+
+/// Problems found by `verify`. An empty list means the store is consistent.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct VerifyReport {
+    /// Committed length read from the length file, None if it couldn't be read.
+    pub length: Option<u64>,
+    pub problems: Vec<String>,
+}
+
+impl VerifyReport {
+    pub fn is_ok(&self) -> bool {
+        self.problems.is_empty()
+    }
+}
+
+/// Checks the files of a closed store without modifying them.
+/// Every problem found is added to the report instead of returning on the first one.
+/// Errors are only returned for failures that aren't about the store contents.
+pub async fn verify(cfg: &Config) -> Result<VerifyReport, TimestoreError> {
+    let dir = cfg.path();
+    let direct_io = cfg.direct_io();
+    let mut report = VerifyReport::default();
+
+    let length = match read_length(
+        dir,
+        DEFAULT_OPEN_BUFFER_SIZE,
+        DEFAULT_OPEN_CONCURRENCY,
+        direct_io,
+        cfg.endianness(),
+        cfg.length_source(),
+    )
+    .await
+    {
+        Ok(length) => length,
+        Err(e) => {
+            report
+                .problems
+                .push(format!("failed to read length file: {:#}", e));
+            return Ok(report);
+        }
+    };
+    report.length = Some(length);
+    let len = usize::try_from(length).unwrap();
+
+    // Files are written in aligned blocks so they can have padding or uncommitted entries after the committed length.
+    // Only the first `length` entries are checked.
+    let mut path = dir.to_owned();
+    path.push("keys");
+    match load_u64_prefix(&path, len, direct_io, cfg.endianness()).await {
+        Ok(Err(count)) => report.problems.push(format!(
+            "keys file has {} entries but length is {}",
+            count, length
+        )),
+        Ok(Ok(keys)) => {
+            if let Some(pos) = keys.windows(2).position(|w| w[0] >= w[1]) {
+                report.problems.push(format!(
+                    "keys are not strictly increasing at position {}. {} >= {}",
+                    pos + 1,
+                    keys[pos],
+                    keys[pos + 1]
+                ));
+            }
+        }
+        Err(e) => report
+            .problems
+            .push(format!("failed to read keys file: {:#}", e)),
+    }
+
+    let mut path = dir.to_owned();
+    path.push("tags");
+    if cfg.tag_index() && path.is_file() {
+        match load_u64_prefix(&path, len, direct_io, cfg.endianness()).await {
+            Ok(Err(count)) => report.problems.push(format!(
+                "tags file has {} entries but length is {}",
+                count, length
+            )),
+            Ok(Ok(_)) => (),
+            Err(e) => report
+                .problems
+                .push(format!("failed to read tags file: {:#}", e)),
+        }
+    }
+
+    for name in cfg.tables().iter() {
+        let path = cfg.table_layout().table_file(dir, name, "offsets");
+
+        let max_offset = match load_u64_prefix(&path, len, direct_io, cfg.endianness()).await {
+            Ok(Err(count)) => {
+                report.problems.push(format!(
+                    "offsets file of table '{}' has {} entries but length is {}",
+                    name, count, length
+                ));
+                continue;
+            }
+            Ok(Ok(offsets)) => {
+                if let Some(pos) = offsets.windows(2).position(|w| w[0] > w[1]) {
+                    report.problems.push(format!(
+                        "offsets of table '{}' are decreasing at position {}. {} > {}",
+                        name,
+                        pos + 1,
+                        offsets[pos],
+                        offsets[pos + 1]
+                    ));
+                }
+                offsets.iter().copied().max().unwrap_or(0)
+            }
+            Err(e) => {
+                report.problems.push(format!(
+                    "failed to read offsets file of table '{}': {:#}",
+                    name, e
+                ));
+                continue;
+            }
+        };
+
+        let segments = match DataSegments::load(
+            cfg.table_layout(),
+            dir,
+            name,
+            cfg.data_segment_size(),
+        )
+        .await
+        {
+            Ok(segments) => segments,
+            Err(e) => {
+                report.problems.push(format!(
+                    "failed to list data files of table '{}': {:#}",
+                    name, e
+                ));
+                continue;
+            }
+        };
+        // Only the data file that holds the end of the data can be short since records never span files.
+        let (_, start) = segments.locate(max_offset);
+        match file_size(&segments.path(start), direct_io).await {
+            Ok(size) if start + size < max_offset => report.problems.push(format!(
+                "data file for table '{}' is {} bytes but offsets require at least {}",
+                name,
+                size,
+                max_offset - start
+            )),
+            Ok(_) => (),
+            Err(e) => report.problems.push(format!(
+                "failed to read data file of table '{}': {:#}",
+                name, e
+            )),
+        }
+    }
+
+    Ok(report)
+}
+
+// Reads the first len values of a file of u64s.
+// Returns the number of values in the file as the inner error if there are less than len.
+async fn load_u64_prefix(
+    path: &Path,
+    len: usize,
+    direct_io: bool,
+    endianness: Endianness,
+) -> Result<std::result::Result<Vec<u64>, u64>> {
+    let size = file_size(path, direct_io).await?;
+    let count = size / 8;
+    if count < u64::try_from(len).unwrap() {
+        return Ok(Err(count));
+    }
+
+    let buf = read_file(
+        path,
+        len * 8,
+        DEFAULT_OPEN_BUFFER_SIZE,
+        DEFAULT_OPEN_CONCURRENCY,
+        direct_io,
+    )
+    .await?;
+
+    Ok(Ok(buf
+        .chunks_exact(8)
+        .map(|chunk| endianness.decode(chunk))
+        .collect()))
+}
+
+// Reads the value of each of the first length records of every table and checks that the read returns
+// the number of bytes given by the offsets. Used by open when Config::verify_on_open is set.
+pub(crate) async fn verify_records(reader: &Reader, length: u64, concurrency: usize) -> Result<()> {
+    let len = usize::try_from(length).unwrap();
+
+    for ((name, offsets), files) in reader
+        .table_names
+        .iter()
+        .zip(reader.table_offsets.iter())
+        .zip(reader.table_files.iter())
+    {
+        let starts = files.segments().starts();
+        let locations = std::iter::once(0)
+            .chain(offsets.iter_from(0))
+            .zip(offsets.iter_from(0).take(len))
+            .enumerate();
+
+        let mut reads = futures::stream::iter(locations.map(|(pos, (start, end))| {
+            let starts = &starts;
+            async move {
+                // Records never span data files, a read past the end of the file would return its padding.
+                let (idx, _) = files.segments().locate(start);
+                if let Some(&next_start) = starts.get(idx + 1) {
+                    if end > next_start {
+                        return Err(anyhow!(
+                            "record at position {} of table '{}' ends at {} which is past the end of its data file at {}",
+                            pos,
+                            name,
+                            end,
+                            next_start
+                        ));
+                    }
+                }
+
+                let expected = usize::try_from(end - start).unwrap();
+                if expected == 0 {
+                    return Ok(());
+                }
+                let (file, file_pos) = files.file_at(start).await?;
+                let buf = file.read_at(file_pos, expected).await.with_context(|| {
+                    format!("read record at position {} of table '{}'", pos, name)
+                })?;
+                if buf.len() != expected {
+                    return Err(anyhow!(
+                        "record at position {} of table '{}' has {} bytes but offsets require {}",
+                        pos,
+                        name,
+                        buf.len(),
+                        expected
+                    ));
+                }
+
+                Ok(())
+            }
+        }))
+        .buffered(concurrency);
+
+        let mut verified = 0;
+        while let Some(res) = reads.next().await {
+            res?;
+            verified += 1;
+            reader.observer.on_verify_progress(name, verified, length);
+        }
+    }
+
+    Ok(())
+}
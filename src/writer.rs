@@ -1,22 +1,123 @@
-use std::{path::PathBuf, rc::Rc};
+use std::{
+    cell::Cell,
+    path::{Path, PathBuf},
+    rc::Rc,
+    sync::Arc,
+    time::Instant,
+};
 
 use anyhow::{anyhow, Context, Result};
 use futures::AsyncWriteExt;
-use glommio::{
-    io::{DmaFile, ImmutableFileBuilder},
-    ByteSliceMutExt,
+use glommio::io::{Directory, OpenOptions};
+
+use crate::{
+    bloom::{BloomFilter, BLOCK_SIZE},
+    data_segments::DataSegments,
+    file::{io_error, set_file_mode, File, Sink},
+    lock::StoreLock,
+    maintenance::{delete_keys, insert_value},
+    open::{load_ordered_u64_file, load_u64_file, open_writer_files, read_length},
+    read_cache::CacheGeneration,
+    reader::{location_at, read_at, ReadBuf, SharedLength},
+    tags::TagIndex,
+    DuplicateKeyPolicy, Endianness, LengthSource, Observer, TableLayout, TimestoreError,
 };
 
 pub struct Writer {
     pub(crate) path: PathBuf,
     pub(crate) keys: caos::Writer<u64>,
-    pub(crate) keys_file: Rc<DmaFile>,
+    pub(crate) keys_reader: caos::Reader<u64>,
+    // Number of records in the in memory keys, shared with the readers that follow the writer.
+    pub(crate) shared_length: SharedLength,
+    pub(crate) keys_file: File,
     pub(crate) table_offsets: Vec<caos::Writer<u64>>,
-    pub(crate) table_offsets_files: Vec<Rc<DmaFile>>,
+    pub(crate) table_offsets_readers: Vec<caos::Reader<u64>>,
+    pub(crate) table_offsets_files: Vec<File>,
+    pub(crate) segment_length: usize,
+    pub(crate) table_segment_lengths: Vec<usize>,
     pub(crate) table_names: Vec<String>,
-    pub(crate) table_files: Vec<Rc<DmaFile>>,
+    // Last data file of each table, this is where new values are written.
+    pub(crate) table_files: Vec<File>,
+    pub(crate) table_segments: Vec<DataSegments>,
+    pub(crate) preallocate_bytes: u64,
+    pub(crate) max_value_bytes: Option<usize>,
+    pub(crate) max_read_bytes: usize,
+    // Buffer size and concurrency of reading the files when the writer reloads the store, see Config::open_buffer_size.
+    pub(crate) open_buffer_size: usize,
+    pub(crate) open_concurrency: usize,
+    pub(crate) write_combine_bytes: usize,
+    // Records that are held in memory until they are written together, see Config::write_combine_bytes.
+    pub(crate) combined: Vec<(u64, Vec<Vec<u8>>)>,
+    pub(crate) combined_bytes: usize,
+    pub(crate) on_duplicate_key: DuplicateKeyPolicy,
+    // Size that is preallocated for the last data file of each table.
+    pub(crate) reserved: Vec<u64>,
     pub(crate) write_offsets: Vec<u64>,
     pub(crate) length: u64,
+    pub(crate) observer: Observer,
+    pub(crate) bloom: Option<(Arc<BloomFilter>, File)>,
+    pub(crate) tags: Option<(TagIndex, File)>,
+    pub(crate) direct_io: bool,
+    pub(crate) file_mode: Option<u32>,
+    pub(crate) table_layout: TableLayout,
+    pub(crate) endianness: Endianness,
+    pub(crate) sync_dir: bool,
+    pub(crate) length_source: LengthSource,
+    // Bumped when records are replaced or removed so readers drop their cached values.
+    pub(crate) cache_generation: CacheGeneration,
+    // Instant the writer was made at and the nanoseconds since the store was created at that instant, see append_now.
+    pub(crate) clock: (Instant, u64),
+    // Exclusive lock of the store that is held while the writer is alive.
+    pub(crate) _lock: StoreLock,
+}
+
+// Puts the observer back into the writer when it is dropped, see Writer::append_with_stats.
+struct RestoreObserver<'a> {
+    writer: &'a mut Writer,
+    observer: Option<Observer>,
+}
+
+impl Drop for RestoreObserver<'_> {
+    fn drop(&mut self) {
+        if let Some(observer) = self.observer.take() {
+            self.writer.observer = observer;
+        }
+    }
+}
+
+// Records that are written to the files of the store but not committed yet.
+pub(crate) struct PreparedAppend {
+    keys: Vec<u64>,
+    // Total size of the values of each record.
+    bytes: Vec<usize>,
+    // Write offsets of the tables after each record.
+    record_offsets: Vec<Vec<u64>>,
+    new_length: u64,
+}
+
+/// Io of an append, returned by `Writer::append_with_stats`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct AppendStats {
+    /// Total size of the values.
+    pub logical_bytes: u64,
+    /// Bytes written to the files of the store, including the offsets, the key, the length commit
+    /// and the blocks that direct io writes are padded to.
+    pub physical_bytes: u64,
+    /// Number of file and directory syncs.
+    pub fsyncs: u64,
+}
+
+/// Result of `Writer::estimate_append`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AppendEstimate {
+    /// Position the record would be written at.
+    pub position: u64,
+    /// End offset of the data of each table after the append.
+    pub write_offsets: Vec<u64>,
+    /// Total size of the values.
+    pub bytes: u64,
+    /// True for the tables that would start a new data segment for the value.
+    pub new_segments: Vec<bool>,
 }
 
 // This order should ensure that we don't lose any data and the writes are completely atomic and serializable.
@@ -26,51 +127,344 @@ pub struct Writer {
 // Write order:
 // 1) write to the data files
 // 2) write to the table offset files
-// 3) write to the keys file
-// 4) create a new length file and rename it onto the old one
-// 5) update write offsets for future writes
-// 6) update length for future writes
-// 7) write the offsets into the in memory table_offsets
-// 8) write the key into in memory keys
+// 3) write to the tags file, set the bits of the key in the bloom filter and write them to the bloom file
+// 4) write to the keys file
+// 5) create a new length file and rename it onto the old one
+//    With LengthSource::KeysFileSize the length is the size of the keys file, so step 4 commits the record instead.
+//    This is why the keys file is written after the tags and bloom files.
+// 6) update write offsets for future writes
+// 7) update length for future writes
+// 8) write the offsets into the in memory table_offsets
+// 9) write the key into in memory keys and publish the new length to the readers
+// Note: setting bloom filter bits early is fine since the filter is advisory, extra bits can only cause false positives.
 
 impl Writer {
     pub fn table_names(&self) -> &[String] {
         &self.table_names
     }
 
-    pub async fn append(&mut self, key: u64, values: Vec<Vec<u8>>) -> Result<()> {
-        if values.len() != self.table_names.len() {
-            return Err(anyhow!(
-                "number of values ({}) does not equal the number of tables ({})",
-                values.len(),
-                self.table_names.len()
-            ));
+    /// Returns the directory of the store.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Returns the alignment that direct io requires for writes to the files of the store.
+    /// Writes that aren't aligned read the surrounding blocks first so they cost more.
+    /// This is 1 if direct io is disabled.
+    pub fn alignment(&self) -> u64 {
+        self.keys_file.alignment()
+    }
+
+    /// Returns the committed length of the store. Appends are durable when they return so all records before it are on disk.
+    /// Records that are held in memory by `Config::write_combine_bytes` aren't counted until they are flushed.
+    /// `ReaderFactory::make_as_of` makes a reader that sees exactly these records.
+    pub fn sync_point(&self) -> u64 {
+        self.length
+    }
+
+    /// Returns the offset in the data of the given table where the value of the next record will be written.
+    /// Offsets are global across the data files of the table if it is split into segments.
+    pub fn write_offset(&self, table: &str) -> Result<u64, TimestoreError> {
+        let pos = self
+            .table_names
+            .iter()
+            .position(|n| n == table)
+            .ok_or_else(|| TimestoreError::TableNotFound(table.to_owned()))?;
+        Ok(self.next_write_offsets()[pos])
+    }
+
+    /// Reads the value of the key in the given table like `Reader::read`, using the files and the in memory offsets of the writer.
+    /// Records that are held in memory by `Config::write_combine_bytes` aren't visible until they are flushed.
+    pub async fn read(&self, table: &str, key: u64) -> Result<Option<ReadBuf>, TimestoreError> {
+        let table_index = self
+            .table_names
+            .iter()
+            .position(|n| n == table)
+            .ok_or_else(|| TimestoreError::TableNotFound(table.to_owned()))?;
+
+        if let Some((bloom, _)) = &self.bloom {
+            if !bloom.may_contain(key) {
+                return Ok(None);
+            }
         }
+        let pos = match self.keys_reader.position(key) {
+            Some(pos) => pos,
+            None => return Ok(None),
+        };
+        let (offset, len) = location_at(&self.table_offsets_readers[table_index], pos, key)?;
 
-        let new_write_offsets = self
-            .write_offsets
+        // Only the last data file is kept open by the writer, values in earlier ones are read by opening their file.
+        let segments = &self.table_segments[table_index];
+        let (segment, start) = segments.locate(offset);
+        let buf = if segment + 1 == segments.starts().len() {
+            read_at(
+                &self.table_files[table_index],
+                offset - start,
+                len,
+                self.max_read_bytes,
+            )
+            .await?
+        } else {
+            let file = File::open_read(&segments.path(start), self.direct_io)
+                .await
+                .context("open data file")?;
+            let buf = read_at(&file, offset - start, len, self.max_read_bytes).await;
+            file.close().await.context("close data file")?;
+            buf?
+        };
+
+        self.observer.on_read(table, buf.len());
+
+        Ok(Some(buf))
+    }
+
+    // Returns the write offsets after the records that are held in memory for write combining.
+    fn next_write_offsets(&self) -> Vec<u64> {
+        let mut offsets = self.write_offsets.clone();
+        for (_, values) in self.combined.iter() {
+            for (offset, value) in offsets.iter_mut().zip(values.iter()) {
+                *offset += u64::try_from(value.len()).unwrap();
+            }
+        }
+        offsets
+    }
+
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            level = "debug",
+            skip_all,
+            fields(
+                key = key,
+                bytes = values.iter().map(|v| v.len()).sum::<usize>(),
+                tables = self.table_names.len(),
+            ),
+            ret,
+            err,
+        )
+    )]
+    /// Appends a record and returns the position it was written at.
+    /// If the key is equal to the last key, `Config::on_duplicate_key` decides what happens.
+    /// If `Config::write_combine_bytes` is set the record is only durable after it is flushed.
+    pub async fn append(&mut self, key: u64, values: Vec<Vec<u8>>) -> Result<u64, TimestoreError> {
+        self.append_record(key, 0, values).await
+    }
+
+    /// Appends a record like `append` and returns the position it was written at with the io the append did.
+    /// Records that are held in memory by `Config::write_combine_bytes` and skipped duplicates don't do any io,
+    /// the io of the records that are flushed by the append is counted.
+    pub async fn append_with_stats(
+        &mut self,
+        key: u64,
+        values: Vec<Vec<u8>>,
+    ) -> Result<(u64, AppendStats), TimestoreError> {
+        let stats = Rc::new(Cell::new(AppendStats {
+            logical_bytes: values.iter().map(|v| u64::try_from(v.len()).unwrap()).sum(),
+            ..AppendStats::default()
+        }));
+
+        // The guard swaps the original observer back even if the future is dropped before the append finishes.
+        let counting = self.observer.with_stats(stats.clone());
+        let guard = RestoreObserver {
+            observer: Some(std::mem::replace(&mut self.observer, counting)),
+            writer: self,
+        };
+        let res = guard.writer.append(key, values).await;
+        drop(guard);
+
+        Ok((res?, stats.get()))
+    }
+
+    /// Appends a record with an empty value in every table, so the keys can be registered first
+    /// and the values filled in later with `fill`.
+    pub async fn append_key_only(&mut self, key: u64) -> Result<(), TimestoreError> {
+        self.append(key, vec![Vec::new(); self.table_names.len()])
+            .await?;
+
+        Ok(())
+    }
+
+    /// Writes the value of a record that has an empty value in the given table, e.g. one that was appended with `append_key_only`.
+    ///
+    /// This is expensive, the data file of the table is rewritten with the value inserted at the record
+    /// and the offsets of the record and all records after it are rewritten to account for it.
+    /// The new files are renamed onto the old ones like the functions in `maintenance` do. The offsets file is
+    /// renamed first, so an interruption between the renames makes opening the store fail because the data file
+    /// is shorter than the offsets require.
+    ///
+    /// Each call costs O(store size) regardless of the order the values are filled in, the whole data file
+    /// of the table is copied and the writer reloads all keys and offsets afterwards.
+    ///
+    /// Readers that were created before this call keep seeing the old in memory state and the old files,
+    /// the store has to be reopened to get readers that see the new state.
+    /// This is not supported for stores with data segments.
+    pub async fn fill(&mut self, key: u64, table: &str, data: &[u8]) -> Result<(), TimestoreError> {
+        let table_index = self
+            .table_names
             .iter()
-            .zip(values.iter())
-            .map(|(&offset, val)| offset + u64::try_from(val.len()).unwrap())
-            .collect::<Vec<u64>>();
+            .position(|n| n == table)
+            .ok_or_else(|| TimestoreError::TableNotFound(table.to_owned()))?;
 
-        // 1) write the values to data files
-        let mut futs = Vec::with_capacity(self.table_names.len());
-        for ((file, &offset), value) in self
-            .table_files
+        self.flush().await?;
+
+        if self.table_segments[table_index].segment_size().is_some() {
+            return Err(anyhow!("fill is not supported for stores with data segments").into());
+        }
+        if let Some(max) = self.max_value_bytes {
+            if data.len() > max {
+                return Err(anyhow!(
+                    "value for table '{}' is {} bytes, exceeds max {}",
+                    table,
+                    data.len(),
+                    max
+                )
+                .into());
+            }
+        }
+
+        let pos = self
+            .keys_reader
+            .position(key)
+            .ok_or(TimestoreError::KeyNotFound(key))?;
+        let (_, len) = location_at(&self.table_offsets_readers[table_index], pos, key)?;
+        if len > 0 {
+            return Err(anyhow!(
+                "key {} already has a value of {} bytes in table '{}'",
+                key,
+                len,
+                table
+            )
+            .into());
+        }
+        if data.is_empty() {
+            return Ok(());
+        }
+
+        insert_value(
+            &self.path,
+            table,
+            self.length,
+            pos,
+            data,
+            self.table_layout,
+            self.file_mode,
+            self.direct_io,
+            self.endianness,
+        )
+        .await
+        .context("insert value into the data file")?;
+
+        self.reload().await.context("reload rewritten files")?;
+
+        Ok(())
+    }
+
+    /// Appends a record with the given tag and returns the position it was written at.
+    /// The store has to be opened with `Config::tag_index`, see `Reader::keys_for_tag`.
+    /// A duplicate key that is overwritten keeps the tag it had.
+    pub async fn append_with_tag(
+        &mut self,
+        key: u64,
+        tag: u64,
+        values: Vec<Vec<u8>>,
+    ) -> Result<u64, TimestoreError> {
+        if self.tags.is_none() {
+            return Err(anyhow!("can't append with a tag, tag_index is not enabled").into());
+        }
+
+        self.append_record(key, tag, values).await
+    }
+
+    /// Appends a record whose values are stored one after the other in `buf` and returns the position it was written at.
+    /// `lengths` holds the size of the value of each table in the order of the tables and has to add up to the size of `buf`.
+    /// This works like `append` but writes the slices of `buf` without copying them into a Vec per value.
+    pub async fn append_concat(
+        &mut self,
+        key: u64,
+        buf: &[u8],
+        lengths: &[usize],
+    ) -> Result<u64, TimestoreError> {
+        let total = lengths
             .iter()
-            .zip(self.write_offsets.iter())
-            .zip(values.into_iter())
-        {
-            let file = file.clone();
-            futs.push(async move { read_write_at(&file, &value, offset).await });
+            .try_fold(0usize, |total, &len| total.checked_add(len));
+        if total != Some(buf.len()) {
+            return Err(anyhow!(
+                "lengths don't add up to the size of the buffer ({} bytes)",
+                buf.len()
+            )
+            .into());
         }
-        futures::future::try_join_all(futs)
+
+        let mut values = Vec::with_capacity(lengths.len());
+        let mut rest = buf;
+        for &len in lengths.iter() {
+            let (value, tail) = rest.split_at(len);
+            values.push(value);
+            rest = tail;
+        }
+
+        self.append_record(key, 0, values).await
+    }
+
+    // Values are converted into owned Vecs only if they have to be held in memory or go through overwrite_last.
+    async fn append_record<V>(
+        &mut self,
+        key: u64,
+        tag: u64,
+        values: Vec<V>,
+    ) -> Result<u64, TimestoreError>
+    where
+        V: AsRef<[u8]> + Into<Vec<u8>>,
+    {
+        self.check_values(&values)?;
+
+        if self.write_combine_bytes > 0 {
+            let last_key = match self.combined.last() {
+                Some((last_key, _)) => Some(*last_key),
+                None => self.keys_reader.last(),
+            };
+            // Tags and duplicate keys are handled by the regular path after flushing the combined records.
+            if tag == 0 && last_key != Some(key) {
+                return self
+                    .combine(key, values.into_iter().map(Into::into).collect())
+                    .await;
+            }
+            self.flush().await?;
+        }
+
+        if self.keys_reader.last() == Some(key) {
+            match self.on_duplicate_key {
+                DuplicateKeyPolicy::Allow => (),
+                DuplicateKeyPolicy::Error => return Err(duplicate_key(key).into()),
+                DuplicateKeyPolicy::Skip => return Ok(self.length - 1),
+                DuplicateKeyPolicy::Overwrite => {
+                    self.overwrite_last(key, values.into_iter().map(Into::into).collect())
+                        .await?;
+                    return Ok(self.length - 1);
+                }
+            }
+        }
+
+        let pos = self.length;
+        let bytes = values.iter().map(|v| v.as_ref().len()).sum::<usize>();
+
+        let new_write_offsets = new_write_offsets(&self.table_names, &self.write_offsets, &values)?;
+        let new_length = self
+            .length
+            .checked_add(1)
+            .ok_or_else(|| anyhow!("length overflow"))?;
+        let offset_write_offset = self
+            .length
+            .checked_mul(8)
+            .ok_or_else(|| anyhow!("offset file position overflow"))?;
+
+        // 1) write the values to data files
+        let write_offsets = self.write_offsets.clone();
+        self.write_data(&write_offsets, std::slice::from_ref(&values))
             .await
             .context("write to table data files")?;
 
         // 2) write to the table offset files
-        let offset_write_offset = self.length * 8;
         let mut futs = Vec::with_capacity(self.table_names.len());
         for (file, &offset) in self
             .table_offsets_files
@@ -78,103 +472,1111 @@ impl Writer {
             .zip(new_write_offsets.iter())
         {
             let file = file.clone();
-            futs.push(async move {
-                read_write_at(&file, &offset.to_be_bytes(), offset_write_offset).await
-            });
+            let buf = self.endianness.encode(offset);
+            let observer = &self.observer;
+            futs.push(
+                async move { read_write_at(&file, &buf, offset_write_offset, observer).await },
+            );
         }
         futures::future::try_join_all(futs)
             .await
             .context("write to table offset files")?;
 
-        // 3) write to the keys file
-        read_write_at(&self.keys_file, &key.to_be_bytes(), offset_write_offset)
+        // 3) write to the tags file and set the bits of the key in the bloom filter and write them to the bloom file
+        if let Some((_, file)) = &self.tags {
+            read_write_at(
+                file,
+                &self.endianness.encode(tag),
+                offset_write_offset,
+                &self.observer,
+            )
             .await
-            .context("write to the keys file")?;
+            .context("write to the tags file")?;
+        }
+        if let Some((bloom, file)) = &self.bloom {
+            write_bloom(bloom, file, key, &self.observer)
+                .await
+                .context("write to the bloom file")?;
+        }
 
-        // 4) create a new length file and rename it onto the old one
-        let mut path = self.path.clone();
-        path.push("new_length");
-        glommio::io::remove(&path).await.ok();
-        let mut sink = ImmutableFileBuilder::new(&path)
-            .build_sink()
+        // 4) write to the keys file
+        read_write_at(
+            &self.keys_file,
+            &self.endianness.encode(key),
+            offset_write_offset,
+            &self.observer,
+        )
+        .await
+        .context("write to the keys file")?;
+
+        // 5) create a new length file and rename it onto the old one
+        self.commit(new_length).await.context("commit new length")?;
+
+        // 6) update write offsets for future writes
+        self.write_offsets = new_write_offsets;
+
+        // 7) update length for future writes
+        self.length = new_length;
+
+        // 8) write the offsets into the in memory table_offsets
+        for (offsets, &offset) in self.table_offsets.iter_mut().zip(self.write_offsets.iter()) {
+            offsets.append(&[offset]);
+        }
+
+        // 9) write the key into in memory keys and the tag index
+        self.keys.append(&[key]);
+        self.shared_length.set(new_length);
+        if let Some((tags, _)) = &self.tags {
+            tags.insert(tag, usize::try_from(pos).unwrap());
+        }
+
+        self.observer.on_append(key, bytes);
+
+        Ok(pos)
+    }
+
+    // Holds the record in memory and flushes the combined records if they reached write_combine_bytes.
+    async fn combine(&mut self, key: u64, values: Vec<Vec<u8>>) -> Result<u64, TimestoreError> {
+        let pos = self.length + u64::try_from(self.combined.len()).unwrap();
+
+        // Each record takes an offset per table and a key on top of its values.
+        self.combined_bytes += values.iter().map(|v| v.len() + 8).sum::<usize>() + 8;
+        self.combined.push((key, values));
+
+        if self.combined_bytes >= self.write_combine_bytes {
+            self.flush().await?;
+        }
+
+        Ok(pos)
+    }
+
+    /// Appends the records that are held in memory because of `Config::write_combine_bytes` and waits until they are durable.
+    /// Their data, offsets and keys are written with a single write per file and their length is committed once.
+    /// Does nothing if there are no such records. The records are dropped if this returns an error.
+    pub async fn flush(&mut self) -> Result<(), TimestoreError> {
+        if self.combined.is_empty() {
+            return Ok(());
+        }
+
+        let records = std::mem::take(&mut self.combined);
+        self.combined_bytes = 0;
+
+        let prepared = self.prepare_append(records).await?;
+        self.commit_prepared(prepared).await?;
+
+        Ok(())
+    }
+
+    /// Appends a record with the key after the last key of the store and returns the key.
+    /// The first record of an empty store gets the key 0.
+    pub async fn append_auto(&mut self, values: Vec<Vec<u8>>) -> Result<u64, TimestoreError> {
+        let key = match self.last_key() {
+            Some(last) => last.checked_add(1).ok_or_else(|| anyhow!("key overflow"))?,
+            None => 0,
+        };
+
+        self.append(key, values).await?;
+
+        Ok(key)
+    }
+
+    /// Appends a record with the current time as the key and returns the key.
+    /// The time is in nanoseconds since the store was created, see `StoreInfo::created_at`.
+    /// It is read from a monotonic clock so changes to the wall clock don't affect it while the writer is alive.
+    /// The key is one after the last key of the store instead if the clock didn't advance past it.
+    pub async fn append_now(&mut self, values: Vec<Vec<u8>>) -> Result<u64, TimestoreError> {
+        let (start, start_nanos) = self.clock;
+        let elapsed = u64::try_from(start.elapsed().as_nanos()).unwrap_or(u64::MAX);
+        let now = start_nanos.saturating_add(elapsed);
+        let key = match self.last_key() {
+            Some(last) if last >= now => {
+                last.checked_add(1).ok_or_else(|| anyhow!("key overflow"))?
+            }
+            _ => now,
+        };
+
+        self.append(key, values).await?;
+
+        Ok(key)
+    }
+
+    /// Appends the record only if the store doesn't have the key yet, so records that are replayed are skipped.
+    /// Returns false without writing anything if the key exists, regardless of `Config::on_duplicate_key`.
+    /// Records that are held in memory by `Config::write_combine_bytes` count as existing.
+    pub async fn append_if_absent(
+        &mut self,
+        key: u64,
+        values: Vec<Vec<u8>>,
+    ) -> Result<bool, TimestoreError> {
+        let combined = self.combined.iter().any(|(k, _)| *k == key);
+        if combined || self.keys_reader.position(key).is_some() {
+            return Ok(false);
+        }
+
+        self.append(key, values).await?;
+
+        Ok(true)
+    }
+
+    // Returns the last key of the store including the records that are held by write combining.
+    pub(crate) fn last_key(&self) -> Option<u64> {
+        match self.combined.last() {
+            Some((last_key, _)) => Some(*last_key),
+            None => self.keys_reader.last(),
+        }
+    }
+
+    /// Returns what appending the given values would do without writing anything.
+    /// Errors the same way `append` would if the values can't be appended.
+    pub fn estimate_append(&self, values: &[Vec<u8>]) -> Result<AppendEstimate, TimestoreError> {
+        self.check_values(values)?;
+
+        let start_offsets = self.next_write_offsets();
+        let write_offsets = new_write_offsets(&self.table_names, &start_offsets, values)?;
+        let new_segments = self
+            .table_segments
+            .iter()
+            .zip(start_offsets.iter())
+            .zip(values.iter())
+            .map(|((segments, &offset), value)| {
+                segments.should_roll(offset, u64::try_from(value.len()).unwrap())
+            })
+            .collect();
+
+        Ok(AppendEstimate {
+            position: self.length + u64::try_from(self.combined.len()).unwrap(),
+            write_offsets,
+            bytes: values.iter().map(|v| u64::try_from(v.len()).unwrap()).sum(),
+            new_segments,
+        })
+    }
+
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            level = "debug",
+            skip_all,
+            fields(records = records.len(), tables = self.table_names.len()),
+            ret,
+            err,
+        )
+    )]
+    /// Appends the records in the given order and returns the position the first one was written at.
+    ///
+    /// The files are written in the same order as `append` but the length is committed once for all records,
+    /// so either all of the records are visible after a crash or none of them are.
+    ///
+    /// `Config::on_duplicate_key` is applied to each record that has the same key as the record before it.
+    /// Overwriting the last record that is already in the store is committed separately before the other records.
+    pub async fn append_many(
+        &mut self,
+        records: Vec<(u64, Vec<Vec<u8>>)>,
+    ) -> Result<u64, TimestoreError> {
+        let length = self.length;
+        let positions = self.append_many_positions(records).await?;
+
+        Ok(positions.first().copied().unwrap_or(length))
+    }
+
+    // Same as append_many but returns the position of each record.
+    // Records that are skipped or overwrite the record before them get the position of that record.
+    pub(crate) async fn append_many_positions(
+        &mut self,
+        records: Vec<(u64, Vec<Vec<u8>>)>,
+    ) -> Result<Vec<u64>, TimestoreError> {
+        for (_, values) in records.iter() {
+            self.check_values(values)?;
+        }
+
+        self.flush().await?;
+
+        let mut positions = Vec::with_capacity(records.len());
+        let mut prev_key = self.keys_reader.last();
+        let mut overwrite_last = None;
+        let mut new_records: Vec<(u64, Vec<Vec<u8>>)> = Vec::with_capacity(records.len());
+        for (key, values) in records.into_iter() {
+            if prev_key == Some(key) {
+                match self.on_duplicate_key {
+                    DuplicateKeyPolicy::Allow => (),
+                    DuplicateKeyPolicy::Error => return Err(duplicate_key(key).into()),
+                    DuplicateKeyPolicy::Skip => {
+                        positions.push(self.length + new_records.len() as u64 - 1);
+                        continue;
+                    }
+                    DuplicateKeyPolicy::Overwrite => {
+                        match new_records.last_mut() {
+                            Some(record) => record.1 = values,
+                            // duplicate of the last record in the store
+                            None => overwrite_last = Some((key, values)),
+                        }
+                        positions.push(self.length + new_records.len() as u64 - 1);
+                        continue;
+                    }
+                }
+            }
+
+            prev_key = Some(key);
+            positions.push(self.length + new_records.len() as u64);
+            new_records.push((key, values));
+        }
+
+        if let Some((key, values)) = overwrite_last {
+            self.overwrite_last(key, values).await?;
+        }
+
+        if new_records.is_empty() {
+            return Ok(positions);
+        }
+
+        let prepared = self.prepare_append(new_records).await?;
+        self.commit_prepared(prepared).await?;
+
+        Ok(positions)
+    }
+
+    // Does steps 1 to 4 of appending the records, they aren't visible until commit_prepared is called.
+    // If this isn't followed by commit_prepared, the next append overwrites the written data.
+    // With LengthSource::KeysFileSize the records are committed on disk by step 4, reopening the store shows them.
+    pub(crate) async fn prepare_append(
+        &mut self,
+        records: Vec<(u64, Vec<Vec<u8>>)>,
+    ) -> Result<PreparedAppend> {
+        for (_, values) in records.iter() {
+            check_table_count(values.len(), self.table_names.len())?;
+        }
+
+        let mut record_offsets = Vec::with_capacity(records.len());
+        let mut write_offsets = self.write_offsets.clone();
+        for (_, values) in records.iter() {
+            write_offsets = new_write_offsets(&self.table_names, &write_offsets, values)?;
+            record_offsets.push(write_offsets.clone());
+        }
+        let new_length = self
+            .length
+            .checked_add(u64::try_from(records.len()).unwrap())
+            .ok_or_else(|| anyhow!("length overflow"))?;
+        let offset_write_offset = self
+            .length
+            .checked_mul(8)
+            .ok_or_else(|| anyhow!("offset file position overflow"))?;
+
+        let (keys, rows): (Vec<u64>, Vec<Vec<Vec<u8>>>) = records.into_iter().unzip();
+
+        // 1) write the values to data files
+        let start_offsets = self.write_offsets.clone();
+        self.write_data(&start_offsets, &rows)
             .await
-            .map_err(|e| anyhow!("{}", e))
-            .context("build new length file")?;
-        sink.write_all(&(self.length + 1).to_be_bytes())
+            .context("write to table data files")?;
+
+        // 2) write to the table offset files
+        let mut futs = Vec::with_capacity(self.table_names.len());
+        for (i, file) in self.table_offsets_files.iter().enumerate() {
+            let buf = record_offsets
+                .iter()
+                .flat_map(|offsets| self.endianness.encode(offsets[i]))
+                .collect::<Vec<u8>>();
+            let file = file.clone();
+            let observer = &self.observer;
+            futs.push(
+                async move { read_write_at(&file, &buf, offset_write_offset, observer).await },
+            );
+        }
+        futures::future::try_join_all(futs)
             .await
-            .context("write to new length file")?;
-        sink.sync()
+            .context("write to table offset files")?;
+
+        // 3) write to the tags file, these records have the tag 0,
+        // and set the bits of the keys in the bloom filter and write them to the bloom file
+        if let Some((_, file)) = &self.tags {
+            read_write_at(
+                file,
+                &vec![0; keys.len() * 8],
+                offset_write_offset,
+                &self.observer,
+            )
             .await
-            .map_err(|e| anyhow!("{}", e))
-            .context("sync new length file to disk")?;
-        sink.close()
+            .context("write to the tags file")?;
+        }
+        if let Some((bloom, file)) = &self.bloom {
+            for &key in keys.iter() {
+                write_bloom(bloom, file, key, &self.observer)
+                    .await
+                    .context("write to the bloom file")?;
+            }
+        }
+
+        // 4) write to the keys file
+        read_write_at(
+            &self.keys_file,
+            &keys
+                .iter()
+                .flat_map(|&key| self.endianness.encode(key))
+                .collect::<Vec<u8>>(),
+            offset_write_offset,
+            &self.observer,
+        )
+        .await
+        .context("write to the keys file")?;
+
+        Ok(PreparedAppend {
+            keys,
+            bytes: rows
+                .iter()
+                .map(|values| values.iter().map(|v| v.len()).sum::<usize>())
+                .collect(),
+            record_offsets,
+            new_length,
+        })
+    }
+
+    // Does steps 5 to 9 of appending the prepared records and returns the position of the first one.
+    pub(crate) async fn commit_prepared(&mut self, prepared: PreparedAppend) -> Result<u64> {
+        let PreparedAppend {
+            keys,
+            bytes,
+            record_offsets,
+            new_length,
+        } = prepared;
+        let pos = self.length;
+
+        // 5) create a new length file and rename it onto the old one
+        self.commit(new_length).await.context("commit new length")?;
+
+        // 6) update write offsets for future writes
+        self.write_offsets = record_offsets.last().unwrap().clone();
+
+        // 7) update length for future writes
+        self.length = new_length;
+
+        // 8) write the offsets into the in memory table_offsets
+        for (i, offsets) in self.table_offsets.iter_mut().enumerate() {
+            offsets.append(
+                &record_offsets
+                    .iter()
+                    .map(|offsets| offsets[i])
+                    .collect::<Vec<u64>>(),
+            );
+        }
+
+        // 9) write the keys into in memory keys and the tag index
+        self.keys.append(&keys);
+        self.shared_length.set(new_length);
+        if let Some((tags, _)) = &self.tags {
+            let pos = usize::try_from(pos).unwrap();
+            for i in 0..keys.len() {
+                tags.insert(0, pos + i);
+            }
+        }
+
+        for (&key, bytes) in keys.iter().zip(bytes) {
+            self.observer.on_append(key, bytes);
+        }
+
+        Ok(pos)
+    }
+
+    /// Removes all records with keys greater than the given key and returns the number of removed records.
+    ///
+    /// Readers that were created before this call keep seeing the old in memory state,
+    /// the store has to be reopened to get readers that see the truncated state.
+    pub async fn truncate_after(&mut self, key: u64) -> Result<u64, TimestoreError> {
+        self.flush().await?;
+
+        let new_len = match self.keys_reader.next_position(key) {
+            Some(pos) => pos,
+            None => return Ok(0),
+        };
+
+        Ok(self.truncate_to(new_len).await?)
+    }
+
+    // Removes the records at and after the given position and returns the number of removed records.
+    pub(crate) async fn truncate_to(&mut self, new_len: usize) -> Result<u64> {
+        let new_length = u64::try_from(new_len).unwrap();
+
+        let new_write_offsets = self
+            .table_offsets_readers
+            .iter()
+            .map(|offsets| {
+                if new_len == 0 {
+                    0
+                } else {
+                    offsets.iter_from(new_len - 1).next().unwrap()
+                }
+            })
+            .collect::<Vec<u64>>();
+
+        // Committing the length first makes the truncation atomic.
+        // Open ignores anything that is beyond the length so a crash after this point leaves a consistent store.
+        self.commit(new_length).await.context("commit new length")?;
+
+        self.truncate_data(&new_write_offsets)
             .await
-            .map_err(|e| anyhow!("{}", e))
-            .context("close new length file")?;
-        let mut final_path = self.path.clone();
-        final_path.push("length");
-        glommio::io::rename(&path, &final_path)
+            .context("truncate data files")?;
+        for file in self.table_offsets_files.iter() {
+            truncate_file(file, new_length * 8, &self.observer)
+                .await
+                .context("truncate offsets file")?;
+        }
+        truncate_file(&self.keys_file, new_length * 8, &self.observer)
             .await
-            .map_err(|e| anyhow!("{}", e))
-            .context("rename length file")?;
+            .context("truncate keys file")?;
+        if let Some((tags, file)) = &mut self.tags {
+            truncate_file(file, new_length * 8, &self.observer)
+                .await
+                .context("truncate tags file")?;
+            *tags = tags.truncated(new_len);
+        }
+
+        let (mut keys, keys_reader) = caos::new::<u64>(self.segment_length);
+        keys.append(
+            &self
+                .keys_reader
+                .iter_from(0)
+                .take(new_len)
+                .collect::<Vec<u64>>(),
+        );
+
+        let (table_offsets, table_offsets_readers) = self.rebuild_table_offsets(new_len);
 
-        // 5) update write offsets for future writes
+        let removed = self.length - new_length;
+
+        self.keys = keys;
+        self.keys_reader = keys_reader;
+        self.shared_length = SharedLength::new(new_length);
+        self.table_offsets = table_offsets;
+        self.table_offsets_readers = table_offsets_readers;
         self.write_offsets = new_write_offsets;
+        self.length = new_length;
+        self.cache_generation.bump();
 
-        // 6) update length for future writes
-        self.length += 1;
+        Ok(removed)
+    }
 
-        // 7) write the offsets into the in memory table_offsets
-        for (offsets, &offset) in self.table_offsets.iter_mut().zip(self.write_offsets.iter()) {
-            offsets.append(&[offset]);
+    /// Replaces the values of the last record. The key has to be equal to the last key in the store.
+    ///
+    /// The length is first committed without the last record so a crash in the middle of this call
+    /// leaves the store either without the last record or with the new values.
+    /// If this returns an error after the first commit, the store has to be reopened before writing to it again.
+    /// If the sizes of any of the values change, readers that were created before this call keep seeing the old offsets,
+    /// the store has to be reopened to get readers that see the new state.
+    pub async fn overwrite_last(
+        &mut self,
+        key: u64,
+        values: Vec<Vec<u8>>,
+    ) -> Result<(), TimestoreError> {
+        self.check_values(&values)?;
+        self.flush().await?;
+
+        let last_key = self
+            .keys_reader
+            .last()
+            .ok_or_else(|| anyhow!("can't overwrite the last record of an empty store"))?;
+        if key != last_key {
+            return Err(anyhow!("key {} does not equal the last key {}", key, last_key).into());
         }
 
-        // 8) write the key into in memory keys
-        self.keys.append(&[key]);
+        let last_len = usize::try_from(self.length - 1).unwrap();
+        let start_offsets = self
+            .table_offsets_readers
+            .iter()
+            .map(|offsets| {
+                if last_len == 0 {
+                    0
+                } else {
+                    offsets.iter_from(last_len - 1).next().unwrap()
+                }
+            })
+            .collect::<Vec<u64>>();
+        let new_write_offsets = new_write_offsets(&self.table_names, &start_offsets, &values)?;
+        let offsets_changed = new_write_offsets != self.write_offsets;
+
+        // 1) commit the length without the last record
+        self.commit(self.length - 1)
+            .await
+            .context("commit length without the last record")?;
+
+        // 2) truncate the data files to the start of the last record and write the new values like append does
+        self.truncate_data(&start_offsets)
+            .await
+            .context("truncate data files")?;
+        self.write_data(&start_offsets, std::slice::from_ref(&values))
+            .await
+            .context("write to table data files")?;
+
+        // 3) write to the table offset files
+        if offsets_changed {
+            let offset_write_offset = (self.length - 1) * 8;
+            let mut futs = Vec::with_capacity(self.table_names.len());
+            for (file, &offset) in self
+                .table_offsets_files
+                .iter()
+                .zip(new_write_offsets.iter())
+            {
+                let file = file.clone();
+                let buf = self.endianness.encode(offset);
+                let observer = &self.observer;
+                futs.push(async move {
+                    read_write_at(&file, &buf, offset_write_offset, observer).await
+                });
+            }
+            futures::future::try_join_all(futs)
+                .await
+                .context("write to table offset files")?;
+        }
+
+        // The keys file lost the last key with the first commit if the length comes from its size.
+        if self.length_source == LengthSource::KeysFileSize {
+            read_write_at(
+                &self.keys_file,
+                &self.endianness.encode(key),
+                (self.length - 1) * 8,
+                &self.observer,
+            )
+            .await
+            .context("write to the keys file")?;
+        }
+
+        // 4) commit the length with the last record again
+        self.commit(self.length)
+            .await
+            .context("commit length with the new last record")?;
+
+        // 5) update the in memory offsets, they are append only so they have to be rebuilt
+        if offsets_changed {
+            let (mut table_offsets, table_offsets_readers) = self.rebuild_table_offsets(last_len);
+            for (offsets, &offset) in table_offsets.iter_mut().zip(new_write_offsets.iter()) {
+                offsets.append(&[offset]);
+            }
+
+            self.table_offsets = table_offsets;
+            self.table_offsets_readers = table_offsets_readers;
+            self.write_offsets = new_write_offsets;
+        }
+
+        // 6) drop the old values from the read caches of the readers
+        self.cache_generation.bump();
 
         Ok(())
     }
-}
 
-// Utility function for direct_io write.
-// Since we need to write a multiple of block size we might need to read some remainder data
-// and combine it with our write.
-async fn read_write_at(file: &DmaFile, data: &[u8], pos: u64) -> Result<()> {
-    let write_pos = file.align_down(pos);
-    assert!(write_pos <= pos);
+    /// Flushes the records that are held for write combining, then syncs and closes the files of the writer.
+    /// Other appends are already durable when they return so this only releases the file handles.
+    pub async fn close(mut self) -> Result<(), TimestoreError> {
+        self.flush().await?;
 
-    let extra_read_size = usize::try_from(pos - write_pos).unwrap();
+        let files = std::iter::once(self.keys_file)
+            .chain(self.table_files)
+            .chain(self.table_offsets_files)
+            .chain(self.bloom.map(|(_, file)| file))
+            .chain(self.tags.map(|(_, file)| file));
+        for file in files {
+            sync_file(&file, &self.observer).await?;
+            file.close().await.context("close file")?;
+        }
 
-    let bufsize = extra_read_size + data.len() + 8;
-    let mut buf = file.alloc_dma_buffer(
-        file.align_up(bufsize.try_into().unwrap())
-            .try_into()
-            .unwrap(),
-    );
+        Ok(())
+    }
+
+    // Checks the number of values and their sizes.
+    pub(crate) fn check_values<V: AsRef<[u8]>>(&self, values: &[V]) -> Result<()> {
+        check_table_count(values.len(), self.table_names.len())?;
 
-    if extra_read_size > 0 {
-        let read_buf = file
-            .read_at(write_pos, extra_read_size)
+        if let Some(max) = self.max_value_bytes {
+            for (name, value) in self.table_names.iter().zip(values.iter()) {
+                let value = value.as_ref();
+                if value.len() > max {
+                    return Err(anyhow!(
+                        "value for table '{}' is {} bytes, exceeds max {}",
+                        name,
+                        value.len(),
+                        max
+                    ));
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    // Writes the values of the given rows one after the other, starting at the given offset for each table.
+    // A new data segment is started for each value that doesn't fit into the current segment of its table.
+    // Values that go into the same file are written with a single write.
+    async fn write_data<V: AsRef<[u8]>>(&mut self, offsets: &[u64], rows: &[Vec<V>]) -> Result<()> {
+        let mut writes = Vec::new();
+        for (i, &offset) in offsets.iter().enumerate() {
+            let mut offset = offset;
+            let mut chunk_start = offset;
+            let mut chunk = Vec::new();
+            for row in rows.iter() {
+                let value = row[i].as_ref();
+                let len = u64::try_from(value.len()).unwrap();
+                let segments = &self.table_segments[i];
+                if segments.should_roll(offset, len) {
+                    let (_, start) = segments.locate(chunk_start);
+                    writes.push((
+                        self.table_files[i].clone(),
+                        chunk_start - start,
+                        std::mem::take(&mut chunk),
+                    ));
+
+                    let file = File::open(
+                        &segments.path(offset),
+                        OpenOptions::new().create(true).read(true).write(true),
+                        self.direct_io,
+                    )
+                    .await
+                    .context("create data segment")?;
+                    set_file_mode(&segments.path(offset), self.file_mode)?;
+                    segments.push(offset);
+                    self.table_files[i] = file;
+                    self.reserved[i] = 0;
+                    chunk_start = offset;
+                }
+
+                chunk.extend_from_slice(value);
+                offset += len;
+            }
+
+            let (_, start) = self.table_segments[i].locate(chunk_start);
+            writes.push((self.table_files[i].clone(), chunk_start - start, chunk));
+            self.reserve(i, offset - start)
+                .await
+                .context("preallocate data file")?;
+        }
+
+        let observer = &self.observer;
+        futures::future::try_join_all(writes.into_iter().map(|(file, pos, buf)| async move {
+            read_write_at(&file, &buf, pos, observer).await
+        }))
+        .await?;
+
+        Ok(())
+    }
+
+    // Preallocates the last data file of the table in multiples of preallocate_bytes so there is space after the given size.
+    pub(crate) async fn reserve(&mut self, table: usize, size: u64) -> Result<()> {
+        let chunk = self.preallocate_bytes;
+        if chunk == 0 || size < self.reserved[table] {
+            return Ok(());
+        }
+
+        let reserved = (size / chunk + 1)
+            .checked_mul(chunk)
+            .ok_or_else(|| anyhow!("preallocation size overflow"))?;
+        self.table_files[table]
+            .pre_allocate(reserved, true)
             .await
-            .map_err(|e| anyhow!("{}", e))
-            .context("read extra data for alignment")?;
+            .context("preallocate file")?;
+        self.reserved[table] = reserved;
+
+        Ok(())
+    }
 
-        if read_buf.len() != extra_read_size {
-            return Err(anyhow!("failed to read extra data, size mismatch"));
+    // Truncates the data of each table to the given offset and removes the data segments that start after it.
+    // This has to be called after the new length is committed so open doesn't see offsets that point to removed data.
+    async fn truncate_data(&mut self, offsets: &[u64]) -> Result<()> {
+        for (i, &offset) in offsets.iter().enumerate() {
+            let segments = &self.table_segments[i];
+            let removed = segments.truncate(offset);
+            let (_, start) = segments.locate(offset);
+            if !removed.is_empty() {
+                let file = File::open(
+                    &segments.path(start),
+                    OpenOptions::new().read(true).write(true),
+                    self.direct_io,
+                )
+                .await
+                .context("open data segment")?;
+                self.table_files[i] = file;
+            }
+
+            truncate_file(&self.table_files[i], offset - start, &self.observer)
+                .await
+                .context("truncate data file")?;
+            // Truncating frees the preallocated space after the new end.
+            self.reserved[i] = 0;
+
+            for start in removed {
+                glommio::io::remove(segments.path(start))
+                    .await
+                    .map_err(io_error)
+                    .context("remove data segment")?;
+            }
         }
 
-        buf.write_at(0, &*read_buf);
+        Ok(())
     }
 
-    buf.write_at(extra_read_size, data);
-    file.write_at(buf, write_pos)
+    // Builds new in memory table offsets that contain the first len offsets of the current ones.
+    fn rebuild_table_offsets(
+        &self,
+        len: usize,
+    ) -> (Vec<caos::Writer<u64>>, Vec<caos::Reader<u64>>) {
+        let mut table_offsets = Vec::with_capacity(self.table_names.len());
+        let mut table_offsets_readers = Vec::with_capacity(self.table_names.len());
+        for (old_offsets, &segment_length) in self
+            .table_offsets_readers
+            .iter()
+            .zip(self.table_segment_lengths.iter())
+        {
+            let (mut offsets, offsets_reader) = caos::new::<u64>(segment_length);
+            offsets.append(&old_offsets.iter_from(0).take(len).collect::<Vec<u64>>());
+            table_offsets.push(offsets);
+            table_offsets_readers.push(offsets_reader);
+        }
+
+        (table_offsets, table_offsets_readers)
+    }
+
+    /// Removes all records with keys less than the given key and returns the number of removed records.
+    /// The data files are rewritten so the first remaining record starts at offset 0.
+    ///
+    /// Readers that were created before this call keep seeing the old in memory state and the old files,
+    /// the store has to be reopened to get readers that see the new state.
+    /// This is not supported for stores with data segments.
+    pub async fn expire_before(&mut self, key: u64) -> Result<u64, TimestoreError> {
+        self.flush().await?;
+
+        if self
+            .table_segments
+            .iter()
+            .any(|s| s.segment_size().is_some())
+        {
+            return Err(
+                anyhow!("expire_before is not supported for stores with data segments").into(),
+            );
+        }
+
+        let removed = delete_keys(
+            &self.path,
+            &self.table_names,
+            0..key,
+            self.table_layout,
+            self.file_mode,
+            self.direct_io,
+            self.endianness,
+            self.length_source,
+        )
         .await
-        .map_err(|e| anyhow!("{}", e))
-        .context("failed to write data")?;
-    file.fdatasync()
+        .context("delete expired records")?;
+
+        if removed > 0 {
+            self.reload().await.context("reload rewritten files")?;
+        }
+
+        Ok(removed)
+    }
+
+    // Commits the new length, see step 5 of the write order.
+    // With LengthSource::KeysFileSize the keys that were written in step 4 committed the new records already,
+    // so only a shorter length has to be committed, by truncating the keys file.
+    async fn commit(&self, new_length: u64) -> Result<()> {
+        match self.length_source {
+            LengthSource::LengthFile => {
+                commit_length(
+                    &self.path,
+                    new_length,
+                    &self.observer,
+                    self.file_mode,
+                    self.direct_io,
+                    self.endianness,
+                    self.sync_dir,
+                )
+                .await
+            }
+            LengthSource::KeysFileSize => {
+                if new_length < self.length {
+                    truncate_file(&self.keys_file, new_length * 8, &self.observer)
+                        .await
+                        .context("truncate keys file")?;
+                }
+                Ok(())
+            }
+        }
+    }
+
+    // Reopens the files and rebuilds the in memory state after the files of the store were rewritten.
+    async fn reload(&mut self) -> Result<()> {
+        let length = read_length(
+            &self.path,
+            self.open_buffer_size,
+            self.open_concurrency,
+            self.direct_io,
+            self.endianness,
+            self.length_source,
+        )
+        .await?;
+        let len = usize::try_from(length).unwrap();
+
+        let (keys_file, table_files, table_offsets_files) = open_writer_files(
+            &self.path,
+            &self.table_names,
+            &self.table_segments,
+            self.table_layout,
+            self.direct_io,
+        )
+        .await?;
+
+        let mut path = self.path.clone();
+        path.push("keys");
+        let vals = load_ordered_u64_file(
+            &path,
+            len,
+            self.open_buffer_size,
+            self.open_concurrency,
+            self.direct_io,
+            self.endianness,
+        )
         .await
-        .map_err(|e| anyhow!("{}", e))
-        .context("fdatasync file")?;
+        .context("read keys file")?;
+        let (mut keys, keys_reader) = caos::new::<u64>(self.segment_length);
+        keys.append(&vals);
+
+        let mut table_offsets = Vec::with_capacity(self.table_names.len());
+        let mut table_offsets_readers = Vec::with_capacity(self.table_names.len());
+        let mut write_offsets = Vec::with_capacity(self.table_names.len());
+        for (name, &segment_length) in self
+            .table_names
+            .iter()
+            .zip(self.table_segment_lengths.iter())
+        {
+            let path = self.table_layout.table_file(&self.path, name, "offsets");
+
+            let vals = load_ordered_u64_file(
+                &path,
+                len,
+                self.open_buffer_size,
+                self.open_concurrency,
+                self.direct_io,
+                self.endianness,
+            )
+            .await
+            .with_context(|| format!("failed to load offsets of table '{}'", name))?;
+
+            write_offsets.push(vals.last().copied().unwrap_or(0));
+
+            let (mut offsets, offsets_reader) = caos::new::<u64>(segment_length);
+            offsets.append(&vals);
+            table_offsets.push(offsets);
+            table_offsets_readers.push(offsets_reader);
+        }
+
+        if self.tags.is_some() {
+            let mut path = self.path.clone();
+            path.push("tags");
+            let file = File::open(
+                &path,
+                OpenOptions::new().write(true).read(true),
+                self.direct_io,
+            )
+            .await
+            .context("open tags file")?;
+            let vals = load_u64_file(
+                &path,
+                len,
+                self.open_buffer_size,
+                self.open_concurrency,
+                self.direct_io,
+                self.endianness,
+            )
+            .await
+            .context("read tags file")?;
+            self.tags = Some((TagIndex::from_tags(&vals), file));
+        }
+
+        self.keys = keys;
+        self.keys_reader = keys_reader;
+        self.shared_length = SharedLength::new(length);
+        self.keys_file = keys_file;
+        self.table_offsets = table_offsets;
+        self.table_offsets_readers = table_offsets_readers;
+        self.table_offsets_files = table_offsets_files;
+        self.table_files = table_files;
+        self.write_offsets = write_offsets;
+        self.length = length;
+        self.reserved = vec![0; self.table_names.len()];
+        self.cache_generation.bump();
+
+        Ok(())
+    }
+}
+
+// Computes the write offsets after appending the given values, erroring instead of wrapping around on overflow.
+fn new_write_offsets<V: AsRef<[u8]>>(
+    table_names: &[String],
+    write_offsets: &[u64],
+    values: &[V],
+) -> Result<Vec<u64>> {
+    table_names
+        .iter()
+        .zip(write_offsets.iter())
+        .zip(values.iter())
+        .map(|((name, &offset), val)| {
+            offset
+                .checked_add(u64::try_from(val.as_ref().len()).unwrap())
+                .ok_or_else(|| anyhow!("table '{}' data offset overflow", name))
+        })
+        .collect()
+}
+
+pub(crate) fn duplicate_key(key: u64) -> anyhow::Error {
+    anyhow!("key {} is equal to the last key", key)
+}
+
+// Writes the data and syncs the file, see File::write_at for how direct io writes are aligned.
+async fn read_write_at(file: &File, data: &[u8], pos: u64, observer: &Observer) -> Result<()> {
+    if data.is_empty() {
+        return Ok(());
+    }
+
+    let written = file.write_at(data, pos).await?;
+    observer.on_write(data.len());
+    observer.count_write(written);
+    sync_file(file, observer).await?;
+
+    Ok(())
+}
+
+async fn write_bloom(
+    bloom: &BloomFilter,
+    file: &File,
+    key: u64,
+    observer: &Observer,
+) -> Result<()> {
+    let mut buf = vec![0; BLOCK_SIZE];
+    for block in bloom.insert(key) {
+        bloom.write_block(block, &mut buf);
+        let written = file
+            .write_at(&buf, u64::try_from(block * BLOCK_SIZE).unwrap())
+            .await
+            .context("write bloom block")?;
+        observer.on_write(buf.len());
+        observer.count_write(written);
+    }
+    sync_file(file, observer).await
+}
+
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(level = "debug", skip(path, observer), ret, err)
+)]
+pub(crate) async fn commit_length(
+    path: &Path,
+    length: u64,
+    observer: &Observer,
+    file_mode: Option<u32>,
+    direct_io: bool,
+    endianness: Endianness,
+    sync_dir: bool,
+) -> Result<()> {
+    let mut tmp_path = path.to_owned();
+    tmp_path.push("new_length");
+    glommio::io::remove(&tmp_path).await.ok();
+    let mut sink = Sink::create(&tmp_path, file_mode, direct_io)
+        .await
+        .context("build new length file")?;
+    sink.write_all(&endianness.encode(length))
+        .await
+        .context("write to new length file")?;
+    observer.count_write(sink.padded_size(8));
+    sync_sink(&mut sink, observer)
+        .await
+        .context("sync new length file to disk")?;
+    sink.close()
+        .await
+        .map_err(io_error)
+        .context("close new length file")?;
+    let mut final_path = path.to_owned();
+    final_path.push("length");
+    glommio::io::rename(&tmp_path, &final_path)
+        .await
+        .map_err(io_error)
+        .context("rename length file")?;
+    if sync_dir {
+        sync_dir_entries(path, observer)
+            .await
+            .context("sync store dir")?;
+    }
+
+    Ok(())
+}
+
+async fn truncate_file(file: &File, size: u64, observer: &Observer) -> Result<()> {
+    file.truncate(size).await.context("truncate file")?;
+    sync_file(file, observer).await
+}
+
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(name = "fsync", level = "debug", skip_all, ret, err)
+)]
+async fn sync_file(file: &File, observer: &Observer) -> Result<()> {
+    file.fdatasync().await.context("fdatasync file")?;
+    observer.on_sync();
+    observer.count_sync();
+
+    Ok(())
+}
+
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(name = "fsync", level = "debug", skip_all, ret, err)
+)]
+async fn sync_sink(sink: &mut Sink, observer: &Observer) -> Result<()> {
+    sink.sync().await.context("sync file")?;
+    observer.on_sync();
+    observer.count_sync();
 
     Ok(())
 }
+
+// Checks that a record has one value per table.
+// Every way of writing records calls this for all of its records before doing any io,
+// so a record with the wrong number of values can't leave part of a batch in the files.
+pub(crate) fn check_table_count(values: usize, tables: usize) -> Result<()> {
+    if values != tables {
+        return Err(anyhow!(
+            "number of values ({}) does not equal the number of tables ({})",
+            values,
+            tables
+        ));
+    }
+
+    Ok(())
+}
+
+// Syncs the directory so renames and new files in it are durable.
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(name = "fsync", level = "debug", skip_all, ret, err)
+)]
+async fn sync_dir_entries(path: &Path, observer: &Observer) -> Result<()> {
+    let dir = Directory::open(path)
+        .await
+        .map_err(io_error)
+        .context("open dir")?;
+    dir.sync().await.map_err(io_error).context("sync dir")?;
+    dir.close().await.map_err(io_error).context("close dir")?;
+    observer.on_dir_sync();
+    observer.count_sync();
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_write_offset_overflow() {
+        let table_names = vec!["table0".to_owned(), "table1".to_owned()];
+
+        let offsets = new_write_offsets(&table_names, &[3, 5], &[vec![0; 2], vec![0; 4]]).unwrap();
+        assert_eq!(offsets, vec![5, 9]);
+
+        let err = new_write_offsets(&table_names, &[3, u64::MAX - 3], &[vec![0; 2], vec![0; 4]])
+            .unwrap_err();
+        assert_eq!(err.to_string(), "table 'table1' data offset overflow");
+    }
+}
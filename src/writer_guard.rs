@@ -0,0 +1,53 @@
+use std::ops::{Deref, DerefMut};
+
+use crate::{TimestoreError, Writer};
+
+/// Owns a Writer and checks that it is closed with `finish`.
+///
+/// Files can't be closed in Drop since that needs async, so dropping the guard without calling `finish`
+/// leaves closing the files to the destructors of the file handles. The guard reports this
+/// through `StoreObserver::on_unfinished_writer` and a tracing warning so leaks can be caught in tests.
+/// The guard derefs to the Writer so it can be used for appends.
+pub struct WriterGuard {
+    writer: Option<Writer>,
+}
+
+impl WriterGuard {
+    pub fn new(writer: Writer) -> Self {
+        Self {
+            writer: Some(writer),
+        }
+    }
+
+    /// Syncs and closes the files of the writer, see `Writer::close`.
+    pub async fn finish(mut self) -> Result<(), TimestoreError> {
+        self.writer.take().unwrap().close().await
+    }
+}
+
+impl Deref for WriterGuard {
+    type Target = Writer;
+
+    fn deref(&self) -> &Writer {
+        self.writer.as_ref().unwrap()
+    }
+}
+
+impl DerefMut for WriterGuard {
+    fn deref_mut(&mut self) -> &mut Writer {
+        self.writer.as_mut().unwrap()
+    }
+}
+
+impl Drop for WriterGuard {
+    fn drop(&mut self) {
+        if let Some(writer) = self.writer.take() {
+            #[cfg(feature = "tracing")]
+            tracing::warn!(
+                path = ?writer.path,
+                "WriterGuard was dropped without calling finish"
+            );
+            writer.observer.on_unfinished_writer();
+        }
+    }
+}
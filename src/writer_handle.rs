@@ -0,0 +1,116 @@
+use anyhow::anyhow;
+use futures::{
+    channel::{mpsc, oneshot},
+    SinkExt, StreamExt,
+};
+use glommio::Task;
+
+use crate::{
+    writer::{check_table_count, duplicate_key},
+    DuplicateKeyPolicy, TimestoreError, Writer,
+};
+
+type Request = (
+    u64,
+    Vec<Vec<u8>>,
+    oneshot::Sender<Result<u64, TimestoreError>>,
+);
+
+/// Handle to a task that owns a Writer and appends the records that are sent to it.
+///
+/// Handles can be cloned and used from many tasks at the same time. Records are appended in the order they are received.
+/// All records that are queued when the task wakes up are appended with a single `Writer::append_many` call,
+/// so concurrent producers share the cost of committing. A record that can't be appended, e.g. a duplicate key
+/// with `DuplicateKeyPolicy::Error`, only fails its own `append`.
+#[derive(Clone)]
+pub struct WriterHandle {
+    sender: mpsc::Sender<Request>,
+    num_tables: usize,
+}
+
+impl WriterHandle {
+    /// Spawns the task that owns the writer on the current executor.
+    ///
+    /// `queue_size` is the number of records that can wait in the queue before `append` waits for space.
+    /// The returned task finishes and gives back the writer after all handles are dropped and the queue is drained.
+    pub fn new(writer: Writer, queue_size: usize) -> (Self, Task<Writer>) {
+        let (sender, receiver) = mpsc::channel(queue_size);
+        let num_tables = writer.table_names().len();
+        let task = glommio::spawn_local(run(writer, receiver));
+
+        (Self { sender, num_tables }, task)
+    }
+
+    /// Appends a record and returns the position it was written at.
+    /// Returns after the record is committed.
+    pub async fn append(&self, key: u64, values: Vec<Vec<u8>>) -> Result<u64, TimestoreError> {
+        // Checked here so an invalid record doesn't fail the other records in its batch.
+        check_table_count(values.len(), self.num_tables)?;
+
+        let (sender, receiver) = oneshot::channel();
+        self.sender
+            .clone()
+            .send((key, values, sender))
+            .await
+            .map_err(|_| anyhow!("writer task stopped"))?;
+
+        receiver
+            .await
+            .map_err(|_| anyhow!("writer task stopped before committing the record"))?
+    }
+}
+
+async fn run(mut writer: Writer, mut receiver: mpsc::Receiver<Request>) -> Writer {
+    while let Some(request) = receiver.next().await {
+        let mut batch = vec![request];
+        while let Ok(request) = receiver.try_recv() {
+            batch.push(request);
+        }
+
+        if batch.len() == 1 {
+            let (key, values, sender) = batch.pop().unwrap();
+            sender.send(writer.append(key, values).await).ok();
+            continue;
+        }
+
+        // Records that can't be appended are answered here so they don't fail the other records in the batch.
+        let mut records = Vec::with_capacity(batch.len());
+        let mut senders = Vec::with_capacity(batch.len());
+        let mut prev_key = writer.last_key();
+        for (key, values, sender) in batch {
+            if let Err(e) = writer.check_values(&values) {
+                sender.send(Err(e.into())).ok();
+                continue;
+            }
+            if writer.on_duplicate_key == DuplicateKeyPolicy::Error && prev_key == Some(key) {
+                sender.send(Err(duplicate_key(key).into())).ok();
+                continue;
+            }
+            prev_key = Some(key);
+            records.push((key, values));
+            senders.push(sender);
+        }
+        if records.is_empty() {
+            continue;
+        }
+
+        match writer.append_many_positions(records).await {
+            Ok(positions) => {
+                for (sender, pos) in senders.into_iter().zip(positions) {
+                    sender.send(Ok(pos)).ok();
+                }
+            }
+            Err(e) => {
+                // The error can't be cloned so every record gets a copy of its message.
+                let msg = format!("{:#}", anyhow::Error::from(e));
+                for sender in senders {
+                    sender
+                        .send(Err(anyhow!("append batch failed: {}", msg).into()))
+                        .ok();
+                }
+            }
+        }
+    }
+
+    writer
+}
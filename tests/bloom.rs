@@ -0,0 +1,127 @@
+use std::env::temp_dir;
+
+use anyhow::Context;
+use glommio::LocalExecutor;
+
+#[test]
+fn test_bloom_filter() {
+    let exec = LocalExecutor::default();
+
+    exec.run(async move {
+        let mut path = temp_dir();
+        path.push(uuid::Uuid::new_v4().to_string());
+
+        let cfg = timestore::ConfigBuilder::default()
+            .path(path)
+            .create_if_not_exists(true)
+            .tables(vec!["table0".to_owned()])
+            .bloom_filter_bits(Some(1 << 16))
+            .build()
+            .unwrap();
+
+        {
+            let (writer_factory, reader_factory) =
+                timestore::open(cfg.clone()).await.context("open db")?;
+
+            let mut writer = writer_factory.make().await.unwrap();
+            let reader = reader_factory.make().await.unwrap();
+
+            for key in [12, 18, 25] {
+                writer
+                    .append(key, vec![key.to_be_bytes().to_vec()])
+                    .await
+                    .unwrap();
+            }
+
+            let bloom = reader.bloom_filter().unwrap();
+            assert!(bloom.may_contain(18));
+
+            let absent = (1000..).find(|&k| !bloom.may_contain(k)).unwrap();
+            assert!(!reader.contains_key(absent));
+            assert!(reader.read("table0", absent).await.unwrap().is_none());
+
+            assert!(reader.contains_key(18));
+            let res = reader.read("table0", 18).await.unwrap().unwrap();
+            assert_eq!(&*res, 18u64.to_be_bytes());
+        }
+
+        {
+            let (_writer_factory, reader_factory) =
+                timestore::open(cfg).await.context("reopen db")?;
+            let reader = reader_factory.make().await.unwrap();
+
+            let bloom = reader.bloom_filter().unwrap();
+            for key in [12, 18, 25] {
+                assert!(bloom.may_contain(key));
+                assert!(reader.contains_key(key));
+            }
+        }
+
+        Ok::<_, anyhow::Error>(())
+    })
+    .unwrap();
+}
+
+#[test]
+fn test_bloom_filter_disabled() {
+    let exec = LocalExecutor::default();
+
+    exec.run(async move {
+        let mut path = temp_dir();
+        path.push(uuid::Uuid::new_v4().to_string());
+
+        let cfg = |bloom_filter_bits: Option<u64>| {
+            timestore::ConfigBuilder::default()
+                .path(path.clone())
+                .create_if_not_exists(true)
+                .tables(vec!["table0".to_owned()])
+                .bloom_filter_bits(bloom_filter_bits)
+                .build()
+                .unwrap()
+        };
+
+        let (mut writer, _reader_factory) = timestore::reopen_writer(cfg(Some(1 << 16)))
+            .await
+            .context("open writer")?;
+        writer.append(12, vec![vec![12]]).await.unwrap();
+
+        // an open without the filter next to the live writer leaves the filter alone
+        let (other_factory, reader_factory) = timestore::open(cfg(None))
+            .await
+            .context("open without filter")?;
+        assert!(reader_factory
+            .make()
+            .await
+            .unwrap()
+            .bloom_filter()
+            .is_none());
+        assert!(path.join("bloom").is_file());
+
+        writer.append(18, vec![vec![18]]).await.unwrap();
+        drop(writer);
+        drop(other_factory);
+
+        // a writer without the filter marks it stale so the filter is rebuilt with its keys
+        let (mut writer, _reader_factory) = timestore::reopen_writer(cfg(None))
+            .await
+            .context("open writer without filter")?;
+        writer.append(25, vec![vec![25]]).await.unwrap();
+        drop(writer);
+        assert!(path.join("bloom").is_file());
+        assert!(path.join("bloom_stale").is_file());
+
+        let (_writer_factory, reader_factory) = timestore::open(cfg(Some(1 << 16)))
+            .await
+            .context("reopen with filter")?;
+        let reader = reader_factory.make().await.unwrap();
+        let bloom = reader.bloom_filter().unwrap();
+        for key in [12, 18, 25] {
+            assert!(bloom.may_contain(key));
+            assert!(reader.contains_key(key));
+        }
+        assert!(!path.join("bloom_stale").exists());
+
+        Ok::<_, anyhow::Error>(())
+    })
+    .unwrap();
+}
@@ -0,0 +1,111 @@
+use std::env::temp_dir;
+
+use anyhow::Context;
+use glommio::LocalExecutor;
+
+#[test]
+fn test_buffered_writer() {
+    let exec = LocalExecutor::default();
+
+    exec.run(async move {
+        let mut path = temp_dir();
+        path.push(uuid::Uuid::new_v4().to_string());
+
+        let cfg = timestore::ConfigBuilder::default()
+            .path(path)
+            .create_if_not_exists(true)
+            .tables(vec!["table0".to_owned(), "table1".to_owned()])
+            .build()
+            .unwrap();
+
+        {
+            let (writer_factory, _reader_factory) =
+                timestore::open(cfg.clone()).await.context("open db")?;
+            let writer = writer_factory.make().await.unwrap();
+
+            let mut writer = timestore::BufferedWriter::new(writer, 7, 100).unwrap();
+
+            assert!(writer.push(1, vec![b"a".to_vec()]).await.is_err());
+
+            for key in 1..=100u64 {
+                writer
+                    .push(
+                        key,
+                        vec![key.to_be_bytes().to_vec(), vec![0; key as usize % 30]],
+                    )
+                    .await
+                    .unwrap();
+            }
+            writer.flush().await.unwrap();
+
+            for key in 101..=103u64 {
+                writer
+                    .push(key, vec![key.to_be_bytes().to_vec(), Vec::new()])
+                    .await
+                    .unwrap();
+            }
+            let writer = writer.close().await.unwrap();
+            assert!(timestore::BufferedWriter::new(writer, 0, 100).is_err());
+        }
+
+        {
+            let (_writer_factory, reader_factory) =
+                timestore::open(cfg).await.context("reopen db")?;
+            let reader = reader_factory.make().await.unwrap();
+
+            let keys = reader.keys().iter_from(0).collect::<Vec<u64>>();
+            assert_eq!(keys, (1..=103).collect::<Vec<u64>>());
+
+            for key in 1..=103u64 {
+                let res = reader.read("table0", key).await.unwrap().unwrap();
+                assert_eq!(&*res, key.to_be_bytes());
+                let expected_len = if key <= 100 { key as usize % 30 } else { 0 };
+                let res = reader.read("table1", key).await.unwrap().unwrap();
+                assert_eq!(res.len(), expected_len);
+            }
+        }
+
+        Ok::<_, anyhow::Error>(())
+    })
+    .unwrap();
+}
+
+#[test]
+fn test_buffered_writer_write_combine() {
+    let exec = LocalExecutor::default();
+
+    exec.run(async move {
+        let mut path = temp_dir();
+        path.push(uuid::Uuid::new_v4().to_string());
+
+        let (writer_factory, reader_factory) = timestore::open(
+            timestore::ConfigBuilder::default()
+                .path(path)
+                .create_if_not_exists(true)
+                .tables(vec!["table0".to_owned()])
+                .write_combine_bytes(1 << 20)
+                .build()
+                .unwrap(),
+        )
+        .await
+        .context("open db")?;
+        let writer = writer_factory.make().await.unwrap();
+        let mut writer = timestore::BufferedWriter::new(writer, 100, 1 << 20).unwrap();
+
+        for key in 1..=5u64 {
+            writer.push(key, vec![vec![key as u8]]).await.unwrap();
+        }
+        // the records the writer holds for write combining are committed by flush too
+        writer.flush().await.unwrap();
+
+        let reader = reader_factory.make().await.unwrap();
+        assert_eq!(
+            reader.keys().iter_from(0).collect::<Vec<u64>>(),
+            (1..=5).collect::<Vec<u64>>()
+        );
+        assert_eq!(&*reader.read("table0", 5).await.unwrap().unwrap(), &[5]);
+
+        Ok::<_, anyhow::Error>(())
+    })
+    .unwrap();
+}
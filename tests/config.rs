@@ -0,0 +1,1490 @@
+use std::{
+    env::temp_dir,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+};
+
+use anyhow::Context;
+use glommio::LocalExecutor;
+use timestore::StoreObserver;
+
+#[test]
+fn test_default_segment_length() {
+    let cfg = timestore::ConfigBuilder::default()
+        .path(temp_dir())
+        .create_if_not_exists(true)
+        .tables(vec!["table0".to_owned()])
+        .build()
+        .unwrap();
+
+    assert_eq!(cfg.segment_length(), 1024);
+}
+
+#[test]
+fn test_zero_segment_length() {
+    let exec = LocalExecutor::default();
+
+    exec.run(async move {
+        let mut path = temp_dir();
+        path.push(uuid::Uuid::new_v4().to_string());
+
+        let res = timestore::open(
+            timestore::ConfigBuilder::default()
+                .path(path.clone())
+                .create_if_not_exists(true)
+                .segment_length(0)
+                .tables(vec!["table0".to_owned()])
+                .build()
+                .unwrap(),
+        )
+        .await;
+
+        assert!(res.is_err());
+        assert!(!path.exists());
+    });
+}
+
+#[test]
+fn test_table_segment_lengths() {
+    let exec = LocalExecutor::default();
+
+    exec.run(async move {
+        let mut path = temp_dir();
+        path.push(uuid::Uuid::new_v4().to_string());
+
+        let cfg = timestore::ConfigBuilder::default()
+            .path(path)
+            .create_if_not_exists(true)
+            .segment_length(4)
+            .table_segment_lengths([("table1".to_owned(), 3)].into_iter().collect())
+            .tables(vec!["table0".to_owned(), "table1".to_owned()])
+            .build()
+            .unwrap();
+        assert_eq!(cfg.table_segment_length("table0"), 4);
+        assert_eq!(cfg.table_segment_length("table1"), 3);
+
+        let (writer_factory, reader_factory) = timestore::open(cfg).await.context("open db")?;
+
+        let mut writer = writer_factory.make().await.unwrap();
+        let reader = reader_factory.make().await.unwrap();
+
+        for key in 1..20u64 {
+            writer
+                .append(key, vec![key.to_be_bytes().to_vec(), vec![0; key as usize]])
+                .await
+                .unwrap();
+        }
+
+        for key in 1..20u64 {
+            let res = reader.read("table0", key).await.unwrap().unwrap();
+            assert_eq!(&*res, key.to_be_bytes());
+            let res = reader.read("table1", key).await.unwrap().unwrap();
+            assert_eq!(res.len(), key as usize);
+        }
+
+        Ok::<_, anyhow::Error>(())
+    })
+    .unwrap();
+}
+
+#[test]
+fn test_open_missing_store() {
+    let exec = LocalExecutor::default();
+
+    exec.run(async move {
+        let mut path = temp_dir();
+        path.push(uuid::Uuid::new_v4().to_string());
+
+        let cfg = timestore::ConfigBuilder::default()
+            .path(path.clone())
+            .create_if_not_exists(false)
+            .tables(vec!["table0".to_owned()])
+            .build()
+            .unwrap();
+
+        let err = timestore::open(cfg.clone()).await.err().unwrap();
+        assert!(err.to_string().contains("does not exist"));
+        assert!(!path.exists());
+
+        // directory exists but the files don't
+        std::fs::create_dir_all(&path).unwrap();
+        std::fs::write(path.join("length"), 0u64.to_be_bytes()).unwrap();
+        std::fs::write(path.join("keys"), []).unwrap();
+        std::fs::create_dir_all(path.join("table0")).unwrap();
+        std::fs::write(path.join("table0").join("offsets"), []).unwrap();
+
+        let err = timestore::open(cfg).await.err().unwrap();
+        assert!(err.to_string().contains("is missing file"));
+        assert!(err.to_string().contains("data"));
+    });
+}
+
+#[test]
+fn test_open_read_options() {
+    let exec = LocalExecutor::default();
+
+    exec.run(async move {
+        let mut path = temp_dir();
+        path.push(uuid::Uuid::new_v4().to_string());
+
+        let cfg = |buffer_size, concurrency| {
+            timestore::ConfigBuilder::default()
+                .path(path.clone())
+                .create_if_not_exists(true)
+                .tables(vec!["table0".to_owned()])
+                .open_buffer_size(buffer_size)
+                .open_concurrency(concurrency)
+                .build()
+                .unwrap()
+        };
+
+        assert_eq!(cfg(4096, 1).open_buffer_size(), 4096);
+        assert_eq!(cfg(4096, 1).open_concurrency(), 1);
+
+        {
+            let (writer_factory, _reader_factory) =
+                timestore::open(cfg(4096, 1)).await.context("open db")?;
+            let mut writer = writer_factory.make().await.unwrap();
+
+            for key in 1..=2000u64 {
+                writer
+                    .append(key, vec![key.to_be_bytes().to_vec()])
+                    .await
+                    .unwrap();
+            }
+        }
+
+        for (buffer_size, concurrency) in [(4096, 1), (1024 * 1024, 32)] {
+            let (_writer_factory, reader_factory) = timestore::open(cfg(buffer_size, concurrency))
+                .await
+                .context("reopen db")?;
+            let reader = reader_factory.make().await.unwrap();
+
+            let keys = reader.keys().iter_from(0).collect::<Vec<u64>>();
+            assert_eq!(keys, (1..=2000).collect::<Vec<u64>>());
+            let res = reader.read("table0", 1234).await.unwrap().unwrap();
+            assert_eq!(&*res, 1234u64.to_be_bytes());
+        }
+
+        assert!(timestore::open(cfg(0, 1)).await.is_err());
+
+        Ok::<_, anyhow::Error>(())
+    })
+    .unwrap();
+}
+
+#[test]
+fn test_open_short_data_file() {
+    let exec = LocalExecutor::default();
+
+    exec.run(async move {
+        let mut path = temp_dir();
+        path.push(uuid::Uuid::new_v4().to_string());
+
+        let cfg = timestore::ConfigBuilder::default()
+            .path(path.clone())
+            .create_if_not_exists(true)
+            .tables(vec!["table0".to_owned()])
+            .build()
+            .unwrap();
+
+        {
+            let (writer_factory, _reader_factory) =
+                timestore::open(cfg.clone()).await.context("open db")?;
+            let mut writer = writer_factory.make().await.unwrap();
+
+            for key in 1..=10u64 {
+                writer.append(key, vec![vec![7; 1000]]).await.unwrap();
+            }
+        }
+
+        std::fs::OpenOptions::new()
+            .write(true)
+            .open(path.join("table0").join("data"))
+            .unwrap()
+            .set_len(4096)
+            .unwrap();
+
+        let err = timestore::open(cfg).await.err().unwrap();
+        assert_eq!(
+            err.to_string(),
+            "data file for table 'table0' is 4096 bytes but offsets require at least 10000"
+        );
+
+        Ok::<_, anyhow::Error>(())
+    })
+    .unwrap();
+}
+
+#[test]
+fn test_open_short_length_file() {
+    let exec = LocalExecutor::default();
+
+    exec.run(async move {
+        let mut path = temp_dir();
+        path.push(uuid::Uuid::new_v4().to_string());
+
+        let cfg = |create_if_not_exists| {
+            timestore::ConfigBuilder::default()
+                .path(path.clone())
+                .create_if_not_exists(create_if_not_exists)
+                .tables(vec!["table0".to_owned()])
+                .build()
+                .unwrap()
+        };
+        let set_length_file_size = |size| {
+            std::fs::OpenOptions::new()
+                .write(true)
+                .open(path.join("length"))
+                .unwrap()
+                .set_len(size)
+                .unwrap()
+        };
+
+        timestore::open(cfg(true)).await.context("create db")?;
+
+        set_length_file_size(3);
+        let err = timestore::open(cfg(false)).await.err().unwrap();
+        assert_eq!(
+            err.to_string(),
+            "length file is 3 bytes, it has to be empty or at least 8 bytes"
+        );
+
+        set_length_file_size(0);
+        let (writer_factory, reader_factory) =
+            timestore::open(cfg(false)).await.context("open db")?;
+        let reader = reader_factory.make().await.unwrap();
+        assert_eq!(reader.keys().last(), None);
+
+        let mut writer = writer_factory.make().await.unwrap();
+        writer.append(1, vec![b"a".to_vec()]).await.unwrap();
+        assert_eq!(&*reader.read("table0", 1).await.unwrap().unwrap(), b"a");
+
+        Ok::<_, anyhow::Error>(())
+    })
+    .unwrap();
+}
+
+#[test]
+fn test_table_names_metadata() {
+    let exec = LocalExecutor::default();
+
+    exec.run(async move {
+        let mut path = temp_dir();
+        path.push(uuid::Uuid::new_v4().to_string());
+
+        let cfg = |tables: &[&str]| {
+            timestore::ConfigBuilder::default()
+                .path(path.clone())
+                .create_if_not_exists(true)
+                .tables(tables.iter().map(|&t| t.to_owned()).collect())
+                .build()
+                .unwrap()
+        };
+
+        {
+            let (writer_factory, _reader_factory) = timestore::open(cfg(&["table0", "table1"]))
+                .await
+                .context("open db")?;
+            let mut writer = writer_factory.make().await.unwrap();
+            writer
+                .append(1, vec![b"zero".to_vec(), b"one".to_vec()])
+                .await
+                .unwrap();
+        }
+
+        assert_eq!(
+            timestore::read_table_names(&path, cfg(&[]).direct_io())
+                .await
+                .unwrap(),
+            vec!["table0".to_owned(), "table1".to_owned()]
+        );
+
+        for tables in [
+            &["table1", "table0"][..],
+            &["table0", "table2"],
+            &["table0", "table1", "table2"],
+        ] {
+            let err = timestore::open(cfg(tables)).await.err().unwrap();
+            assert!(err.to_string().contains("don't match"), "{}", err);
+        }
+        assert!(!path.join("table2").exists());
+
+        // the tables are taken from the store if none are configured
+        let (_, reader_factory) = timestore::open(cfg(&[]))
+            .await
+            .context("open db without tables")?;
+        let reader = reader_factory.make().await.unwrap();
+        assert_eq!(reader.table_names(), ["table0", "table1"]);
+        assert_eq!(&*reader.read("table1", 1).await.unwrap().unwrap(), b"one");
+
+        // stores without the tables file get one from the config when a writer is made
+        std::fs::remove_file(path.join("tables")).unwrap();
+        let (writer_factory, _reader_factory) = timestore::open(
+            timestore::ConfigBuilder::default()
+                .path(path.clone())
+                .create_if_not_exists(false)
+                .tables(vec!["table0".to_owned(), "table1".to_owned()])
+                .build()
+                .unwrap(),
+        )
+        .await
+        .context("open db without tables file")?;
+        assert!(!path.join("tables").exists());
+        writer_factory.make().await.unwrap();
+        assert_eq!(
+            timestore::read_table_names(&path, cfg(&[]).direct_io())
+                .await
+                .unwrap(),
+            vec!["table0".to_owned(), "table1".to_owned()]
+        );
+
+        Ok::<_, anyhow::Error>(())
+    })
+    .unwrap();
+}
+
+#[test]
+fn test_open_table_subset() {
+    let exec = LocalExecutor::default();
+
+    exec.run(async move {
+        let mut path = temp_dir();
+        path.push(uuid::Uuid::new_v4().to_string());
+
+        let cfg = |tables: &[&str]| {
+            timestore::ConfigBuilder::default()
+                .path(path.clone())
+                .create_if_not_exists(true)
+                .tables(tables.iter().map(|&t| t.to_owned()).collect())
+                .build()
+                .unwrap()
+        };
+
+        {
+            let (writer_factory, _reader_factory) =
+                timestore::open(cfg(&["table0", "table1", "table2"]))
+                    .await
+                    .context("open db")?;
+            let mut writer = writer_factory.make().await.unwrap();
+            for key in 1..=10u64 {
+                writer
+                    .append(
+                        key,
+                        vec![
+                            vec![0; key as usize],
+                            key.to_be_bytes().to_vec(),
+                            vec![2; 3],
+                        ],
+                    )
+                    .await
+                    .unwrap();
+            }
+        }
+
+        assert!(timestore::open(cfg(&["table1", "table1"])).await.is_err());
+
+        // the other tables aren't loaded so their files can be missing
+        std::fs::remove_file(path.join("table0").join("offsets")).unwrap();
+        std::fs::remove_dir_all(path.join("table2")).unwrap();
+
+        let (writer_factory, reader_factory) = timestore::open(cfg(&["table1"]))
+            .await
+            .context("open table subset")?;
+        let reader = reader_factory.make().await.unwrap();
+        assert_eq!(reader.table_names(), ["table1"]);
+        for key in 1..=10u64 {
+            let res = reader.read("table1", key).await.unwrap().unwrap();
+            assert_eq!(&*res, key.to_be_bytes());
+        }
+        match reader.read("table0", 1).await {
+            Err(timestore::TimestoreError::TableNotFound(_)) => (),
+            res => panic!("unexpected result {:?}", res.map(|r| r.is_some())),
+        }
+        assert!(writer_factory.make().await.is_err());
+
+        Ok::<_, anyhow::Error>(())
+    })
+    .unwrap();
+}
+
+#[test]
+fn test_preallocate() {
+    use std::os::unix::fs::MetadataExt;
+
+    let exec = LocalExecutor::default();
+
+    exec.run(async move {
+        const CHUNK: u64 = 1024 * 1024;
+
+        let values = |key: u64| vec![vec![key as u8; 300 * 1024], key.to_be_bytes().to_vec()];
+        let allocated = |path: &std::path::Path| std::fs::metadata(path).unwrap().blocks() * 512;
+
+        let mut paths = Vec::new();
+        for preallocate_bytes in [0, CHUNK] {
+            let mut path = temp_dir();
+            path.push(uuid::Uuid::new_v4().to_string());
+
+            let cfg = timestore::ConfigBuilder::default()
+                .path(path.clone())
+                .create_if_not_exists(true)
+                .tables(vec!["table0".to_owned(), "table1".to_owned()])
+                .preallocate_bytes(preallocate_bytes)
+                .build()
+                .unwrap();
+
+            let (writer_factory, _reader_factory) =
+                timestore::open(cfg.clone()).await.context("open db")?;
+            let mut writer = writer_factory.make().await.unwrap();
+
+            let data_path = path.join("table0").join("data");
+            if preallocate_bytes > 0 {
+                assert!(allocated(&data_path) >= CHUNK);
+                assert_eq!(std::fs::metadata(&data_path).unwrap().len(), 0);
+            }
+
+            for key in 1..=5u64 {
+                writer.append(key, values(key)).await.unwrap();
+            }
+            if preallocate_bytes > 0 {
+                assert!(allocated(&data_path) >= 2 * CHUNK);
+            }
+
+            paths.push(cfg);
+        }
+
+        for cfg in paths {
+            let (_writer_factory, reader_factory) =
+                timestore::open(cfg).await.context("reopen db")?;
+            let reader = reader_factory.make().await.unwrap();
+            for key in 1..=5u64 {
+                for (table, value) in ["table0", "table1"].into_iter().zip(values(key)) {
+                    let res = reader.read(table, key).await.unwrap().unwrap();
+                    assert_eq!(&*res, &*value);
+                }
+            }
+        }
+
+        Ok::<_, anyhow::Error>(())
+    })
+    .unwrap();
+}
+
+#[test]
+fn test_buffered_io() {
+    use futures::StreamExt;
+    use glommio::io::{MergedBufferLimit, ReadAmplificationLimit};
+
+    let exec = LocalExecutor::default();
+
+    exec.run(async move {
+        let mut path = temp_dir();
+        path.push(uuid::Uuid::new_v4().to_string());
+
+        let cfg = timestore::ConfigBuilder::default()
+            .path(path.clone())
+            .create_if_not_exists(true)
+            .tables(vec!["table0".to_owned(), "table1".to_owned()])
+            .bloom_filter_bits(Some(4096 * 8))
+            .data_segment_size(Some(1000))
+            .direct_io(false)
+            .build()
+            .unwrap();
+
+        let values = |key: u64| {
+            vec![
+                vec![key as u8; key as usize * 10],
+                key.to_be_bytes().to_vec(),
+            ]
+        };
+
+        {
+            let (writer_factory, reader_factory) =
+                timestore::open(cfg.clone()).await.context("open db")?;
+            let mut writer = writer_factory.make().await.unwrap();
+            let reader = reader_factory.make().await.unwrap();
+
+            assert_eq!(writer.alignment(), 1);
+
+            for key in 1..=20u64 {
+                writer.append(key, values(key)).await.unwrap();
+            }
+            assert_eq!(writer.truncate_after(18).await.unwrap(), 2);
+
+            assert_eq!(reader.alignment("table0").unwrap(), 1);
+            for key in 1..=18u64 {
+                for (table, value) in ["table0", "table1"].into_iter().zip(values(key)) {
+                    let res = reader.read(table, key).await.unwrap().unwrap();
+                    assert_eq!(&*res, &*value);
+                }
+            }
+
+            let mut reads = reader
+                .read_many(
+                    "table0",
+                    15,
+                    futures::stream::iter(vec![(0u64, 5usize), (100, 50)]),
+                    2,
+                    MergedBufferLimit::NoMerging,
+                    ReadAmplificationLimit::NoAmplification,
+                )
+                .await
+                .unwrap()
+                .unwrap()
+                .map(|res| res.unwrap())
+                .map(|(index, buf)| (index, buf.to_vec()))
+                .collect::<Vec<_>>()
+                .await;
+            reads.sort();
+            assert_eq!(reads, vec![(0, vec![15; 5]), (1, vec![15; 50])]);
+        }
+
+        // the small data segments make the iterator switch files
+        assert!(path.join("table0").join("data.910").is_file());
+
+        let (_writer_factory, reader_factory) =
+            timestore::open(cfg.clone()).await.context("reopen db")?;
+        let reader = reader_factory.make().await.unwrap();
+        let mut iter = reader
+            .iter(
+                timestore::IterParamsBuilder::default()
+                    .from(0)
+                    .to(100)
+                    .table(Some("table0"))
+                    .build()
+                    .unwrap(),
+            )
+            .await
+            .unwrap()
+            .unwrap();
+        for key in 1..=18u64 {
+            let ((_, to), buf) = iter.next().await.unwrap().unwrap();
+            assert_eq!(to, key);
+            assert_eq!(buf, values(key)[0]);
+        }
+        assert!(iter.next().await.unwrap().is_none());
+
+        assert!(timestore::verify(&cfg).await.unwrap().is_ok());
+
+        Ok::<_, anyhow::Error>(())
+    })
+    .unwrap();
+}
+
+#[test]
+fn test_flat_table_layout() {
+    let exec = LocalExecutor::default();
+
+    exec.run(async move {
+        let mut path = temp_dir();
+        path.push(uuid::Uuid::new_v4().to_string());
+
+        let cfg = timestore::ConfigBuilder::default()
+            .path(path.clone())
+            .create_if_not_exists(true)
+            .tables(vec!["table0".to_owned(), "table1".to_owned()])
+            .table_layout(timestore::TableLayout::Flat)
+            .build()
+            .unwrap();
+
+        let values = |key: u64| vec![vec![key as u8; key as usize], key.to_be_bytes().to_vec()];
+
+        {
+            let (writer_factory, _reader_factory) =
+                timestore::open(cfg.clone()).await.context("open db")?;
+            let mut writer = writer_factory.make().await.unwrap();
+            for key in 1..=10u64 {
+                writer.append(key, values(key)).await.unwrap();
+            }
+        }
+
+        for table in ["table0", "table1"] {
+            assert!(path.join(format!("{}.data", table)).is_file());
+            assert!(path.join(format!("{}.offsets", table)).is_file());
+            assert!(!path.join(table).exists());
+        }
+
+        assert_eq!(timestore::delete_range(&cfg, 3, 5).await.unwrap(), 2);
+        assert!(timestore::verify(&cfg).await.unwrap().is_ok());
+        timestore::compact(&cfg).await.unwrap();
+
+        let (_writer_factory, reader_factory) =
+            timestore::open(cfg.clone()).await.context("reopen db")?;
+        let reader = reader_factory.make().await.unwrap();
+        let keys = reader.keys().iter_from(0).collect::<Vec<u64>>();
+        assert_eq!(keys, vec![1, 2, 5, 6, 7, 8, 9, 10]);
+        for key in keys {
+            for (table, value) in ["table0", "table1"].into_iter().zip(values(key)) {
+                let res = reader.read(table, key).await.unwrap().unwrap();
+                assert_eq!(&*res, &*value);
+            }
+        }
+
+        // the layout can't be changed after the store is created
+        let nested_cfg = timestore::ConfigBuilder::default()
+            .path(path)
+            .create_if_not_exists(false)
+            .tables(vec!["table0".to_owned(), "table1".to_owned()])
+            .build()
+            .unwrap();
+        assert!(timestore::open(nested_cfg).await.is_err());
+
+        Ok::<_, anyhow::Error>(())
+    })
+    .unwrap();
+}
+
+#[test]
+fn test_max_value_bytes() {
+    let exec = LocalExecutor::default();
+
+    exec.run(async move {
+        let mut path = temp_dir();
+        path.push(uuid::Uuid::new_v4().to_string());
+
+        let cfg = timestore::ConfigBuilder::default()
+            .path(path)
+            .create_if_not_exists(true)
+            .tables(vec!["table0".to_owned(), "table1".to_owned()])
+            .max_value_bytes(Some(10))
+            .build()
+            .unwrap();
+
+        {
+            let (writer_factory, _reader_factory) =
+                timestore::open(cfg.clone()).await.context("open db")?;
+            let mut writer = writer_factory.make().await.unwrap();
+
+            writer.append(1, vec![vec![1; 10], vec![]]).await.unwrap();
+
+            let err = writer
+                .append(2, vec![vec![2; 3], vec![2; 11]])
+                .await
+                .unwrap_err();
+            assert_eq!(
+                err.to_string(),
+                "value for table 'table1' is 11 bytes, exceeds max 10"
+            );
+            assert!(writer
+                .append_many(vec![
+                    (2, vec![vec![2], vec![2]]),
+                    (3, vec![vec![3; 1 << 20], vec![3]])
+                ])
+                .await
+                .is_err());
+            assert!(writer.estimate_append(&[vec![4; 11], vec![]]).is_err());
+
+            assert_eq!(writer.write_offset("table0").unwrap(), 10);
+            assert_eq!(writer.write_offset("table1").unwrap(), 0);
+        }
+
+        let (_writer_factory, reader_factory) =
+            timestore::open(cfg.clone()).await.context("reopen db")?;
+        let reader = reader_factory.make().await.unwrap();
+        assert_eq!(reader.keys().iter_from(0).collect::<Vec<u64>>(), vec![1]);
+        let res = reader.read("table0", 1).await.unwrap().unwrap();
+        assert_eq!(&*res, &[1; 10]);
+        assert!(timestore::verify(&cfg).await.unwrap().is_ok());
+
+        Ok::<_, anyhow::Error>(())
+    })
+    .unwrap();
+}
+
+#[test]
+fn test_store_config() {
+    let exec = LocalExecutor::default();
+
+    exec.run(async move {
+        let mut path = temp_dir();
+        path.push(uuid::Uuid::new_v4().to_string());
+
+        let cfg_with = |segment_length: u32, data_segment_size: u64, table_layout| {
+            timestore::ConfigBuilder::default()
+                .path(path.clone())
+                .create_if_not_exists(true)
+                .tables(vec!["table0".to_owned()])
+                .segment_length(segment_length)
+                .data_segment_size(Some(data_segment_size))
+                .table_layout(table_layout)
+                .build()
+                .unwrap()
+        };
+        let cfg =
+            |segment_length: u32| cfg_with(segment_length, 1 << 20, timestore::TableLayout::Flat);
+
+        {
+            let (writer_factory, _reader_factory) =
+                timestore::open(cfg(64)).await.context("open db")?;
+            let mut writer = writer_factory.make().await.unwrap();
+            writer.append(1, vec![b"one".to_vec()]).await.unwrap();
+        }
+
+        let (_writer_factory, reader_factory) =
+            timestore::open(cfg(64)).await.context("reopen db")?;
+        let reader = reader_factory.make().await.unwrap();
+        let info = reader.store_config();
+        assert_eq!(
+            info,
+            timestore::StoreInfo {
+                format_version: 1,
+                segment_length: 64,
+                data_segment_size: Some(1 << 20),
+                table_layout: timestore::TableLayout::Flat,
+                endianness: timestore::Endianness::Big,
+                length_source: timestore::LengthSource::LengthFile,
+                created_at: info.created_at,
+            }
+        );
+        assert!(info.created_at > 0);
+        assert_eq!(
+            timestore::read_store_info(&path, cfg(64).direct_io())
+                .await
+                .unwrap(),
+            info
+        );
+
+        // the stored settings are the ones the store was created with
+        let (_writer_factory, reader_factory) = timestore::open(cfg(128))
+            .await
+            .context("reopen db with another segment length")?;
+        let reader = reader_factory.make().await.unwrap();
+        assert_eq!(reader.store_config(), info);
+        assert_eq!(
+            timestore::read_store_info(&path, cfg(64).direct_io())
+                .await
+                .unwrap(),
+            info
+        );
+
+        // settings that can't change are checked against the stored ones
+        assert!(
+            timestore::open(cfg_with(64, 1 << 21, timestore::TableLayout::Flat))
+                .await
+                .is_err()
+        );
+        assert!(
+            timestore::open(cfg_with(64, 1 << 20, timestore::TableLayout::Nested))
+                .await
+                .is_err()
+        );
+        assert_eq!(
+            timestore::read_store_info(&path, cfg(64).direct_io())
+                .await
+                .unwrap(),
+            info
+        );
+
+        Ok::<_, anyhow::Error>(())
+    })
+    .unwrap();
+}
+
+#[cfg(unix)]
+#[test]
+fn test_file_mode() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let exec = LocalExecutor::default();
+
+    exec.run(async move {
+        let mut path = temp_dir();
+        path.push(uuid::Uuid::new_v4().to_string());
+
+        let cfg = timestore::ConfigBuilder::default()
+            .path(path.clone())
+            .create_if_not_exists(true)
+            .tables(vec!["table0".to_owned()])
+            .file_mode(Some(0o640))
+            .build()
+            .unwrap();
+
+        let (writer_factory, _reader_factory) = timestore::open(cfg).await.context("open db")?;
+        let mut writer = writer_factory.make().await.unwrap();
+        writer.append(1, vec![b"one".to_vec()]).await.unwrap();
+
+        let mode = |path: std::path::PathBuf| {
+            std::fs::metadata(&path).unwrap().permissions().mode() & 0o777
+        };
+        assert_eq!(mode(path.clone()), 0o750);
+        assert_eq!(mode(path.join("table0")), 0o750);
+        // the length file is replaced on every append
+        for name in [
+            "length",
+            "keys",
+            "tables",
+            "store_info",
+            "table0/offsets",
+            "table0/data",
+        ] {
+            assert_eq!(mode(path.join(name)), 0o640, "{}", name);
+        }
+
+        Ok::<_, anyhow::Error>(())
+    })
+    .unwrap();
+}
+
+#[test]
+fn test_recover_new_length() {
+    let exec = LocalExecutor::default();
+
+    exec.run(async move {
+        let mut path = temp_dir();
+        path.push(uuid::Uuid::new_v4().to_string());
+
+        let cfg = timestore::ConfigBuilder::default()
+            .path(path.clone())
+            .create_if_not_exists(true)
+            .tables(vec!["table0".to_owned()])
+            .build()
+            .unwrap();
+        let keys = |reader: &timestore::Reader| reader.keys().iter_from(0).collect::<Vec<u64>>();
+
+        {
+            let (writer_factory, _reader_factory) =
+                timestore::open(cfg.clone()).await.context("open db")?;
+            let mut writer = writer_factory.make().await.unwrap();
+            for key in 1..=4u64 {
+                writer.append(key, vec![vec![key as u8]]).await.unwrap();
+            }
+        }
+
+        // the last append was written but its length wasn't renamed onto the length file, it is rolled forward
+        std::fs::write(path.join("length"), 3u64.to_be_bytes()).unwrap();
+        std::fs::write(path.join("new_length"), 4u64.to_be_bytes()).unwrap();
+        let (_, reader_factory) = timestore::open(cfg.clone()).await.context("reopen db")?;
+        let reader = reader_factory.make().await.unwrap();
+        assert_eq!(keys(&reader), vec![1, 2, 3, 4]);
+        assert_eq!(&*reader.read("table0", 4).await.unwrap().unwrap(), &[4]);
+        assert!(!path.join("new_length").exists());
+
+        // only a single append is rolled forward even if the files hold the records
+        std::fs::write(path.join("length"), 2u64.to_be_bytes()).unwrap();
+        std::fs::write(path.join("new_length"), 4u64.to_be_bytes()).unwrap();
+        let (_, reader_factory) = timestore::open(cfg.clone()).await.context("reopen db")?;
+        assert_eq!(keys(&reader_factory.make().await.unwrap()), vec![1, 2]);
+        assert!(!path.join("new_length").exists());
+        std::fs::write(path.join("length"), 4u64.to_be_bytes()).unwrap();
+
+        // a reader only open doesn't touch the file since a writer can be committing through it
+        {
+            let (writer_factory, _reader_factory) =
+                timestore::open(cfg.clone()).await.context("reopen db")?;
+            let _writer = writer_factory.make().await.unwrap();
+            std::fs::write(path.join("new_length"), 5u64.to_be_bytes()).unwrap();
+            let (_, reader_factory) = timestore::open(cfg.clone()).await.context("reopen db")?;
+            assert_eq!(
+                keys(&reader_factory.make().await.unwrap()),
+                vec![1, 2, 3, 4]
+            );
+            assert!(path.join("new_length").exists());
+        }
+
+        // the files don't hold 10 records so the length is rolled back
+        std::fs::write(path.join("new_length"), 10u64.to_be_bytes()).unwrap();
+        let (_, reader_factory) = timestore::open(cfg.clone()).await.context("reopen db")?;
+        assert_eq!(
+            keys(&reader_factory.make().await.unwrap()),
+            vec![1, 2, 3, 4]
+        );
+        assert!(!path.join("new_length").exists());
+
+        // a partially written file is rolled back
+        std::fs::write(path.join("new_length"), [0, 0, 0]).unwrap();
+        let (_, reader_factory) = timestore::open(cfg).await.context("reopen db")?;
+        assert_eq!(
+            keys(&reader_factory.make().await.unwrap()),
+            vec![1, 2, 3, 4]
+        );
+        assert!(!path.join("new_length").exists());
+
+        Ok::<_, anyhow::Error>(())
+    })
+    .unwrap();
+}
+
+#[derive(Default)]
+struct WriteCounter {
+    writes: AtomicUsize,
+}
+
+impl StoreObserver for WriteCounter {
+    fn on_write(&self, _bytes: usize) {
+        self.writes.fetch_add(1, Ordering::SeqCst);
+    }
+}
+
+#[test]
+fn test_write_combine_bytes() {
+    let exec = LocalExecutor::default();
+
+    exec.run(async move {
+        let mut path = temp_dir();
+        path.push(uuid::Uuid::new_v4().to_string());
+
+        let observer = Arc::new(WriteCounter::default());
+
+        let (writer_factory, reader_factory) = timestore::open(
+            timestore::ConfigBuilder::default()
+                .path(path)
+                .create_if_not_exists(true)
+                .tables(vec!["table0".to_owned()])
+                .write_combine_bytes(4096usize)
+                .observer(observer.clone())
+                .build()
+                .unwrap(),
+        )
+        .await
+        .context("open db")?;
+
+        let mut writer = writer_factory.make().await.unwrap();
+        let reader = reader_factory.make().await.unwrap();
+
+        let num_records = 5000u32;
+        for i in 0..num_records {
+            let pos = writer
+                .append_auto(vec![i.to_be_bytes().to_vec()])
+                .await
+                .unwrap();
+            assert_eq!(pos, u64::from(i));
+        }
+        assert!(writer.sync_point() < u64::from(num_records));
+        assert_eq!(writer.write_offset("table0").unwrap(), 4 * 5000);
+
+        writer.flush().await.unwrap();
+        assert_eq!(writer.sync_point(), u64::from(num_records));
+
+        // a data, offsets and keys write per flush instead of per record
+        let writes = observer.writes.load(Ordering::SeqCst);
+        assert!(writes * 50 < 3 * num_records as usize, "{} writes", writes);
+
+        for i in 0..num_records {
+            let res = reader.read("table0", u64::from(i)).await.unwrap().unwrap();
+            assert_eq!(&*res, i.to_be_bytes());
+        }
+
+        // duplicate keys flush the combined records and go through the regular path
+        writer.append(5000, vec![vec![1]]).await.unwrap();
+        writer.append(5000, vec![vec![2]]).await.unwrap();
+        assert_eq!(writer.sync_point(), 5002);
+
+        writer.close().await.unwrap();
+
+        Ok::<_, anyhow::Error>(())
+    })
+    .unwrap();
+}
+
+#[test]
+fn test_little_endian() {
+    let exec = LocalExecutor::default();
+
+    exec.run(async move {
+        let mut path = temp_dir();
+        path.push(uuid::Uuid::new_v4().to_string());
+
+        // files of a store that was written by another tool
+        let le = |vals: &[u64]| {
+            vals.iter()
+                .flat_map(|val| val.to_le_bytes())
+                .collect::<Vec<u8>>()
+        };
+        std::fs::create_dir_all(path.join("table0")).unwrap();
+        std::fs::write(path.join("length"), le(&[3])).unwrap();
+        std::fs::write(path.join("keys"), le(&[5, 260, 1 << 40])).unwrap();
+        std::fs::write(path.join("table0/offsets"), le(&[1, 3, 6])).unwrap();
+        std::fs::write(path.join("table0/data"), b"abbccc").unwrap();
+
+        let cfg = timestore::ConfigBuilder::default()
+            .path(path.clone())
+            .tables(vec!["table0".to_owned()])
+            .create_if_not_exists(false)
+            .endianness(timestore::Endianness::Little)
+            .build()
+            .unwrap();
+
+        {
+            let (writer_factory, reader_factory) =
+                timestore::open(cfg.clone()).await.context("open db")?;
+            let mut writer = writer_factory.make().await.unwrap();
+            let reader = reader_factory.make().await.unwrap();
+
+            assert_eq!(
+                reader.keys().iter_from(0).collect::<Vec<u64>>(),
+                vec![5, 260, 1 << 40]
+            );
+            assert_eq!(&*reader.read("table0", 260).await.unwrap().unwrap(), b"bb");
+
+            writer
+                .append((1 << 40) + 1, vec![b"dddd".to_vec()])
+                .await
+                .unwrap();
+        }
+
+        assert_eq!(
+            &std::fs::read(path.join("keys")).unwrap()[..32],
+            &le(&[5, 260, 1 << 40, (1 << 40) + 1])
+        );
+        assert_eq!(
+            &std::fs::read(path.join("table0/offsets")).unwrap()[..32],
+            &le(&[1, 3, 6, 10])
+        );
+        assert_eq!(&std::fs::read(path.join("length")).unwrap()[..8], &le(&[4]));
+
+        let (_writer_factory, reader_factory) =
+            timestore::open(cfg.clone()).await.context("reopen db")?;
+        let reader = reader_factory.make().await.unwrap();
+        assert_eq!(
+            &*reader.read("table0", (1 << 40) + 1).await.unwrap().unwrap(),
+            b"dddd"
+        );
+        assert_eq!(
+            reader.store_config().endianness,
+            timestore::Endianness::Little
+        );
+
+        let lazy = timestore::open_lazy(cfg.clone()).await.unwrap();
+        assert_eq!(lazy.position(1 << 40).await.unwrap(), Some(2));
+        assert_eq!(&*lazy.read("table0", 5).await.unwrap().unwrap(), b"a");
+        assert!(timestore::verify(&cfg).await.unwrap().is_ok());
+
+        let big_endian = timestore::ConfigBuilder::default()
+            .path(path)
+            .create_if_not_exists(false)
+            .tables(vec!["table0".to_owned()])
+            .build()
+            .unwrap();
+        assert!(timestore::open(big_endian).await.is_err());
+
+        Ok::<_, anyhow::Error>(())
+    })
+    .unwrap();
+}
+
+#[derive(Default)]
+struct VerifyProgress {
+    progress: std::sync::Mutex<Vec<(String, u64, u64)>>,
+}
+
+impl StoreObserver for VerifyProgress {
+    fn on_verify_progress(&self, table: &str, verified: u64, total: u64) {
+        self.progress
+            .lock()
+            .unwrap()
+            .push((table.to_owned(), verified, total));
+    }
+}
+
+#[test]
+fn test_verify_on_open() {
+    let exec = LocalExecutor::default();
+
+    exec.run(async move {
+        let mut path = temp_dir();
+        path.push(uuid::Uuid::new_v4().to_string());
+
+        let builder = || {
+            let mut builder = timestore::ConfigBuilder::default();
+            builder
+                .path(path.clone())
+                .create_if_not_exists(true)
+                .tables(vec!["table0".to_owned(), "table1".to_owned()])
+                .data_segment_size(Some(100));
+            builder
+        };
+
+        {
+            let (writer_factory, _reader_factory) = timestore::open(builder().build().unwrap())
+                .await
+                .context("open db")?;
+            let mut writer = writer_factory.make().await.unwrap();
+            for key in 1..=4u64 {
+                writer
+                    .append(key, vec![vec![key as u8; 30], Vec::new()])
+                    .await
+                    .unwrap();
+            }
+        }
+
+        let observer = Arc::new(VerifyProgress::default());
+        let (_writer_factory, reader_factory) = timestore::open(
+            builder()
+                .verify_on_open(true)
+                .observer(observer.clone())
+                .build()
+                .unwrap(),
+        )
+        .await
+        .context("open healthy db")?;
+        let progress = std::mem::take(&mut *observer.progress.lock().unwrap());
+        assert_eq!(progress.len(), 8);
+        assert_eq!(progress[3], ("table0".to_owned(), 4, 4));
+        assert_eq!(progress[7], ("table1".to_owned(), 4, 4));
+        drop(reader_factory);
+
+        // The third record of table0 now ends past the end of the first data file at 90.
+        // The data files are still long enough for the offsets so a plain open doesn't notice.
+        {
+            use std::os::unix::fs::FileExt;
+            let file = std::fs::OpenOptions::new()
+                .write(true)
+                .open(path.join("table0/offsets"))
+                .unwrap();
+            file.write_all_at(&100u64.to_be_bytes(), 16).unwrap();
+        }
+
+        assert!(timestore::open(builder().build().unwrap()).await.is_ok());
+        let err = timestore::open(builder().verify_on_open(true).build().unwrap())
+            .await
+            .err()
+            .unwrap();
+        assert!(format!("{:#}", err).contains("record at position 2 of table 'table0'"));
+
+        Ok::<_, anyhow::Error>(())
+    })
+    .unwrap();
+}
+
+#[test]
+fn test_concurrent_open() {
+    let exec = LocalExecutor::default();
+
+    exec.run(async move {
+        let mut path = temp_dir();
+        path.push(uuid::Uuid::new_v4().to_string());
+
+        let cfg = timestore::ConfigBuilder::default()
+            .path(path.clone())
+            .create_if_not_exists(true)
+            .tables(vec!["table0".to_owned(), "table1".to_owned()])
+            .build()
+            .unwrap();
+
+        // Separate tasks so each open is only polled when its own io completes.
+        let first = glommio::spawn_local(timestore::open(cfg.clone()));
+        let second = glommio::spawn_local(timestore::open(cfg.clone()));
+        let (first_writer_factory, first_reader_factory) = first.await.context("first open")?;
+        let (second_writer_factory, second_reader_factory) = second.await.context("second open")?;
+        assert_ne!(
+            first_reader_factory.created(),
+            second_reader_factory.created()
+        );
+
+        for reader_factory in [&first_reader_factory, &second_reader_factory] {
+            let reader = reader_factory.make().await.unwrap();
+            assert_eq!(reader.keys().last(), None);
+        }
+        assert_eq!(std::fs::read(path.join("length")).unwrap()[..8], [0; 8]);
+
+        // Only the open that initialized the store has the lock, the other one can't make a writer while it is held.
+        let first_writer = first_writer_factory.make().await;
+        let second_writer = second_writer_factory.make().await;
+        assert_eq!(first_writer.is_ok() as u8 + second_writer.is_ok() as u8, 1);
+        let err = first_writer
+            .as_ref()
+            .err()
+            .or(second_writer.as_ref().err())
+            .unwrap();
+        assert!(err.to_string().contains("locked by another writer"));
+
+        let mut writer = first_writer.or(second_writer).unwrap();
+        writer.append(1, vec![vec![1], vec![2]]).await.unwrap();
+        assert!(timestore::reopen_writer(cfg.clone()).await.is_err());
+        drop(writer);
+
+        let (_writer, reader_factory) = timestore::reopen_writer(cfg).await.unwrap();
+        let reader = reader_factory.make().await.unwrap();
+        assert_eq!(&*reader.read("table1", 1).await.unwrap().unwrap(), &[2]);
+
+        Ok::<_, anyhow::Error>(())
+    })
+    .unwrap();
+}
+
+#[test]
+fn test_make_after_other_writer() {
+    let exec = LocalExecutor::default();
+
+    exec.run(async move {
+        let mut path = temp_dir();
+        path.push(uuid::Uuid::new_v4().to_string());
+
+        let cfg = timestore::ConfigBuilder::default()
+            .path(path.clone())
+            .create_if_not_exists(true)
+            .tables(vec!["table0".to_owned()])
+            .build()
+            .unwrap();
+
+        let (first_writer_factory, _first_reader_factory) =
+            timestore::open(cfg.clone()).await.context("open db")?;
+        let mut first_writer = first_writer_factory.make().await.unwrap();
+        first_writer.append(1, vec![vec![1]]).await.unwrap();
+
+        let (second_writer_factory, _second_reader_factory) = timestore::open(cfg.clone())
+            .await
+            .context("open db again")?;
+        first_writer.append(2, vec![vec![2]]).await.unwrap();
+        drop(first_writer);
+
+        // the second factory doesn't have the record the first writer appended after it was opened
+        let err = second_writer_factory.make().await.err().unwrap();
+        assert!(err.to_string().contains("opened again"), "{}", err);
+
+        let (mut writer, reader_factory) = timestore::reopen_writer(cfg).await.unwrap();
+        writer.append(3, vec![vec![3]]).await.unwrap();
+        let reader = reader_factory.make().await.unwrap();
+        assert_eq!(
+            reader.keys().iter_from(0).collect::<Vec<u64>>(),
+            vec![1, 2, 3]
+        );
+        assert_eq!(&*reader.read("table0", 2).await.unwrap().unwrap(), &[2]);
+
+        Ok::<_, anyhow::Error>(())
+    })
+    .unwrap();
+}
+
+#[test]
+fn test_open_initialized_store_creates_no_files() {
+    let exec = LocalExecutor::default();
+
+    exec.run(async move {
+        let mut path = temp_dir();
+        path.push(uuid::Uuid::new_v4().to_string());
+
+        let cfg = |create_if_not_exists: bool| {
+            timestore::ConfigBuilder::default()
+                .path(path.clone())
+                .create_if_not_exists(create_if_not_exists)
+                .tables(vec!["table0".to_owned()])
+                .bloom_filter_bits(Some(1 << 10))
+                .build()
+                .unwrap()
+        };
+        let files = || {
+            let mut names = std::fs::read_dir(&path)
+                .unwrap()
+                .map(|entry| entry.unwrap().file_name())
+                .collect::<Vec<_>>();
+            names.sort();
+            names
+        };
+
+        {
+            let (writer_factory, _reader_factory) =
+                timestore::open(cfg(true)).await.context("open db")?;
+            let mut writer = writer_factory.make().await.unwrap();
+            writer.append(1, vec![vec![1]]).await.unwrap();
+        }
+
+        // e.g. a store that was copied without its lock and bloom files
+        std::fs::remove_file(path.join("lock")).unwrap();
+        std::fs::remove_file(path.join("bloom")).unwrap();
+        let before = files();
+        for create_if_not_exists in [false, true] {
+            let (_, reader_factory) = timestore::open(cfg(create_if_not_exists))
+                .await
+                .context("reopen db")?;
+            let reader = reader_factory.make().await.unwrap();
+            assert_eq!(&*reader.read("table0", 1).await.unwrap().unwrap(), &[1]);
+            assert_eq!(files(), before);
+        }
+
+        // making a writer creates the lock file and writes the rebuilt filter
+        let (writer_factory, _reader_factory) =
+            timestore::open(cfg(false)).await.context("reopen db")?;
+        writer_factory.make().await.unwrap();
+        assert!(path.join("lock").is_file());
+        assert!(path.join("bloom").is_file());
+
+        Ok::<_, anyhow::Error>(())
+    })
+    .unwrap();
+}
+
+#[test]
+fn test_open_created() {
+    let exec = LocalExecutor::default();
+
+    exec.run(async move {
+        let mut path = temp_dir();
+        path.push(uuid::Uuid::new_v4().to_string());
+
+        let cfg = timestore::ConfigBuilder::default()
+            .path(path)
+            .create_if_not_exists(true)
+            .tables(vec!["table0".to_owned()])
+            .build()
+            .unwrap();
+
+        {
+            let (writer_factory, reader_factory) =
+                timestore::open(cfg.clone()).await.context("open db")?;
+            assert!(writer_factory.created());
+            assert!(reader_factory.created());
+        }
+
+        let (writer_factory, reader_factory) = timestore::open(cfg).await.context("reopen db")?;
+        assert!(!writer_factory.created());
+        assert!(!reader_factory.created());
+
+        Ok::<_, anyhow::Error>(())
+    })
+    .unwrap();
+}
+
+#[test]
+fn test_store_path() {
+    let exec = LocalExecutor::default();
+
+    exec.run(async move {
+        let mut path = temp_dir();
+        path.push(uuid::Uuid::new_v4().to_string());
+
+        let cfg = timestore::ConfigBuilder::default()
+            .path(path.clone())
+            .create_if_not_exists(true)
+            .tables(vec!["table0".to_owned()])
+            .build()
+            .unwrap();
+
+        let (writer_factory, reader_factory) = timestore::open(cfg.clone()).await?;
+        let writer = writer_factory.make().await.unwrap();
+        let reader = reader_factory.make().await.unwrap();
+
+        assert_eq!(writer.path(), cfg.path());
+        assert_eq!(reader.path(), cfg.path());
+        assert_eq!(reader.clone().path(), path.as_path());
+
+        Ok::<_, anyhow::Error>(())
+    })
+    .unwrap();
+}
+
+#[test]
+fn test_keys_file_size_length() {
+    let exec = LocalExecutor::default();
+
+    exec.run(async move {
+        let mut path = temp_dir();
+        path.push(uuid::Uuid::new_v4().to_string());
+
+        let cfg = |length_source| {
+            timestore::ConfigBuilder::default()
+                .path(path.clone())
+                .create_if_not_exists(true)
+                .tables(vec!["table0".to_owned()])
+                .direct_io(false)
+                .length_source(length_source)
+                .build()
+                .unwrap()
+        };
+        let keys_size = || std::fs::metadata(path.join("keys")).unwrap().len();
+        let read_all = |reader: timestore::Reader| async move {
+            let mut values = Vec::new();
+            for key in reader.keys().iter_from(0) {
+                values.push((
+                    key,
+                    reader.read("table0", key).await.unwrap().unwrap().to_vec(),
+                ));
+            }
+            values
+        };
+
+        {
+            let (writer_factory, _reader_factory) =
+                timestore::open(cfg(timestore::LengthSource::KeysFileSize)).await?;
+            let mut writer = writer_factory.make().await.unwrap();
+            writer.append(1, vec![b"one".to_vec()]).await.unwrap();
+            writer.append(2, vec![b"two".to_vec()]).await.unwrap();
+            writer
+                .append_many(vec![(3, vec![b"three".to_vec()]), (4, vec![Vec::new()])])
+                .await
+                .unwrap();
+            writer
+                .overwrite_last(4, vec![b"four".to_vec()])
+                .await
+                .unwrap();
+        }
+
+        // the length file is never rewritten
+        assert_eq!(
+            std::fs::read(path.join("length")).unwrap(),
+            0u64.to_be_bytes()
+        );
+        assert!(!path.join("new_length").exists());
+        assert_eq!(keys_size(), 32);
+
+        // a key that was only partially written at the end of the file is removed on open
+        {
+            let mut file = std::fs::OpenOptions::new()
+                .append(true)
+                .open(path.join("keys"))
+                .unwrap();
+            std::io::Write::write_all(&mut file, &[0, 0, 1]).unwrap();
+        }
+        assert_eq!(keys_size(), 35);
+
+        {
+            let (writer_factory, reader_factory) =
+                timestore::open(cfg(timestore::LengthSource::KeysFileSize)).await?;
+            assert_eq!(keys_size(), 32);
+            let reader = reader_factory.make().await.unwrap();
+            assert_eq!(
+                read_all(reader).await,
+                vec![
+                    (1, b"one".to_vec()),
+                    (2, b"two".to_vec()),
+                    (3, b"three".to_vec()),
+                    (4, b"four".to_vec())
+                ]
+            );
+
+            let mut writer = writer_factory.make().await.unwrap();
+            assert_eq!(writer.append(5, vec![b"five".to_vec()]).await.unwrap(), 4);
+            assert_eq!(writer.truncate_after(3).await.unwrap(), 2);
+            assert_eq!(keys_size(), 24);
+        }
+
+        let (writer_factory, reader_factory) =
+            timestore::open(cfg(timestore::LengthSource::KeysFileSize)).await?;
+        let reader = reader_factory.make().await.unwrap();
+        assert_eq!(
+            reader.store_config().length_source,
+            timestore::LengthSource::KeysFileSize
+        );
+        assert_eq!(
+            read_all(reader).await,
+            vec![
+                (1, b"one".to_vec()),
+                (2, b"two".to_vec()),
+                (3, b"three".to_vec())
+            ]
+        );
+        drop(writer_factory);
+        drop(reader_factory);
+
+        // the store can't be opened with the length file
+        assert!(timestore::open(cfg(timestore::LengthSource::LengthFile))
+            .await
+            .is_err());
+
+        // direct io pads the keys file so it can't be used for the length
+        let direct_cfg = timestore::ConfigBuilder::default()
+            .path(path.clone())
+            .create_if_not_exists(true)
+            .tables(vec!["table0".to_owned()])
+            .direct_io(true)
+            .length_source(timestore::LengthSource::KeysFileSize)
+            .build()
+            .unwrap();
+        assert!(timestore::open(direct_cfg).await.is_err());
+
+        Ok::<_, anyhow::Error>(())
+    })
+    .unwrap();
+}
@@ -0,0 +1,184 @@
+use std::{
+    env::temp_dir,
+    fs::OpenOptions,
+    io::{Seek, SeekFrom, Write},
+};
+
+use anyhow::Context;
+use glommio::LocalExecutor;
+use timestore::TimestoreError;
+
+#[test]
+fn test_error_variants() {
+    let exec = LocalExecutor::default();
+
+    exec.run(async move {
+        let mut path = temp_dir();
+        path.push(uuid::Uuid::new_v4().to_string());
+
+        let cfg = timestore::ConfigBuilder::default()
+            .path(path.clone())
+            .create_if_not_exists(true)
+            .tables(vec!["table0".to_owned()])
+            .build()
+            .unwrap();
+
+        {
+            let (writer_factory, reader_factory) =
+                timestore::open(cfg.clone()).await.context("open db")?;
+            let mut writer = writer_factory.make().await.unwrap();
+            let reader = reader_factory.make().await.unwrap();
+
+            for key in 1..=3u64 {
+                writer.append(key, vec![vec![1, 2, 3]]).await.unwrap();
+            }
+
+            match reader.read("table1", 1).await {
+                Err(TimestoreError::TableNotFound(table)) => assert_eq!(table, "table1"),
+                res => panic!("unexpected result {:?}", res.map(|r| r.is_some())),
+            }
+
+            let input = futures::io::Cursor::new(b"4,\xff\xfe\n".to_vec());
+            match timestore::import_csv(&mut writer, input, 0, &[1]).await {
+                Err(TimestoreError::Io(e)) => {
+                    assert_eq!(e.kind(), std::io::ErrorKind::InvalidData)
+                }
+                res => panic!("unexpected result {:?}", res),
+            }
+
+            // errors of the file io keep their io::Error
+            let missing = path.join("missing");
+            match timestore::read_store_info(&missing, cfg.direct_io()).await {
+                Err(TimestoreError::Io(e)) => assert_eq!(e.kind(), std::io::ErrorKind::NotFound),
+                res => panic!("unexpected result {:?}", res),
+            }
+
+            match writer.append(4, Vec::new()).await {
+                Err(TimestoreError::Other(e)) => {
+                    assert!(e.to_string().contains("number of values"))
+                }
+                res => panic!("unexpected result {:?}", res),
+            }
+        }
+
+        let mut file = OpenOptions::new()
+            .write(true)
+            .open(path.join("keys"))
+            .unwrap();
+        file.seek(SeekFrom::Start(16)).unwrap();
+        file.write_all(&1u64.to_be_bytes()).unwrap();
+
+        match timestore::open(cfg).await {
+            Err(TimestoreError::OrderingViolation { index, prev, val }) => {
+                assert_eq!((index, prev, val), (2, 2, 1))
+            }
+            res => panic!("unexpected result {:?}", res.err()),
+        }
+
+        Ok::<_, anyhow::Error>(())
+    })
+    .unwrap();
+}
+
+fn is_count_error<T>(res: Result<T, TimestoreError>) -> bool {
+    match res {
+        Err(TimestoreError::Other(e)) => e.to_string().contains("number of values"),
+        _ => false,
+    }
+}
+
+// Contents of all files of the store.
+fn snapshot(path: &std::path::Path) -> std::collections::BTreeMap<std::path::PathBuf, Vec<u8>> {
+    let mut files = std::collections::BTreeMap::new();
+    let mut dirs = vec![path.to_owned()];
+    while let Some(dir) = dirs.pop() {
+        for entry in std::fs::read_dir(dir).unwrap() {
+            let path = entry.unwrap().path();
+            if path.is_dir() {
+                dirs.push(path);
+            } else {
+                files.insert(path.clone(), std::fs::read(path).unwrap());
+            }
+        }
+    }
+    files
+}
+
+#[test]
+fn test_wrong_table_count() {
+    let exec = LocalExecutor::default();
+
+    exec.run(async move {
+        let mut path = temp_dir();
+        path.push(uuid::Uuid::new_v4().to_string());
+
+        let cfg = timestore::ConfigBuilder::default()
+            .path(path.clone())
+            .create_if_not_exists(true)
+            .tables(vec!["table0".to_owned(), "table1".to_owned()])
+            .tag_index(true)
+            .build()
+            .unwrap();
+
+        let (writer_factory, _reader_factory) =
+            timestore::open(cfg.clone()).await.context("open db")?;
+        let mut writer = writer_factory.make().await.unwrap();
+        writer.append(1, vec![vec![1], vec![2]]).await.unwrap();
+
+        let before = snapshot(&path);
+        assert!(is_count_error(writer.append(2, vec![vec![1]]).await));
+        assert!(is_count_error(
+            writer.append_with_tag(2, 5, vec![vec![1]]).await
+        ));
+        assert!(is_count_error(
+            writer.append_concat(2, &[1, 2, 3], &[3]).await
+        ));
+        assert!(is_count_error(
+            writer.append_auto(vec![vec![1], vec![2], vec![3]]).await
+        ));
+        assert!(is_count_error(writer.append_now(Vec::new()).await));
+        assert!(is_count_error(
+            writer
+                .append_many(vec![(2, vec![vec![1], vec![2]]), (3, vec![vec![1]])])
+                .await
+        ));
+        assert!(is_count_error(
+            writer.overwrite_last(1, vec![vec![1]]).await
+        ));
+        assert!(is_count_error(writer.estimate_append(&[vec![1]])));
+        let frame = {
+            let mut frame = 2u64.to_be_bytes().to_vec();
+            frame.extend_from_slice(&1u32.to_be_bytes());
+            frame.extend_from_slice(&1u64.to_be_bytes());
+            frame.push(7);
+            frame
+        };
+        let res = writer
+            .import_frames(futures::stream::iter(vec![Ok(frame)]))
+            .await;
+        assert!(format!("{:#}", res.unwrap_err()).contains("number of values"));
+
+        let mut multi_writer = timestore::MultiWriter::new(vec![writer]).await.unwrap();
+        assert!(is_count_error(
+            multi_writer.append_all(2, vec![vec![vec![1]]]).await
+        ));
+        let writer = multi_writer.into_writers().pop().unwrap();
+
+        let mut buffered = timestore::BufferedWriter::new(writer, 1, 1).unwrap();
+        assert!(is_count_error(buffered.push(2, vec![vec![1]]).await));
+        let writer = buffered.close().await.unwrap();
+
+        let (handle, task) = timestore::WriterHandle::new(writer, 4);
+        assert!(is_count_error(handle.append(2, vec![vec![1]]).await));
+        drop(handle);
+        let mut writer = task.await;
+
+        assert_eq!(snapshot(&path), before);
+
+        // the writer is still usable after the rejected records
+        assert_eq!(writer.append(2, vec![vec![3], vec![4]]).await.unwrap(), 1);
+
+        Ok::<_, anyhow::Error>(())
+    })
+    .unwrap();
+}
@@ -0,0 +1,145 @@
+use std::{env::temp_dir, fs::File};
+
+use anyhow::Context;
+use arrow::array::{Array, BinaryArray, UInt64Array};
+use glommio::LocalExecutor;
+use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+
+#[test]
+fn test_export_to_parquet() {
+    let exec = LocalExecutor::default();
+
+    exec.run(async move {
+        let mut path = temp_dir();
+        path.push(uuid::Uuid::new_v4().to_string());
+
+        let (writer_factory, reader_factory) = timestore::open(
+            timestore::ConfigBuilder::default()
+                .path(path.clone())
+                .create_if_not_exists(true)
+                .segment_length(1024)
+                .tables(vec!["table0".to_owned(), "table1".to_owned()])
+                .build()
+                .unwrap(),
+        )
+        .await
+        .context("open db")?;
+
+        let mut writer = writer_factory.make().await.unwrap();
+        let reader = reader_factory.make().await.unwrap();
+
+        writer
+            .append(12, vec![b"123".to_vec(), b"345".to_vec()])
+            .await
+            .unwrap();
+        writer
+            .append(18, vec![b"888".to_vec(), b"999".to_vec()])
+            .await
+            .unwrap();
+
+        path.push("export.parquet");
+        timestore::export_to_parquet(&reader, "table1", 8, 20, &path)
+            .await
+            .unwrap();
+
+        let file = File::open(&path).unwrap();
+        let batches = ParquetRecordBatchReaderBuilder::try_new(file)
+            .unwrap()
+            .build()
+            .unwrap()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(batches.len(), 1);
+        let batch = &batches[0];
+        assert_eq!(batch.num_rows(), 2);
+
+        let keys = batch
+            .column(0)
+            .as_any()
+            .downcast_ref::<UInt64Array>()
+            .unwrap();
+        let values = batch
+            .column(1)
+            .as_any()
+            .downcast_ref::<BinaryArray>()
+            .unwrap();
+        assert_eq!(keys.values(), &[12, 18]);
+        assert_eq!(values.value(0), b"345");
+        assert_eq!(values.value(1), b"999");
+        assert_eq!(values.len(), 2);
+
+        Ok::<_, anyhow::Error>(())
+    })
+    .unwrap();
+}
+
+#[test]
+fn test_export_to_parquet_many_batches() {
+    let exec = LocalExecutor::default();
+
+    exec.run(async move {
+        let mut path = temp_dir();
+        path.push(uuid::Uuid::new_v4().to_string());
+
+        let (writer_factory, reader_factory) = timestore::open(
+            timestore::ConfigBuilder::default()
+                .path(path.clone())
+                .create_if_not_exists(true)
+                .tables(vec!["table".to_owned()])
+                .build()
+                .unwrap(),
+        )
+        .await
+        .context("open db")?;
+
+        let mut writer = writer_factory.make().await.unwrap();
+        let reader = reader_factory.make().await.unwrap();
+
+        writer
+            .append_many(
+                (1..=150_000u64)
+                    .map(|k| (k, vec![k.to_be_bytes().to_vec()]))
+                    .collect(),
+            )
+            .await
+            .unwrap();
+
+        path.push("export.parquet");
+        timestore::export_to_parquet(&reader, "table", 0, 200_000, &path)
+            .await
+            .unwrap();
+
+        let file = File::open(&path).unwrap();
+        let builder = ParquetRecordBatchReaderBuilder::try_new(file).unwrap();
+        // the rows are written in more than one batch
+        assert_eq!(builder.metadata().num_row_groups(), 3);
+        let batches = builder
+            .build()
+            .unwrap()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+
+        let mut expected = 1u64;
+        for batch in batches.iter() {
+            let keys = batch
+                .column(0)
+                .as_any()
+                .downcast_ref::<UInt64Array>()
+                .unwrap();
+            let values = batch
+                .column(1)
+                .as_any()
+                .downcast_ref::<BinaryArray>()
+                .unwrap();
+            for i in 0..batch.num_rows() {
+                assert_eq!(keys.value(i), expected);
+                assert_eq!(values.value(i), expected.to_be_bytes());
+                expected += 1;
+            }
+        }
+        assert_eq!(expected, 150_001);
+
+        Ok::<_, anyhow::Error>(())
+    })
+    .unwrap();
+}
@@ -0,0 +1,89 @@
+use std::env::temp_dir;
+
+use anyhow::Context;
+use futures::StreamExt;
+use glommio::LocalExecutor;
+
+fn config(tables: &[&str]) -> timestore::Config {
+    let mut path = temp_dir();
+    path.push(uuid::Uuid::new_v4().to_string());
+
+    timestore::ConfigBuilder::default()
+        .path(path)
+        .create_if_not_exists(true)
+        .tables(tables.iter().map(|&t| t.to_owned()).collect())
+        .build()
+        .unwrap()
+}
+
+#[test]
+fn test_frames_round_trip() {
+    let exec = LocalExecutor::default();
+
+    exec.run(async move {
+        let values = |key: u64| {
+            vec![
+                vec![key as u8; key as usize % 7],
+                key.to_be_bytes().to_vec(),
+            ]
+        };
+
+        let (writer_factory, reader_factory) = timestore::open(config(&["table0", "table1"]))
+            .await
+            .context("open source db")?;
+        let mut writer = writer_factory.make().await.unwrap();
+        for key in 1..=20u64 {
+            writer.append(key * 3, values(key)).await.unwrap();
+        }
+        let reader = reader_factory.make().await.unwrap();
+
+        // frames from the whole store are piped into the writer of another store
+        let (writer_factory, dst_reader_factory) = timestore::open(config(&["a", "b"]))
+            .await
+            .context("open destination db")?;
+        let mut dst_writer = writer_factory.make().await.unwrap();
+        let imported = dst_writer
+            .import_frames(reader.export_frames(0, u64::MAX))
+            .await
+            .unwrap();
+        assert_eq!(imported, 20);
+
+        let dst_reader = dst_reader_factory.make().await.unwrap();
+        assert_eq!(
+            dst_reader.keys().iter_from(0).collect::<Vec<u64>>(),
+            reader.keys().iter_from(0).collect::<Vec<u64>>()
+        );
+        for key in 1..=20u64 {
+            for (table, value) in ["a", "b"].into_iter().zip(values(key)) {
+                let res = dst_reader.read(table, key * 3).await.unwrap().unwrap();
+                assert_eq!(&*res, &*value);
+            }
+        }
+
+        // ranges follow iter
+        let frames = reader
+            .export_frames(15, 30)
+            .map(|frame| frame.unwrap())
+            .collect::<Vec<_>>()
+            .await;
+        assert_eq!(frames.len(), 5);
+        assert_eq!(&frames[0][..8], &18u64.to_be_bytes());
+
+        let (writer_factory, _reader_factory) = timestore::open(config(&["a"]))
+            .await
+            .context("open db with one table")?;
+        let mut dst_writer = writer_factory.make().await.unwrap();
+        assert!(dst_writer
+            .import_frames(futures::stream::iter(vec![Ok(frames[0].clone())]))
+            .await
+            .is_err());
+        let truncated = frames[0][..frames[0].len() - 1].to_vec();
+        assert!(dst_writer
+            .import_frames(futures::stream::iter(vec![Ok(truncated)]))
+            .await
+            .is_err());
+
+        Ok::<_, anyhow::Error>(())
+    })
+    .unwrap();
+}
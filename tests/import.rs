@@ -0,0 +1,81 @@
+use std::env::temp_dir;
+
+use anyhow::Context;
+use glommio::LocalExecutor;
+use timestore::IterParamsBuilder;
+
+#[test]
+fn test_import_csv() {
+    let exec = LocalExecutor::default();
+
+    exec.run(async move {
+        let mut path = temp_dir();
+        path.push(uuid::Uuid::new_v4().to_string());
+
+        let (writer_factory, reader_factory) = timestore::open(
+            timestore::ConfigBuilder::default()
+                .path(path)
+                .create_if_not_exists(true)
+                .segment_length(1024)
+                .tables(vec!["table0".to_owned(), "table1".to_owned()])
+                .build()
+                .unwrap(),
+        )
+        .await
+        .context("open db")?;
+
+        let mut writer = writer_factory.make().await.unwrap();
+        let reader = reader_factory.make().await.unwrap();
+
+        let csv = b"a,12,123\nb,18,888\nc,21,777\n";
+        let count = timestore::import_csv(&mut writer, &csv[..], 1, &[2, 0])
+            .await
+            .unwrap();
+        assert_eq!(count, 3);
+
+        let res = reader.read("table0", 18).await.unwrap().unwrap();
+        assert_eq!(&*res, b"888");
+        let res = reader.read("table1", 21).await.unwrap().unwrap();
+        assert_eq!(&*res, b"c");
+
+        let mut iter = reader
+            .iter(IterParamsBuilder::default().from(8).to(30).build().unwrap())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(iter.next().await.unwrap().unwrap().0, (0, 12));
+        assert_eq!(iter.next().await.unwrap().unwrap().0, (12, 18));
+        assert_eq!(iter.next().await.unwrap().unwrap().0, (18, 21));
+        assert!(iter.next().await.unwrap().is_none());
+
+        let csv = b"d,30,1\ne,25,2\n";
+        let err = timestore::import_csv(&mut writer, &csv[..], 1, &[2, 0])
+            .await
+            .unwrap_err();
+        assert!(format!("{:#}", err).contains("line 2"));
+
+        let csv = b"f,40\n";
+        let err = timestore::import_csv(&mut writer, &csv[..], 1, &[2, 0])
+            .await
+            .unwrap_err();
+        assert!(format!("{:#}", err).contains("line 1"));
+
+        // the first key is checked against the last key of the store
+        let csv = b"g,20,1\n";
+        let err = timestore::import_csv(&mut writer, &csv[..], 1, &[2, 0])
+            .await
+            .unwrap_err();
+        assert!(format!("{:#}", err).contains("line 1"));
+        assert_eq!(reader.keys().last(), Some(21));
+
+        // rows of a batch are only appended if all of them can be imported
+        let csv = b"h,50,1\ni,60\n";
+        assert!(timestore::import_csv(&mut writer, &csv[..], 1, &[2, 0])
+            .await
+            .is_err());
+        assert_eq!(reader.keys().last(), Some(21));
+
+        Ok::<_, anyhow::Error>(())
+    })
+    .unwrap();
+}
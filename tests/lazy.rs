@@ -0,0 +1,115 @@
+use std::{
+    env::temp_dir,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+};
+
+use anyhow::Context;
+use glommio::LocalExecutor;
+use timestore::StoreObserver;
+
+#[derive(Default)]
+struct ReadObserver {
+    reads: AtomicUsize,
+    read_bytes: AtomicUsize,
+}
+
+impl StoreObserver for ReadObserver {
+    fn on_read(&self, _table: &str, bytes: usize) {
+        self.reads.fetch_add(1, Ordering::SeqCst);
+        self.read_bytes.fetch_add(bytes, Ordering::SeqCst);
+    }
+}
+
+#[test]
+fn test_open_lazy() {
+    let exec = LocalExecutor::default();
+
+    exec.run(async move {
+        let mut path = temp_dir();
+        path.push(uuid::Uuid::new_v4().to_string());
+
+        let observer = Arc::new(ReadObserver::default());
+        let cfg = timestore::ConfigBuilder::default()
+            .path(path)
+            .create_if_not_exists(true)
+            .tables(vec!["table0".to_owned(), "table1".to_owned()])
+            .open_buffer_size(4096)
+            .open_concurrency(1)
+            .observer(observer.clone())
+            .build()
+            .unwrap();
+
+        let values = |key: u64| {
+            vec![
+                key.to_be_bytes().to_vec(),
+                vec![key as u8; key as usize % 5],
+            ]
+        };
+
+        let (writer_factory, _reader_factory) =
+            timestore::open(cfg.clone()).await.context("open db")?;
+        let mut writer = writer_factory.make().await.unwrap();
+        for chunk in 0..20u64 {
+            writer
+                .append_many(
+                    (chunk * 10_000..(chunk + 1) * 10_000)
+                        .map(|i| (i * 2, values(i * 2)))
+                        .collect(),
+                )
+                .await
+                .unwrap();
+        }
+
+        let reads = observer.reads.load(Ordering::SeqCst);
+        let read_bytes = observer.read_bytes.load(Ordering::SeqCst);
+
+        // opening doesn't read the keys and offsets of the records
+        let mut reader = timestore::open_lazy(cfg).await.context("open lazy")?;
+        assert_eq!(reader.len(), 200_000);
+        assert_eq!(observer.reads.load(Ordering::SeqCst), reads);
+        assert_eq!(observer.read_bytes.load(Ordering::SeqCst), read_bytes);
+
+        // the values are read when they are looked up
+        reader.read("table0", 1234).await.unwrap().unwrap();
+        assert_eq!(observer.reads.load(Ordering::SeqCst), reads + 1);
+        assert_eq!(observer.read_bytes.load(Ordering::SeqCst), read_bytes + 8);
+
+        for key in [0, 2, 1234, 99_998, 250_000, 399_998] {
+            let position = reader.position(key).await.unwrap().unwrap();
+            assert_eq!(position, key / 2);
+            assert_eq!(reader.key_at_position(position).await.unwrap(), Some(key));
+            for (table, value) in ["table0", "table1"].into_iter().zip(values(key)) {
+                let res = reader.read(table, key).await.unwrap().unwrap();
+                assert_eq!(&*res, &*value);
+                let res = reader
+                    .read_at_position(table, position)
+                    .await
+                    .unwrap()
+                    .unwrap();
+                assert_eq!(&*res, &*value);
+            }
+        }
+
+        for key in [1, 1235, 399_999, 400_000] {
+            assert!(reader.position(key).await.unwrap().is_none());
+            assert!(reader.read("table0", key).await.unwrap().is_none());
+        }
+        assert!(reader.key_at_position(200_000).await.unwrap().is_none());
+        assert!(reader.read("table2", 0).await.is_err());
+
+        // appends are visible after a refresh
+        writer.append(400_000, values(400_000)).await.unwrap();
+        assert!(reader.read("table0", 400_000).await.unwrap().is_none());
+        reader.refresh().await.unwrap();
+        let res = reader.read("table0", 400_000).await.unwrap().unwrap();
+        assert_eq!(&*res, 400_000u64.to_be_bytes());
+
+        reader.close().await.unwrap();
+
+        Ok::<_, anyhow::Error>(())
+    })
+    .unwrap();
+}
@@ -0,0 +1,263 @@
+use std::{env::temp_dir, io::Write};
+
+use anyhow::Context;
+use glommio::LocalExecutor;
+use timestore::IterParamsBuilder;
+
+fn config(path: std::path::PathBuf) -> timestore::Config {
+    timestore::ConfigBuilder::default()
+        .path(path)
+        .create_if_not_exists(true)
+        .tables(vec!["table0".to_owned(), "table1".to_owned()])
+        .build()
+        .unwrap()
+}
+
+#[test]
+fn test_delete_range() {
+    let exec = LocalExecutor::default();
+
+    exec.run(async move {
+        let mut path = temp_dir();
+        path.push(uuid::Uuid::new_v4().to_string());
+        let cfg = config(path);
+
+        {
+            let (writer_factory, _reader_factory) =
+                timestore::open(cfg.clone()).await.context("open db")?;
+            let mut writer = writer_factory.make().await.unwrap();
+
+            for key in 1..=10u64 {
+                writer
+                    .append(
+                        key * 10,
+                        vec![key.to_be_bytes().to_vec(), vec![key as u8; key as usize]],
+                    )
+                    .await
+                    .unwrap();
+            }
+        }
+
+        assert_eq!(timestore::delete_range(&cfg, 30, 60).await.unwrap(), 3);
+        assert_eq!(timestore::delete_range(&cfg, 31, 39).await.unwrap(), 0);
+
+        let (_writer_factory, reader_factory) = timestore::open(cfg).await.context("reopen db")?;
+        let reader = reader_factory.make().await.unwrap();
+
+        let keys = reader.keys().iter_from(0).collect::<Vec<u64>>();
+        assert_eq!(keys, vec![10, 20, 60, 70, 80, 90, 100]);
+
+        for key in [1u64, 2, 6, 7, 8, 9, 10] {
+            let res = reader.read("table0", key * 10).await.unwrap().unwrap();
+            assert_eq!(&*res, key.to_be_bytes());
+            let res = reader.read("table1", key * 10).await.unwrap().unwrap();
+            assert_eq!(&*res, vec![key as u8; key as usize]);
+        }
+        assert!(reader.read("table0", 40).await.unwrap().is_none());
+
+        let mut iter = reader
+            .iter(
+                IterParamsBuilder::default()
+                    .from(0)
+                    .to(100)
+                    .table(Some("table1"))
+                    .build()
+                    .unwrap(),
+            )
+            .await
+            .unwrap()
+            .unwrap();
+        let mut prev = 0;
+        for key in [1u64, 2, 6, 7, 8, 9, 10] {
+            let ((from, to), buf) = iter.next().await.unwrap().unwrap();
+            assert_eq!((from, to), (prev, key * 10));
+            assert_eq!(buf, vec![key as u8; key as usize]);
+            prev = key * 10;
+        }
+        assert!(iter.next().await.unwrap().is_none());
+
+        Ok::<_, anyhow::Error>(())
+    })
+    .unwrap();
+}
+
+#[test]
+fn test_compact() {
+    let exec = LocalExecutor::default();
+
+    exec.run(async move {
+        let mut path = temp_dir();
+        path.push(uuid::Uuid::new_v4().to_string());
+        let cfg = config(path.clone());
+
+        {
+            let (writer_factory, _reader_factory) =
+                timestore::open(cfg.clone()).await.context("open db")?;
+            let mut writer = writer_factory.make().await.unwrap();
+
+            for key in 1..=10u64 {
+                writer
+                    .append(
+                        key * 10,
+                        vec![key.to_be_bytes().to_vec(), vec![key as u8; key as usize]],
+                    )
+                    .await
+                    .unwrap();
+            }
+        }
+
+        let data_path = path.join("table0").join("data");
+        let mut file = std::fs::OpenOptions::new()
+            .append(true)
+            .open(&data_path)
+            .unwrap();
+        file.write_all(&[0xff; 10000]).unwrap();
+        drop(file);
+        let size = std::fs::metadata(&data_path).unwrap().len();
+
+        let report = timestore::compact(&cfg).await.unwrap();
+        assert_eq!(report.reclaimed[0], ("table0".to_owned(), size - 80));
+        assert_eq!(report.reclaimed[1].0, "table1");
+        assert_eq!(std::fs::metadata(&data_path).unwrap().len(), 80);
+        assert_eq!(
+            std::fs::metadata(path.join("table1").join("data"))
+                .unwrap()
+                .len(),
+            55
+        );
+
+        assert_eq!(timestore::compact(&cfg).await.unwrap().total(), 0);
+
+        let (writer_factory, reader_factory) = timestore::open(cfg).await.context("reopen db")?;
+        let reader = reader_factory.make().await.unwrap();
+        for key in 1..=10u64 {
+            let res = reader.read("table0", key * 10).await.unwrap().unwrap();
+            assert_eq!(&*res, key.to_be_bytes());
+            let res = reader.read("table1", key * 10).await.unwrap().unwrap();
+            assert_eq!(&*res, vec![key as u8; key as usize]);
+        }
+
+        let mut writer = writer_factory.make().await.unwrap();
+        writer
+            .append(110, vec![b"after".to_vec(), b"compact".to_vec()])
+            .await
+            .unwrap();
+        assert_eq!(
+            &*reader.read("table0", 110).await.unwrap().unwrap(),
+            b"after"
+        );
+
+        Ok::<_, anyhow::Error>(())
+    })
+    .unwrap();
+}
+
+#[test]
+fn test_rebuild_offsets() {
+    let exec = LocalExecutor::default();
+
+    exec.run(async move {
+        let mut path = temp_dir();
+        path.push(uuid::Uuid::new_v4().to_string());
+        let cfg = config(path.clone());
+
+        {
+            let (writer_factory, _reader_factory) =
+                timestore::open(cfg.clone()).await.context("open db")?;
+            let mut writer = writer_factory.make().await.unwrap();
+
+            for key in 1..=10u64 {
+                writer
+                    .append(
+                        key,
+                        vec![
+                            vec![key as u8; key as usize * 2],
+                            key.to_be_bytes().to_vec(),
+                        ],
+                    )
+                    .await
+                    .unwrap();
+            }
+        }
+
+        // rewrite the data file of table0 with every value halved
+        let data = (1..=10u64)
+            .flat_map(|key| vec![key as u8; key as usize])
+            .collect::<Vec<u8>>();
+        std::fs::write(path.join("table0").join("data"), &data).unwrap();
+        let new_lengths = (1..=10u64).collect::<Vec<u64>>();
+
+        assert!(
+            timestore::rebuild_offsets(&cfg, "table0", &new_lengths[..9])
+                .await
+                .is_err()
+        );
+        assert!(timestore::rebuild_offsets(
+            &cfg,
+            "table0",
+            &[new_lengths[..9].to_vec(), vec![11]].concat()
+        )
+        .await
+        .is_err());
+        assert!(matches!(
+            timestore::rebuild_offsets(&cfg, "table2", &new_lengths).await,
+            Err(timestore::TimestoreError::TableNotFound(_))
+        ));
+
+        timestore::rebuild_offsets(&cfg, "table0", &new_lengths)
+            .await
+            .unwrap();
+
+        let (_writer_factory, reader_factory) = timestore::open(cfg).await.context("reopen db")?;
+        let reader = reader_factory.make().await.unwrap();
+
+        assert_eq!(
+            reader.keys().iter_from(0).collect::<Vec<u64>>(),
+            (1..=10).collect::<Vec<u64>>()
+        );
+        for key in 1..=10u64 {
+            let res = reader.read("table0", key).await.unwrap().unwrap();
+            assert_eq!(&*res, vec![key as u8; key as usize]);
+            let res = reader.read("table1", key).await.unwrap().unwrap();
+            assert_eq!(&*res, key.to_be_bytes());
+        }
+
+        Ok::<_, anyhow::Error>(())
+    })
+    .unwrap();
+}
+
+#[test]
+fn test_maintenance_locked_store() {
+    let exec = LocalExecutor::default();
+
+    exec.run(async move {
+        let mut path = temp_dir();
+        path.push(uuid::Uuid::new_v4().to_string());
+        let cfg = config(path);
+
+        let (writer_factory, _reader_factory) =
+            timestore::open(cfg.clone()).await.context("open db")?;
+        let mut writer = writer_factory.make().await.unwrap();
+        for key in 1..=4u64 {
+            writer
+                .append(key, vec![vec![key as u8], vec![key as u8]])
+                .await
+                .unwrap();
+        }
+
+        // the files can't be rewritten while the writer has the store open
+        let err = timestore::delete_range(&cfg, 1, 3).await.unwrap_err();
+        assert!(err.to_string().contains("locked by a writer"), "{}", err);
+        assert!(timestore::compact(&cfg).await.is_err());
+        assert!(timestore::rebuild_offsets(&cfg, "table0", &[1, 1, 1, 1])
+            .await
+            .is_err());
+
+        drop(writer);
+        assert_eq!(timestore::delete_range(&cfg, 1, 3).await.unwrap(), 2);
+
+        Ok::<_, anyhow::Error>(())
+    })
+    .unwrap();
+}
@@ -0,0 +1,168 @@
+use std::{env::temp_dir, path::PathBuf};
+
+use anyhow::Context;
+use glommio::LocalExecutor;
+
+fn config(path: PathBuf, tables: &[&str]) -> timestore::Config {
+    timestore::ConfigBuilder::default()
+        .path(path)
+        .create_if_not_exists(true)
+        .tables(tables.iter().map(|&t| t.to_owned()).collect())
+        .build()
+        .unwrap()
+}
+
+#[test]
+fn test_multi_writer() {
+    let exec = LocalExecutor::default();
+
+    exec.run(async move {
+        let cfgs = [&["table0"][..], &["table1", "table2"]]
+            .into_iter()
+            .map(|tables| {
+                let mut path = temp_dir();
+                path.push(uuid::Uuid::new_v4().to_string());
+                config(path, tables)
+            })
+            .collect::<Vec<_>>();
+
+        let open_writers = || async {
+            let mut writers = Vec::new();
+            let mut readers = Vec::new();
+            for cfg in cfgs.iter() {
+                let (writer_factory, reader_factory) = timestore::open(cfg.clone()).await.unwrap();
+                writers.push(writer_factory.make().await.unwrap());
+                readers.push(reader_factory.make().await.unwrap());
+            }
+            (writers, readers)
+        };
+        let values = |key: u64| {
+            vec![
+                vec![key.to_be_bytes().to_vec()],
+                vec![vec![key as u8; 3], vec![key as u8; key as usize]],
+            ]
+        };
+
+        {
+            let (writers, _readers) = open_writers().await;
+            let mut writer = timestore::MultiWriter::new(writers).await.unwrap();
+
+            assert!(writer.append_all(1, vec![vec![vec![1]]]).await.is_err());
+            assert!(writer
+                .append_all(1, vec![vec![vec![1]], vec![vec![1]]])
+                .await
+                .is_err());
+
+            for key in 1..=5u64 {
+                assert_eq!(writer.append_all(key, values(key)).await.unwrap(), key - 1);
+            }
+
+            // Same state as a crash after committing the first store but before committing the second one.
+            let mut writers = writer.into_writers();
+            writers[0]
+                .append(6, values(6).swap_remove(0))
+                .await
+                .unwrap();
+        }
+
+        {
+            let (writers, _readers) = open_writers().await;
+            let mut writer = timestore::MultiWriter::new(writers)
+                .await
+                .context("recover half committed record")?;
+
+            assert_eq!(writer.append_all(6, values(6)).await.unwrap(), 5);
+            assert_eq!(writer.append_all(7, values(7)).await.unwrap(), 6);
+
+            // more than one record of difference can't come from a crash
+            let mut writers = writer.into_writers();
+            for key in 8..=9u64 {
+                writers[1]
+                    .append(key, values(key).swap_remove(1))
+                    .await
+                    .unwrap();
+            }
+        }
+
+        let (writers, readers) = open_writers().await;
+        assert!(timestore::MultiWriter::new(writers).await.is_err());
+
+        // the rolled back record isn't in the first store twice
+        assert_eq!(
+            readers[0].keys().iter_from(0).collect::<Vec<u64>>(),
+            (1..=7).collect::<Vec<u64>>()
+        );
+
+        for key in 1..=7u64 {
+            let res = readers[0].read("table0", key).await.unwrap().unwrap();
+            assert_eq!(&*res, key.to_be_bytes());
+            let res = readers[1].read("table2", key).await.unwrap().unwrap();
+            assert_eq!(&*res, vec![key as u8; key as usize]);
+        }
+
+        Ok::<_, anyhow::Error>(())
+    })
+    .unwrap();
+}
+
+#[test]
+fn test_multi_writer_duplicate_key() {
+    let exec = LocalExecutor::default();
+
+    exec.run(async move {
+        let open = |policies: &[timestore::DuplicateKeyPolicy]| {
+            let policies = policies.to_vec();
+            async move {
+                let mut writers = Vec::new();
+                let mut readers = Vec::new();
+                for policy in policies {
+                    let mut path = temp_dir();
+                    path.push(uuid::Uuid::new_v4().to_string());
+                    let cfg = timestore::ConfigBuilder::default()
+                        .path(path)
+                        .create_if_not_exists(true)
+                        .tables(vec!["table".to_owned()])
+                        .on_duplicate_key(policy)
+                        .build()
+                        .unwrap();
+                    let (writer_factory, reader_factory) = timestore::open(cfg).await.unwrap();
+                    writers.push(writer_factory.make().await.unwrap());
+                    readers.push(reader_factory.make().await.unwrap());
+                }
+                (timestore::MultiWriter::new(writers).await.unwrap(), readers)
+            }
+        };
+        let values = |v: u8| vec![vec![vec![v]], vec![vec![v]]];
+
+        use timestore::DuplicateKeyPolicy::*;
+
+        let (mut writer, readers) = open(&[Skip, Skip]).await;
+        assert_eq!(writer.append_all(1, values(1)).await.unwrap(), 0);
+        assert_eq!(writer.append_all(2, values(2)).await.unwrap(), 1);
+        assert_eq!(writer.append_all(2, values(3)).await.unwrap(), 1);
+        for reader in readers.iter() {
+            assert_eq!(reader.keys().iter_from(0).collect::<Vec<u64>>(), [1, 2]);
+            assert_eq!(&*reader.read("table", 2).await.unwrap().unwrap(), [2]);
+        }
+
+        let (mut writer, readers) = open(&[Error, Error]).await;
+        assert_eq!(writer.append_all(1, values(1)).await.unwrap(), 0);
+        assert!(writer.append_all(1, values(2)).await.is_err());
+        for reader in readers.iter() {
+            assert_eq!(reader.keys().iter_from(0).collect::<Vec<u64>>(), [1]);
+        }
+
+        let (mut writer, _readers) = open(&[Overwrite, Overwrite]).await;
+        assert_eq!(writer.append_all(1, values(1)).await.unwrap(), 0);
+        assert!(writer.append_all(1, values(2)).await.is_err());
+
+        let (mut writer, _readers) = open(&[Allow, Skip]).await;
+        assert_eq!(writer.append_all(1, values(1)).await.unwrap(), 0);
+        assert!(writer.append_all(1, values(2)).await.is_err());
+        assert_eq!(writer.append_all(2, values(2)).await.unwrap(), 1);
+
+        let (mut writer, _readers) = open(&[Allow, Allow]).await;
+        assert_eq!(writer.append_all(1, values(1)).await.unwrap(), 0);
+        assert_eq!(writer.append_all(1, values(2)).await.unwrap(), 1);
+    });
+}
@@ -0,0 +1,209 @@
+use std::{
+    env::temp_dir,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+};
+
+use anyhow::Context;
+use glommio::LocalExecutor;
+use timestore::StoreObserver;
+
+#[derive(Default)]
+struct CountingObserver {
+    appends: AtomicUsize,
+    appended_bytes: AtomicUsize,
+    reads: AtomicUsize,
+    read_bytes: AtomicUsize,
+    syncs: AtomicUsize,
+    dir_syncs: AtomicUsize,
+}
+
+impl StoreObserver for CountingObserver {
+    fn on_append(&self, _key: u64, bytes: usize) {
+        self.appends.fetch_add(1, Ordering::SeqCst);
+        self.appended_bytes.fetch_add(bytes, Ordering::SeqCst);
+    }
+
+    fn on_read(&self, _table: &str, bytes: usize) {
+        self.reads.fetch_add(1, Ordering::SeqCst);
+        self.read_bytes.fetch_add(bytes, Ordering::SeqCst);
+    }
+
+    fn on_sync(&self) {
+        self.syncs.fetch_add(1, Ordering::SeqCst);
+    }
+
+    fn on_dir_sync(&self) {
+        self.dir_syncs.fetch_add(1, Ordering::SeqCst);
+    }
+}
+
+#[test]
+fn test_observer_counts() {
+    let exec = LocalExecutor::default();
+
+    exec.run(async move {
+        let mut path = temp_dir();
+        path.push(uuid::Uuid::new_v4().to_string());
+
+        let observer = Arc::new(CountingObserver::default());
+
+        let (writer_factory, reader_factory) = timestore::open(
+            timestore::ConfigBuilder::default()
+                .path(path)
+                .create_if_not_exists(true)
+                .tables(vec!["table0".to_owned(), "table1".to_owned()])
+                .observer(observer.clone())
+                .build()
+                .unwrap(),
+        )
+        .await
+        .context("open db")?;
+
+        let mut writer = writer_factory.make().await.unwrap();
+        let reader = reader_factory.make().await.unwrap();
+
+        writer
+            .append(12, vec![b"123".to_vec(), b"345".to_vec()])
+            .await
+            .unwrap();
+        writer
+            .append(18, vec![b"8888".to_vec(), b"999".to_vec()])
+            .await
+            .unwrap();
+
+        assert_eq!(observer.appends.load(Ordering::SeqCst), 2);
+        assert_eq!(observer.appended_bytes.load(Ordering::SeqCst), 13);
+        // two data files, two offset files, the keys file and the length file per append
+        assert_eq!(observer.syncs.load(Ordering::SeqCst), 12);
+        // the store directory after each rename of the length file
+        assert_eq!(observer.dir_syncs.load(Ordering::SeqCst), 2);
+
+        reader.read("table0", 18).await.unwrap().unwrap();
+        assert!(reader.read("table0", 13).await.unwrap().is_none());
+
+        assert_eq!(observer.reads.load(Ordering::SeqCst), 1);
+        assert_eq!(observer.read_bytes.load(Ordering::SeqCst), 4);
+
+        Ok::<_, anyhow::Error>(())
+    })
+    .unwrap();
+}
+
+#[test]
+fn test_sync_dir() {
+    let exec = LocalExecutor::default();
+
+    exec.run(async move {
+        let mut path = temp_dir();
+        path.push(uuid::Uuid::new_v4().to_string());
+
+        let cfg = |sync_dir: bool, observer: Arc<CountingObserver>| {
+            timestore::ConfigBuilder::default()
+                .path(path.clone())
+                .create_if_not_exists(true)
+                .tables(vec!["table0".to_owned()])
+                .sync_dir(sync_dir)
+                .observer(observer)
+                .build()
+                .unwrap()
+        };
+
+        for (i, sync_dir) in [true, false].into_iter().enumerate() {
+            let observer = Arc::new(CountingObserver::default());
+            let (mut writer, _reader_factory) =
+                timestore::reopen_writer(cfg(sync_dir, observer.clone()))
+                    .await
+                    .context("open writer")?;
+            for key in 0..5u64 {
+                writer
+                    .append(i as u64 * 10 + key, vec![vec![key as u8]])
+                    .await
+                    .unwrap();
+            }
+            writer.truncate_after(i as u64 * 10 + 3).await.unwrap();
+
+            let expected = if sync_dir { 6 } else { 0 };
+            assert_eq!(observer.dir_syncs.load(Ordering::SeqCst), expected);
+        }
+
+        let (_writer_factory, reader_factory) = timestore::open(cfg(true, Default::default()))
+            .await
+            .context("reopen db")?;
+        let reader = reader_factory.make().await.unwrap();
+        assert_eq!(
+            reader.keys().iter_from(0).collect::<Vec<u64>>(),
+            vec![0, 1, 2, 3, 10, 11, 12, 13]
+        );
+
+        Ok::<_, anyhow::Error>(())
+    })
+    .unwrap();
+}
+
+#[test]
+fn test_append_with_stats() {
+    let exec = LocalExecutor::default();
+
+    exec.run(async move {
+        let cases = [
+            (true, timestore::LengthSource::LengthFile, None),
+            (false, timestore::LengthSource::LengthFile, None),
+            (true, timestore::LengthSource::KeysFileSize, Some(false)),
+        ];
+        for (sync_dir, length_source, direct_io) in cases {
+            let mut path = temp_dir();
+            path.push(uuid::Uuid::new_v4().to_string());
+
+            let observer = Arc::new(CountingObserver::default());
+            let mut cfg = timestore::ConfigBuilder::default();
+            cfg.path(path)
+                .create_if_not_exists(true)
+                .tables(vec!["table0".to_owned(), "table1".to_owned()])
+                .sync_dir(sync_dir)
+                .length_source(length_source)
+                .observer(observer.clone());
+            if let Some(direct_io) = direct_io {
+                cfg.direct_io(direct_io);
+            }
+            let (mut writer, _reader_factory) = timestore::reopen_writer(cfg.build().unwrap())
+                .await
+                .context("open writer")?;
+
+            let (pos, stats) = writer
+                .append_with_stats(12, vec![b"123".to_vec(), b"34567".to_vec()])
+                .await
+                .unwrap();
+            assert_eq!(pos, 0);
+            assert_eq!(stats.logical_bytes, 8);
+
+            // two data files, two offset files and the keys file,
+            // the length file and the store directory unless the length is the size of the keys file
+            let (files, length_commit) = match length_source {
+                timestore::LengthSource::LengthFile => (5, 1 + u64::from(sync_dir)),
+                timestore::LengthSource::KeysFileSize => (5, 0),
+            };
+            assert_eq!(stats.fsyncs, files + length_commit);
+            assert_eq!(
+                observer.syncs.load(Ordering::SeqCst) + observer.dir_syncs.load(Ordering::SeqCst),
+                usize::try_from(stats.fsyncs).unwrap()
+            );
+            assert_eq!(observer.appends.load(Ordering::SeqCst), 1);
+
+            // the values, an offset per table and the key on top of the length
+            let length_bytes = if length_commit > 0 { 8 } else { 0 };
+            assert!(stats.physical_bytes >= stats.logical_bytes + 3 * 8 + length_bytes);
+            assert!(stats.physical_bytes >= files * writer.alignment());
+            if writer.alignment() > 1 {
+                assert!(stats.physical_bytes > stats.logical_bytes + 3 * 8 + length_bytes);
+            }
+
+            writer.close().await.unwrap();
+        }
+
+        Ok::<_, anyhow::Error>(())
+    })
+    .unwrap();
+}
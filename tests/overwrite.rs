@@ -0,0 +1,181 @@
+use std::env::temp_dir;
+
+use anyhow::Context;
+use glommio::LocalExecutor;
+
+#[test]
+fn test_overwrite_last() {
+    let exec = LocalExecutor::default();
+
+    exec.run(async move {
+        let mut path = temp_dir();
+        path.push(uuid::Uuid::new_v4().to_string());
+
+        let cfg = timestore::ConfigBuilder::default()
+            .path(path)
+            .create_if_not_exists(true)
+            .tables(vec!["table0".to_owned(), "table1".to_owned()])
+            .build()
+            .unwrap();
+
+        {
+            let (writer_factory, _reader_factory) =
+                timestore::open(cfg.clone()).await.context("open db")?;
+            let mut writer = writer_factory.make().await.unwrap();
+
+            assert!(writer
+                .overwrite_last(10, vec![b"a".to_vec(), b"b".to_vec()])
+                .await
+                .is_err());
+
+            writer
+                .append(10, vec![b"first".to_vec(), b"1".to_vec()])
+                .await
+                .unwrap();
+            writer
+                .append(20, vec![b"second".to_vec(), b"2".to_vec()])
+                .await
+                .unwrap();
+
+            assert!(writer
+                .overwrite_last(10, vec![b"a".to_vec(), b"b".to_vec()])
+                .await
+                .is_err());
+
+            // same sizes, rewritten in place
+            writer
+                .overwrite_last(20, vec![b"SECOND".to_vec(), b"3".to_vec()])
+                .await
+                .unwrap();
+        }
+
+        {
+            let (writer_factory, reader_factory) =
+                timestore::open(cfg.clone()).await.context("reopen db")?;
+            let reader = reader_factory.make().await.unwrap();
+            assert_eq!(
+                &*reader.read("table0", 20).await.unwrap().unwrap(),
+                b"SECOND"
+            );
+            assert_eq!(&*reader.read("table1", 20).await.unwrap().unwrap(), b"3");
+
+            let mut writer = writer_factory.make().await.unwrap();
+            writer
+                .overwrite_last(20, vec![b"2nd".to_vec(), b"a longer value".to_vec()])
+                .await
+                .unwrap();
+            writer
+                .append(30, vec![b"third".to_vec(), b"4".to_vec()])
+                .await
+                .unwrap();
+        }
+
+        {
+            let (_writer_factory, reader_factory) =
+                timestore::open(cfg).await.context("reopen db")?;
+            let reader = reader_factory.make().await.unwrap();
+
+            let keys = reader.keys().iter_from(0).collect::<Vec<u64>>();
+            assert_eq!(keys, vec![10, 20, 30]);
+
+            assert_eq!(
+                &*reader.read("table0", 10).await.unwrap().unwrap(),
+                b"first"
+            );
+            assert_eq!(&*reader.read("table1", 10).await.unwrap().unwrap(), b"1");
+            assert_eq!(&*reader.read("table0", 20).await.unwrap().unwrap(), b"2nd");
+            assert_eq!(
+                &*reader.read("table1", 20).await.unwrap().unwrap(),
+                b"a longer value"
+            );
+            assert_eq!(
+                &*reader.read("table0", 30).await.unwrap().unwrap(),
+                b"third"
+            );
+            assert_eq!(&*reader.read("table1", 30).await.unwrap().unwrap(), b"4");
+        }
+
+        Ok::<_, anyhow::Error>(())
+    })
+    .unwrap();
+}
+
+#[test]
+fn test_fill() {
+    let exec = LocalExecutor::default();
+
+    exec.run(async move {
+        let mut path = temp_dir();
+        path.push(uuid::Uuid::new_v4().to_string());
+
+        let cfg = timestore::ConfigBuilder::default()
+            .path(path)
+            .create_if_not_exists(true)
+            .tables(vec!["table0".to_owned(), "table1".to_owned()])
+            .build()
+            .unwrap();
+
+        {
+            let (mut writer, _reader_factory) = timestore::reopen_writer(cfg.clone())
+                .await
+                .context("open writer")?;
+
+            for key in 1..=5u64 {
+                writer.append_key_only(key * 10).await.unwrap();
+            }
+            assert_eq!(writer.sync_point(), 5);
+            assert_eq!(writer.write_offset("table0").unwrap(), 0);
+
+            // out of key order so the offsets of the following records have to be moved
+            writer.fill(30, "table0", b"three").await.unwrap();
+            writer.fill(10, "table0", b"1").await.unwrap();
+            writer.fill(50, "table0", b"fifty").await.unwrap();
+
+            assert!(writer.fill(30, "table0", b"again").await.is_err());
+            assert!(matches!(
+                writer.fill(35, "table0", b"missing").await,
+                Err(timestore::TimestoreError::KeyNotFound(35))
+            ));
+            assert!(matches!(
+                writer.fill(20, "table2", b"x").await,
+                Err(timestore::TimestoreError::TableNotFound(_))
+            ));
+
+            assert_eq!(
+                &*writer.read("table0", 30).await.unwrap().unwrap(),
+                b"three"
+            );
+            assert_eq!(writer.write_offset("table0").unwrap(), 11);
+
+            writer
+                .append(60, vec![b"six".to_vec(), b"6".to_vec()])
+                .await
+                .unwrap();
+        }
+
+        let (_writer_factory, reader_factory) = timestore::open(cfg).await.context("reopen db")?;
+        let reader = reader_factory.make().await.unwrap();
+        assert_eq!(
+            reader.keys().iter_from(0).collect::<Vec<u64>>(),
+            vec![10, 20, 30, 40, 50, 60]
+        );
+
+        let expected: [(u64, &[u8]); 6] = [
+            (10, b"1"),
+            (20, b""),
+            (30, b"three"),
+            (40, b""),
+            (50, b"fifty"),
+            (60, b"six"),
+        ];
+        for (key, value) in expected {
+            let res = reader.read("table0", key).await.unwrap().unwrap();
+            assert_eq!(&*res, value);
+        }
+        assert_eq!(&*reader.read("table1", 60).await.unwrap().unwrap(), b"6");
+        assert!(reader.read("table1", 30).await.unwrap().unwrap().is_empty());
+
+        Ok::<_, anyhow::Error>(())
+    })
+    .unwrap();
+}
@@ -0,0 +1,2365 @@
+use std::{
+    env::temp_dir,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+};
+
+use anyhow::Context;
+use futures::{FutureExt, StreamExt};
+use glommio::{
+    io::{MergedBufferLimit, ReadAmplificationLimit},
+    LocalExecutor,
+};
+use timestore::{IterParamsBuilder, ReadOptionsBuilder, StoreObserver};
+
+#[test]
+fn test_prefetch() {
+    let exec = LocalExecutor::default();
+
+    exec.run(async move {
+        let mut path = temp_dir();
+        path.push(uuid::Uuid::new_v4().to_string());
+
+        let (writer_factory, reader_factory) = timestore::open(
+            timestore::ConfigBuilder::default()
+                .path(path)
+                .create_if_not_exists(true)
+                .tables(vec!["table0".to_owned(), "table1".to_owned()])
+                .build()
+                .unwrap(),
+        )
+        .await
+        .context("open db")?;
+
+        let mut writer = writer_factory.make().await.unwrap();
+        let reader = reader_factory.make().await.unwrap();
+
+        reader.prefetch("table0", 0, 100).await.unwrap();
+
+        for key in 1..=10u64 {
+            writer
+                .append(key * 10, vec![vec![1; 4096], vec![2; 10]])
+                .await
+                .unwrap();
+        }
+
+        reader.prefetch("table0", 0, 100).await.unwrap();
+        reader.prefetch("table1", 35, 62).await.unwrap();
+        reader.prefetch("table0", 200, 300).await.unwrap();
+        assert!(reader.prefetch("table2", 0, 100).await.is_err());
+
+        Ok::<_, anyhow::Error>(())
+    })
+    .unwrap();
+}
+
+#[test]
+fn test_value_location() {
+    let exec = LocalExecutor::default();
+
+    exec.run(async move {
+        let mut path = temp_dir();
+        path.push(uuid::Uuid::new_v4().to_string());
+
+        let (writer_factory, reader_factory) = timestore::open(
+            timestore::ConfigBuilder::default()
+                .path(path.clone())
+                .create_if_not_exists(true)
+                .tables(vec!["table0".to_owned(), "table1".to_owned()])
+                .build()
+                .unwrap(),
+        )
+        .await
+        .context("open db")?;
+
+        let mut writer = writer_factory.make().await.unwrap();
+        let reader = reader_factory.make().await.unwrap();
+
+        assert!(reader.value_location("table0", 10).unwrap().is_none());
+
+        for key in 1..=5u64 {
+            writer
+                .append(
+                    key * 10,
+                    vec![vec![key as u8; key as usize], b"abc".to_vec()],
+                )
+                .await
+                .unwrap();
+        }
+
+        let mut data_path = path;
+        data_path.push("table0");
+        data_path.push("data");
+        let data = std::fs::read(&data_path).unwrap();
+
+        let mut expected_pos = 0;
+        for key in 1..=5u64 {
+            let (pos, len) = reader.value_location("table0", key * 10).unwrap().unwrap();
+            assert_eq!(pos, expected_pos);
+            assert_eq!(len, key as usize);
+            expected_pos += key;
+
+            let res = reader.read("table0", key * 10).await.unwrap().unwrap();
+            assert_eq!(&*res, &data[pos as usize..pos as usize + len]);
+        }
+
+        assert_eq!(reader.value_location("table1", 30).unwrap(), Some((6, 3)));
+        assert!(reader.value_location("table0", 35).unwrap().is_none());
+        assert!(reader.value_location("table2", 30).is_err());
+
+        Ok::<_, anyhow::Error>(())
+    })
+    .unwrap();
+}
+
+#[test]
+fn test_iter_step() {
+    assert!(IterParamsBuilder::default()
+        .from(0)
+        .to(10)
+        .step(0)
+        .build()
+        .is_err());
+
+    let exec = LocalExecutor::default();
+
+    exec.run(async move {
+        let mut path = temp_dir();
+        path.push(uuid::Uuid::new_v4().to_string());
+
+        let (writer_factory, reader_factory) = timestore::open(
+            timestore::ConfigBuilder::default()
+                .path(path)
+                .create_if_not_exists(true)
+                .tables(vec!["table0".to_owned(), "table1".to_owned()])
+                .build()
+                .unwrap(),
+        )
+        .await
+        .context("open db")?;
+
+        let mut writer = writer_factory.make().await.unwrap();
+        let reader = reader_factory.make().await.unwrap();
+
+        for pos in 0..10u64 {
+            writer
+                .append(
+                    (pos + 1) * 10,
+                    vec![
+                        vec![pos as u8; pos as usize + 1],
+                        pos.to_be_bytes().to_vec(),
+                    ],
+                )
+                .await
+                .unwrap();
+        }
+
+        let mut iter = reader
+            .iter(
+                IterParamsBuilder::default()
+                    .from(0)
+                    .to(1000)
+                    .table(Some("table0"))
+                    .step(3)
+                    .build()
+                    .unwrap(),
+            )
+            .await
+            .unwrap()
+            .unwrap();
+
+        let mut prev_key = 0;
+        for pos in [0u64, 3, 6, 9] {
+            let key = (pos + 1) * 10;
+            let ((from, to), buf) = iter.next().await.unwrap().unwrap();
+            assert_eq!((from, to), (prev_key, key));
+            assert_eq!(buf, vec![pos as u8; pos as usize + 1]);
+            assert_eq!(&*iter.read("table1").await.unwrap(), pos.to_be_bytes());
+            prev_key = key;
+        }
+        assert!(iter.next().await.unwrap().is_none());
+
+        Ok::<_, anyhow::Error>(())
+    })
+    .unwrap();
+}
+
+#[test]
+fn test_iter_read_all() {
+    let exec = LocalExecutor::default();
+
+    exec.run(async move {
+        let mut path = temp_dir();
+        path.push(uuid::Uuid::new_v4().to_string());
+
+        let (writer_factory, reader_factory) = timestore::open(
+            timestore::ConfigBuilder::default()
+                .path(path)
+                .create_if_not_exists(true)
+                .tables(vec![
+                    "table0".to_owned(),
+                    "table1".to_owned(),
+                    "table2".to_owned(),
+                ])
+                .build()
+                .unwrap(),
+        )
+        .await
+        .context("open db")?;
+
+        let mut writer = writer_factory.make().await.unwrap();
+        let reader = reader_factory.make().await.unwrap();
+
+        for key in 1..=5u64 {
+            writer
+                .append(
+                    key,
+                    vec![
+                        vec![key as u8; key as usize],
+                        key.to_be_bytes().to_vec(),
+                        vec![0; 5000 * key as usize],
+                    ],
+                )
+                .await
+                .unwrap();
+        }
+
+        let mut iter = reader
+            .iter(IterParamsBuilder::default().from(0).to(5).build().unwrap())
+            .await
+            .unwrap()
+            .unwrap();
+
+        assert!(iter.read_all().await.is_err());
+
+        while iter.next().await.unwrap().is_some() {
+            let all = iter.read_all().await.unwrap();
+            assert_eq!(all.len(), 3);
+            for (name, buf) in all {
+                assert_eq!(&*buf, &*iter.read(&name).await.unwrap());
+            }
+        }
+
+        Ok::<_, anyhow::Error>(())
+    })
+    .unwrap();
+}
+
+#[test]
+fn test_empty_values() {
+    let exec = LocalExecutor::default();
+
+    exec.run(async move {
+        let mut path = temp_dir();
+        path.push(uuid::Uuid::new_v4().to_string());
+
+        let (writer_factory, reader_factory) = timestore::open(
+            timestore::ConfigBuilder::default()
+                .path(path)
+                .create_if_not_exists(true)
+                .tables(vec!["table0".to_owned(), "table1".to_owned()])
+                .build()
+                .unwrap(),
+        )
+        .await
+        .context("open db")?;
+
+        let mut writer = writer_factory.make().await.unwrap();
+        let reader = reader_factory.make().await.unwrap();
+
+        writer
+            .append(1, vec![Vec::new(), b"a".to_vec()])
+            .await
+            .unwrap();
+        writer
+            .append(2, vec![b"bb".to_vec(), b"b".to_vec()])
+            .await
+            .unwrap();
+        writer
+            .append(3, vec![Vec::new(), Vec::new()])
+            .await
+            .unwrap();
+        writer
+            .append(4, vec![b"dddd".to_vec(), b"d".to_vec()])
+            .await
+            .unwrap();
+
+        assert!(reader.read("table0", 1).await.unwrap().unwrap().is_empty());
+        assert!(reader.read("table0", 3).await.unwrap().unwrap().is_empty());
+        assert!(reader.read("table1", 3).await.unwrap().unwrap().is_empty());
+        assert_eq!(&*reader.read("table0", 4).await.unwrap().unwrap(), b"dddd");
+
+        let bufs = reader
+            .read_many(
+                "table0",
+                3,
+                futures::stream::iter(vec![(0u64, 0usize)]),
+                8,
+                MergedBufferLimit::NoMerging,
+                ReadAmplificationLimit::NoAmplification,
+            )
+            .await
+            .unwrap()
+            .unwrap()
+            .collect::<Vec<_>>()
+            .await;
+        assert_eq!(bufs.len(), 1);
+        let (index, buf) = bufs[0].as_ref().unwrap();
+        assert_eq!(*index, 0);
+        assert!(buf.is_empty());
+
+        let mut iter = reader
+            .iter(
+                IterParamsBuilder::default()
+                    .from(0)
+                    .to(4)
+                    .table(Some("table0"))
+                    .build()
+                    .unwrap(),
+            )
+            .await
+            .unwrap()
+            .unwrap();
+        for (key, expected) in [(1, &b""[..]), (2, b"bb"), (3, b""), (4, b"dddd")] {
+            let ((_, k), buf) = iter.next().await.unwrap().unwrap();
+            assert_eq!(k, key);
+            assert_eq!(buf, expected);
+            assert_eq!(&*iter.read("table0").await.unwrap(), expected);
+        }
+
+        Ok::<_, anyhow::Error>(())
+    })
+    .unwrap();
+}
+
+#[test]
+fn test_iter_peek_key() {
+    let exec = LocalExecutor::default();
+
+    exec.run(async move {
+        let mut path = temp_dir();
+        path.push(uuid::Uuid::new_v4().to_string());
+
+        let (writer_factory, reader_factory) = timestore::open(
+            timestore::ConfigBuilder::default()
+                .path(path)
+                .create_if_not_exists(true)
+                .tables(vec!["table0".to_owned()])
+                .build()
+                .unwrap(),
+        )
+        .await
+        .context("open db")?;
+
+        let mut writer = writer_factory.make().await.unwrap();
+        let reader = reader_factory.make().await.unwrap();
+
+        for key in [5u64, 7, 12, 20, 31] {
+            writer
+                .append(key, vec![key.to_be_bytes().to_vec()])
+                .await
+                .unwrap();
+        }
+
+        for step in [1, 2] {
+            let mut iter = reader
+                .iter(
+                    IterParamsBuilder::default()
+                        .from(6)
+                        .to(25)
+                        .table(Some("table0"))
+                        .step(step)
+                        .build()
+                        .unwrap(),
+                )
+                .await
+                .unwrap()
+                .unwrap();
+
+            loop {
+                let peeked = iter.peek_key();
+                assert_eq!(iter.peek_key(), peeked);
+
+                match iter.next().await.unwrap() {
+                    Some(((_, key), buf)) => {
+                        assert_eq!(Some(key), peeked);
+                        assert_eq!(buf, key.to_be_bytes());
+                    }
+                    None => {
+                        assert_eq!(peeked, None);
+                        break;
+                    }
+                }
+            }
+        }
+
+        Ok::<_, anyhow::Error>(())
+    })
+    .unwrap();
+}
+
+#[test]
+fn test_iter_buffer_settings() {
+    assert!(IterParamsBuilder::default()
+        .from(0)
+        .to(10)
+        .read_ahead(Some(0))
+        .build()
+        .is_err());
+
+    let exec = LocalExecutor::default();
+
+    exec.run(async move {
+        let mut path = temp_dir();
+        path.push(uuid::Uuid::new_v4().to_string());
+
+        let (writer_factory, reader_factory) = timestore::open(
+            timestore::ConfigBuilder::default()
+                .path(path)
+                .create_if_not_exists(true)
+                .tables(vec!["table0".to_owned(), "table1".to_owned()])
+                .build()
+                .unwrap(),
+        )
+        .await
+        .context("open db")?;
+
+        let mut writer = writer_factory.make().await.unwrap();
+        let reader = reader_factory.make().await.unwrap();
+
+        for key in 1..=20u64 {
+            writer
+                .append(key, vec![vec![key as u8; 10000], vec![key as u8]])
+                .await
+                .unwrap();
+        }
+
+        for (buffer_size, concurrency, read_ahead) in [
+            (4096, 1, Some(16)),
+            (64 * 1024, 4, Some(1)),
+            (4096, 2, None),
+        ] {
+            let mut iter = reader
+                .iter(
+                    IterParamsBuilder::default()
+                        .from(0)
+                        .to(100)
+                        .table(Some("table0"))
+                        .buffer_size(buffer_size)
+                        .concurrency(concurrency)
+                        .read_ahead(read_ahead)
+                        .build()
+                        .unwrap(),
+                )
+                .await
+                .unwrap()
+                .unwrap();
+
+            for key in 1..=20u64 {
+                let ((_, k), buf) = iter.next().await.unwrap().unwrap();
+                assert_eq!(k, key);
+                assert_eq!(buf, vec![key as u8; 10000]);
+                assert_eq!(&*iter.read("table1").await.unwrap(), &[key as u8]);
+            }
+            assert!(iter.next().await.unwrap().is_none());
+        }
+
+        Ok::<_, anyhow::Error>(())
+    })
+    .unwrap();
+}
+
+#[test]
+fn test_table_offsets() {
+    let exec = LocalExecutor::default();
+
+    exec.run(async move {
+        let mut path = temp_dir();
+        path.push(uuid::Uuid::new_v4().to_string());
+
+        let (writer_factory, reader_factory) = timestore::open(
+            timestore::ConfigBuilder::default()
+                .path(path.clone())
+                .create_if_not_exists(true)
+                .tables(vec!["table0".to_owned(), "table1".to_owned()])
+                .build()
+                .unwrap(),
+        )
+        .await
+        .context("open db")?;
+
+        let mut writer = writer_factory.make().await.unwrap();
+        let reader = reader_factory.make().await.unwrap();
+
+        for key in 1..=10u64 {
+            writer
+                .append(key, vec![vec![0; key as usize], vec![1; 3]])
+                .await
+                .unwrap();
+        }
+
+        assert!(reader.table_offsets("table2").is_err());
+
+        for name in ["table0", "table1"] {
+            let offsets = reader
+                .table_offsets(name)
+                .unwrap()
+                .iter_from(0)
+                .collect::<Vec<u64>>();
+
+            let file = std::fs::read(path.join(name).join("offsets")).unwrap();
+            let on_disk = file[..10 * 8]
+                .chunks_exact(8)
+                .map(|chunk| u64::from_be_bytes(chunk.try_into().unwrap()))
+                .collect::<Vec<u64>>();
+
+            assert_eq!(offsets, on_disk);
+        }
+
+        let offsets = reader
+            .table_offsets("table0")
+            .unwrap()
+            .iter_from(0)
+            .collect::<Vec<u64>>();
+        assert_eq!(offsets, vec![1, 3, 6, 10, 15, 21, 28, 36, 45, 55]);
+
+        Ok::<_, anyhow::Error>(())
+    })
+    .unwrap();
+}
+
+#[test]
+fn test_iter_rows() {
+    let exec = LocalExecutor::default();
+
+    exec.run(async move {
+        let mut path = temp_dir();
+        path.push(uuid::Uuid::new_v4().to_string());
+
+        let (writer_factory, reader_factory) = timestore::open(
+            timestore::ConfigBuilder::default()
+                .path(path)
+                .create_if_not_exists(true)
+                .tables(vec![
+                    "table0".to_owned(),
+                    "table1".to_owned(),
+                    "table2".to_owned(),
+                ])
+                .build()
+                .unwrap(),
+        )
+        .await
+        .context("open db")?;
+
+        let mut writer = writer_factory.make().await.unwrap();
+        let reader = reader_factory.make().await.unwrap();
+
+        for key in 1..=20u64 {
+            writer
+                .append(
+                    key * 10,
+                    vec![
+                        vec![key as u8; key as usize * 100],
+                        key.to_be_bytes().to_vec(),
+                        if key % 3 == 0 {
+                            Vec::new()
+                        } else {
+                            vec![1; 5000]
+                        },
+                    ],
+                )
+                .await
+                .unwrap();
+        }
+
+        let tables = ["table2", "table0"];
+        for step in [1, 3] {
+            let params = |table| {
+                IterParamsBuilder::default()
+                    .from(25)
+                    .to(180)
+                    .table(table)
+                    .step(step)
+                    .buffer_size(4096)
+                    .build()
+                    .unwrap()
+            };
+
+            let mut rows = reader
+                .iter_rows(params(None), &tables)
+                .await
+                .unwrap()
+                .unwrap();
+            let mut iters = Vec::new();
+            for table in tables {
+                iters.push(reader.iter(params(Some(table))).await.unwrap().unwrap());
+            }
+
+            let mut count = 0;
+            while let Some((range, row)) = rows.next().await.unwrap() {
+                assert_eq!(row.len(), tables.len());
+                for (iter, buf) in iters.iter_mut().zip(row) {
+                    assert_eq!(iter.next().await.unwrap().unwrap(), (range, buf));
+                }
+                count += 1;
+            }
+            for iter in iters.iter_mut() {
+                assert!(iter.next().await.unwrap().is_none());
+            }
+            assert!(count > 0);
+        }
+
+        let params = IterParamsBuilder::default()
+            .from(0)
+            .to(100)
+            .build()
+            .unwrap();
+        assert!(reader.iter_rows(params, &["table3"]).await.is_err());
+
+        Ok::<_, anyhow::Error>(())
+    })
+    .unwrap();
+}
+
+#[test]
+fn test_iter_params_require_bounds() {
+    assert!(IterParamsBuilder::default().to(10).build().is_err());
+    assert!(IterParamsBuilder::default().from(0).build().is_err());
+    assert!(IterParamsBuilder::default()
+        .table(Some("table0"))
+        .buffer_size(4096)
+        .concurrency(2)
+        .build()
+        .is_err());
+
+    let params = IterParamsBuilder::default().from(0).to(10).build().unwrap();
+    assert_eq!(
+        params,
+        IterParamsBuilder::default()
+            .from(0)
+            .to(10)
+            .table(None)
+            .buffer_size(512 * 1024)
+            .concurrency(8)
+            .build()
+            .unwrap()
+    );
+}
+
+#[test]
+fn test_count_range() {
+    let exec = LocalExecutor::default();
+
+    exec.run(async move {
+        let mut path = temp_dir();
+        path.push(uuid::Uuid::new_v4().to_string());
+
+        let (writer_factory, reader_factory) = timestore::open(
+            timestore::ConfigBuilder::default()
+                .path(path)
+                .create_if_not_exists(true)
+                .tables(vec!["table0".to_owned()])
+                .segment_length(3u32)
+                .build()
+                .unwrap(),
+        )
+        .await
+        .context("open db")?;
+
+        let mut writer = writer_factory.make().await.unwrap();
+        let reader = reader_factory.make().await.unwrap();
+
+        assert_eq!(reader.count_range(0, u64::MAX), 0);
+
+        for key in 1..=10u64 {
+            writer.append(key * 10, vec![vec![1]]).await.unwrap();
+        }
+
+        for (from, to, count) in [
+            (0, 5, 0),
+            (0, 10, 0),
+            (0, 11, 1),
+            (0, 1000, 10),
+            (0, u64::MAX, 10),
+            (10, 20, 1),
+            (15, 55, 4),
+            (20, 100, 8),
+            (20, 101, 9),
+            (100, 101, 1),
+            (101, 200, 0),
+            (50, 50, 0),
+            (60, 40, 0),
+        ] {
+            assert_eq!(reader.count_range(from, to), count, "[{}, {})", from, to);
+        }
+
+        Ok::<_, anyhow::Error>(())
+    })
+    .unwrap();
+}
+
+#[test]
+fn test_count_range_duplicate_keys() {
+    let exec = LocalExecutor::default();
+
+    exec.run(async move {
+        let mut path = temp_dir();
+        path.push(uuid::Uuid::new_v4().to_string());
+
+        let (mut writer, reader_factory) = timestore::reopen_writer(
+            timestore::ConfigBuilder::default()
+                .path(path)
+                .create_if_not_exists(true)
+                .tables(vec!["table0".to_owned()])
+                .segment_length(4u32)
+                .build()
+                .unwrap(),
+        )
+        .await
+        .context("open writer")?;
+        let reader = reader_factory.make().await.unwrap();
+
+        // the default policy keeps duplicate keys, so the last key repeats across segments
+        for key in [10u64, 20, 30, 30, 30, 30, 30] {
+            writer.append(key, vec![vec![1]]).await.unwrap();
+        }
+
+        for (from, to, count) in [
+            (0, u64::MAX, 7),
+            (0, 31, 7),
+            (30, 31, 5),
+            (25, u64::MAX, 5),
+            (0, 30, 2),
+            (31, u64::MAX, 0),
+        ] {
+            assert_eq!(reader.count_range(from, to), count, "[{}, {})", from, to);
+        }
+
+        Ok::<_, anyhow::Error>(())
+    })
+    .unwrap();
+}
+
+#[test]
+fn test_read_many_indices() {
+    let exec = LocalExecutor::default();
+
+    exec.run(async move {
+        let mut path = temp_dir();
+        path.push(uuid::Uuid::new_v4().to_string());
+
+        let (writer_factory, reader_factory) = timestore::open(
+            timestore::ConfigBuilder::default()
+                .path(path)
+                .create_if_not_exists(true)
+                .tables(vec!["table0".to_owned()])
+                .build()
+                .unwrap(),
+        )
+        .await
+        .context("open db")?;
+
+        let mut writer = writer_factory.make().await.unwrap();
+        let reader = reader_factory.make().await.unwrap();
+
+        let value = |key: u64| {
+            (0..20000u64)
+                .map(|i| ((i + key) % 251) as u8)
+                .collect::<Vec<u8>>()
+        };
+        for key in 1..=3u64 {
+            writer.append(key, vec![value(key)]).await.unwrap();
+        }
+
+        let iovs = vec![
+            (5000u64, 9000usize),
+            (0, 1),
+            (100, 0),
+            (19000, 1000),
+            (10, 4096),
+            (12000, 7),
+        ];
+        let check = |key: u64, results: Vec<Result<(usize, _), timestore::TimestoreError>>| {
+            let expected = value(key);
+            let mut seen = vec![false; iovs.len()];
+            for res in results {
+                let (index, buf): (usize, glommio::io::ReadResult) = res.unwrap();
+                let (pos, size) = iovs[index];
+                assert_eq!(&*buf, &expected[pos as usize..pos as usize + size]);
+                assert!(!seen[index]);
+                seen[index] = true;
+            }
+            assert!(seen.iter().all(|&s| s));
+        };
+
+        let results = reader
+            .read_many(
+                "table0",
+                2,
+                futures::stream::iter(iovs.clone()),
+                8,
+                MergedBufferLimit::NoMerging,
+                ReadAmplificationLimit::NoAmplification,
+            )
+            .await
+            .unwrap()
+            .unwrap()
+            .collect::<Vec<_>>()
+            .await;
+        check(2, results);
+
+        let mut iter = reader
+            .iter(IterParamsBuilder::default().from(2).to(3).build().unwrap())
+            .await
+            .unwrap()
+            .unwrap();
+        iter.next().await.unwrap().unwrap();
+        let results = iter
+            .read_many(
+                "table0",
+                futures::stream::iter(iovs.clone()),
+                MergedBufferLimit::DeviceMaxSingleRequest,
+                ReadAmplificationLimit::NoAmplification,
+            )
+            .await
+            .unwrap()
+            .collect::<Vec<_>>()
+            .await;
+        check(3, results);
+
+        Ok::<_, anyhow::Error>(())
+    })
+    .unwrap();
+}
+
+#[test]
+fn test_iter_unknown_table() {
+    let exec = LocalExecutor::default();
+
+    exec.run(async move {
+        let mut path = temp_dir();
+        path.push(uuid::Uuid::new_v4().to_string());
+
+        let (writer_factory, reader_factory) = timestore::open(
+            timestore::ConfigBuilder::default()
+                .path(path)
+                .create_if_not_exists(true)
+                .tables(vec!["table0".to_owned()])
+                .build()
+                .unwrap(),
+        )
+        .await
+        .context("open db")?;
+
+        let mut writer = writer_factory.make().await.unwrap();
+        let reader = reader_factory.make().await.unwrap();
+
+        let check = |from, to| {
+            let reader = &reader;
+            async move {
+                let params = |table| {
+                    IterParamsBuilder::default()
+                        .from(from)
+                        .to(to)
+                        .table(table)
+                        .build()
+                        .unwrap()
+                };
+                match reader.iter(params(Some("table1"))).await {
+                    Err(timestore::TimestoreError::TableNotFound(table)) => {
+                        assert_eq!(table, "table1")
+                    }
+                    res => panic!("unexpected result {:?}", res.map(|r| r.is_some())),
+                }
+                assert!(matches!(
+                    reader.iter_rows(params(None), &["table0", "table1"]).await,
+                    Err(timestore::TimestoreError::TableNotFound(_))
+                ));
+            }
+        };
+
+        // empty store
+        check(0, 10).await;
+
+        for key in 1..=5u64 {
+            writer.append(key, vec![vec![1]]).await.unwrap();
+        }
+
+        check(0, 10).await;
+        check(2, 4).await;
+        // no keys after from
+        check(100, 200).await;
+
+        Ok::<_, anyhow::Error>(())
+    })
+    .unwrap();
+}
+
+#[test]
+fn test_alignment() {
+    let exec = LocalExecutor::default();
+
+    exec.run(async move {
+        let mut path = temp_dir();
+        path.push(uuid::Uuid::new_v4().to_string());
+
+        let (writer_factory, reader_factory) = timestore::open(
+            timestore::ConfigBuilder::default()
+                .path(path.clone())
+                .create_if_not_exists(true)
+                .tables(vec!["table0".to_owned()])
+                .build()
+                .unwrap(),
+        )
+        .await
+        .context("open db")?;
+
+        let writer = writer_factory.make().await.unwrap();
+        let reader = reader_factory.make().await.unwrap();
+
+        let file = glommio::io::DmaFile::open(path.join("table0").join("data"))
+            .await
+            .unwrap();
+        let expected = file.alignment();
+        file.close().await.unwrap();
+
+        let alignment = reader.alignment("table0").unwrap();
+        assert!(alignment.is_power_of_two());
+        assert_eq!(alignment, expected);
+        assert_eq!(writer.alignment(), expected);
+        assert!(reader.alignment("table1").is_err());
+
+        Ok::<_, anyhow::Error>(())
+    })
+    .unwrap();
+}
+
+#[test]
+fn test_iter_lengths() {
+    let exec = LocalExecutor::default();
+
+    exec.run(async move {
+        let mut path = temp_dir();
+        path.push(uuid::Uuid::new_v4().to_string());
+
+        let (writer_factory, reader_factory) = timestore::open(
+            timestore::ConfigBuilder::default()
+                .path(path)
+                .create_if_not_exists(true)
+                .tables(vec!["table0".to_owned(), "table1".to_owned()])
+                .segment_length(4u32)
+                .build()
+                .unwrap(),
+        )
+        .await
+        .context("open db")?;
+
+        let mut writer = writer_factory.make().await.unwrap();
+        let reader = reader_factory.make().await.unwrap();
+
+        assert!(reader.iter_lengths(0, 100).unwrap().is_none());
+
+        let lengths = |key: u64| vec![(key * 7 % 5) as usize, (key * 100) as usize % 33];
+        for key in 1..=20u64 {
+            let values = lengths(key).into_iter().map(|len| vec![0; len]).collect();
+            writer.append(key * 10, values).await.unwrap();
+        }
+
+        for (from, to) in [
+            (0, 1000),
+            (10, 11),
+            (15, 95),
+            (100, 200),
+            (200, 300),
+            (50, 50),
+        ] {
+            let expected = (1..=20u64)
+                .filter(|key| (from..to).contains(&(key * 10)))
+                .map(|key| (key * 10, lengths(key)))
+                .collect::<Vec<_>>();
+            let got = reader
+                .iter_lengths(from, to)
+                .unwrap()
+                .map(|iter| iter.collect::<Vec<_>>())
+                .unwrap_or_default();
+            assert_eq!(got, expected, "[{}, {})", from, to);
+        }
+
+        Ok::<_, anyhow::Error>(())
+    })
+    .unwrap();
+}
+
+#[test]
+fn test_keys_in_range() {
+    let exec = LocalExecutor::default();
+
+    exec.run(async move {
+        let mut path = temp_dir();
+        path.push(uuid::Uuid::new_v4().to_string());
+
+        let (writer_factory, reader_factory) = timestore::open(
+            timestore::ConfigBuilder::default()
+                .path(path)
+                .create_if_not_exists(true)
+                .tables(vec!["table0".to_owned()])
+                .segment_length(4u32)
+                .build()
+                .unwrap(),
+        )
+        .await
+        .context("open db")?;
+
+        let mut writer = writer_factory.make().await.unwrap();
+        let reader = reader_factory.make().await.unwrap();
+
+        assert!(reader.keys_in_range(0, 100).is_empty());
+
+        for key in 1..=20u64 {
+            writer
+                .append(key * 10, vec![vec![key as u8]])
+                .await
+                .unwrap();
+        }
+
+        for (from, to) in [
+            (0, 1000),
+            (0, 0),
+            (0, 10),
+            (10, 11),
+            (15, 95),
+            (20, 90),
+            (55, 30),
+            (200, 300),
+            (199, 300),
+        ] {
+            let mut expected = Vec::new();
+            if let Some(mut iter) = reader
+                .iter(
+                    IterParamsBuilder::default()
+                        .from(from)
+                        .to(to)
+                        .build()
+                        .unwrap(),
+                )
+                .await
+                .unwrap()
+            {
+                while let Some(((_, key), _)) = iter.next().await.unwrap() {
+                    expected.push(key);
+                }
+            }
+            assert_eq!(
+                reader.keys_in_range(from, to),
+                expected,
+                "({}, {})",
+                from,
+                to
+            );
+            assert_eq!(
+                reader.raw_key_iter(from, to).collect::<Vec<u64>>(),
+                expected,
+                "raw ({}, {})",
+                from,
+                to
+            );
+        }
+
+        Ok::<_, anyhow::Error>(())
+    })
+    .unwrap();
+}
+
+#[test]
+fn test_read_many_auto_concurrency() {
+    let exec = LocalExecutor::default();
+
+    exec.run(async move {
+        let mut path = temp_dir();
+        path.push(uuid::Uuid::new_v4().to_string());
+
+        let (writer_factory, reader_factory) = timestore::open(
+            timestore::ConfigBuilder::default()
+                .path(path)
+                .create_if_not_exists(true)
+                .tables(vec!["table0".to_owned()])
+                .build()
+                .unwrap(),
+        )
+        .await
+        .context("open db")?;
+
+        let mut writer = writer_factory.make().await.unwrap();
+        let reader = reader_factory.make().await.unwrap();
+
+        let value = (0..300000u64).map(|i| (i % 251) as u8).collect::<Vec<u8>>();
+        writer.append(1, vec![value.clone()]).await.unwrap();
+
+        let small = (0..500u64).map(|i| (i * 577, 33usize)).collect::<Vec<_>>();
+        let large = vec![(0u64, 150000usize), (150000, 150000), (7, 0)];
+        for iovs in [small, large] {
+            let results = reader
+                .read_many(
+                    "table0",
+                    1,
+                    futures::stream::iter(iovs.clone()),
+                    timestore::ReadConcurrency::Auto,
+                    MergedBufferLimit::NoMerging,
+                    ReadAmplificationLimit::NoAmplification,
+                )
+                .await
+                .unwrap()
+                .unwrap()
+                .collect::<Vec<_>>()
+                .await;
+            assert_eq!(results.len(), iovs.len());
+            for res in results {
+                let (index, buf) = res.unwrap();
+                let (pos, size) = iovs[index];
+                assert_eq!(&*buf, &value[pos as usize..pos as usize + size]);
+            }
+        }
+
+        let auto = timestore::ReadConcurrency::Auto;
+        for (count, total_bytes) in [
+            (0, 0),
+            (1, 10),
+            (3, 300000),
+            (1000, 33000),
+            (1_000_000, 1_000_000),
+            (10, 10 << 30),
+        ] {
+            let (concurrency, _) = auto.resolve(count, total_bytes, MergedBufferLimit::NoMerging);
+            assert!(concurrency >= 1);
+            assert!(concurrency <= timestore::ReadConcurrency::MAX_AUTO);
+            assert!(concurrency <= count.max(1));
+        }
+        assert!(matches!(
+            auto.resolve(1000, 33000, MergedBufferLimit::NoMerging).1,
+            MergedBufferLimit::DeviceMaxSingleRequest
+        ));
+        assert!(matches!(
+            auto.resolve(2, 300000, MergedBufferLimit::DeviceMaxSingleRequest)
+                .1,
+            MergedBufferLimit::NoMerging
+        ));
+        assert_eq!(
+            timestore::ReadConcurrency::from(5)
+                .resolve(1000, 33000, MergedBufferLimit::NoMerging)
+                .0,
+            5
+        );
+
+        Ok::<_, anyhow::Error>(())
+    })
+    .unwrap();
+}
+
+#[test]
+fn test_tail() {
+    let exec = LocalExecutor::default();
+
+    exec.run(async move {
+        let mut path = temp_dir();
+        path.push(uuid::Uuid::new_v4().to_string());
+
+        let (writer_factory, reader_factory) = timestore::open(
+            timestore::ConfigBuilder::default()
+                .path(path)
+                .create_if_not_exists(true)
+                .tables(vec!["table0".to_owned()])
+                .build()
+                .unwrap(),
+        )
+        .await
+        .context("open db")?;
+
+        let mut writer = writer_factory.make().await.unwrap();
+        let reader = reader_factory.make().await.unwrap();
+
+        assert!(reader.tail("table0", 3).await.unwrap().is_empty());
+        assert!(reader.tail("table1", 3).await.is_err());
+
+        for key in 1..=10u64 {
+            writer
+                .append(key * 10, vec![vec![key as u8; key as usize]])
+                .await
+                .unwrap();
+        }
+
+        let tail = |res: Vec<(u64, timestore::ReadBuf)>| {
+            res.into_iter()
+                .map(|(key, buf)| (key, buf.to_vec()))
+                .collect::<Vec<_>>()
+        };
+        let expected = |keys: std::ops::RangeInclusive<u64>| {
+            keys.map(|key| (key * 10, vec![key as u8; key as usize]))
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            tail(reader.tail("table0", 3).await.unwrap()),
+            expected(8..=10)
+        );
+        assert_eq!(
+            tail(reader.tail("table0", 10).await.unwrap()),
+            expected(1..=10)
+        );
+        assert_eq!(
+            tail(reader.tail("table0", 20).await.unwrap()),
+            expected(1..=10)
+        );
+        assert!(reader.tail("table0", 0).await.unwrap().is_empty());
+
+        // duplicates of the last key are all part of the tail
+        for value in [11u8, 12] {
+            writer.append(100, vec![vec![value]]).await.unwrap();
+        }
+        assert_eq!(
+            tail(reader.tail("table0", 3).await.unwrap()),
+            vec![(100, vec![10u8; 10]), (100, vec![11]), (100, vec![12])]
+        );
+
+        Ok::<_, anyhow::Error>(())
+    })
+    .unwrap();
+}
+
+#[test]
+fn test_close_clones() {
+    let exec = LocalExecutor::default();
+
+    exec.run(async move {
+        let mut path = temp_dir();
+        path.push(uuid::Uuid::new_v4().to_string());
+
+        let (writer_factory, reader_factory) = timestore::open(
+            timestore::ConfigBuilder::default()
+                .path(path)
+                .create_if_not_exists(true)
+                .tables(vec!["table0".to_owned()])
+                .build()
+                .unwrap(),
+        )
+        .await
+        .context("open db")?;
+
+        let mut writer = writer_factory.make().await.unwrap();
+        for key in 1..=5u64 {
+            writer.append(key, vec![vec![key as u8; 10]]).await.unwrap();
+        }
+
+        let reader = reader_factory.make().await.unwrap();
+        let clone = reader.clone();
+        let other = reader_factory.make().await.unwrap();
+        for r in [&reader, &clone] {
+            let res = r.read("table0", 3).await.unwrap().unwrap();
+            assert_eq!(&*res, &[3; 10]);
+        }
+
+        let mut iter = reader
+            .iter(
+                IterParamsBuilder::default()
+                    .from(0)
+                    .to(10)
+                    .table(Some("table0"))
+                    .build()
+                    .unwrap(),
+            )
+            .await
+            .unwrap()
+            .unwrap();
+
+        // the files are still used by the other clone and the iterator
+        assert!(!clone.close().await.unwrap());
+        let res = reader.read("table0", 4).await.unwrap().unwrap();
+        assert_eq!(&*res, &[4; 10]);
+
+        let reader_clone = reader.clone();
+        assert!(!reader.close().await.unwrap());
+        let ((_, key), buf) = iter.next().await.unwrap().unwrap();
+        assert_eq!(key, 1);
+        assert_eq!(buf, vec![1; 10]);
+        drop(iter);
+
+        assert!(reader_clone.close().await.unwrap());
+
+        // readers from another make have their own files
+        let res = other.read("table0", 5).await.unwrap().unwrap();
+        assert_eq!(&*res, &[5; 10]);
+        assert!(other.close().await.unwrap());
+
+        Ok::<_, anyhow::Error>(())
+    })
+    .unwrap();
+}
+
+#[test]
+fn test_read_at_position() {
+    let exec = LocalExecutor::default();
+
+    exec.run(async move {
+        let mut path = temp_dir();
+        path.push(uuid::Uuid::new_v4().to_string());
+
+        let (writer_factory, reader_factory) = timestore::open(
+            timestore::ConfigBuilder::default()
+                .path(path)
+                .create_if_not_exists(true)
+                .tables(vec!["table0".to_owned(), "table1".to_owned()])
+                .segment_length(3u32)
+                .validate_reads(true)
+                .build()
+                .unwrap(),
+        )
+        .await
+        .context("open db")?;
+
+        let mut writer = writer_factory.make().await.unwrap();
+        let reader = reader_factory.make().await.unwrap();
+
+        assert_eq!(reader.key_at_position(0), None);
+        assert!(reader
+            .read_at_position("table0", 0)
+            .await
+            .unwrap()
+            .is_none());
+
+        for key in 1..=10u64 {
+            writer
+                .append(key * 7, vec![vec![key as u8; key as usize], vec![]])
+                .await
+                .unwrap();
+        }
+
+        for position in 0..10usize {
+            let key = reader.key_at_position(position).unwrap();
+            assert_eq!(key, (position as u64 + 1) * 7);
+            for table in ["table0", "table1"] {
+                let res = reader
+                    .read_at_position(table, position)
+                    .await
+                    .unwrap()
+                    .unwrap();
+                let expected = reader.read(table, key).await.unwrap().unwrap();
+                assert_eq!(&*res, &*expected);
+            }
+        }
+
+        assert_eq!(reader.key_at_position(10), None);
+        assert!(reader
+            .read_at_position("table0", 10)
+            .await
+            .unwrap()
+            .is_none());
+        assert!(reader
+            .read_at_position("table0", 1000)
+            .await
+            .unwrap()
+            .is_none());
+        assert!(reader.read_at_position("table2", 0).await.is_err());
+
+        Ok::<_, anyhow::Error>(())
+    })
+    .unwrap();
+}
+
+#[test]
+fn test_make_at_sync_point() {
+    let exec = LocalExecutor::default();
+
+    exec.run(async move {
+        let mut path = temp_dir();
+        path.push(uuid::Uuid::new_v4().to_string());
+
+        let (writer_factory, reader_factory) = timestore::open(
+            timestore::ConfigBuilder::default()
+                .path(path)
+                .create_if_not_exists(true)
+                .tables(vec!["table0".to_owned()])
+                .segment_length(4u32)
+                .build()
+                .unwrap(),
+        )
+        .await
+        .context("open db")?;
+
+        let mut writer = writer_factory.make().await.unwrap();
+        assert_eq!(writer.sync_point(), 0);
+        let empty = reader_factory.make_at(0).await.unwrap();
+        assert!(empty.keys().last().is_none());
+
+        for key in 1..=10u64 {
+            writer.append(key, vec![vec![key as u8]]).await.unwrap();
+        }
+        let sync_point = writer.sync_point();
+        assert_eq!(sync_point, 10);
+        let reader = reader_factory.make_at(sync_point).await.unwrap();
+
+        for key in 11..=20u64 {
+            writer.append(key, vec![vec![key as u8]]).await.unwrap();
+        }
+
+        assert_eq!(
+            reader.keys().iter_from(0).collect::<Vec<u64>>(),
+            (1..=10).collect::<Vec<u64>>()
+        );
+        assert_eq!(&*reader.read("table0", 10).await.unwrap().unwrap(), &[10u8]);
+        assert!(reader.read("table0", 11).await.unwrap().is_none());
+        assert_eq!(reader.count_range(0, 100), 10);
+        assert!(empty.read("table0", 1).await.unwrap().is_none());
+
+        // the factory's reader sees everything
+        let latest = reader_factory.make().await.unwrap();
+        assert_eq!(latest.count_range(0, 100), 20);
+
+        assert!(reader_factory.make_at(21).await.is_err());
+
+        // records with the same key as the last one are counted
+        writer.append(20, vec![vec![20]]).await.unwrap();
+        writer.append(20, vec![vec![20]]).await.unwrap();
+        let reader = reader_factory.make_at(22).await.unwrap();
+        assert_eq!(reader.count_range(20, 21), 3);
+        assert!(reader_factory.make_at(23).await.is_err());
+
+        Ok::<_, anyhow::Error>(())
+    })
+    .unwrap();
+}
+
+#[test]
+fn test_iter_current_value_len() {
+    let exec = LocalExecutor::default();
+
+    exec.run(async move {
+        let mut path = temp_dir();
+        path.push(uuid::Uuid::new_v4().to_string());
+
+        let (writer_factory, reader_factory) = timestore::open(
+            timestore::ConfigBuilder::default()
+                .path(path)
+                .create_if_not_exists(true)
+                .tables(vec!["table0".to_owned(), "table1".to_owned()])
+                .build()
+                .unwrap(),
+        )
+        .await
+        .context("open db")?;
+
+        let mut writer = writer_factory.make().await.unwrap();
+        let reader = reader_factory.make().await.unwrap();
+
+        for key in 1..=6u64 {
+            writer
+                .append(
+                    key,
+                    vec![
+                        vec![1; key as usize * 3],
+                        vec![2; 7000 * (key as usize % 3)],
+                    ],
+                )
+                .await
+                .unwrap();
+        }
+
+        let mut iter = reader
+            .iter(
+                IterParamsBuilder::default()
+                    .from(0)
+                    .to(10)
+                    .table(Some("table0"))
+                    .build()
+                    .unwrap(),
+            )
+            .await
+            .unwrap()
+            .unwrap();
+
+        assert!(iter.current_value_len("table0").is_err());
+
+        let mut steps = 0;
+        while let Some((_, buf)) = iter.next().await.unwrap() {
+            assert_eq!(iter.current_value_len("table0").unwrap(), buf.len());
+            for table in ["table0", "table1"] {
+                assert_eq!(
+                    iter.current_value_len(table).unwrap(),
+                    iter.read(table).await.unwrap().len()
+                );
+            }
+            assert!(iter.current_value_len("table2").is_err());
+            steps += 1;
+        }
+        assert_eq!(steps, 6);
+
+        Ok::<_, anyhow::Error>(())
+    })
+    .unwrap();
+}
+
+#[test]
+fn test_iter_dropped_next() {
+    let exec = LocalExecutor::default();
+
+    exec.run(async move {
+        let mut path = temp_dir();
+        path.push(uuid::Uuid::new_v4().to_string());
+
+        let (writer_factory, reader_factory) = timestore::open(
+            timestore::ConfigBuilder::default()
+                .path(path)
+                .create_if_not_exists(true)
+                .tables(vec!["table0".to_owned()])
+                .build()
+                .unwrap(),
+        )
+        .await
+        .context("open db")?;
+
+        let mut writer = writer_factory.make().await.unwrap();
+        let reader = reader_factory.make().await.unwrap();
+
+        for key in 1..=4u64 {
+            writer
+                .append(key, vec![vec![key as u8; 1 << 20]])
+                .await
+                .unwrap();
+        }
+
+        let mut iter = reader
+            .iter(
+                IterParamsBuilder::default()
+                    .from(0)
+                    .to(10)
+                    .table(Some("table0"))
+                    .buffer_size(4096)
+                    .build()
+                    .unwrap(),
+            )
+            .await
+            .unwrap()
+            .unwrap();
+
+        let (range, buf) = iter.next().await.unwrap().unwrap();
+        assert_eq!(range, (0, 1));
+        assert_eq!(buf, vec![1; 1 << 20]);
+
+        // drop a next that is in the middle of reading the value, the next call reads the same record
+        {
+            let timeout = glommio::timer::sleep(std::time::Duration::from_micros(1)).fuse();
+            let next = iter.next().fuse();
+            futures::pin_mut!(timeout, next);
+            futures::select_biased! {
+                _ = timeout => (),
+                _ = next => panic!("read finished before the timeout"),
+            }
+        }
+        assert_eq!(iter.peek_key(), Some(2));
+        let (range, buf) = iter.next().await.unwrap().unwrap();
+        assert_eq!(range, (1, 2));
+        assert_eq!(buf, vec![2; 1 << 20]);
+
+        // cancel skips the record of the dropped next
+        {
+            let next = iter.next();
+            futures::pin_mut!(next);
+            assert!(futures::poll!(next).is_pending());
+        }
+        assert_eq!(iter.peek_key(), Some(3));
+        iter.cancel();
+        assert_eq!(iter.peek_key(), Some(4));
+        let (range, buf) = iter.next().await.unwrap().unwrap();
+        assert_eq!(range, (3, 4));
+        assert_eq!(buf, vec![4; 1 << 20]);
+
+        assert!(iter.next().await.unwrap().is_none());
+
+        Ok::<_, anyhow::Error>(())
+    })
+    .unwrap();
+}
+
+#[derive(Default)]
+struct CacheObserver {
+    reads: AtomicUsize,
+    cache_hits: AtomicUsize,
+}
+
+impl StoreObserver for CacheObserver {
+    fn on_read(&self, _table: &str, _bytes: usize) {
+        self.reads.fetch_add(1, Ordering::SeqCst);
+    }
+
+    fn on_cache_hit(&self, _table: &str, _bytes: usize) {
+        self.cache_hits.fetch_add(1, Ordering::SeqCst);
+    }
+}
+
+#[test]
+fn test_read_cache() {
+    let exec = LocalExecutor::default();
+
+    exec.run(async move {
+        let mut path = temp_dir();
+        path.push(uuid::Uuid::new_v4().to_string());
+
+        let observer = Arc::new(CacheObserver::default());
+
+        let (writer_factory, reader_factory) = timestore::open(
+            timestore::ConfigBuilder::default()
+                .path(path)
+                .create_if_not_exists(true)
+                .tables(vec!["table0".to_owned()])
+                .read_cache_bytes(100usize)
+                .observer(observer.clone())
+                .build()
+                .unwrap(),
+        )
+        .await
+        .context("open db")?;
+
+        let mut writer = writer_factory.make().await.unwrap();
+        let reader = reader_factory.make().await.unwrap();
+
+        for key in 1..=4u64 {
+            writer.append(key, vec![vec![key as u8; 40]]).await.unwrap();
+        }
+
+        let counts = || {
+            (
+                observer.reads.load(Ordering::SeqCst),
+                observer.cache_hits.load(Ordering::SeqCst),
+            )
+        };
+
+        assert_eq!(&*reader.read("table0", 1).await.unwrap().unwrap(), &[1; 40]);
+        assert_eq!(counts(), (1, 0));
+        assert_eq!(&*reader.read("table0", 1).await.unwrap().unwrap(), &[1; 40]);
+        assert_eq!(counts(), (1, 1));
+
+        // clones share the cache, other keys still go to disk
+        let clone = reader.clone();
+        assert_eq!(&*clone.read("table0", 1).await.unwrap().unwrap(), &[1; 40]);
+        assert_eq!(&*clone.read("table0", 2).await.unwrap().unwrap(), &[2; 40]);
+        assert_eq!(counts(), (2, 2));
+
+        // only two values fit, the least recently used one is evicted
+        reader.read("table0", 3).await.unwrap().unwrap();
+        reader.read("table0", 1).await.unwrap().unwrap();
+        assert_eq!(counts(), (4, 2));
+        reader.read("table0", 3).await.unwrap().unwrap();
+        assert_eq!(counts(), (4, 3));
+
+        // replacing a value drops the cached one even if the size doesn't change
+        reader.read("table0", 4).await.unwrap().unwrap();
+        writer.overwrite_last(4, vec![vec![9; 40]]).await.unwrap();
+        assert_eq!(&*reader.read("table0", 4).await.unwrap().unwrap(), &[9; 40]);
+        assert_eq!(counts(), (6, 3));
+        assert_eq!(&*reader.read("table0", 4).await.unwrap().unwrap(), &[9; 40]);
+        assert_eq!(counts(), (6, 4));
+
+        // new records are read from disk
+        writer.append(5, vec![vec![5; 40]]).await.unwrap();
+        assert_eq!(&*reader.read("table0", 5).await.unwrap().unwrap(), &[5; 40]);
+        assert_eq!(counts(), (7, 4));
+
+        Ok::<_, anyhow::Error>(())
+    })
+    .unwrap();
+}
+
+#[test]
+fn test_iter_join() {
+    let exec = LocalExecutor::default();
+
+    exec.run(async move {
+        let mut path = temp_dir();
+        path.push(uuid::Uuid::new_v4().to_string());
+
+        let (writer_factory, reader_factory) = timestore::open(
+            timestore::ConfigBuilder::default()
+                .path(path)
+                .create_if_not_exists(true)
+                .tables(vec![
+                    "table0".to_owned(),
+                    "table1".to_owned(),
+                    "table2".to_owned(),
+                ])
+                .build()
+                .unwrap(),
+        )
+        .await
+        .context("open db")?;
+
+        let mut writer = writer_factory.make().await.unwrap();
+        let reader = reader_factory.make().await.unwrap();
+
+        // table0 has a value for even keys and table2 for keys divisible by 3
+        for key in 1..=30u64 {
+            let sparse = |present: bool| {
+                if present {
+                    vec![key as u8; key as usize * 10]
+                } else {
+                    Vec::new()
+                }
+            };
+            writer
+                .append(
+                    key,
+                    vec![sparse(key % 2 == 0), vec![1; 100], sparse(key % 3 == 0)],
+                )
+                .await
+                .unwrap();
+        }
+
+        let params = IterParamsBuilder::default()
+            .from(5)
+            .to(25)
+            .buffer_size(4096)
+            .build()
+            .unwrap();
+        let mut join = reader
+            .iter_join(params, "table2", "table0")
+            .await
+            .unwrap()
+            .unwrap();
+
+        let mut keys = Vec::new();
+        while let Some((key, left, right)) = join.next().await.unwrap() {
+            assert_eq!(left, vec![key as u8; key as usize * 10]);
+            assert_eq!(right, left);
+            keys.push(key);
+        }
+        assert_eq!(keys, vec![6, 12, 18, 24]);
+
+        let params = IterParamsBuilder::default()
+            .from(0)
+            .to(100)
+            .build()
+            .unwrap();
+        assert!(reader.iter_join(params, "table0", "table3").await.is_err());
+
+        Ok::<_, anyhow::Error>(())
+    })
+    .unwrap();
+}
+
+#[test]
+fn test_read_sub() {
+    let exec = LocalExecutor::default();
+
+    exec.run(async move {
+        let mut path = temp_dir();
+        path.push(uuid::Uuid::new_v4().to_string());
+
+        let (writer_factory, reader_factory) = timestore::open(
+            timestore::ConfigBuilder::default()
+                .path(path)
+                .create_if_not_exists(true)
+                .tables(vec!["table0".to_owned()])
+                .build()
+                .unwrap(),
+        )
+        .await
+        .context("open db")?;
+
+        let mut writer = writer_factory.make().await.unwrap();
+        let reader = reader_factory.make().await.unwrap();
+
+        // Each value is a u64 id, a u32 count and a u64 sum.
+        let value = |key: u64| {
+            let mut value = Vec::new();
+            value.extend_from_slice(&key.to_be_bytes());
+            value.extend_from_slice(&(key as u32 * 3).to_be_bytes());
+            value.extend_from_slice(&(key * 1000).to_be_bytes());
+            value
+        };
+        for key in 1..=10u64 {
+            writer.append(key, vec![value(key)]).await.unwrap();
+        }
+
+        for key in 1..=10u64 {
+            let count = reader.read_sub("table0", key, 8, 4).await.unwrap().unwrap();
+            assert_eq!(
+                u32::from_be_bytes((&*count).try_into().unwrap()),
+                key as u32 * 3
+            );
+            let sum = reader
+                .read_sub("table0", key, 12, 8)
+                .await
+                .unwrap()
+                .unwrap();
+            assert_eq!(&*sum, &value(key)[12..]);
+        }
+
+        assert!(reader
+            .read_sub("table0", 5, 20, 0)
+            .await
+            .unwrap()
+            .unwrap()
+            .is_empty());
+        assert!(reader.read_sub("table0", 5, 16, 8).await.is_err());
+        assert!(reader.read_sub("table0", 5, usize::MAX, 2).await.is_err());
+        assert!(reader.read_sub("table0", 11, 0, 4).await.unwrap().is_none());
+        assert!(reader.read_sub("table1", 5, 0, 4).await.is_err());
+
+        Ok::<_, anyhow::Error>(())
+    })
+    .unwrap();
+}
+
+#[test]
+fn test_read_options() {
+    let exec = LocalExecutor::default();
+
+    exec.run(async move {
+        let mut path = temp_dir();
+        path.push(uuid::Uuid::new_v4().to_string());
+
+        let (writer_factory, reader_factory) = timestore::open(
+            timestore::ConfigBuilder::default()
+                .path(path)
+                .create_if_not_exists(true)
+                .tables(vec!["table0".to_owned()])
+                .build()
+                .unwrap(),
+        )
+        .await
+        .context("open db")?;
+
+        let mut writer = writer_factory.make().await.unwrap();
+        let reader = reader_factory.make().await.unwrap();
+
+        let value = (0..50000u64).map(|i| (i % 241) as u8).collect::<Vec<u8>>();
+        writer.append(1, vec![value.clone()]).await.unwrap();
+
+        let iovs = vec![
+            (0u64, 100usize),
+            (100, 300),
+            (1000, 20000),
+            (30000, 1),
+            (40000, 0),
+        ];
+
+        let default = ReadOptionsBuilder::default().build().unwrap();
+        assert_eq!(default, timestore::ReadOptions::default());
+        let overridden = ReadOptionsBuilder::default()
+            .concurrency(2usize)
+            .max_merged_bytes(Some(8192))
+            .max_read_amplification(4096usize)
+            .build()
+            .unwrap();
+        let no_merging = ReadOptionsBuilder::default()
+            .concurrency(timestore::ReadConcurrency::Fixed(4))
+            .max_merged_bytes(Some(0))
+            .build()
+            .unwrap();
+
+        for opts in [default, overridden, no_merging] {
+            let results = reader
+                .read_many_with("table0", 1, futures::stream::iter(iovs.clone()), opts)
+                .await
+                .unwrap()
+                .unwrap()
+                .collect::<Vec<_>>()
+                .await;
+            assert_eq!(results.len(), iovs.len());
+            for res in results {
+                let (index, buf) = res.unwrap();
+                let (pos, size) = iovs[index];
+                assert_eq!(&*buf, &value[pos as usize..pos as usize + size]);
+            }
+        }
+
+        assert!(reader
+            .read_many_with("table0", 2, futures::stream::iter(iovs.clone()), default)
+            .await
+            .unwrap()
+            .is_none());
+
+        Ok::<_, anyhow::Error>(())
+    })
+    .unwrap();
+}
+
+#[test]
+fn test_key_stream() {
+    let exec = LocalExecutor::default();
+
+    exec.run(async move {
+        let mut path = temp_dir();
+        path.push(uuid::Uuid::new_v4().to_string());
+
+        let (writer_factory, reader_factory) = timestore::open(
+            timestore::ConfigBuilder::default()
+                .path(path)
+                .create_if_not_exists(true)
+                .tables(vec!["table0".to_owned()])
+                .segment_length(4u32)
+                .build()
+                .unwrap(),
+        )
+        .await
+        .context("open db")?;
+
+        let mut writer = writer_factory.make().await.unwrap();
+        let reader = reader_factory.make().await.unwrap();
+
+        assert!(reader
+            .key_stream(0, 100)
+            .collect::<Vec<u64>>()
+            .await
+            .is_empty());
+
+        for key in 1..=20u64 {
+            writer
+                .append(key * 10, vec![vec![key as u8]])
+                .await
+                .unwrap();
+        }
+
+        for (from, to) in [(0, 1000), (0, 0), (10, 11), (15, 95), (55, 30), (199, 300)] {
+            assert_eq!(
+                reader.key_stream(from, to).collect::<Vec<u64>>().await,
+                reader.keys_in_range(from, to),
+                "({}, {})",
+                from,
+                to
+            );
+        }
+
+        // the stream doesn't borrow the reader
+        let stream = reader.key_stream(0, 1000);
+        reader.close().await.unwrap();
+        assert_eq!(stream.count().await, 20);
+
+        Ok::<_, anyhow::Error>(())
+    })
+    .unwrap();
+}
+
+#[test]
+fn test_total_data_size() {
+    let exec = LocalExecutor::default();
+
+    exec.run(async move {
+        let mut path = temp_dir();
+        path.push(uuid::Uuid::new_v4().to_string());
+
+        let (writer_factory, reader_factory) = timestore::open(
+            timestore::ConfigBuilder::default()
+                .path(path)
+                .create_if_not_exists(true)
+                .tables(vec!["table0".to_owned(), "table1".to_owned()])
+                .segment_length(4u32)
+                .preallocate_bytes(1u64 << 20)
+                .data_segment_size(Some(1000))
+                .build()
+                .unwrap(),
+        )
+        .await
+        .context("open db")?;
+
+        let mut writer = writer_factory.make().await.unwrap();
+        let reader = reader_factory.make().await.unwrap();
+
+        assert_eq!(reader.total_data_size().unwrap(), 0);
+
+        let mut expected = 0;
+        for key in 1..=30u64 {
+            let values = vec![
+                vec![1; key as usize * 13],
+                vec![2; (key % 4) as usize * 100],
+            ];
+            expected += values.iter().map(|v| v.len() as u64).sum::<u64>();
+            writer.append(key, values).await.unwrap();
+            assert_eq!(reader.total_data_size().unwrap(), expected);
+        }
+
+        // records with the same key as the last one are counted
+        for _ in 0..3 {
+            writer
+                .append(30, vec![vec![3; 7], vec![4; 5]])
+                .await
+                .unwrap();
+            expected += 12;
+            assert_eq!(reader.total_data_size().unwrap(), expected);
+        }
+
+        Ok::<_, anyhow::Error>(())
+    })
+    .unwrap();
+}
+
+#[test]
+fn test_iter_prefetch_window() {
+    let exec = LocalExecutor::default();
+
+    exec.run(async move {
+        let mut path = temp_dir();
+        path.push(uuid::Uuid::new_v4().to_string());
+
+        let (writer_factory, reader_factory) = timestore::open(
+            timestore::ConfigBuilder::default()
+                .path(path)
+                .create_if_not_exists(true)
+                .tables(vec!["table0".to_owned(), "table1".to_owned()])
+                .data_segment_size(Some(300))
+                .build()
+                .unwrap(),
+        )
+        .await
+        .context("open db")?;
+
+        let mut writer = writer_factory.make().await.unwrap();
+        let reader = reader_factory.make().await.unwrap();
+
+        for pos in 0..50u64 {
+            // every fifth value is empty
+            let len = (pos % 5) as usize * 7;
+            writer
+                .append(
+                    pos + 1,
+                    vec![vec![pos as u8; len], pos.to_be_bytes().to_vec()],
+                )
+                .await
+                .unwrap();
+        }
+
+        let collect = |step: usize, prefetch_window: usize| {
+            let reader = reader.clone();
+            async move {
+                let mut iter = reader
+                    .iter(
+                        IterParamsBuilder::default()
+                            .from(3)
+                            .to(48)
+                            .table(Some("table0"))
+                            .step(step)
+                            .prefetch_window(prefetch_window)
+                            .build()
+                            .unwrap(),
+                    )
+                    .await
+                    .unwrap()
+                    .unwrap();
+
+                let mut records = Vec::new();
+                while let Some(record) = iter.next().await.unwrap() {
+                    records.push(record);
+                }
+                records
+            }
+        };
+
+        for step in [1, 3] {
+            let expected = collect(step, 0).await;
+            assert!(!expected.is_empty());
+            for prefetch_window in [1, 4, 16, 100] {
+                assert_eq!(collect(step, prefetch_window).await, expected);
+            }
+        }
+
+        let mut rows = reader
+            .iter_rows(
+                IterParamsBuilder::default()
+                    .from(0)
+                    .to(1000)
+                    .prefetch_window(8)
+                    .build()
+                    .unwrap(),
+                &["table0", "table1"],
+            )
+            .await
+            .unwrap()
+            .unwrap();
+        for pos in 0..50u64 {
+            let (_, row) = rows.next().await.unwrap().unwrap();
+            assert_eq!(
+                row,
+                vec![
+                    vec![pos as u8; (pos % 5) as usize * 7],
+                    pos.to_be_bytes().to_vec()
+                ]
+            );
+        }
+        assert!(rows.next().await.unwrap().is_none());
+
+        Ok::<_, anyhow::Error>(())
+    })
+    .unwrap();
+}
+
+#[test]
+fn test_iter_next_result() {
+    let exec = LocalExecutor::default();
+
+    exec.run(async move {
+        let mut path = temp_dir();
+        path.push(uuid::Uuid::new_v4().to_string());
+
+        let (writer_factory, reader_factory) = timestore::open(
+            timestore::ConfigBuilder::default()
+                .path(path)
+                .create_if_not_exists(true)
+                .tables(vec!["table0".to_owned(), "table1".to_owned()])
+                .build()
+                .unwrap(),
+        )
+        .await
+        .context("open db")?;
+
+        let mut writer = writer_factory.make().await.unwrap();
+        let reader = reader_factory.make().await.unwrap();
+
+        for pos in 0..20u64 {
+            writer
+                .append(
+                    pos * 2 + 1,
+                    vec![
+                        vec![pos as u8; (pos % 4) as usize * 5],
+                        pos.to_be_bytes().to_vec(),
+                    ],
+                )
+                .await
+                .unwrap();
+        }
+
+        let params = |table| {
+            IterParamsBuilder::default()
+                .from(4)
+                .to(30)
+                .table(table)
+                .step(2)
+                .build()
+                .unwrap()
+        };
+
+        let mut iter = reader.iter(params(Some("table0"))).await.unwrap().unwrap();
+        let mut result_iter = reader.iter(params(Some("table0"))).await.unwrap().unwrap();
+        let mut count = 0;
+        while let Some((range, buf)) = iter.next().await.unwrap() {
+            let (result_range, result) = result_iter.next_result().await.unwrap().unwrap();
+            assert_eq!(result_range, range);
+            assert_eq!(&*result.unwrap(), buf.as_slice());
+            assert_eq!(
+                &*result_iter.read("table1").await.unwrap(),
+                &*iter.read("table1").await.unwrap()
+            );
+            count += 1;
+        }
+        assert_eq!(count, 8);
+        assert!(result_iter.next_result().await.unwrap().is_none());
+
+        // without a selected table only the key ranges are returned
+        let mut iter = reader.iter(params(None)).await.unwrap().unwrap();
+        let mut result_iter = reader.iter(params(None)).await.unwrap().unwrap();
+        let (range, _) = iter.next().await.unwrap().unwrap();
+        let (result_range, result) = result_iter.next_result().await.unwrap().unwrap();
+        assert_eq!(result_range, range);
+        assert!(result.is_none());
+
+        Ok::<_, anyhow::Error>(())
+    })
+    .unwrap();
+}
+
+#[test]
+fn test_iter_empty_as_iter() {
+    let exec = LocalExecutor::default();
+
+    exec.run(async move {
+        let mut path = temp_dir();
+        path.push(uuid::Uuid::new_v4().to_string());
+
+        let (writer_factory, reader_factory) = timestore::open(
+            timestore::ConfigBuilder::default()
+                .path(path)
+                .create_if_not_exists(true)
+                .tables(vec!["table0".to_owned()])
+                .build()
+                .unwrap(),
+        )
+        .await
+        .context("open db")?;
+
+        let params = |from, empty_as_iter| {
+            IterParamsBuilder::default()
+                .from(from)
+                .to(100)
+                .table(Some("table0"))
+                .empty_as_iter(empty_as_iter)
+                .build()
+                .unwrap()
+        };
+
+        // an empty store gives an iterator without records
+        let reader = reader_factory.make().await.unwrap();
+        assert!(reader.iter(params(0, false)).await.unwrap().is_none());
+        let mut iter = reader.iter(params(0, true)).await.unwrap().unwrap();
+        assert!(iter.next().await.unwrap().is_none());
+        let mut rows = reader
+            .iter_rows(params(0, true), &["table0"])
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(rows.next().await.unwrap().is_none());
+
+        let mut writer = writer_factory.make().await.unwrap();
+        writer.append(10, vec![b"ten".to_vec()]).await.unwrap();
+        let reader = reader_factory.make().await.unwrap();
+
+        // from past the last key is still out of range
+        assert!(reader.iter(params(10, true)).await.unwrap().is_none());
+        let mut iter = reader.iter(params(5, true)).await.unwrap().unwrap();
+        assert_eq!(
+            iter.next().await.unwrap().unwrap(),
+            ((0, 10), b"ten".to_vec())
+        );
+        assert!(iter.next().await.unwrap().is_none());
+
+        Ok::<_, anyhow::Error>(())
+    })
+    .unwrap();
+}
+
+#[test]
+fn test_read_large_value() {
+    let exec = LocalExecutor::default();
+
+    exec.run(async move {
+        let mut path = temp_dir();
+        path.push(uuid::Uuid::new_v4().to_string());
+
+        let cfg = timestore::ConfigBuilder::default()
+            .path(path)
+            .create_if_not_exists(true)
+            .tables(vec!["table0".to_owned()])
+            .max_read_bytes(4096)
+            .build()
+            .unwrap();
+        let (writer_factory, reader_factory) =
+            timestore::open(cfg.clone()).await.context("open db")?;
+
+        let mut writer = writer_factory.make().await.unwrap();
+        let large = (0..100_003u32)
+            .map(|i| (i % 251) as u8)
+            .collect::<Vec<u8>>();
+        writer.append(1, vec![b"small".to_vec()]).await.unwrap();
+        writer.append(2, vec![large.clone()]).await.unwrap();
+        writer.append(3, vec![Vec::new()]).await.unwrap();
+
+        // values larger than max_read_bytes are read in chunks by every read path
+        let reader = reader_factory.make().await.unwrap();
+        assert_eq!(
+            &*reader.read("table0", 2).await.unwrap().unwrap(),
+            &large[..]
+        );
+        assert_eq!(&*reader.read("table0", 1).await.unwrap().unwrap(), b"small");
+        assert!(reader.read("table0", 3).await.unwrap().unwrap().is_empty());
+        assert!(reader.read("table0", 4).await.unwrap().is_none());
+        assert_eq!(
+            &*reader.read_at_position("table0", 1).await.unwrap().unwrap(),
+            &large[..]
+        );
+        assert_eq!(
+            &*reader
+                .read_sub("table0", 2, 10, 50_000)
+                .await
+                .unwrap()
+                .unwrap(),
+            &large[10..50_010]
+        );
+        assert_eq!(&*reader.tail("table0", 2).await.unwrap()[0].1, &large[..]);
+        assert_eq!(
+            &*writer.read("table0", 2).await.unwrap().unwrap(),
+            &large[..]
+        );
+
+        let mut iter = reader
+            .iter(IterParamsBuilder::default().from(1).to(3).build().unwrap())
+            .await
+            .unwrap()
+            .unwrap();
+        iter.next().await.unwrap().unwrap();
+        assert_eq!(&*iter.read("table0").await.unwrap(), &large[..]);
+        assert_eq!(&*iter.read_all().await.unwrap()[0].1, &large[..]);
+
+        let lazy = timestore::open_lazy(cfg).await.unwrap();
+        assert_eq!(&*lazy.read("table0", 2).await.unwrap().unwrap(), &large[..]);
+
+        Ok::<_, anyhow::Error>(())
+    })
+    .unwrap();
+}
+
+#[test]
+fn test_make_as_of() {
+    let exec = LocalExecutor::default();
+
+    exec.run(async move {
+        let mut path = temp_dir();
+        path.push(uuid::Uuid::new_v4().to_string());
+
+        let (writer_factory, reader_factory) = timestore::open(
+            timestore::ConfigBuilder::default()
+                .path(path)
+                .create_if_not_exists(true)
+                .tables(vec!["table0".to_owned()])
+                .build()
+                .unwrap(),
+        )
+        .await
+        .context("open db")?;
+
+        let mut writer = writer_factory.make().await.unwrap();
+        for key in 1..=10u64 {
+            writer.append(key, vec![vec![key as u8]]).await.unwrap();
+        }
+
+        let reader = reader_factory.make_as_of(5).await.unwrap();
+        for key in 11..=15u64 {
+            writer.append(key, vec![vec![key as u8]]).await.unwrap();
+        }
+
+        let mut iter = reader
+            .iter(
+                IterParamsBuilder::default()
+                    .from(0)
+                    .to(u64::MAX)
+                    .table(Some("table0"))
+                    .build()
+                    .unwrap(),
+            )
+            .await
+            .unwrap()
+            .unwrap();
+        let mut keys = Vec::new();
+        while let Some(((_, key), buf)) = iter.next().await.unwrap() {
+            assert_eq!(buf, vec![key as u8]);
+            keys.push(key);
+        }
+        assert_eq!(keys, vec![1, 2, 3, 4, 5]);
+
+        assert_eq!(reader.keys().iter_from(0).count(), 5);
+        assert_eq!(reader.count_range(0, u64::MAX), 5);
+        for key in 6..=15u64 {
+            assert!(reader.read("table0", key).await.unwrap().is_none());
+        }
+        assert!(reader
+            .iter(
+                IterParamsBuilder::default()
+                    .from(5)
+                    .to(u64::MAX)
+                    .build()
+                    .unwrap()
+            )
+            .await
+            .unwrap()
+            .is_none());
+
+        Ok::<_, anyhow::Error>(())
+    })
+    .unwrap();
+}
@@ -0,0 +1,131 @@
+use std::env::temp_dir;
+
+use anyhow::Context;
+use glommio::LocalExecutor;
+use timestore::IterParamsBuilder;
+
+fn value(key: u64) -> Vec<u8> {
+    vec![key as u8; 30]
+}
+
+#[test]
+fn test_data_segments() {
+    let exec = LocalExecutor::default();
+
+    exec.run(async move {
+        let mut path = temp_dir();
+        path.push(uuid::Uuid::new_v4().to_string());
+
+        let cfg = timestore::ConfigBuilder::default()
+            .path(path.clone())
+            .create_if_not_exists(true)
+            .tables(vec!["table0".to_owned(), "table1".to_owned()])
+            .data_segment_size(Some(100))
+            .build()
+            .unwrap();
+
+        {
+            let (writer_factory, reader_factory) =
+                timestore::open(cfg.clone()).await.context("open db")?;
+            let mut writer = writer_factory.make().await.unwrap();
+            let reader = reader_factory.make().await.unwrap();
+
+            // table0 gets three values per segment, table1 gets one value that is larger than a segment
+            for key in 1..=10u64 {
+                let big = if key == 5 { vec![5; 250] } else { Vec::new() };
+                writer.append(key, vec![value(key), big]).await.unwrap();
+            }
+
+            for start in [0, 90, 180, 270] {
+                assert!(path
+                    .join("table0")
+                    .join(format!("data.{}", start))
+                    .is_file());
+            }
+            assert!(!path.join("table0").join("data").exists());
+            assert!(path.join("table1").join("data.0").is_file());
+            assert!(!path.join("table1").join("data.250").exists());
+
+            // readers that were created before a segment was started can read from it
+            for key in 1..=10u64 {
+                assert_eq!(
+                    &*reader.read("table0", key).await.unwrap().unwrap(),
+                    &*value(key)
+                );
+            }
+            assert_eq!(
+                &*reader.read("table1", 5).await.unwrap().unwrap(),
+                &[5; 250]
+            );
+            reader.prefetch("table0", 1, 11).await.unwrap();
+
+            let mut iter = reader
+                .iter(
+                    IterParamsBuilder::default()
+                        .from(0)
+                        .to(11)
+                        .table(Some("table0"))
+                        .step(2)
+                        .build()
+                        .unwrap(),
+                )
+                .await
+                .unwrap()
+                .unwrap();
+            for key in [1u64, 3, 5, 7, 9] {
+                let ((_, to), buf) = iter.next().await.unwrap().unwrap();
+                assert_eq!(to, key);
+                assert_eq!(buf, value(key));
+                assert_eq!(&*iter.read("table0").await.unwrap(), &*value(key));
+            }
+            assert!(iter.next().await.unwrap().is_none());
+
+            // truncating removes the segments that start after the end of the data
+            assert_eq!(writer.truncate_after(4).await.unwrap(), 6);
+            assert!(path.join("table0").join("data.90").is_file());
+            assert!(!path.join("table0").join("data.180").exists());
+            assert!(!path.join("table0").join("data.270").exists());
+
+            writer.append(5, vec![value(5), Vec::new()]).await.unwrap();
+            writer.append(6, vec![value(6), Vec::new()]).await.unwrap();
+            writer
+                .overwrite_last(6, vec![vec![6; 60], b"six".to_vec()])
+                .await
+                .unwrap();
+
+            assert!(timestore::delete_range(&cfg, 1, 2).await.is_err());
+            assert!(writer.expire_before(2).await.is_err());
+        }
+
+        {
+            let (_writer_factory, reader_factory) =
+                timestore::open(cfg.clone()).await.context("reopen db")?;
+            let reader = reader_factory.make().await.unwrap();
+            for key in 1..=5u64 {
+                assert_eq!(
+                    &*reader.read("table0", key).await.unwrap().unwrap(),
+                    &*value(key)
+                );
+            }
+            assert_eq!(&*reader.read("table0", 6).await.unwrap().unwrap(), &[6; 60]);
+            assert_eq!(&*reader.read("table1", 6).await.unwrap().unwrap(), b"six");
+            assert!(path.join("table0").join("data.150").is_file());
+            // the data of table1 was truncated to 0 so it only has the first segment
+            assert!(path.join("table1").join("data.0").is_file());
+            assert!(!path.join("table1").join("data.250").exists());
+        }
+
+        assert!(timestore::verify(&cfg).await.unwrap().is_ok());
+
+        let unsegmented = timestore::ConfigBuilder::default()
+            .path(path.clone())
+            .create_if_not_exists(false)
+            .tables(vec!["table0".to_owned(), "table1".to_owned()])
+            .build()
+            .unwrap();
+        assert!(timestore::open(unsegmented).await.is_err());
+
+        Ok::<_, anyhow::Error>(())
+    })
+    .unwrap();
+}
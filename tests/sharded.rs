@@ -0,0 +1,103 @@
+use std::env::temp_dir;
+
+use anyhow::Context;
+use glommio::LocalExecutor;
+use timestore::IterParamsBuilder;
+
+#[test]
+fn test_sharded() {
+    let exec = LocalExecutor::default();
+
+    exec.run(async move {
+        let mut path = temp_dir();
+        path.push(uuid::Uuid::new_v4().to_string());
+
+        let cfg = timestore::ConfigBuilder::default()
+            .path(path.clone())
+            .create_if_not_exists(true)
+            .tables(vec!["table0".to_owned()])
+            .shard_boundaries(vec![100])
+            .build()
+            .unwrap();
+
+        // a sharded store can't be opened as a single store
+        assert!(timestore::open(cfg.clone()).await.is_err());
+
+        let (writer_factory, reader_factory) = timestore::open_sharded(cfg.clone())
+            .await
+            .context("open db")?;
+        let mut writer = writer_factory.make().await.unwrap();
+
+        // keys are only ordered within each shard
+        for key in [10u64, 150, 50, 200, 60] {
+            writer
+                .append(key, vec![key.to_be_bytes().to_vec()])
+                .await
+                .unwrap();
+        }
+        assert_eq!(writer.shard_of(99), 0);
+        assert_eq!(writer.shard_of(100), 1);
+        assert!(path.join("shard.0").is_dir());
+        assert!(path.join("shard.1").is_dir());
+
+        let reader = reader_factory.make().await.unwrap();
+        assert_eq!(
+            reader.keys().collect::<Vec<u64>>(),
+            vec![10, 50, 60, 150, 200]
+        );
+        for key in [10u64, 50, 60, 150, 200] {
+            let res = reader.read("table0", key).await.unwrap().unwrap();
+            assert_eq!(&*res, key.to_be_bytes());
+        }
+        assert!(reader.read("table0", 100).await.unwrap().is_none());
+
+        let collect = |from, to| {
+            let reader = reader.clone();
+            async move {
+                let mut iter = reader
+                    .iter(
+                        IterParamsBuilder::default()
+                            .from(from)
+                            .to(to)
+                            .table(Some("table0"))
+                            .build()
+                            .unwrap(),
+                    )
+                    .await
+                    .unwrap();
+                let mut records = Vec::new();
+                while let Some(((prev_key, key), buf)) = iter.next().await.unwrap() {
+                    assert_eq!(buf, key.to_be_bytes());
+                    records.push((prev_key, key));
+                }
+                records
+            }
+        };
+        assert_eq!(
+            collect(0, 1000).await,
+            vec![(0, 10), (10, 50), (50, 60), (60, 150), (150, 200)]
+        );
+        assert_eq!(
+            collect(55, 160).await,
+            vec![(50, 60), (60, 150), (150, 200)]
+        );
+        assert_eq!(collect(0, 30).await, vec![(0, 10), (10, 50)]);
+        assert_eq!(collect(160, 1000).await, vec![(150, 200)]);
+
+        // each shard is a store of its own
+        drop(reader);
+        drop(reader_factory);
+        drop(writer);
+        let (_writer_factory, reader_factory) = timestore::open(cfg.shard_config(1))
+            .await
+            .context("open shard")?;
+        let reader = reader_factory.make().await.unwrap();
+        assert_eq!(
+            reader.keys().iter_from(0).collect::<Vec<u64>>(),
+            vec![150, 200]
+        );
+
+        Ok::<_, anyhow::Error>(())
+    })
+    .unwrap();
+}
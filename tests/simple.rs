@@ -171,3 +171,842 @@ fn test_reopen() {
     })
     .unwrap();
 }
+
+#[test]
+fn test_append_position() {
+    let exec = LocalExecutor::default();
+
+    exec.run(async move {
+        let mut path = temp_dir();
+        path.push(uuid::Uuid::new_v4().to_string());
+
+        let cfg = timestore::ConfigBuilder::default()
+            .path(path)
+            .create_if_not_exists(true)
+            .segment_length(1024)
+            .tables(vec!["table0".to_owned()])
+            .build()
+            .unwrap();
+
+        {
+            let (writer_factory, _reader_factory) =
+                timestore::open(cfg.clone()).await.context("open db")?;
+            let mut writer = writer_factory.make().await.unwrap();
+
+            for (pos, key) in [3u64, 7, 8].into_iter().enumerate() {
+                assert_eq!(
+                    writer.append(key, vec![b"abc".to_vec()]).await.unwrap(),
+                    pos as u64
+                );
+            }
+        }
+
+        let (writer_factory, _reader_factory) = timestore::open(cfg).await.context("reopen db")?;
+        let mut writer = writer_factory.make().await.unwrap();
+        assert_eq!(writer.append(9, vec![b"abc".to_vec()]).await.unwrap(), 3);
+
+        Ok::<_, anyhow::Error>(())
+    })
+    .unwrap();
+}
+
+#[test]
+fn test_interleaved_block_writes() {
+    let exec = LocalExecutor::default();
+
+    exec.run(async move {
+        let mut path = temp_dir();
+        path.push(uuid::Uuid::new_v4().to_string());
+
+        let cfg = timestore::ConfigBuilder::default()
+            .path(path)
+            .create_if_not_exists(true)
+            .segment_length(64)
+            .tables(vec!["table0".to_owned(), "table1".to_owned()])
+            .build()
+            .unwrap();
+
+        let value = |key: u64| {
+            vec![
+                key.to_be_bytes().to_vec(),
+                vec![key as u8; key as usize % 7],
+            ]
+        };
+
+        {
+            let (writer_factory, _reader_factory) =
+                timestore::open(cfg.clone()).await.context("open db")?;
+            let mut writer = writer_factory.make().await.unwrap();
+
+            // keys and offsets of consecutive records share dma blocks
+            for key in 1..=600u64 {
+                writer.append(key, value(key)).await.unwrap();
+            }
+
+            assert_eq!(writer.truncate_after(300).await.unwrap(), 300);
+
+            for key in 301..=700u64 {
+                writer.append(key, value(key)).await.unwrap();
+            }
+        }
+
+        let (_writer_factory, reader_factory) = timestore::open(cfg).await.context("reopen db")?;
+        let reader = reader_factory.make().await.unwrap();
+
+        let keys = reader.keys().iter_from(0).collect::<Vec<u64>>();
+        assert_eq!(keys, (1..=700).collect::<Vec<u64>>());
+
+        for key in 1..=700u64 {
+            let expected = value(key);
+            let res = reader.read("table0", key).await.unwrap().unwrap();
+            assert_eq!(&*res, &*expected[0]);
+            let res = reader.read("table1", key).await.unwrap().unwrap();
+            assert_eq!(&*res, &*expected[1]);
+        }
+
+        Ok::<_, anyhow::Error>(())
+    })
+    .unwrap();
+}
+
+#[test]
+fn test_reopen_many_tables() {
+    let exec = LocalExecutor::default();
+
+    exec.run(async move {
+        let mut path = temp_dir();
+        path.push(uuid::Uuid::new_v4().to_string());
+
+        let tables = (0..8).map(|i| format!("table{}", i)).collect::<Vec<_>>();
+        let cfg = timestore::ConfigBuilder::default()
+            .path(path)
+            .create_if_not_exists(true)
+            .tables(tables.clone())
+            .build()
+            .unwrap();
+
+        let values = |key: u64| {
+            (0..8u64)
+                .map(|t| vec![t as u8; (key * (t + 1)) as usize % 13])
+                .collect::<Vec<_>>()
+        };
+
+        {
+            let (writer_factory, _reader_factory) =
+                timestore::open(cfg.clone()).await.context("open db")?;
+            let mut writer = writer_factory.make().await.unwrap();
+
+            for key in 1..=50u64 {
+                writer.append(key, values(key)).await.unwrap();
+            }
+        }
+
+        let (_writer_factory, reader_factory) = timestore::open(cfg).await.context("reopen db")?;
+        let reader = reader_factory.make().await.unwrap();
+
+        let mut expected_pos = [0u64; 8];
+        for key in 1..=50u64 {
+            for (t, (table, value)) in tables.iter().zip(values(key)).enumerate() {
+                let (pos, len) = reader.value_location(table, key).unwrap().unwrap();
+                assert_eq!((pos, len), (expected_pos[t], value.len()));
+                expected_pos[t] += len as u64;
+
+                let res = reader.read(table, key).await.unwrap().unwrap();
+                assert_eq!(&*res, &*value);
+            }
+        }
+
+        Ok::<_, anyhow::Error>(())
+    })
+    .unwrap();
+}
+
+#[test]
+fn test_append_many() {
+    let exec = LocalExecutor::default();
+
+    exec.run(async move {
+        let mut path = temp_dir();
+        path.push(uuid::Uuid::new_v4().to_string());
+
+        let cfg = timestore::ConfigBuilder::default()
+            .path(path)
+            .create_if_not_exists(true)
+            .tables(vec!["table0".to_owned(), "table1".to_owned()])
+            .data_segment_size(Some(64))
+            .build()
+            .unwrap();
+
+        {
+            let (writer_factory, reader_factory) =
+                timestore::open(cfg.clone()).await.context("open db")?;
+            let mut writer = writer_factory.make().await.unwrap();
+            let reader = reader_factory.make().await.unwrap();
+
+            assert_eq!(writer.append_many(Vec::new()).await.unwrap(), 0);
+            assert_eq!(
+                writer
+                    .append(1, vec![vec![1; 10], Vec::new()])
+                    .await
+                    .unwrap(),
+                0
+            );
+            assert!(writer
+                .append_many(vec![(2, vec![vec![2]]), (3, vec![vec![3], vec![3]])])
+                .await
+                .is_err());
+
+            let records = (2..=20u64)
+                .map(|key| (key, vec![vec![key as u8; 10], key.to_be_bytes().to_vec()]))
+                .collect::<Vec<_>>();
+            assert_eq!(writer.append_many(records).await.unwrap(), 1);
+            assert_eq!(
+                writer
+                    .append(21, vec![vec![21; 10], Vec::new()])
+                    .await
+                    .unwrap(),
+                20
+            );
+
+            for key in 2..=20u64 {
+                assert_eq!(
+                    &*reader.read("table0", key).await.unwrap().unwrap(),
+                    &[key as u8; 10]
+                );
+            }
+        }
+
+        let (_writer_factory, reader_factory) = timestore::open(cfg).await.context("reopen db")?;
+        let reader = reader_factory.make().await.unwrap();
+        assert_eq!(
+            reader.keys().iter_from(0).collect::<Vec<u64>>(),
+            (1..=21).collect::<Vec<u64>>()
+        );
+        for key in 2..=20u64 {
+            assert_eq!(
+                &*reader.read("table0", key).await.unwrap().unwrap(),
+                &[key as u8; 10]
+            );
+            assert_eq!(
+                &*reader.read("table1", key).await.unwrap().unwrap(),
+                key.to_be_bytes()
+            );
+        }
+        assert_eq!(
+            &*reader.read("table0", 21).await.unwrap().unwrap(),
+            &[21; 10]
+        );
+
+        Ok::<_, anyhow::Error>(())
+    })
+    .unwrap();
+}
+
+#[test]
+fn test_estimate_append() {
+    let exec = LocalExecutor::default();
+
+    exec.run(async move {
+        let mut path = temp_dir();
+        path.push(uuid::Uuid::new_v4().to_string());
+
+        let cfg = timestore::ConfigBuilder::default()
+            .path(path)
+            .create_if_not_exists(true)
+            .tables(vec!["table0".to_owned(), "table1".to_owned()])
+            .data_segment_size(Some(64))
+            .build()
+            .unwrap();
+
+        let (writer_factory, reader_factory) = timestore::open(cfg).await.context("open db")?;
+        let mut writer = writer_factory.make().await.unwrap();
+        let reader = reader_factory.make().await.unwrap();
+
+        assert!(writer.estimate_append(&[vec![1]]).is_err());
+
+        for key in 1..=10u64 {
+            let values = vec![vec![key as u8; 20], vec![key as u8; key as usize]];
+            let estimate = writer.estimate_append(&values).unwrap();
+            assert_eq!(estimate.bytes, 20 + key);
+            // table0 starts a new segment every third value since three of them don't fit into 64 bytes
+            assert_eq!(estimate.new_segments, vec![key % 3 == 1 && key > 1, false]);
+
+            let pos = writer.append(key, values).await.unwrap();
+            assert_eq!(estimate.position, pos);
+            assert_eq!(
+                estimate.write_offsets,
+                vec![
+                    reader.table_offsets("table0").unwrap().last().unwrap(),
+                    reader.table_offsets("table1").unwrap().last().unwrap(),
+                ]
+            );
+        }
+
+        Ok::<_, anyhow::Error>(())
+    })
+    .unwrap();
+}
+
+#[test]
+fn test_no_tables() {
+    let exec = LocalExecutor::default();
+
+    exec.run(async move {
+        let mut path = temp_dir();
+        path.push(uuid::Uuid::new_v4().to_string());
+
+        let cfg = timestore::ConfigBuilder::default()
+            .path(path)
+            .create_if_not_exists(true)
+            .tables(Vec::new())
+            .build()
+            .unwrap();
+
+        {
+            let (writer_factory, reader_factory) =
+                timestore::open(cfg.clone()).await.context("open db")?;
+            let mut writer = writer_factory.make().await.unwrap();
+            let reader = reader_factory.make().await.unwrap();
+
+            for key in 1..=5u64 {
+                assert_eq!(writer.append(key * 10, Vec::new()).await.unwrap(), key - 1);
+            }
+            assert!(writer.append(60, vec![vec![1]]).await.is_err());
+            writer
+                .append_many((6..=10u64).map(|key| (key * 10, Vec::new())).collect())
+                .await
+                .unwrap();
+
+            assert!(reader.contains_key(30));
+            assert!(!reader.contains_key(35));
+            assert_eq!(reader.count_range(20, 60), 4);
+            assert!(matches!(
+                reader.read("table0", 10).await,
+                Err(timestore::TimestoreError::TableNotFound(_))
+            ));
+        }
+
+        let (_writer_factory, reader_factory) = timestore::open(cfg).await.context("reopen db")?;
+        let reader = reader_factory.make().await.unwrap();
+        assert!(reader.table_names().is_empty());
+
+        let mut iter = reader
+            .iter(
+                IterParamsBuilder::default()
+                    .from(25)
+                    .to(70)
+                    .build()
+                    .unwrap(),
+            )
+            .await
+            .unwrap()
+            .unwrap();
+        let mut prev = 25;
+        let mut keys = Vec::new();
+        while let Some(((from, to), buf)) = iter.next().await.unwrap() {
+            assert!(buf.is_empty());
+            assert!(iter.read_all().await.unwrap().is_empty());
+            if !keys.is_empty() {
+                assert_eq!(from, prev);
+            }
+            prev = to;
+            keys.push(to);
+        }
+        assert_eq!(keys, vec![30, 40, 50, 60, 70]);
+
+        Ok::<_, anyhow::Error>(())
+    })
+    .unwrap();
+}
+
+#[test]
+fn test_reopen_writer() {
+    let exec = LocalExecutor::default();
+
+    exec.run(async move {
+        let mut path = temp_dir();
+        path.push(uuid::Uuid::new_v4().to_string());
+
+        let cfg = timestore::ConfigBuilder::default()
+            .path(path)
+            .create_if_not_exists(true)
+            .tables(vec!["table0".to_owned()])
+            .build()
+            .unwrap();
+
+        let (writer_factory, _reader_factory) =
+            timestore::open(cfg.clone()).await.context("open db")?;
+        let mut writer = writer_factory.make().await.unwrap();
+        writer.append(1, vec![b"one".to_vec()]).await.unwrap();
+        drop(writer);
+
+        for key in 2..=4u64 {
+            let (mut writer, reader_factory) = timestore::reopen_writer(cfg.clone())
+                .await
+                .context("reopen writer")?;
+            let reader = reader_factory.make().await.unwrap();
+
+            assert_eq!(
+                writer.append(key, vec![vec![key as u8]]).await.unwrap(),
+                key - 1
+            );
+            assert_eq!(
+                reader.keys().iter_from(0).collect::<Vec<u64>>(),
+                (1..=key).collect::<Vec<u64>>()
+            );
+            assert_eq!(&*reader.read("table0", 1).await.unwrap().unwrap(), b"one");
+            assert_eq!(
+                &*reader.read("table0", key).await.unwrap().unwrap(),
+                &[key as u8]
+            );
+        }
+
+        Ok::<_, anyhow::Error>(())
+    })
+    .unwrap();
+}
+
+#[test]
+fn test_append_auto() {
+    let exec = LocalExecutor::default();
+
+    exec.run(async move {
+        let mut path = temp_dir();
+        path.push(uuid::Uuid::new_v4().to_string());
+
+        let cfg = timestore::ConfigBuilder::default()
+            .path(path)
+            .create_if_not_exists(true)
+            .tables(vec!["table0".to_owned()])
+            .build()
+            .unwrap();
+
+        {
+            let (writer_factory, _reader_factory) =
+                timestore::open(cfg.clone()).await.context("open db")?;
+            let mut writer = writer_factory.make().await.unwrap();
+            for key in 0..5u64 {
+                assert_eq!(
+                    writer.append_auto(vec![vec![key as u8]]).await.unwrap(),
+                    key
+                );
+            }
+        }
+
+        let (mut writer, reader_factory) = timestore::reopen_writer(cfg)
+            .await
+            .context("reopen writer")?;
+        assert_eq!(writer.append_auto(vec![vec![5]]).await.unwrap(), 5);
+        writer.append(10, vec![vec![10]]).await.unwrap();
+        assert_eq!(writer.append_auto(vec![vec![11]]).await.unwrap(), 11);
+
+        let reader = reader_factory.make().await.unwrap();
+        let keys = reader.keys().iter_from(0).collect::<Vec<u64>>();
+        assert_eq!(keys, vec![0, 1, 2, 3, 4, 5, 10, 11]);
+        for key in keys {
+            let res = reader.read("table0", key).await.unwrap().unwrap();
+            assert_eq!(&*res, &[key as u8]);
+        }
+
+        Ok::<_, anyhow::Error>(())
+    })
+    .unwrap();
+}
+
+#[test]
+fn test_append_now() {
+    let exec = LocalExecutor::default();
+
+    exec.run(async move {
+        let mut path = temp_dir();
+        path.push(uuid::Uuid::new_v4().to_string());
+
+        let cfg = timestore::ConfigBuilder::default()
+            .path(path)
+            .create_if_not_exists(true)
+            .tables(vec!["table0".to_owned()])
+            .build()
+            .unwrap();
+
+        let mut keys = Vec::new();
+        {
+            let (writer_factory, _reader_factory) =
+                timestore::open(cfg.clone()).await.context("open db")?;
+            let mut writer = writer_factory.make().await.unwrap();
+            for i in 0..100u8 {
+                keys.push(writer.append_now(vec![vec![i]]).await.unwrap());
+            }
+        }
+
+        // keys are nanoseconds since the store was created
+        assert!(keys[99] < 60 * 1_000_000_000);
+
+        let (mut writer, reader_factory) = timestore::reopen_writer(cfg)
+            .await
+            .context("reopen writer")?;
+        keys.push(writer.append_now(vec![vec![100]]).await.unwrap());
+        // a key that is ahead of the clock is bumped by one
+        writer.append(u64::MAX - 10, vec![vec![101]]).await.unwrap();
+        keys.push(u64::MAX - 10);
+        assert_eq!(
+            writer.append_now(vec![vec![102]]).await.unwrap(),
+            u64::MAX - 9
+        );
+        keys.push(u64::MAX - 9);
+
+        assert!(keys.windows(2).all(|w| w[0] < w[1]));
+        let reader = reader_factory.make().await.unwrap();
+        assert_eq!(reader.keys().iter_from(0).collect::<Vec<u64>>(), keys);
+        for (i, &key) in keys.iter().enumerate() {
+            let res = reader.read("table0", key).await.unwrap().unwrap();
+            assert_eq!(&*res, &[i as u8]);
+        }
+
+        Ok::<_, anyhow::Error>(())
+    })
+    .unwrap();
+}
+
+#[test]
+fn test_write_offset() {
+    let exec = LocalExecutor::default();
+
+    exec.run(async move {
+        let mut path = temp_dir();
+        path.push(uuid::Uuid::new_v4().to_string());
+
+        let cfg = timestore::ConfigBuilder::default()
+            .path(path)
+            .create_if_not_exists(true)
+            .tables(vec!["table0".to_owned(), "table1".to_owned()])
+            .build()
+            .unwrap();
+
+        let values = |key: u64| vec![vec![0; key as usize * 3], vec![0; 100 - key as usize]];
+        let sum = |table: usize, keys: std::ops::RangeInclusive<u64>| {
+            keys.map(|key| values(key)[table].len() as u64).sum::<u64>()
+        };
+
+        {
+            let (writer_factory, _reader_factory) =
+                timestore::open(cfg.clone()).await.context("open db")?;
+            assert_eq!(writer_factory.write_offset("table0").unwrap(), 0);
+            assert!(writer_factory.write_offset("table2").is_err());
+
+            let mut writer = writer_factory.make().await.unwrap();
+            for key in 1..=10u64 {
+                writer.append(key, values(key)).await.unwrap();
+                assert_eq!(writer.write_offset("table0").unwrap(), sum(0, 1..=key));
+                assert_eq!(writer.write_offset("table1").unwrap(), sum(1, 1..=key));
+            }
+            assert!(writer.write_offset("table2").is_err());
+        }
+
+        let (writer_factory, _reader_factory) = timestore::open(cfg).await.context("reopen db")?;
+        assert_eq!(
+            writer_factory.write_offset("table0").unwrap(),
+            sum(0, 1..=10)
+        );
+        assert_eq!(
+            writer_factory.write_offset("table1").unwrap(),
+            sum(1, 1..=10)
+        );
+
+        Ok::<_, anyhow::Error>(())
+    })
+    .unwrap();
+}
+
+#[test]
+fn test_duplicate_key_policy() {
+    use timestore::DuplicateKeyPolicy;
+
+    let exec = LocalExecutor::default();
+
+    exec.run(async move {
+        for policy in [
+            DuplicateKeyPolicy::Allow,
+            DuplicateKeyPolicy::Error,
+            DuplicateKeyPolicy::Skip,
+            DuplicateKeyPolicy::Overwrite,
+        ] {
+            let mut path = temp_dir();
+            path.push(uuid::Uuid::new_v4().to_string());
+
+            let cfg = timestore::ConfigBuilder::default()
+                .path(path)
+                .create_if_not_exists(true)
+                .tables(vec!["table0".to_owned()])
+                .on_duplicate_key(policy)
+                .build()
+                .unwrap();
+
+            let (writer_factory, _reader_factory) =
+                timestore::open(cfg.clone()).await.context("open db")?;
+            let mut writer = writer_factory.make().await.unwrap();
+
+            writer.append(5, vec![b"a".to_vec()]).await.unwrap();
+            let res = writer.append(5, vec![b"bb".to_vec()]).await;
+            match policy {
+                DuplicateKeyPolicy::Error => assert!(res.is_err()),
+                DuplicateKeyPolicy::Allow => assert_eq!(res.unwrap(), 1),
+                _ => assert_eq!(res.unwrap(), 0),
+            }
+
+            let res = writer
+                .append_many(vec![
+                    (5, vec![b"c".to_vec()]),
+                    (6, vec![b"d".to_vec()]),
+                    (6, vec![b"e".to_vec()]),
+                    (7, vec![b"f".to_vec()]),
+                ])
+                .await;
+            match policy {
+                DuplicateKeyPolicy::Error => assert!(res.is_err()),
+                DuplicateKeyPolicy::Allow => assert_eq!(res.unwrap(), 2),
+                _ => assert_eq!(res.unwrap(), 0),
+            }
+            drop(writer);
+
+            let expected: Vec<(u64, &[u8])> = match policy {
+                DuplicateKeyPolicy::Allow => vec![
+                    (5, b"a"),
+                    (5, b"bb"),
+                    (5, b"c"),
+                    (6, b"d"),
+                    (6, b"e"),
+                    (7, b"f"),
+                ],
+                DuplicateKeyPolicy::Error => vec![(5, b"a")],
+                DuplicateKeyPolicy::Skip => vec![(5, b"a"), (6, b"d"), (7, b"f")],
+                DuplicateKeyPolicy::Overwrite => vec![(5, b"c"), (6, b"e"), (7, b"f")],
+            };
+
+            let (_writer_factory, reader_factory) =
+                timestore::open(cfg).await.context("reopen db")?;
+            let reader = reader_factory.make().await.unwrap();
+            assert_eq!(
+                reader.keys().iter_from(0).collect::<Vec<u64>>(),
+                expected.iter().map(|(key, _)| *key).collect::<Vec<u64>>(),
+                "{:?}",
+                policy
+            );
+            for (position, (_, value)) in expected.iter().enumerate() {
+                let res = reader
+                    .read_at_position("table0", position)
+                    .await
+                    .unwrap()
+                    .unwrap();
+                assert_eq!(&*res, *value, "{:?}", policy);
+            }
+        }
+
+        Ok::<_, anyhow::Error>(())
+    })
+    .unwrap();
+}
+
+#[test]
+fn test_append_concat() {
+    let exec = LocalExecutor::default();
+
+    exec.run(async move {
+        let tables = vec![
+            "table0".to_owned(),
+            "table1".to_owned(),
+            "table2".to_owned(),
+        ];
+        let mut paths = Vec::new();
+        let mut writers = Vec::new();
+        let mut reader_factories = Vec::new();
+        for _ in 0..2 {
+            let mut path = temp_dir();
+            path.push(uuid::Uuid::new_v4().to_string());
+
+            let (writer_factory, reader_factory) = timestore::open(
+                timestore::ConfigBuilder::default()
+                    .path(path.clone())
+                    .create_if_not_exists(true)
+                    .tables(tables.clone())
+                    .build()
+                    .unwrap(),
+            )
+            .await
+            .context("open db")?;
+
+            paths.push(path);
+            writers.push(writer_factory.make().await.unwrap());
+            reader_factories.push(reader_factory);
+        }
+
+        for key in 1..=20u64 {
+            let values = vec![
+                vec![key as u8; key as usize % 7],
+                key.to_be_bytes().to_vec(),
+                vec![0xAA; key as usize * 301],
+            ];
+            let lengths = values.iter().map(|v| v.len()).collect::<Vec<usize>>();
+            let buf = values.concat();
+
+            let pos = writers[0].append(key, values).await.unwrap();
+            assert_eq!(
+                writers[1].append_concat(key, &buf, &lengths).await.unwrap(),
+                pos
+            );
+        }
+
+        assert!(writers[1]
+            .append_concat(30, &[0; 4], &[1, 2, 3])
+            .await
+            .is_err());
+        assert!(writers[1]
+            .append_concat(30, &[0; 4], &[1, 3])
+            .await
+            .is_err());
+        assert!(writers[1]
+            .append_concat(30, &[0; 4], &[usize::MAX, 2, 3])
+            .await
+            .is_err());
+
+        let readers = [
+            reader_factories.remove(0).make().await.unwrap(),
+            reader_factories.remove(0).make().await.unwrap(),
+        ];
+        assert_eq!(
+            readers[0].keys().iter_from(0).collect::<Vec<u64>>(),
+            readers[1].keys().iter_from(0).collect::<Vec<u64>>()
+        );
+        for table in tables.iter() {
+            assert_eq!(
+                readers[0]
+                    .table_offsets(table)
+                    .unwrap()
+                    .iter_from(0)
+                    .collect::<Vec<u64>>(),
+                readers[1]
+                    .table_offsets(table)
+                    .unwrap()
+                    .iter_from(0)
+                    .collect::<Vec<u64>>()
+            );
+            for key in 1..=20u64 {
+                let expected = readers[0].read(table, key).await.unwrap().unwrap();
+                let got = readers[1].read(table, key).await.unwrap().unwrap();
+                assert_eq!(&*expected, &*got);
+            }
+            assert_eq!(
+                std::fs::read(paths[0].join(table).join("data")).unwrap(),
+                std::fs::read(paths[1].join(table).join("data")).unwrap()
+            );
+        }
+
+        Ok::<_, anyhow::Error>(())
+    })
+    .unwrap();
+}
+
+#[test]
+fn test_writer_read() {
+    let exec = LocalExecutor::default();
+
+    exec.run(async move {
+        let mut path = temp_dir();
+        path.push(uuid::Uuid::new_v4().to_string());
+
+        let (writer_factory, _reader_factory) = timestore::open(
+            timestore::ConfigBuilder::default()
+                .path(path)
+                .create_if_not_exists(true)
+                .tables(vec!["table0".to_owned(), "table1".to_owned()])
+                .data_segment_size(Some(64))
+                .build()
+                .unwrap(),
+        )
+        .await
+        .context("open db")?;
+
+        let mut writer = writer_factory.make().await.unwrap();
+
+        let value = |key: u64| vec![key as u8; 20];
+        for key in 1..=10u64 {
+            writer
+                .append(key * 2, vec![value(key), Vec::new()])
+                .await
+                .unwrap();
+            // reads back the record that was just appended
+            assert_eq!(
+                &*writer.read("table0", key * 2).await.unwrap().unwrap(),
+                &*value(key)
+            );
+        }
+
+        // values in data files that the writer has rolled over from
+        for key in 1..=10u64 {
+            assert_eq!(
+                &*writer.read("table0", key * 2).await.unwrap().unwrap(),
+                &*value(key)
+            );
+            assert!(writer
+                .read("table1", key * 2)
+                .await
+                .unwrap()
+                .unwrap()
+                .is_empty());
+        }
+
+        assert!(writer.read("table0", 3).await.unwrap().is_none());
+        assert!(writer.read("table2", 2).await.is_err());
+
+        Ok::<_, anyhow::Error>(())
+    })
+    .unwrap();
+}
+
+#[test]
+fn test_append_if_absent() {
+    let exec = LocalExecutor::default();
+
+    exec.run(async move {
+        let mut path = temp_dir();
+        path.push(uuid::Uuid::new_v4().to_string());
+
+        let (mut writer, reader_factory) = timestore::reopen_writer(
+            timestore::ConfigBuilder::default()
+                .path(path)
+                .create_if_not_exists(true)
+                .tables(vec!["table0".to_owned()])
+                .on_duplicate_key(timestore::DuplicateKeyPolicy::Overwrite)
+                .build()
+                .unwrap(),
+        )
+        .await
+        .context("open writer")?;
+
+        for key in [3u64, 5, 8] {
+            assert!(writer
+                .append_if_absent(key, vec![vec![key as u8]])
+                .await
+                .unwrap());
+        }
+        assert_eq!(writer.sync_point(), 3);
+
+        // replays of the last key and an earlier key are skipped, even though duplicates overwrite
+        for key in [8u64, 5] {
+            assert!(!writer.append_if_absent(key, vec![vec![0]]).await.unwrap());
+            assert_eq!(writer.sync_point(), 3);
+        }
+
+        let reader = reader_factory.make().await.unwrap();
+        assert_eq!(
+            reader.keys().iter_from(0).collect::<Vec<u64>>(),
+            vec![3, 5, 8]
+        );
+        for key in [3u64, 5, 8] {
+            let res = reader.read("table0", key).await.unwrap().unwrap();
+            assert_eq!(&*res, &[key as u8]);
+        }
+
+        Ok::<_, anyhow::Error>(())
+    })
+    .unwrap();
+}
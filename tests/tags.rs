@@ -0,0 +1,153 @@
+use std::env::temp_dir;
+
+use anyhow::Context;
+use glommio::LocalExecutor;
+
+#[test]
+fn test_tag_index() {
+    let exec = LocalExecutor::default();
+
+    exec.run(async move {
+        let mut path = temp_dir();
+        path.push(uuid::Uuid::new_v4().to_string());
+
+        let cfg = timestore::ConfigBuilder::default()
+            .path(path)
+            .create_if_not_exists(true)
+            .tables(vec!["table0".to_owned()])
+            .tag_index(true)
+            .build()
+            .unwrap();
+
+        {
+            let (writer_factory, reader_factory) =
+                timestore::open(cfg.clone()).await.context("open db")?;
+
+            let mut writer = writer_factory.make().await.unwrap();
+            let reader = reader_factory.make().await.unwrap();
+
+            for key in 1..=9u64 {
+                writer
+                    .append_with_tag(key, key % 3 + 1, vec![key.to_be_bytes().to_vec()])
+                    .await
+                    .unwrap();
+            }
+            writer
+                .append(10, vec![10u64.to_be_bytes().to_vec()])
+                .await
+                .unwrap();
+            writer
+                .append_many(vec![
+                    (11, vec![11u64.to_be_bytes().to_vec()]),
+                    (12, vec![12u64.to_be_bytes().to_vec()]),
+                ])
+                .await
+                .unwrap();
+
+            assert_eq!(reader.keys_for_tag(1).unwrap(), vec![3, 6, 9]);
+            assert_eq!(reader.keys_for_tag(2).unwrap(), vec![1, 4, 7]);
+            assert_eq!(reader.keys_for_tag(0).unwrap(), vec![10, 11, 12]);
+            assert!(reader.keys_for_tag(4).unwrap().is_empty());
+        }
+
+        {
+            let (writer_factory, reader_factory) =
+                timestore::open(cfg.clone()).await.context("reopen db")?;
+
+            let reader = reader_factory.make().await.unwrap();
+            assert_eq!(reader.keys_for_tag(3).unwrap(), vec![2, 5, 8]);
+            assert_eq!(reader.keys_for_tag(0).unwrap(), vec![10, 11, 12]);
+
+            let mut writer = writer_factory.make().await.unwrap();
+            writer.truncate_after(5).await.unwrap();
+            writer
+                .append_with_tag(6, 3, vec![6u64.to_be_bytes().to_vec()])
+                .await
+                .unwrap();
+        }
+
+        {
+            let (_writer_factory, reader_factory) =
+                timestore::open(cfg).await.context("reopen db")?;
+
+            let reader = reader_factory.make().await.unwrap();
+            assert_eq!(reader.keys_for_tag(1).unwrap(), vec![3]);
+            assert_eq!(reader.keys_for_tag(3).unwrap(), vec![2, 5, 6]);
+            assert!(reader.keys_for_tag(0).unwrap().is_empty());
+        }
+
+        Ok::<_, anyhow::Error>(())
+    })
+    .unwrap();
+}
+
+#[test]
+fn test_tag_index_disabled() {
+    let exec = LocalExecutor::default();
+
+    exec.run(async move {
+        let mut path = temp_dir();
+        path.push(uuid::Uuid::new_v4().to_string());
+
+        let cfg = timestore::ConfigBuilder::default()
+            .path(path)
+            .create_if_not_exists(true)
+            .tables(vec!["table0".to_owned()])
+            .build()
+            .unwrap();
+
+        let (writer_factory, reader_factory) = timestore::open(cfg).await.context("open db")?;
+
+        let mut writer = writer_factory.make().await.unwrap();
+        assert!(writer.append_with_tag(1, 1, vec![vec![1]]).await.is_err());
+
+        let reader = reader_factory.make().await.unwrap();
+        assert!(reader.keys_for_tag(0).is_err());
+
+        Ok::<_, anyhow::Error>(())
+    })
+    .unwrap();
+}
+
+#[test]
+fn test_tag_index_kept_when_disabled() {
+    let exec = LocalExecutor::default();
+
+    exec.run(async move {
+        let mut path = temp_dir();
+        path.push(uuid::Uuid::new_v4().to_string());
+
+        let cfg = |tag_index: bool| {
+            timestore::ConfigBuilder::default()
+                .path(path.clone())
+                .create_if_not_exists(true)
+                .tables(vec!["table0".to_owned()])
+                .tag_index(tag_index)
+                .build()
+                .unwrap()
+        };
+
+        let (mut writer, _reader_factory) = timestore::reopen_writer(cfg(true))
+            .await
+            .context("open writer")?;
+        writer.append_with_tag(1, 7, vec![vec![1]]).await.unwrap();
+
+        // an open without the index next to the live writer errors instead of removing the tags
+        let err = timestore::open(cfg(false)).await.err().unwrap();
+        assert!(err.to_string().contains("tag index"), "{}", err);
+        assert!(path.join("tags").is_file());
+
+        writer.append_with_tag(2, 7, vec![vec![2]]).await.unwrap();
+        drop(writer);
+
+        assert!(timestore::reopen_writer(cfg(false)).await.is_err());
+
+        let (_writer_factory, reader_factory) =
+            timestore::open(cfg(true)).await.context("reopen db")?;
+        let reader = reader_factory.make().await.unwrap();
+        assert_eq!(reader.keys_for_tag(7).unwrap(), vec![1, 2]);
+
+        Ok::<_, anyhow::Error>(())
+    })
+    .unwrap();
+}
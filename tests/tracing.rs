@@ -0,0 +1,42 @@
+use std::env::temp_dir;
+
+use anyhow::Context;
+use glommio::LocalExecutor;
+use tracing_test::traced_test;
+
+#[test]
+#[traced_test]
+fn test_append_spans() {
+    let exec = LocalExecutor::default();
+
+    exec.run(async move {
+        let mut path = temp_dir();
+        path.push(uuid::Uuid::new_v4().to_string());
+
+        let (writer_factory, _reader_factory) = timestore::open(
+            timestore::ConfigBuilder::default()
+                .path(path)
+                .create_if_not_exists(true)
+                .segment_length(1024)
+                .tables(vec!["table0".to_owned(), "table1".to_owned()])
+                .build()
+                .unwrap(),
+        )
+        .await
+        .context("open db")?;
+
+        let mut writer = writer_factory.make().await.unwrap();
+
+        writer
+            .append(12, vec![b"123".to_vec(), b"3456".to_vec()])
+            .await
+            .unwrap();
+
+        Ok::<_, anyhow::Error>(())
+    })
+    .unwrap();
+
+    assert!(logs_contain("append{key=12 bytes=7 tables=2}"));
+    assert!(logs_contain("commit_length{length=1}"));
+    assert!(logs_contain("fsync"));
+}
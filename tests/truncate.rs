@@ -0,0 +1,128 @@
+use std::env::temp_dir;
+
+use anyhow::Context;
+use glommio::LocalExecutor;
+
+#[test]
+fn test_truncate_after() {
+    let exec = LocalExecutor::default();
+
+    exec.run(async move {
+        let mut path = temp_dir();
+        path.push(uuid::Uuid::new_v4().to_string());
+
+        let cfg = timestore::ConfigBuilder::default()
+            .path(path)
+            .create_if_not_exists(true)
+            .tables(vec!["table0".to_owned(), "table1".to_owned()])
+            .build()
+            .unwrap();
+
+        {
+            let (writer_factory, _reader_factory) =
+                timestore::open(cfg.clone()).await.context("open db")?;
+            let mut writer = writer_factory.make().await.unwrap();
+
+            for key in 1..=10u64 {
+                writer
+                    .append(
+                        key * 10,
+                        vec![key.to_be_bytes().to_vec(), vec![0; key as usize]],
+                    )
+                    .await
+                    .unwrap();
+            }
+
+            assert_eq!(writer.truncate_after(50).await.unwrap(), 5);
+            assert_eq!(writer.truncate_after(50).await.unwrap(), 0);
+
+            writer
+                .append(60, vec![b"new".to_vec(), b"record".to_vec()])
+                .await
+                .unwrap();
+        }
+
+        {
+            let (_writer_factory, reader_factory) =
+                timestore::open(cfg).await.context("reopen db")?;
+            let reader = reader_factory.make().await.unwrap();
+
+            let keys = reader.keys().iter_from(0).collect::<Vec<u64>>();
+            assert_eq!(keys, vec![10, 20, 30, 40, 50, 60]);
+
+            for key in 1..=5u64 {
+                let res = reader.read("table0", key * 10).await.unwrap().unwrap();
+                assert_eq!(&*res, key.to_be_bytes());
+                let res = reader.read("table1", key * 10).await.unwrap().unwrap();
+                assert_eq!(res.len(), key as usize);
+            }
+
+            let res = reader.read("table0", 60).await.unwrap().unwrap();
+            assert_eq!(&*res, b"new");
+            let res = reader.read("table1", 60).await.unwrap().unwrap();
+            assert_eq!(&*res, b"record");
+            assert!(reader.read("table0", 70).await.unwrap().is_none());
+        }
+
+        Ok::<_, anyhow::Error>(())
+    })
+    .unwrap();
+}
+
+#[test]
+fn test_expire_before() {
+    let exec = LocalExecutor::default();
+
+    exec.run(async move {
+        let mut path = temp_dir();
+        path.push(uuid::Uuid::new_v4().to_string());
+
+        let cfg = timestore::ConfigBuilder::default()
+            .path(path)
+            .create_if_not_exists(true)
+            .tables(vec!["table0".to_owned(), "table1".to_owned()])
+            .build()
+            .unwrap();
+
+        {
+            let (writer_factory, _reader_factory) =
+                timestore::open(cfg.clone()).await.context("open db")?;
+            let mut writer = writer_factory.make().await.unwrap();
+
+            for key in 1..=10u64 {
+                writer
+                    .append(
+                        key * 10,
+                        vec![key.to_be_bytes().to_vec(), vec![key as u8; key as usize]],
+                    )
+                    .await
+                    .unwrap();
+            }
+
+            assert_eq!(writer.expire_before(40).await.unwrap(), 3);
+            assert_eq!(writer.expire_before(40).await.unwrap(), 0);
+
+            writer
+                .append(110, vec![11u64.to_be_bytes().to_vec(), vec![11; 11]])
+                .await
+                .unwrap();
+        }
+
+        let (_writer_factory, reader_factory) = timestore::open(cfg).await.context("reopen db")?;
+        let reader = reader_factory.make().await.unwrap();
+
+        let keys = reader.keys().iter_from(0).collect::<Vec<u64>>();
+        assert_eq!(keys, (4..=11).map(|k| k * 10).collect::<Vec<u64>>());
+
+        for key in 4..=11u64 {
+            let res = reader.read("table0", key * 10).await.unwrap().unwrap();
+            assert_eq!(&*res, key.to_be_bytes());
+            let res = reader.read("table1", key * 10).await.unwrap().unwrap();
+            assert_eq!(&*res, vec![key as u8; key as usize]);
+        }
+        assert!(reader.read("table0", 30).await.unwrap().is_none());
+
+        Ok::<_, anyhow::Error>(())
+    })
+    .unwrap();
+}
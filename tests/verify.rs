@@ -0,0 +1,136 @@
+use std::{
+    env::temp_dir,
+    fs::OpenOptions,
+    io::{Seek, SeekFrom, Write},
+    path::Path,
+};
+
+use anyhow::Context;
+use glommio::LocalExecutor;
+
+fn config(path: &Path) -> timestore::Config {
+    timestore::ConfigBuilder::default()
+        .path(path.to_owned())
+        .create_if_not_exists(true)
+        .tables(vec!["table0".to_owned(), "table1".to_owned()])
+        .build()
+        .unwrap()
+}
+
+async fn create_store(path: &Path) -> anyhow::Result<()> {
+    let (writer_factory, _reader_factory) =
+        timestore::open(config(path)).await.context("open db")?;
+    let mut writer = writer_factory.make().await.unwrap();
+
+    for key in [3u64, 5, 9] {
+        writer
+            .append(key, vec![vec![1; 100], vec![2; 10]])
+            .await
+            .unwrap();
+    }
+
+    Ok(())
+}
+
+fn overwrite(path: &Path, pos: u64, data: &[u8]) {
+    let mut file = OpenOptions::new().write(true).open(path).unwrap();
+    file.seek(SeekFrom::Start(pos)).unwrap();
+    file.write_all(data).unwrap();
+}
+
+fn truncate(path: &Path, len: u64) {
+    OpenOptions::new()
+        .write(true)
+        .open(path)
+        .unwrap()
+        .set_len(len)
+        .unwrap();
+}
+
+#[test]
+fn test_verify() {
+    let exec = LocalExecutor::default();
+
+    exec.run(async move {
+        let new_store = || {
+            let mut path = temp_dir();
+            path.push(uuid::Uuid::new_v4().to_string());
+            path
+        };
+
+        let path = new_store();
+        create_store(&path).await?;
+        let report = timestore::verify(&config(&path)).await.unwrap();
+        assert!(report.is_ok(), "{:?}", report);
+        assert_eq!(report.length, Some(3));
+
+        // unreadable length file
+        let path = new_store();
+        create_store(&path).await?;
+        truncate(&path.join("length"), 4);
+        let report = timestore::verify(&config(&path)).await.unwrap();
+        assert_eq!(report.length, None);
+        assert_eq!(report.problems.len(), 1);
+        assert!(report.problems[0].contains("length file"));
+
+        // keys out of order
+        let path = new_store();
+        create_store(&path).await?;
+        overwrite(&path.join("keys"), 8, &10u64.to_be_bytes());
+        let report = timestore::verify(&config(&path)).await.unwrap();
+        assert_eq!(
+            report.problems,
+            vec!["keys are not strictly increasing at position 2. 10 >= 9".to_owned()]
+        );
+
+        // keys file shorter than the length
+        let path = new_store();
+        create_store(&path).await?;
+        truncate(&path.join("keys"), 16);
+        let report = timestore::verify(&config(&path)).await.unwrap();
+        assert_eq!(
+            report.problems,
+            vec!["keys file has 2 entries but length is 3".to_owned()]
+        );
+
+        // offsets out of order
+        let path = new_store();
+        create_store(&path).await?;
+        overwrite(
+            &path.join("table1").join("offsets"),
+            0,
+            &25u64.to_be_bytes(),
+        );
+        let report = timestore::verify(&config(&path)).await.unwrap();
+        assert_eq!(
+            report.problems,
+            vec!["offsets of table 'table1' are decreasing at position 1. 25 > 20".to_owned()]
+        );
+
+        // offsets file shorter than the length
+        let path = new_store();
+        create_store(&path).await?;
+        truncate(&path.join("table0").join("offsets"), 8);
+        let report = timestore::verify(&config(&path)).await.unwrap();
+        assert_eq!(
+            report.problems,
+            vec!["offsets file of table 'table0' has 1 entries but length is 3".to_owned()]
+        );
+
+        // data file smaller than the max offset
+        let path = new_store();
+        create_store(&path).await?;
+        truncate(&path.join("table0").join("data"), 200);
+        let report = timestore::verify(&config(&path)).await.unwrap();
+        assert_eq!(
+            report.problems,
+            vec![
+                "data file for table 'table0' is 200 bytes but offsets require at least 300"
+                    .to_owned()
+            ]
+        );
+
+        Ok::<_, anyhow::Error>(())
+    })
+    .unwrap();
+}
@@ -0,0 +1,80 @@
+use std::{
+    env::temp_dir,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+};
+
+use anyhow::Context;
+use glommio::LocalExecutor;
+use timestore::StoreObserver;
+
+#[derive(Default)]
+struct GuardObserver {
+    syncs: AtomicUsize,
+    unfinished: AtomicUsize,
+}
+
+impl StoreObserver for GuardObserver {
+    fn on_sync(&self) {
+        self.syncs.fetch_add(1, Ordering::SeqCst);
+    }
+
+    fn on_unfinished_writer(&self) {
+        self.unfinished.fetch_add(1, Ordering::SeqCst);
+    }
+}
+
+#[test]
+fn test_writer_guard() {
+    let exec = LocalExecutor::default();
+
+    exec.run(async move {
+        let mut path = temp_dir();
+        path.push(uuid::Uuid::new_v4().to_string());
+
+        let observer = Arc::new(GuardObserver::default());
+
+        let cfg = timestore::ConfigBuilder::default()
+            .path(path)
+            .create_if_not_exists(true)
+            .tables(vec!["table0".to_owned(), "table1".to_owned()])
+            .observer(observer.clone())
+            .build()
+            .unwrap();
+
+        {
+            let (writer_factory, _reader_factory) =
+                timestore::open(cfg.clone()).await.context("open db")?;
+            let mut guard = timestore::WriterGuard::new(writer_factory.make().await.unwrap());
+            guard.append(1, vec![vec![1], vec![1, 1]]).await.unwrap();
+
+            let syncs = observer.syncs.load(Ordering::SeqCst);
+            guard.finish().await.unwrap();
+            // the keys file, two data files and two offset files
+            assert_eq!(observer.syncs.load(Ordering::SeqCst), syncs + 5);
+            assert_eq!(observer.unfinished.load(Ordering::SeqCst), 0);
+        }
+
+        {
+            let (mut writer, _reader_factory) = timestore::reopen_writer(cfg.clone())
+                .await
+                .context("reopen writer")?;
+            writer.append(2, vec![vec![2], vec![2, 2]]).await.unwrap();
+            let guard = timestore::WriterGuard::new(writer);
+            drop(guard);
+            assert_eq!(observer.unfinished.load(Ordering::SeqCst), 1);
+        }
+
+        let (_writer_factory, reader_factory) = timestore::open(cfg).await.context("reopen db")?;
+        let reader = reader_factory.make().await.unwrap();
+        for key in 1..=2u64 {
+            let res = reader.read("table1", key).await.unwrap().unwrap();
+            assert_eq!(&*res, &[key as u8; 2]);
+        }
+
+        Ok::<_, anyhow::Error>(())
+    })
+    .unwrap();
+}
@@ -0,0 +1,142 @@
+use std::{cell::Cell, env::temp_dir, rc::Rc};
+
+use anyhow::Context;
+use glommio::LocalExecutor;
+
+#[test]
+fn test_writer_handle() {
+    let exec = LocalExecutor::default();
+
+    exec.run(async move {
+        let mut path = temp_dir();
+        path.push(uuid::Uuid::new_v4().to_string());
+
+        let cfg = timestore::ConfigBuilder::default()
+            .path(path)
+            .create_if_not_exists(true)
+            .tables(vec!["table0".to_owned(), "table1".to_owned()])
+            .build()
+            .unwrap();
+
+        {
+            let (writer_factory, _reader_factory) =
+                timestore::open(cfg.clone()).await.context("open db")?;
+            let writer = writer_factory.make().await.unwrap();
+
+            let (handle, task) = timestore::WriterHandle::new(writer, 4);
+
+            assert!(handle.append(1, vec![b"a".to_vec()]).await.is_err());
+
+            let next_key = Rc::new(Cell::new(1u64));
+            let mut producers = Vec::new();
+            for producer in 0..4u8 {
+                let handle = handle.clone();
+                let next_key = next_key.clone();
+                producers.push(glommio::spawn_local(async move {
+                    let mut positions = Vec::new();
+                    for _ in 0..25 {
+                        // Keys are taken right before sending so they arrive at the writer in increasing order.
+                        let key = next_key.get();
+                        next_key.set(key + 1);
+                        let pos = handle
+                            .append(key, vec![key.to_be_bytes().to_vec(), vec![producer; 3]])
+                            .await
+                            .unwrap();
+                        positions.push((key, pos));
+                    }
+                    positions
+                }));
+            }
+            drop(handle);
+
+            for producer in producers {
+                for (key, pos) in producer.await {
+                    assert_eq!(pos, key - 1);
+                }
+            }
+
+            let writer = task.await;
+            assert_eq!(writer.table_names().len(), 2);
+        }
+
+        {
+            let (_writer_factory, reader_factory) =
+                timestore::open(cfg).await.context("reopen db")?;
+            let reader = reader_factory.make().await.unwrap();
+
+            let keys = reader.keys().iter_from(0).collect::<Vec<u64>>();
+            assert_eq!(keys, (1..=100).collect::<Vec<u64>>());
+
+            for key in 1..=100u64 {
+                let res = reader.read("table0", key).await.unwrap().unwrap();
+                assert_eq!(&*res, key.to_be_bytes());
+                let res = reader.read("table1", key).await.unwrap().unwrap();
+                assert_eq!(res.len(), 3);
+            }
+        }
+
+        Ok::<_, anyhow::Error>(())
+    })
+    .unwrap();
+}
+
+#[test]
+fn test_writer_handle_batch_errors() {
+    let exec = LocalExecutor::default();
+
+    exec.run(async move {
+        for policy in [
+            timestore::DuplicateKeyPolicy::Skip,
+            timestore::DuplicateKeyPolicy::Error,
+        ] {
+            let mut path = temp_dir();
+            path.push(uuid::Uuid::new_v4().to_string());
+
+            let (writer_factory, reader_factory) = timestore::open(
+                timestore::ConfigBuilder::default()
+                    .path(path)
+                    .create_if_not_exists(true)
+                    .tables(vec!["table0".to_owned()])
+                    .on_duplicate_key(policy)
+                    .max_value_bytes(Some(4))
+                    .build()
+                    .unwrap(),
+            )
+            .await
+            .context("open db")?;
+            let mut writer = writer_factory.make().await.unwrap();
+            writer.append(1, vec![b"a".to_vec()]).await.unwrap();
+
+            let (handle, task) = timestore::WriterHandle::new(writer, 8);
+            // The appends are all queued before the writer task runs, so they are appended as one batch.
+            let results = futures::future::join_all([
+                handle.append(2, vec![b"b".to_vec()]),
+                handle.append(2, vec![b"c".to_vec()]),
+                handle.append(3, vec![b"too long".to_vec()]),
+                handle.append(4, vec![b"d".to_vec()]),
+            ])
+            .await;
+            drop(handle);
+            task.await;
+
+            assert_eq!(results[0].as_ref().unwrap(), &1);
+            match policy {
+                timestore::DuplicateKeyPolicy::Skip => assert_eq!(results[1].as_ref().unwrap(), &1),
+                _ => assert!(results[1].is_err()),
+            }
+            assert!(results[2].is_err());
+            assert_eq!(results[3].as_ref().unwrap(), &2);
+
+            let reader = reader_factory.make().await.unwrap();
+            assert_eq!(
+                reader.keys().iter_from(0).collect::<Vec<u64>>(),
+                vec![1, 2, 4]
+            );
+            assert_eq!(&*reader.read("table0", 2).await.unwrap().unwrap(), b"b");
+            assert_eq!(&*reader.read("table0", 4).await.unwrap().unwrap(), b"d");
+        }
+
+        Ok::<_, anyhow::Error>(())
+    })
+    .unwrap();
+}